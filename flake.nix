@@ -22,6 +22,10 @@
 
           buildInputs = [
             rustToolchain
+          ] ++ lib.optionals stdenv.isLinux [
+            # for the audio feature
+            pkg-config
+            alsa-lib
           ];
 
           LD_LIBRARY_PATH = lib.makeLibraryPath [
@@ -0,0 +1,54 @@
+-- draws the frames of two instances above each other, shifted so the same
+-- spot of the level lines up in both
+-- YIELD number (x, y, scale, first, second, gap?, scaling?, top?, bottom?)
+local FASTNES_COMPARE = canvas.instructions.nes_compare
+
+-- rows of overscan cropped off by default, see nes_frame
+local OVERSCAN = 8
+
+---@class Compare : Shape
+---
+---@field first  signal<integer>
+---@field second signal<integer>
+---@field size   signal<number>
+---@field gap    signal<number> NES pixels between the two frames
+---@field scaling? "nearest"|"snap"|"sharp" resampling at scales that aren't whole, --scaling if nil
+---@field crop? integer[] rows cropped off the top and bottom, the overscan if nil
+---
+---@field width  fun(): number
+---@field height fun(): number
+local Compare = shapes.newshape()
+
+---@param self Compare
+---@param emit fun(...)
+function Compare:draw(emit)
+  local crop = self.crop or {}
+  emit(FASTNES_COMPARE, 0, 0, self.size(), self.first(), self.second(), self.gap(), self.scaling,
+    crop[1], crop[2])
+end
+
+---@param pos?    signalValue<vec2,    Compare>
+---@param first?  signalValue<integer, Compare>
+---@param second? signalValue<integer, Compare>
+---@param size?   signalValue<number,  Compare>
+---@return Compare
+---@nodiscard
+function Compare.new(pos, first, second, size)
+  local compare = shapes.Shape(pos, { size = size or 1, gap = 8 }, Compare)
+  compare.first = signal.signal(first or 1, tweens.interp.integer, compare)
+  compare.second = signal.signal(second or 2, tweens.interp.integer, compare)
+
+  -- divide by 3.75 to make it pixel perfect on full HD screens
+  compare.width = 256 / 3.75 * compare.size
+  compare.height = function()
+    local top = compare.crop and compare.crop[1] or OVERSCAN
+    local bottom = compare.crop and compare.crop[2] or OVERSCAN
+    local rows = 240 - top - bottom
+    -- the second frame ends where the bottom is cropped off it
+    return (rows + compare.gap() + 240 - bottom) / 3.75 * compare.size()
+  end
+
+  return compare
+end
+
+return Compare
@@ -0,0 +1,107 @@
+---- LIBRARY ----
+local Rewind = require("rewind")
+
+---- CODE ----
+local vec2 = vector.vec2
+
+-- what only the operator sees, in the window opened with --dashboard: how the
+-- simulation is doing, every Mario, and the warnings and errors logged lately
+
+local marios = canvas.signal("marios")
+local count = #(marios())
+
+local ROW = 9
+local SIZE = 0.5
+local ROWS = 28
+
+-- the rewind viewer is driven from here too: a number opens it on that Mario,
+-- page up and down scrub through his states and escape closes it
+canvas.on("keydown", function(key)
+  local digit = key:match("^key(%d)$")
+  if digit and tonumber(digit) >= 1 and tonumber(digit) <= count then
+    rewind.show(tonumber(digit))
+  elseif key == "pageup" then
+    rewind.scrub(-1)
+  elseif key == "pagedown" then
+    rewind.scrub(1)
+  elseif key == "escape" then
+    rewind.show(nil)
+  end
+end)
+
+-- a column of rows starting at x, filled in by line(i) for row i
+local function column(root, x, line)
+  for i = 1, ROWS do
+    root:add_child(shapes.Text(vec2(x, -136 + i * ROW), function()
+      return line(i) or ""
+    end, SIZE))
+  end
+end
+
+local function simulation_line(i)
+  local sim = simulation()
+  local lines = {
+    "SIMULATION",
+    string.format("rate     %.1f", sim.rate),
+    string.format("behind   %d", sim.behind),
+    string.format("missed   %d", sim.missed),
+    string.format("slowest  #%d %.2fms", sim.slowest, sim.slowest_ms),
+    string.format("crashes  %d", sim.crashes),
+    string.format("restarts %d", sim.restarts),
+    string.format("respawns %d", sim.respawns),
+    string.format("stalled  %.1fs", sim.stalled),
+    sim.paused and "PAUSED" or "",
+    "",
+    "TIMINGS",
+  }
+  if i <= #lines then
+    return lines[i]
+  end
+  local names = {}
+  for name in pairs(sim.timings_ms) do
+    table.insert(names, name)
+  end
+  table.sort(names)
+  local name = names[i - #lines]
+  return name and string.format("%-12s %.2fms", name, sim.timings_ms[name])
+end
+
+local function mario_line(i)
+  if i == 1 then
+    return "MARIOS"
+  end
+  local mario = marios()[i - 1]
+  if mario == nil then
+    return nil
+  end
+  return string.format("#%-3d %8d %d %s%s", i - 1, mario.fitness, mario.lives, mario.powerup,
+    mario.errored and " ERRORED" or "")
+end
+
+-- below the rewind viewer
+local LOG_FROM = 10
+
+local function log_line(i)
+  if i < LOG_FROM then
+    return nil
+  elseif i == LOG_FROM then
+    return "LOG"
+  end
+  local lines = logbook()
+  -- the newest at the bottom
+  local line = lines[#lines - (ROWS - i)]
+  if line == nil then
+    return nil
+  end
+  local repeats = line.repeats > 1 and string.format(" (x%d)", line.repeats) or ""
+  return string.format("%-5s %4ds ago %s%s", line.level, math.floor(line.age), line.message, repeats)
+end
+
+local function dashboard(scene, root)
+  column(root, -248, simulation_line)
+  column(root, -128, mario_line)
+  column(root, -8, log_line)
+  root:add_child(Rewind.new(vec2(-8, -136 + ROW / 2), 1))
+end
+
+return shapes.start(dashboard)
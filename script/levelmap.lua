@@ -0,0 +1,54 @@
+-- draws the map of an area, stitched together from the frames of the Mario
+-- furthest ahead, without the status bar; the area he is in if nil
+-- YIELD number (x, y, scale, area?)
+local LEVEL_MAP = canvas.instructions.level_map
+local CIRCLE = canvas.instructions.circle
+
+local marios = canvas.signal("marios")
+
+---@class LevelMap : Shape
+---
+---@field size signal<number>
+---@field area? integer the area to show, world << 8 | area as in fitness >> 16, the leader's if nil
+---@field dot  signal<number> radius of the dot every Mario in the area is plotted as
+---
+---@field width  fun(): number
+---@field height fun(): number
+local LevelMap = shapes.newshape()
+
+---@param self LevelMap
+---@param emit fun(...)
+function LevelMap:draw(emit)
+  local info = level_map_info(self.area)
+  if info == nil then
+    return
+  end
+  emit(LEVEL_MAP, 0, 0, self.size(), info.area)
+
+  -- divide by 3.75 to make it the same size as a frame
+  local pixel = self.size() / 3.75
+  for _, mario in ipairs(marios()) do
+    if mario.fitness >> 16 == info.area then
+      -- positions are by the top left of the 16 pixel sprite
+      emit(CIRCLE, (mario.x + 8) * pixel, (mario.y + 8 - info.status_bar) * pixel, self.dot())
+    end
+  end
+end
+
+---@param pos?  signalValue<vec2,   LevelMap>
+---@param size? signalValue<number, LevelMap>
+---@return LevelMap
+---@nodiscard
+function LevelMap.new(pos, size)
+  local map = shapes.Shape(pos, { size = size or 1, dot = 2 }, LevelMap)
+
+  map.width = function()
+    local info = level_map_info(map.area)
+    return info and info.width / 3.75 * map.size() or 0
+  end
+  map.height = 208 / 3.75 * map.size
+
+  return map
+end
+
+return LevelMap
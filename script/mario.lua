@@ -1,52 +1,5 @@
 ---- LIBRARY ----
-local vec2 = vector.vec2
-
--- custom IR codes start from 128
-
--- draws a frame from fastnes
--- YIELD number (x, y, scale, instance)
-local FASTNES_BG = 128
-
--- draws the sprites from a fastnes frame
--- YIELD number (x, y, scale, instance, xo, yo, opacity)
-local FASTNES_SPR = 129
-
----@class Playback : Shape
----
----@field instance signal<integer>
----@field size     signal<number>
----@field offset   signal<vec2>
----@field opacity  signal<number>
----@field ghost    signal<boolean>
----
----@field width  fun(): number
----@field height fun(): number
-local Playback = shapes.newshape()
-
----@param self Playback
----@param emit fun(...)
-function Playback:draw(emit)
-  if not self.ghost() then
-    emit(FASTNES_BG, 0, 0, self.size(), self.instance())
-  end
-  emit(FASTNES_SPR, 0, 0, self.size(), self.instance(), self.offset().x, self.offset().y, self.opacity())
-end
-
----@param pos?      signalValue<vec2,    Playback>
----@param instance? signalValue<integer, Playback>
----@param size?     signalValue<number,  Playback>
----@return Playback
----@nodiscard
-function Playback.new(pos, instance, size)
-  local playback = shapes.Shape(pos, { size = size or 1, offset = vec2(0), opacity = 1, ghost = false }, Playback)
-  playback.instance = signal.signal(instance or 1, tweens.interp.integer, playback)
-
-  -- divide by 3.75 to make it pixel perfect on full HD screens
-  playback.width = 256 / 3.75 * playback.size
-  playback.height = 240 / 3.75 * playback.size
-
-  return playback
-end
+local Playback = require("playback")
 
 ---- CODE ----
 local vec2 = vector.vec2
@@ -0,0 +1,73 @@
+local vec2 = vector.vec2
+
+-- custom IR codes are registered by name in rust
+
+-- top and bottom are the NES pixel rows cropped off the frame, the 8 rows of
+-- overscan each if nil
+
+-- draws a frame from fastnes
+-- YIELD number (x, y, scale, instance, scaling?, top?, bottom?)
+local FASTNES_BG = canvas.instructions.nes_frame
+
+-- draws the sprites from a fastnes frame
+-- YIELD number (x, y, scale, instance, xo, yo, opacity, scaling?, top?, bottom?)
+local FASTNES_SPR = canvas.instructions.nes_sprites
+
+-- draws a frame from fastnes at a quarter of the resolution, sprites included
+-- YIELD number (x, y, scale, instance, opacity?, top?, bottom?)
+local FASTNES_THUMB = canvas.instructions.nes_thumbnail
+
+-- outlines Mario and the enemies and draws Mario's velocity, as read from RAM
+-- YIELD number (x, y, scale, instance)
+local FASTNES_HITBOXES = canvas.instructions.nes_hitboxes
+
+---@class Playback : Shape
+---
+---@field instance signal<integer>
+---@field size     signal<number>
+---@field offset   signal<vec2>
+---@field opacity  signal<number>
+---@field ghost    signal<boolean>
+---@field thumbnail signal<boolean> draw a downscaled frame, for when it is shown too small to tell
+---@field hitboxes  signal<boolean> draw what the game knows of Mario and the enemies over the frame
+---@field scaling? "nearest"|"snap"|"sharp" resampling at scales that aren't whole, --scaling if nil
+---@field crop? integer[] rows cropped off the top and bottom, the overscan if nil
+---
+---@field width  fun(): number
+---@field height fun(): number
+local Playback = shapes.newshape()
+
+---@param self Playback
+---@param emit fun(...)
+function Playback:draw(emit)
+  local crop = self.crop or {}
+  if self.thumbnail() then
+    emit(FASTNES_THUMB, 0, 0, self.size(), self.instance(), self.opacity(), crop[1], crop[2])
+    return
+  end
+  if not self.ghost() then
+    emit(FASTNES_BG, 0, 0, self.size(), self.instance(), self.scaling, crop[1], crop[2])
+  end
+  emit(FASTNES_SPR, 0, 0, self.size(), self.instance(), self.offset().x, self.offset().y, self.opacity(), self.scaling, crop[1], crop[2])
+  if self.hitboxes() then
+    emit(FASTNES_HITBOXES, 0, 0, self.size(), self.instance())
+  end
+end
+
+---@param pos?      signalValue<vec2,    Playback>
+---@param instance? signalValue<integer, Playback>
+---@param size?     signalValue<number,  Playback>
+---@return Playback
+---@nodiscard
+function Playback.new(pos, instance, size)
+  local playback = shapes.Shape(pos, { size = size or 1, offset = vec2(0), opacity = 1, ghost = false, thumbnail = false, hitboxes = false }, Playback)
+  playback.instance = signal.signal(instance or 1, tweens.interp.integer, playback)
+
+  -- divide by 3.75 to make it pixel perfect on full HD screens
+  playback.width = 256 / 3.75 * playback.size
+  playback.height = 240 / 3.75 * playback.size
+
+  return playback
+end
+
+return Playback
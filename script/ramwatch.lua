@@ -0,0 +1,28 @@
+-- draws the addresses of RAM watched for a Mario with ram_watch.add, one per
+-- row with its name, starting with the baseline of the first at the origin
+-- YIELD number (x, y, size, instance, font?)
+local RAM_WATCH = canvas.instructions.ram_watch
+
+---@class RamWatch : Shape
+---
+---@field size     signal<number>
+---@field instance signal<number>
+---@field font? string the default font if nil
+local RamWatch = shapes.newshape()
+
+---@param self RamWatch
+---@param emit fun(...)
+function RamWatch:draw(emit)
+  emit(RAM_WATCH, 0, 0, self.size(), self.instance(), self.font)
+end
+
+---@param pos?      signalValue<vec2,   RamWatch>
+---@param instance? signalValue<number, RamWatch>
+---@param size?     signalValue<number, RamWatch>
+---@return RamWatch
+---@nodiscard
+function RamWatch.new(pos, instance, size)
+  return shapes.Shape(pos, { instance = instance or 1, size = size or 1 }, RamWatch)
+end
+
+return RamWatch
@@ -0,0 +1,34 @@
+-- draws the state the rewind viewer shows, opened with Ctrl+Alt and the number
+-- of a Mario or rewind.show, or nothing while it is closed
+-- YIELD number (x, y, scale)
+local NES_REWIND = canvas.instructions.nes_rewind
+
+---@class Rewind : Shape
+---
+---@field size signal<number>
+---
+---@field width  fun(): number
+---@field height fun(): number
+local Rewind = shapes.newshape()
+
+---@param self Rewind
+---@param emit fun(...)
+function Rewind:draw(emit)
+  emit(NES_REWIND, 0, 0, self.size())
+end
+
+---@param pos?  signalValue<vec2,   Rewind>
+---@param size? signalValue<number, Rewind>
+---@return Rewind
+---@nodiscard
+function Rewind.new(pos, size)
+  local rewind = shapes.Shape(pos, { size = size or 1 }, Rewind)
+
+  -- divide by 3.75 to make it the same size as a frame
+  rewind.width = 256 / 3.75 * rewind.size
+  rewind.height = 240 / 3.75 * rewind.size
+
+  return rewind
+end
+
+return Rewind
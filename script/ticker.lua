@@ -0,0 +1,29 @@
+-- draws the messages queued with ticker.push scrolling from right to left,
+-- on a strip that starts at the origin
+-- YIELD number (x, y, width, size, speed?, font?)
+local TICKER = canvas.instructions.ticker
+
+---@class Ticker : Shape
+---
+---@field width signal<number>
+---@field size  signal<number>
+---@field speed signal<number> units scrolled every second
+---@field font? string the default font if nil
+local Ticker = shapes.newshape()
+
+---@param self Ticker
+---@param emit fun(...)
+function Ticker:draw(emit)
+  emit(TICKER, 0, 0, self.width(), self.size(), self.speed(), self.font)
+end
+
+---@param pos?   signalValue<vec2,   Ticker>
+---@param width? signalValue<number, Ticker>
+---@param size?  signalValue<number, Ticker>
+---@return Ticker
+---@nodiscard
+function Ticker.new(pos, width, size)
+  return shapes.Shape(pos, { width = width or 512, size = size or 1, speed = 64 }, Ticker)
+end
+
+return Ticker
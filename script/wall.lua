@@ -0,0 +1,49 @@
+---- LIBRARY ----
+local Playback = require("playback")
+
+---- CODE ----
+local vec2 = vector.vec2
+
+local count = env.instances
+
+-- rank of every instance on the leaderboard, kept up to date by "rank" events
+local ranks = {}
+for i = 1, count do
+  ranks[i] = i
+end
+canvas.on("rank", function(instance, _, new)
+  ranks[instance] = new
+end)
+
+local function wall(scene, root)
+  local cells = glide()
+  local layout = grid_layout()
+
+  for i = 1, count do
+    local playback = Playback.new(function()
+      local cell = cells:cell(i)
+      return cell and vec2(cell.x, cell.y) or vec2(0)
+    end, i, function()
+      local cell = cells:cell(i)
+      return cell and cell.scale or 0
+    end)
+    playback.visible(function()
+      return cells:cell(i) ~= nil
+    end)
+    -- cells no wider than a thumbnail don't need the full frame
+    playback.thumbnail(layout[1].width * env.width / 512 <= 64)
+    root:add_child(playback)
+  end
+
+  -- screens glide to their new place on the leaderboard
+  while true do
+    local targets = {}
+    for i = 1, count do
+      targets[i] = layout[ranks[i]]
+    end
+    cells:move(targets)
+    scene:wait(0.25)
+  end
+end
+
+return shapes.start(wall)
@@ -0,0 +1,137 @@
+//! Which cores threads run on and how eagerly, so the simulation workers and
+//! the render loop don't take turns stuttering on a busy machine.
+//!
+//! Both are only supported on Linux, where they are set per thread.
+
+use std::{fmt, io, str::FromStr};
+
+use tracing::warn;
+
+/// A set of cores by number, written like `0-5,7`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cores(Vec<usize>);
+
+impl Cores {
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl FromStr for Cores {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid cores {:?}, expected a list like 0-5,7", s);
+        let mut cores = Vec::new();
+        for part in s.split(',') {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            let (first, last): (usize, usize) = match (first.trim().parse(), last.trim().parse()) {
+                (Ok(first), Ok(last)) if first <= last => (first, last),
+                _ => return Err(invalid()),
+            };
+            cores.extend(first..=last);
+        }
+        cores.sort_unstable();
+        cores.dedup();
+        Ok(Cores(cores))
+    }
+}
+
+impl fmt::Display for Cores {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cores: Vec<_> = self.0.iter().map(usize::to_string).collect();
+        f.write_str(&cores.join(","))
+    }
+}
+
+/// Where a kind of thread runs and at what priority.
+#[derive(Clone, Debug, Default)]
+pub struct Placement {
+    pub cores: Option<Cores>,
+    /// Niceness from -20 to 19, higher giving way to other threads.
+    pub nice: Option<i32>,
+}
+
+impl Placement {
+    pub fn is_default(&self) -> bool {
+        self.cores.is_none() && self.nice.is_none()
+    }
+
+    /// Moves the calling thread, logging what couldn't be done.
+    pub fn apply(&self, thread: &str) {
+        if let Some(cores) = &self.cores {
+            if let Err(e) = pin(cores) {
+                warn!(thread, %cores, "could not pin thread: {}", e);
+            }
+        }
+        if let Some(nice) = self.nice {
+            if let Err(e) = renice(nice) {
+                warn!(thread, nice, "could not set thread priority: {}", e);
+            }
+        }
+    }
+}
+
+/// Lets the calling thread run only on `cores`.
+#[cfg(target_os = "linux")]
+pub fn pin(cores: &Cores) -> io::Result<()> {
+    // SAFETY: cpu_set_t is plain data, zeroed is an empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for core in cores.iter() {
+        if core >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("core {} is past the last one supported", core),
+            ));
+        }
+        // SAFETY: the core is within the set
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+    // SAFETY: the set outlives the call, and 0 is the calling thread
+    let result = unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Sets the niceness of the calling thread.
+#[cfg(target_os = "linux")]
+pub fn renice(nice: i32) -> io::Result<()> {
+    // SAFETY: plain system calls, setting only the calling thread
+    let result = unsafe {
+        let tid = libc::gettid();
+        libc::setpriority(libc::PRIO_PROCESS, tid as libc::id_t, nice)
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin(_cores: &Cores) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn renice(_nice: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cores_parse() {
+        let cores: Cores = "4-6, 0,5".parse().unwrap();
+        assert_eq!(cores.iter().collect::<Vec<_>>(), [0, 4, 5, 6]);
+        assert_eq!(cores.to_string(), "0,4,5,6");
+        assert!("3-1".parse::<Cores>().is_err());
+        assert!("".parse::<Cores>().is_err());
+        assert!("a".parse::<Cores>().is_err());
+    }
+}
@@ -0,0 +1,444 @@
+//! Starting the script of a scene on the canvas of a window, with the
+//! instructions that draw the Marios and the globals that reach into them.
+
+use std::{
+    cell::RefCell,
+    path::Path,
+    rc::Rc,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use anyhow::Context;
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+use femtovg::{renderer::OpenGl, Canvas};
+use mlua::{FromLua, FromLuaMulti, Table, ToLua, Value};
+use tracing::info;
+
+use crate::{
+    error::{self, Error},
+    filmstrip::{self, Filmstrip},
+    luanim::{Animation, FontCanvas, Vec2},
+    mario::{self, Mario},
+    observation::{Observation, Position},
+    poke::Poke,
+    ramwatch,
+    rewind::Viewer,
+    savestate,
+    scaling::Scaling,
+    smb::{map, Memory},
+    widgets,
+};
+
+use super::{
+    atlas::{Atlas, Crop, Thumbnails},
+    frames::{frame_pixels, Layer},
+    images::{LevelImages, RewindImage},
+    nes_draw::{self, compare_spacing, scaling_mode, NesDraw},
+    platform::Surface,
+    script::{
+        check_instance, check_visible, script_options, NesCompareArgs, NesFrameArgs,
+        NesSpritesArgs, NesThumbnailArgs, ScriptState,
+    },
+    HEIGHT, WIDTH,
+};
+
+/// Every how many kept states one goes on a filmstrip, unless the script
+/// says otherwise.
+const FILMSTRIP_EVERY: usize = 10;
+
+pub fn animate(
+    path: &Path,
+    surface: &Surface,
+    marios: &[Arc<Mutex<Mario>>],
+    state: &ScriptState,
+    scaling: Scaling,
+) -> error::Result<Animation<FontCanvas<OpenGl>>> {
+    let opengl = surface.renderer()?;
+    let mut canvas = Canvas::new(opengl)?;
+    canvas.set_size(WIDTH as u32, HEIGHT as u32, 1.0);
+    let mut canvas = FontCanvas::new(canvas);
+    if surface.transparent {
+        canvas.set_background(femtovg::Color::rgba(0, 0, 0, 0));
+    }
+    // every new canvas needs the fonts again, but the files are only read once
+    for font in state.fonts.iter() {
+        canvas
+            .add_font_mem(&font.name, &font.data)
+            .map_err(|source| Error::Font {
+                path: font.path.clone(),
+                source,
+            })?;
+    }
+
+    let personalities = marios
+        .iter()
+        .map(|mario| mario::lock(mario).personality.clone())
+        .collect();
+
+    let background = Rc::new(RefCell::new(Atlas::new(&mut canvas, state)?));
+    let sprites = Rc::new(RefCell::new(Atlas::new(&mut canvas, state)?));
+    let thumbnails = RefCell::new(Thumbnails::new(&mut canvas, state)?);
+    let thumb_marios = marios.to_vec();
+    let (cmp_background, cmp_sprites) = (background.clone(), sprites.clone());
+    let cmp_marios = marios.to_vec();
+    let hitbox_marios = marios.to_vec();
+    let level_images = RefCell::new(LevelImages::new(state));
+    let bg_marios = marios.to_vec();
+    let spr_marios = marios.to_vec();
+    let save_marios = marios.to_vec();
+    let load_marios = marios.to_vec();
+    let filmstrip_marios = marios.to_vec();
+    let observe_marios = marios.to_vec();
+    let poke_marios = marios.to_vec();
+    let (writable, pokes) = (state.writable.clone(), state.pokes.clone());
+    let rewind_image = RefCell::new(RewindImage::new(&mut canvas, state)?);
+    let (rewind_marios, rewind_shown) = (marios.to_vec(), state.rewind.clone());
+    let (rewinding_marios, rewinding) = (marios.to_vec(), state.rewind.clone());
+    let options = script_options::<FontCanvas<OpenGl>>(personalities, state)
+        // FASTNES
+        .instruction("nes_frame", move |lua, args, screen| {
+            let (x, y, scale, instance, mode, top, bottom): NesFrameArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)?;
+
+            let draw = NesDraw {
+                origin: Vec2::new(x, y),
+                // divide by 3.75 to make it pixel perfect on full HD screens
+                pixel: 1.0 / 3.75 * scale,
+                offset: Vec2::new(0.0, 0.0),
+                crop: Crop::new(top, bottom)?,
+                alpha: 1.0,
+                scaling: scaling_mode(mode, scaling)?,
+            };
+            let mario = &bg_marios[instance - 1];
+            let mut atlas = background.borrow_mut();
+            draw.run(screen, &mut atlas, mario, instance, Layer::Background)
+        })
+        .instruction("nes_sprites", move |lua, args, screen| {
+            let (x, y, scale, instance, xo, yo, opacity, mode, top, bottom): NesSpritesArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)?;
+
+            let draw = NesDraw {
+                origin: Vec2::new(x, y),
+                // divide by 3.75 to make it pixel perfect on full HD screens
+                pixel: 1.0 / 3.75 * scale,
+                offset: Vec2::new(xo, yo),
+                crop: Crop::new(top, bottom)?,
+                alpha: opacity,
+                scaling: scaling_mode(mode, scaling)?,
+            };
+            let mario = &spr_marios[instance - 1];
+            let mut atlas = sprites.borrow_mut();
+            draw.run(screen, &mut atlas, mario, instance, Layer::Sprites)
+        })
+        .instruction("nes_thumbnail", move |lua, args, screen| {
+            let (x, y, scale, instance, opacity, top, bottom): NesThumbnailArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)?;
+            let crop = Crop::new(top, bottom)?;
+
+            let mut thumbnails = thumbnails.borrow_mut();
+            let mario = &thumb_marios[instance - 1];
+            let tile = thumbnails.tile(&mut screen.canvas, instance, mario, crop)?;
+            // divide by 3.75 to make it the same size as nes_frame
+            let pixel = 1.0 / 3.75 * scale;
+            let alpha = opacity.unwrap_or(1.0);
+            thumbnails.draw(screen, Vec2::new(x, y), pixel, tile, crop, alpha);
+            Ok(())
+        })
+        .instruction("nes_compare", move |lua, args, screen| {
+            let (x, y, scale, first, second, gap, mode, top, bottom): NesCompareArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(first)?;
+            check_instance(second)?;
+            check_visible(lua, first)?;
+            check_visible(lua, second)?;
+
+            let crop = Crop::new(top, bottom)?;
+            // divide by 3.75 to make it pixel perfect on full HD screens
+            let pixel = 1.0 / 3.75 * scale;
+            let below = compare_spacing(crop, gap) * pixel;
+            let scaling = scaling_mode(mode, scaling)?;
+            let draws = [(first, y), (second, y + below)].map(|(instance, y)| {
+                let draw = NesDraw {
+                    origin: Vec2::new(x, y),
+                    pixel,
+                    offset: Vec2::new(0.0, 0.0),
+                    crop,
+                    alpha: 1.0,
+                    scaling,
+                };
+                (instance, &*cmp_marios[instance - 1], draw)
+            });
+            let mut background = cmp_background.borrow_mut();
+            let mut sprites = cmp_sprites.borrow_mut();
+            nes_draw::compare(screen, &mut background, &mut sprites, draws)
+        })
+        .instruction("nes_hitboxes", move |lua, args, screen| {
+            let (x, y, scale, instance): (f32, f32, f32, usize) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)?;
+
+            let mario = &hitbox_marios[instance - 1];
+            let observation = Observation::read(mario::lock(mario).nes_mut());
+            // divide by 3.75 to make it the same size as nes_frame
+            widgets::hitboxes(screen, x, y, scale / 3.75, &observation);
+            Ok(())
+        })
+        .instruction("level_map", move |lua, args, screen| {
+            let (x, y, scale, area): (f32, f32, f32, Option<u16>) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            // divide by 3.75 to make it the same size as nes_frame
+            let pixel = 1.0 / 3.75 * scale;
+            level_images
+                .borrow_mut()
+                .draw(screen, Vec2::new(x, y), pixel, area)
+        })
+        .instruction("nes_rewind", move |lua, args, screen| {
+            let (x, y, scale): (f32, f32, f32) = FromLuaMulti::from_lua_multi(args, lua)?;
+            let viewer = match *rewind_shown.lock().unwrap_or_else(PoisonError::into_inner) {
+                Some(viewer) => viewer,
+                None => return Ok(()),
+            };
+            let mario = mario::lock(&rewind_marios[viewer.instance - 1]);
+            let state = &mario.states[viewer.index(&saved(&mario))];
+            // divide by 3.75 to make it the same size as nes_frame
+            let pixel = 1.0 / 3.75 * scale;
+            rewind_image
+                .borrow_mut()
+                .draw(screen, Vec2::new(x, y), pixel, state)
+        })
+        .global("save_state", move |lua| {
+            let marios = save_marios.clone();
+            let save = lua.create_function(move |_, (instance, path): (usize, String)| {
+                check_instance(instance)?;
+                savestate::save(&mut mario::lock(&marios[instance - 1]), path.as_ref())
+                    .map_err(mlua::Error::external)
+            })?;
+            Ok(Value::Function(save))
+        })
+        .global("load_state", move |lua| {
+            let marios = load_marios.clone();
+            let load = lua.create_function(move |_, (instance, path): (usize, String)| {
+                check_instance(instance)?;
+                savestate::load(&mut mario::lock(&marios[instance - 1]), path.as_ref())
+                    .map_err(mlua::Error::external)
+            })?;
+            Ok(Value::Function(load))
+        })
+        .global("filmstrip", move |lua| {
+            let marios = filmstrip_marios.clone();
+            let save = lua.create_function(
+                move |_, (instance, path, every): (usize, String, Option<usize>)| {
+                    check_instance(instance)?;
+                    let every = every.unwrap_or(FILMSTRIP_EVERY);
+                    let frames = save_filmstrip(&marios[instance - 1], every, path.as_ref())
+                        .map_err(|e| mlua::Error::RuntimeError(format!("{:#}", e)))?;
+                    info!(instance, frames, %path, "saved filmstrip");
+                    Ok(frames)
+                },
+            )?;
+            Ok(Value::Function(save))
+        })
+        .global("poke", move |lua| {
+            let marios = poke_marios.clone();
+            let (writable, pokes) = (writable.clone(), pokes.clone());
+            let poke = lua.create_function(
+                move |lua, (instance, target, value): (usize, Value, Value)| {
+                    check_instance(instance)?;
+                    let (address, target) = match target {
+                        Value::String(target) => {
+                            let target = target.to_str()?;
+                            let (address, _) =
+                                ramwatch::target(target).map_err(mlua::Error::RuntimeError)?;
+                            (address, target.to_owned())
+                        }
+                        address => {
+                            let address = u16::from_lua(address, lua)?;
+                            (address, format!("{:#06x}", address))
+                        }
+                    };
+                    // a sequence writes the bytes from the address on
+                    let bytes: Vec<u8> = match value {
+                        Value::Table(bytes) => {
+                            bytes.sequence_values().collect::<mlua::Result<_>>()?
+                        }
+                        value => vec![u8::from_lua(value, lua)?],
+                    };
+                    if bytes.is_empty() || !writable.allows(address, bytes.len()) {
+                        return Err(mlua::Error::RuntimeError(format!(
+                            "writing {} byte(s) to {} is not allowed, see --allow-write",
+                            bytes.len(),
+                            target
+                        )));
+                    }
+
+                    let mut mario = mario::lock(&marios[instance - 1]);
+                    let nes = mario.nes_mut();
+                    let mut old = Vec::with_capacity(bytes.len());
+                    for (address, &byte) in (address..).zip(bytes.iter()) {
+                        old.push(Memory::read(nes, address));
+                        nes.write(map::translate(address), byte);
+                    }
+                    drop(mario);
+                    info!(instance, %target, ?bytes, ?old, "script wrote to RAM");
+                    pokes
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(Poke {
+                            instance: instance - 1,
+                            target,
+                            address,
+                            bytes,
+                            old,
+                        });
+                    Ok(())
+                },
+            )?;
+            Ok(Value::Function(poke))
+        })
+        .global("rewind", move |lua| {
+            let table = lua.create_table()?;
+            let show = rewinding.clone();
+            table.set(
+                "show",
+                lua.create_function(move |_, instance: Option<usize>| {
+                    if let Some(instance) = instance {
+                        check_instance(instance)?;
+                    }
+                    *show.lock().unwrap_or_else(PoisonError::into_inner) =
+                        instance.map(Viewer::new);
+                    Ok(())
+                })?,
+            )?;
+            let (marios, seek) = (rewinding_marios.clone(), rewinding.clone());
+            table.set(
+                "seek",
+                lua.create_function(move |_, index: usize| {
+                    if let Some(viewer) =
+                        seek.lock().unwrap_or_else(PoisonError::into_inner).as_mut()
+                    {
+                        let saved = saved(&mario::lock(&marios[viewer.instance - 1]));
+                        viewer.seek(&saved, index.saturating_sub(1));
+                    }
+                    Ok(())
+                })?,
+            )?;
+            let (marios, scrub) = (rewinding_marios.clone(), rewinding.clone());
+            table.set(
+                "scrub",
+                lua.create_function(move |_, by: isize| {
+                    if let Some(viewer) = scrub
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .as_mut()
+                    {
+                        let saved = saved(&mario::lock(&marios[viewer.instance - 1]));
+                        viewer.scrub(&saved, by);
+                    }
+                    Ok(())
+                })?,
+            )?;
+            let (marios, get) = (rewinding_marios.clone(), rewinding.clone());
+            table.set(
+                "get",
+                lua.create_function(move |lua, ()| {
+                    let viewer = match *get.lock().unwrap_or_else(PoisonError::into_inner) {
+                        Some(viewer) => viewer,
+                        None => return Ok(Value::Nil),
+                    };
+                    let (index, count, frame) =
+                        rewound(&mario::lock(&marios[viewer.instance - 1]), &viewer);
+                    let table = lua.create_table()?;
+                    table.set("instance", viewer.instance)?;
+                    table.set("index", index)?;
+                    table.set("count", count)?;
+                    table.set("frame", frame)?;
+                    Ok(Value::Table(table))
+                })?,
+            )?;
+            table.to_lua(lua)
+        })
+        .global("observe", move |lua| {
+            let marios = observe_marios.clone();
+            let observe = lua.create_function(move |lua, instance: usize| {
+                check_instance(instance)?;
+                let observation = Observation::read(mario::lock(&marios[instance - 1]).nes_mut());
+                let position = |position: Position| -> mlua::Result<Table> {
+                    let table = lua.create_table()?;
+                    table.set("x", position.x)?;
+                    table.set("y", position.y)?;
+                    Ok(table)
+                };
+                let table = lua.create_table()?;
+                table.set("player", position(observation.player)?)?;
+                table.set("x_speed", observation.x_speed)?;
+                table.set("y_speed", observation.y_speed)?;
+                let enemies = lua.create_table()?;
+                for (i, enemy) in observation.enemies.into_iter().flatten().enumerate() {
+                    enemies.set(i + 1, position(enemy)?)?;
+                }
+                table.set("enemies", enemies)?;
+                let tiles = lua.create_table()?;
+                for (i, row) in observation.tiles.into_iter().enumerate() {
+                    tiles.set(i + 1, row.to_vec())?;
+                }
+                table.set("tiles", tiles)?;
+                Ok(Value::Table(table))
+            })?;
+            Ok(Value::Function(observe))
+        });
+
+    Animation::new(path, canvas, options).map_err(Error::from)
+}
+
+/// When every state `mario` keeps was saved, oldest first, to find the one
+/// a [`Viewer`] shows.
+pub fn saved(mario: &Mario) -> Vec<u64> {
+    mario.states.iter().map(|state| state.saved).collect()
+}
+
+/// Which state of `mario` `viewer` shows counting from 1, how many he keeps,
+/// and the frame number of the NES in it.
+pub fn rewound(mario: &Mario, viewer: &Viewer) -> (usize, usize, u64) {
+    let index = viewer.index(&saved(mario));
+    (
+        index + 1,
+        mario.states.len(),
+        mario.states[index].nes.frame_number() as u64,
+    )
+}
+
+/// Saves every `every`th state `mario` keeps side by side as a PNG, counted
+/// back from the newest, returning how many went on it.
+fn save_filmstrip(mario: &Mutex<Mario>, every: usize, path: &Path) -> anyhow::Result<usize> {
+    // draw copies, so the Mario isn't held up while they are drawn
+    let states: Vec<NES<NROM, FastPPU>> = {
+        let mario = mario::lock(mario);
+        filmstrip::picked(mario.states.len(), every)
+            .into_iter()
+            .map(|index| mario.states[index].nes.clone())
+            .collect()
+    };
+    let mut strip = Filmstrip::new(states.len());
+    for (index, nes) in states.iter().enumerate() {
+        let pixels: Vec<[u8; 4]> = frame_pixels(nes)
+            .into_iter()
+            .map(|color| [color.r, color.g, color.b, color.a])
+            .collect();
+        strip.paste(index, &pixels, nes.frame_number() as u64);
+    }
+
+    let mut pixmap = tiny_skia::Pixmap::new(strip.width as u32, strip.height as u32)
+        .context("could not allocate filmstrip")?;
+    for (pixel, [r, g, b, a]) in pixmap.pixels_mut().iter_mut().zip(strip.pixels) {
+        *pixel = tiny_skia::ColorU8::from_rgba(r, g, b, a).premultiply();
+    }
+    pixmap.save_png(path)?;
+    Ok(states.len())
+}
@@ -0,0 +1,355 @@
+//! NES frames uploaded as images for scripts to draw: a texture holding the
+//! last frame of every instance, and one holding a thumbnail of it.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use femtovg::{
+    imgref::Img, renderer::OpenGl, rgb::RGBA8, Canvas, ImageFlags, ImageId, Paint, Path,
+    PixelFormat,
+};
+
+use crate::{
+    error,
+    luanim::{FontCanvas, Screen, Vec2},
+    mario::Mario,
+};
+
+use super::{
+    frames::{Frames, Layer},
+    script::{ScriptState, MAX_INSTANCES},
+};
+
+/// Instances per row of an [`Atlas`].
+const ATLAS_COLUMNS: usize = 16;
+const ATLAS_ROWS: usize = (MAX_INSTANCES + ATLAS_COLUMNS - 1) / ATLAS_COLUMNS;
+/// Times smaller both sides of a [`Thumbnails`] frame are.
+pub const THUMBNAIL_FACTOR: usize = 4;
+const THUMBNAIL_WIDTH: usize = 256 / THUMBNAIL_FACTOR;
+const THUMBNAIL_HEIGHT: usize = 240 / THUMBNAIL_FACTOR;
+
+/// Rows at the top and bottom of a frame that TVs hid, where games leave
+/// garbage and flickering sprites.
+const OVERSCAN: usize = 8;
+
+/// NES pixel rows cut off the top and bottom of a frame. Only the rows left
+/// are uploaded, and they are drawn where they would be in the whole frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    top: usize,
+    bottom: usize,
+}
+
+impl Crop {
+    /// The rows a script asked to cut off, the overscan for those it didn't
+    /// give.
+    pub fn new(top: Option<usize>, bottom: Option<usize>) -> mlua::Result<Crop> {
+        let crop = Crop {
+            top: top.unwrap_or(OVERSCAN),
+            bottom: bottom.unwrap_or(OVERSCAN),
+        };
+        if crop.top + crop.bottom >= 240 {
+            return Err(mlua::Error::RuntimeError(format!(
+                "cannot crop {} rows off a frame of 240",
+                crop.top + crop.bottom
+            )));
+        }
+        Ok(crop)
+    }
+
+    /// The rows left of a frame scaled down `factor` times, including any
+    /// that are only partly cut off.
+    pub fn rows(self, factor: usize) -> Range<usize> {
+        self.top / factor..(240 - self.bottom).div_ceil(factor)
+    }
+
+    /// The frame number `uploaded` is of, if it was cropped like this.
+    fn frame(self, uploaded: Option<(u64, Crop)>) -> Option<u64> {
+        uploaded
+            .filter(|&(_, crop)| crop == self)
+            .map(|(frame, _)| frame)
+    }
+}
+
+/// A single texture holding the last drawn frame of every instance, so that
+/// drawing them doesn't need an image and a flush per instance per frame.
+pub struct Atlas {
+    image: ImageId,
+    /// Frame number of the NES each tile was last uploaded from, and how it
+    /// was cropped.
+    uploaded: Vec<Option<(u64, Crop)>>,
+    /// Frames scaled up for [`Scaling::Sharp`](crate::scaling::Scaling::Sharp), by
+    /// instance.
+    prescaled: HashMap<usize, Prescaled>,
+    /// Position in the game of the frame of every instance uploaded last,
+    /// see [`scroll`](crate::smb::scroll).
+    scrolls: Vec<u32>,
+    /// Time spent drawing and uploading frames, see [`ScriptState::uploads`].
+    uploads: Arc<Mutex<Duration>>,
+    frames: Arc<Frames>,
+}
+
+/// A frame scaled up by a whole `factor`, to be smoothed the rest of the way.
+struct Prescaled {
+    image: ImageId,
+    factor: usize,
+    frame: Option<(u64, Crop)>,
+}
+
+impl Atlas {
+    pub fn new(canvas: &mut Canvas<OpenGl>, state: &ScriptState) -> error::Result<Atlas> {
+        let image = canvas.create_image_empty(
+            256 * ATLAS_COLUMNS,
+            240 * ATLAS_ROWS,
+            PixelFormat::Rgba8,
+            ImageFlags::NEAREST,
+        )?;
+        Ok(Atlas {
+            image,
+            uploaded: vec![None; MAX_INSTANCES],
+            prescaled: HashMap::new(),
+            scrolls: vec![0; MAX_INSTANCES],
+            uploads: state.uploads.clone(),
+            frames: state.frames.clone(),
+        })
+    }
+
+    /// Uploads the rows `crop` leaves of the current frame of `mario` to the
+    /// tile of `instance` unless they are already there, returning where the
+    /// tile is in the atlas.
+    pub fn tile(
+        &mut self,
+        canvas: &mut Canvas<OpenGl>,
+        instance: usize,
+        mario: &Mutex<Mario>,
+        layer: Layer,
+        crop: Crop,
+    ) -> mlua::Result<(f32, f32)> {
+        let index = instance - 1;
+        let (x, y) = (index % ATLAS_COLUMNS * 256, index / ATLAS_COLUMNS * 240);
+
+        let started = Instant::now();
+        let uploaded = crop.frame(self.uploaded[index]);
+        if let Some(pixels) = self.frames.latest(index, layer, mario, uploaded) {
+            let rows = crop.rows(1);
+            let img = Img::new(&pixels[rows.start * 256..rows.end * 256], 256, rows.len());
+            canvas
+                .update_image(self.image, img, x, y + rows.start)
+                .map_err(mlua::Error::external)?;
+            self.uploaded[index] = Some((pixels.frame().number, crop));
+            self.scrolls[index] = pixels.frame().scroll;
+            *self.uploads.lock().unwrap() += started.elapsed();
+        }
+        Ok((x as f32, y as f32))
+    }
+
+    /// Uploads the rows `crop` leaves of the current frame of `mario` scaled
+    /// up `factor` times to an image of its own, unless they are already
+    /// there.
+    pub fn prescaled(
+        &mut self,
+        canvas: &mut Canvas<OpenGl>,
+        instance: usize,
+        mario: &Mutex<Mario>,
+        layer: Layer,
+        crop: Crop,
+        factor: usize,
+    ) -> mlua::Result<ImageId> {
+        if let Some(old) = self
+            .prescaled
+            .get(&instance)
+            .filter(|old| old.factor != factor)
+        {
+            // need to flush the canvas before being able to delete the image
+            canvas.flush();
+            canvas.delete_image(old.image);
+            self.prescaled.remove(&instance);
+        }
+        let prescaled = match self.prescaled.entry(instance) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let image = canvas
+                    .create_image_empty(
+                        256 * factor,
+                        240 * factor,
+                        PixelFormat::Rgba8,
+                        ImageFlags::empty(),
+                    )
+                    .map_err(mlua::Error::external)?;
+                entry.insert(Prescaled {
+                    image,
+                    factor,
+                    frame: None,
+                })
+            }
+        };
+
+        let started = Instant::now();
+        let uploaded = crop.frame(prescaled.frame);
+        let latest = self.frames.latest(instance - 1, layer, mario, uploaded);
+        if let Some(pixels) = latest {
+            let rows = crop.rows(1);
+            let upscaled = upscale(&pixels[rows.start * 256..rows.end * 256], factor);
+            let img = Img::new(&upscaled[..], 256 * factor, rows.len() * factor);
+            canvas
+                .update_image(prescaled.image, img, 0, rows.start * factor)
+                .map_err(mlua::Error::external)?;
+            prescaled.frame = Some((pixels.frame().number, crop));
+            self.scrolls[instance - 1] = pixels.frame().scroll;
+            *self.uploads.lock().unwrap() += started.elapsed();
+        }
+        Ok(prescaled.image)
+    }
+
+    /// Position in the game of the frame of `instance` uploaded last.
+    pub fn scroll(&self, instance: usize) -> u32 {
+        self.scrolls[instance - 1]
+    }
+
+    /// A paint that draws `tile` with its top left corner at `x`, `y`, at
+    /// `pixel` units per NES pixel.
+    pub fn paint(&self, x: f32, y: f32, pixel: f32, tile: (f32, f32), alpha: f32) -> Paint {
+        Paint::image(
+            self.image,
+            x - tile.0 * pixel,
+            y - tile.1 * pixel,
+            (256 * ATLAS_COLUMNS) as f32 * pixel,
+            (240 * ATLAS_ROWS) as f32 * pixel,
+            0.0,
+            alpha,
+        )
+    }
+}
+
+/// A single texture holding a thumbnail of the last frame of every instance,
+/// for scenes that show so many of them at once that uploading full frames
+/// isn't worth it.
+pub struct Thumbnails {
+    image: ImageId,
+    /// Frame number of the NES each thumbnail was last made from, and how it
+    /// was cropped.
+    uploaded: Vec<Option<(u64, Crop)>>,
+    /// Time spent drawing and uploading frames, see [`ScriptState::uploads`].
+    uploads: Arc<Mutex<Duration>>,
+    frames: Arc<Frames>,
+}
+
+impl Thumbnails {
+    pub fn new(canvas: &mut Canvas<OpenGl>, state: &ScriptState) -> error::Result<Thumbnails> {
+        let image = canvas.create_image_empty(
+            THUMBNAIL_WIDTH * ATLAS_COLUMNS,
+            THUMBNAIL_HEIGHT * ATLAS_ROWS,
+            PixelFormat::Rgba8,
+            ImageFlags::empty(),
+        )?;
+        Ok(Thumbnails {
+            image,
+            uploaded: vec![None; MAX_INSTANCES],
+            uploads: state.uploads.clone(),
+            frames: state.frames.clone(),
+        })
+    }
+
+    /// Uploads the rows `crop` leaves of a thumbnail of the current frame of
+    /// `mario`, sprites included, unless they are already there, returning
+    /// where it is in the texture.
+    pub fn tile(
+        &mut self,
+        canvas: &mut Canvas<OpenGl>,
+        instance: usize,
+        mario: &Mutex<Mario>,
+        crop: Crop,
+    ) -> mlua::Result<(f32, f32)> {
+        let index = instance - 1;
+        let (x, y) = (
+            index % ATLAS_COLUMNS * THUMBNAIL_WIDTH,
+            index / ATLAS_COLUMNS * THUMBNAIL_HEIGHT,
+        );
+
+        let started = Instant::now();
+        let uploaded = crop.frame(self.uploaded[index]);
+        if let Some(pixels) = self.frames.latest(index, Layer::Thumbnail, mario, uploaded) {
+            let rows = crop.rows(THUMBNAIL_FACTOR);
+            let cropped = &pixels[rows.start * THUMBNAIL_WIDTH..rows.end * THUMBNAIL_WIDTH];
+            let img = Img::new(cropped, THUMBNAIL_WIDTH, rows.len());
+            canvas
+                .update_image(self.image, img, x, y + rows.start)
+                .map_err(mlua::Error::external)?;
+            self.uploaded[index] = Some((pixels.frame().number, crop));
+            *self.uploads.lock().unwrap() += started.elapsed();
+        }
+        Ok((x as f32, y as f32))
+    }
+
+    /// Draws the thumbnail at `tile` as a whole frame with its top left corner
+    /// at `origin`, at `pixel` units per NES pixel, leaving out the rows
+    /// `crop` cuts off.
+    pub fn draw(
+        &self,
+        screen: &mut Screen<FontCanvas<OpenGl>>,
+        origin: Vec2,
+        pixel: f32,
+        tile: (f32, f32),
+        crop: Crop,
+        alpha: f32,
+    ) {
+        let texel = pixel * THUMBNAIL_FACTOR as f32;
+        let paint = Paint::image(
+            self.image,
+            origin.x - tile.0 * texel,
+            origin.y - tile.1 * texel,
+            (THUMBNAIL_WIDTH * ATLAS_COLUMNS) as f32 * texel,
+            (THUMBNAIL_HEIGHT * ATLAS_ROWS) as f32 * texel,
+            0.0,
+            alpha,
+        );
+        let mut path = Path::new();
+        let rows = crop.rows(1);
+        path.rect(
+            origin.x,
+            origin.y + rows.start as f32 * pixel,
+            256.0 * pixel,
+            rows.len() as f32 * pixel,
+        );
+
+        let transform = screen.transform();
+        screen.canvas.set_transform(&transform.into());
+        screen.canvas.fill_path(&mut path, &paint);
+        screen.canvas.reset_transform();
+    }
+}
+
+/// Scales a 256x240 frame down `factor` times, averaging every block of
+/// pixels. `factor` has to divide both sides.
+pub fn downscale(frame: &[RGBA8], factor: usize) -> Vec<RGBA8> {
+    let (width, height) = (256 / factor, 240 / factor);
+    let area = (factor * factor) as u32;
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width * factor, i / width * factor);
+            let mut sum = [0u32; 4];
+            for row in frame[y * 256..].chunks(256).take(factor) {
+                for pixel in &row[x..x + factor] {
+                    sum[0] += u32::from(pixel.r);
+                    sum[1] += u32::from(pixel.g);
+                    sum[2] += u32::from(pixel.b);
+                    sum[3] += u32::from(pixel.a);
+                }
+            }
+            let [r, g, b, a] = sum.map(|channel| (channel / area) as u8);
+            RGBA8::new(r, g, b, a)
+        })
+        .collect()
+}
+
+/// Scales rows of a frame up `factor` times with nearest neighbour.
+fn upscale(frame: &[RGBA8], factor: usize) -> Vec<RGBA8> {
+    let width = 256 * factor;
+    (0..frame.len() * factor * factor)
+        .map(|i| frame[i / width / factor * 256 + i % width / factor])
+        .collect()
+}
@@ -0,0 +1,351 @@
+//! The commands that don't open a window: running scripts without Marios,
+//! checking input logs and states, and measuring how well Marios play.
+
+use std::{
+    fs::{create_dir_all, File},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use mlua::FromLuaMulti;
+use tracing::info;
+
+use crate::{
+    input_log::{timeline, Entry, Reader},
+    luanim::{Animation, Backend, Headless, Options, Raster, Screen, Vec2},
+    mario::{self, Cost, Mario, Personality, Settings},
+    population,
+    practice::{self, Segment},
+    ramdiff, savestate,
+    smb::{scroll, Objective, Ram},
+};
+
+use super::{
+    atlas::Crop,
+    nes_draw::{compare_spacing, raster_frame},
+    script::{
+        check_instance, check_visible, instances, script_options, NesCompareArgs, NesFrameArgs,
+        NesSpritesArgs, NesThumbnailArgs, ScriptState,
+    },
+    HEIGHT, WIDTH,
+};
+
+/// Draws what stands in for an NES frame at an origin, with a pixel size and
+/// crop, where there is no emulator to draw one.
+type Placeholder<B> = fn(&mut Screen<B>, Vec2, f32, Crop);
+
+/// Runs the script at `path` for `frames` frames without drawing anything,
+/// to check that it runs without errors.
+pub fn test_script(path: &Path, frames: u32, state: &ScriptState) -> anyhow::Result<()> {
+    let options = offline_options::<Headless>(state, |_, _, _, _| {});
+    let canvas = Headless::new(WIDTH as f32, HEIGHT as f32);
+    run_offline(path, frames, canvas, options, |_, _| Ok(()))?;
+    info!(frames, "{} ran without errors", path.display());
+    Ok(())
+}
+
+/// Renders the first `frames` frames of the script at `path` to numbered
+/// PNGs in `out`. There is no emulator, so frames are drawn as boxes and
+/// sprites not at all.
+pub fn render(path: &Path, frames: u32, out: &Path, state: &ScriptState) -> anyhow::Result<()> {
+    let options = offline_options::<Raster>(state, raster_frame);
+    let canvas = Raster::new(WIDTH as u32, HEIGHT as u32).expect("window size is not zero");
+    create_dir_all(out).with_context(|| format!("could not create {}", out.display()))?;
+    run_offline(path, frames, canvas, options, |animation, frame| {
+        let file = out.join(format!("{:05}.png", frame + 1));
+        animation
+            .canvas_mut()
+            .save_png(&file)
+            .with_context(|| format!("could not write {}", file.display()))
+    })?;
+    info!(frames, "rendered {} to {}", path.display(), out.display());
+    Ok(())
+}
+
+/// Options for a script run without Marios, with random personalities for
+/// them. The emulator instructions check their arguments like they would,
+/// and `placeholder` draws what stands in for the frames they draw.
+fn offline_options<B: Backend + 'static>(
+    state: &ScriptState,
+    placeholder: Placeholder<B>,
+) -> Options<B> {
+    let mut rng = rand::thread_rng();
+    let personalities = (0..instances())
+        .map(|_| Personality::random(&mut rng))
+        .collect();
+
+    script_options::<B>(personalities, state)
+        .instruction("nes_frame", move |lua, args, screen| {
+            let (x, y, scale, instance, _, top, bottom): NesFrameArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)?;
+            // divide by 3.75 to make it pixel perfect on full HD screens
+            let crop = Crop::new(top, bottom)?;
+            placeholder(screen, Vec2::new(x, y), scale / 3.75, crop);
+            Ok(())
+        })
+        .instruction("nes_sprites", |lua, args, _screen| {
+            let (_, _, _, instance, _, _, _, _, top, bottom): NesSpritesArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            Crop::new(top, bottom)?;
+            check_visible(lua, instance)
+        })
+        .instruction("nes_thumbnail", move |lua, args, screen| {
+            let (x, y, scale, instance, _, top, bottom): NesThumbnailArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)?;
+            let crop = Crop::new(top, bottom)?;
+            placeholder(screen, Vec2::new(x, y), scale / 3.75, crop);
+            Ok(())
+        })
+        .instruction("nes_compare", move |lua, args, screen| {
+            let (x, y, scale, first, second, gap, _, top, bottom): NesCompareArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(first)?;
+            check_instance(second)?;
+            check_visible(lua, first)?;
+            check_visible(lua, second)?;
+            let crop = Crop::new(top, bottom)?;
+            let pixel = scale / 3.75;
+            let below = compare_spacing(crop, gap) * pixel;
+            placeholder(screen, Vec2::new(x, y), pixel, crop);
+            placeholder(screen, Vec2::new(x, y + below), pixel, crop);
+            Ok(())
+        })
+        .instruction("nes_hitboxes", |lua, args, _screen| {
+            let (_, _, _, instance): (f32, f32, f32, usize) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            check_visible(lua, instance)
+        })
+        .instruction("level_map", |lua, args, _screen| {
+            let _: (f32, f32, f32, Option<u16>) = FromLuaMulti::from_lua_multi(args, lua)?;
+            Ok(())
+        })
+        .instruction("nes_rewind", move |lua, args, screen| {
+            let (x, y, scale): (f32, f32, f32) = FromLuaMulti::from_lua_multi(args, lua)?;
+            let crop = Crop::new(Some(0), Some(0))?;
+            placeholder(screen, Vec2::new(x, y), scale / 3.75, crop);
+            Ok(())
+        })
+}
+
+/// Runs the script at `path` on `canvas` for `frames` frames at 60 a second,
+/// handing the animation to `after` after every frame.
+fn run_offline<B: Backend>(
+    path: &Path,
+    frames: u32,
+    canvas: B,
+    options: Options<B>,
+    mut after: impl FnMut(&mut Animation<B>, u32) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut animation = Animation::new(path, canvas, options)
+        .with_context(|| format!("could not load {}", path.display()))?;
+    for frame in 0..frames {
+        animation
+            .values(|_lua, table| table.set("frame", frame + 1))
+            .and_then(|_| animation.advance_time(frame as f32 / 60.0))
+            .with_context(|| format!("{} failed at frame {}", path.display(), frame))?;
+        after(&mut animation, frame)?;
+    }
+    Ok(())
+}
+
+/// Replays the input log at `path` up to the last check in it, and fails if
+/// the game doesn't end up in the same state.
+pub fn verify(rom: &[u8], path: &Path) -> anyhow::Result<()> {
+    let mut entries = Vec::new();
+    let mut last_check = None;
+    let reader =
+        Reader::open(path).with_context(|| format!("could not open {}", path.display()))?;
+    for entry in reader {
+        let entry = entry.with_context(|| format!("could not read {}", path.display()))?;
+        if let Entry::Check {
+            frame,
+            position,
+            ram,
+        } = entry
+        {
+            last_check = Some((frame, position, ram, entries.len()));
+        }
+        entries.push(entry);
+    }
+    let (frame, position, ram, end) = match last_check {
+        Some(check) => check,
+        None => anyhow::bail!("{} has nothing to check against", path.display()),
+    };
+
+    let inputs = timeline(entries.drain(..end));
+    if inputs.len() as u64 != frame {
+        anyhow::bail!(
+            "{} is inconsistent: {} inputs lead up to the check at frame {}",
+            path.display(),
+            inputs.len(),
+            frame
+        );
+    }
+    let mut nes = mario::replay(rom.to_vec(), &inputs);
+    let (replayed_position, replayed_ram) = (scroll(&mut nes), Ram::of(&mut nes).checksum());
+
+    if replayed_ram != ram || replayed_position != position {
+        anyhow::bail!(
+            "replay out of sync at frame {}: position {:#x}, expected {:#x}, RAM {:016x}, \
+             expected {:016x}",
+            frame,
+            replayed_position,
+            position,
+            replayed_ram,
+            ram
+        );
+    }
+    println!("{} replays in sync for {} frames", path.display(), frame);
+    Ok(())
+}
+
+pub fn diff_states(
+    rom: &[u8],
+    before: &Path,
+    after: &Path,
+    out: Option<&Path>,
+) -> anyhow::Result<()> {
+    let ram = |path: &Path| -> anyhow::Result<Ram> {
+        let (mut nes, _) = savestate::open(rom, path)
+            .with_context(|| format!("could not load state {}", path.display()))?;
+        Ok(Ram::of(&mut nes))
+    };
+    let changes = ramdiff::diff(&ram(before)?, &ram(after)?);
+    match out {
+        Some(path) => {
+            let mut file = File::create(path)
+                .with_context(|| format!("could not create {}", path.display()))?;
+            ramdiff::write_csv(&changes, &mut file)
+                .with_context(|| format!("could not write {}", path.display()))?;
+        }
+        None => {
+            for change in changes.iter() {
+                println!("{}", change);
+            }
+        }
+    }
+    println!("{} bytes differ", changes.len());
+    Ok(())
+}
+
+pub fn practice(
+    marios: &[Arc<Mutex<Mario>>],
+    threads: usize,
+    settings: Settings,
+    segment: &Segment,
+    attempts: u32,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let tallies = practice::run(marios, threads, settings, segment, attempts);
+
+    let mut ranked: Vec<_> = marios
+        .iter()
+        .map(|mario| mario::lock(mario).personality.clone())
+        .zip(tallies.iter())
+        .enumerate()
+        .collect();
+    ranked.sort_by(|(_, (_, a)), (_, (_, b))| b.rate().total_cmp(&a.rate()));
+
+    let cleared: u32 = tallies.iter().map(|tally| tally.cleared).sum();
+    let total: u32 = tallies.iter().map(|tally| tally.attempts).sum();
+    println!(
+        "{} of {} attempts got to {} in {:.2?}",
+        cleared,
+        total,
+        segment.target,
+        start.elapsed()
+    );
+    println!("instance patient bold twitchy jumpy  cleared  died timeout  frames");
+    for (i, (personality, tally)) in ranked {
+        let frames = match tally.mean_frames() {
+            Some(frames) => format!("{:.0}", frames),
+            None => "-".to_owned(),
+        };
+        println!(
+            "{:>8} {:>7} {:>4} {:>7.3} {:>5.3} {:>7.0}% {:>5} {:>7} {:>7}",
+            i + 1,
+            personality.patient,
+            personality.bold,
+            personality.twitchy,
+            personality.jumpy,
+            tally.rate() * 100.0,
+            tally.died,
+            tally.timed_out,
+            frames
+        );
+    }
+    Ok(())
+}
+
+pub fn bench(
+    rom: &[u8],
+    instances: usize,
+    frames: u32,
+    threads: usize,
+    settings: Settings,
+    objective: Objective,
+) -> anyhow::Result<()> {
+    let marios = population::spawn(rom, instances, objective);
+    let time = population::bench(&marios, threads, settings, frames);
+
+    let mut cost = Cost::default();
+    for mario in marios.iter() {
+        cost += mario::lock(mario).cost;
+    }
+    let calls = cost.frames.max(1) as u32;
+    let share = |part: Duration| part.as_secs_f64() / cost.total.as_secs_f64().max(f64::EPSILON);
+    let other = cost
+        .total
+        .saturating_sub(cost.rollouts + cost.step + cost.clone);
+
+    println!("{} instances, {} frames in {:.2?}", instances, frames, time);
+    println!(
+        "  throughput   {:>10.1} frames/s ({:.1} per instance)",
+        cost.frames as f64 / time.as_secs_f64(),
+        frames as f64 / time.as_secs_f64()
+    );
+    println!("  next_frame   {:>10.2?} per call", cost.total / calls);
+    for (name, part) in [
+        ("rollouts", cost.rollouts),
+        ("step", cost.step),
+        ("clone", cost.clone),
+        ("other", other),
+    ] {
+        println!(
+            "    {:<10} {:>10.2?} {:>5.1}%",
+            name,
+            part / calls,
+            share(part) * 100.0
+        );
+    }
+    match resident_memory() {
+        Some(bytes) => println!(
+            "  memory       {:>10.1} MiB resident",
+            bytes as f64 / 1048576.0
+        ),
+        None => println!("  memory       unknown on this platform"),
+    }
+    println!(
+        "  rollouts     {:>10} played, {} cached ({:.1}% hit rate)",
+        cost.cache_misses,
+        cost.cache_hits,
+        population::cache_hit_rate(cost.cache_hits, cost.cache_misses) * 100.0
+    );
+    Ok(())
+}
+
+/// Resident memory of this process in bytes, where the platform says.
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
@@ -0,0 +1,155 @@
+//! The second window opened with --dashboard, drawing a script only the
+//! operator sees. Its scene is kept with the others in [`Scenes`], but is drawn
+//! to a context of its own, so whatever holds its images has to be made and
+//! dropped while that context is current.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use femtovg::renderer::OpenGl;
+use tracing::{error, info, warn};
+use winit::{
+    event::{ElementState, KeyboardInput, WindowEvent},
+    event_loop::EventLoop,
+    window::WindowId,
+};
+
+use crate::{
+    error::{self, Error},
+    luanim::{FontCanvas, Input},
+    mario::Mario,
+    scaling::Scaling,
+    scene::{Scene, Scenes},
+};
+
+use super::{
+    animate::animate,
+    input::{key_name, pointer_input},
+    platform::Surface,
+    script::ScriptState,
+    HEIGHT, WIDTH,
+};
+
+/// Name of the dashboard's scene, which no other scene can have.
+pub const DASHBOARD: &str = "dashboard";
+
+/// The dashboard window and the script drawn to it.
+pub struct Dashboard {
+    window: Surface,
+    path: PathBuf,
+}
+
+impl Dashboard {
+    /// Opens the window and starts the script at `path` in it as the
+    /// dashboard of `scenes`, leaving the main `surface` current.
+    pub fn open(
+        el: &EventLoop<()>,
+        surface: &Surface,
+        path: &Path,
+        marios: &[Arc<Mutex<Mario>>],
+        state: &ScriptState,
+        scaling: Scaling,
+        scenes: &mut Scenes<FontCanvas<OpenGl>>,
+    ) -> anyhow::Result<Dashboard> {
+        let window = Surface::new(
+            el,
+            "shellkick dashboard",
+            WIDTH as u32,
+            HEIGHT as u32,
+            false,
+        )?;
+        // only the main window waits for the display to refresh
+        if let Err(e) = window.set_vsync(false) {
+            warn!("could not turn off vsync of the dashboard: {}", e);
+        }
+        let animation = animate(path, &window, marios, state, scaling)
+            .with_context(|| format!("could not start dashboard ({})", path.display()))?;
+        scenes.set_dashboard(Scene::new(DASHBOARD, animation));
+        surface.make_current()?;
+        info!(path = %path.display(), "opened dashboard");
+        Ok(Dashboard {
+            window,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Whether `id` is of the dashboard window.
+    pub fn owns(&self, id: WindowId) -> bool {
+        self.window.window.id() == id
+    }
+
+    /// Starts the script over, for when it changed.
+    pub fn reload(
+        &self,
+        surface: &Surface,
+        marios: &[Arc<Mutex<Mario>>],
+        state: &ScriptState,
+        scaling: Scaling,
+        scenes: &mut Scenes<FontCanvas<OpenGl>>,
+    ) -> error::Result<()> {
+        let reloaded = self.on_window(surface, || {
+            let animation = animate(&self.path, &self.window, marios, state, scaling)?;
+            scenes.set_dashboard(Scene::new(DASHBOARD, animation));
+            Ok::<_, Error>(())
+        });
+        reloaded.and_then(|reloaded| reloaded)
+    }
+
+    /// Draws the dashboard of `scenes` and shows it.
+    pub fn draw(&self, surface: &Surface, scenes: &mut Scenes<FontCanvas<OpenGl>>) {
+        let drawn = self.on_window(surface, || {
+            scenes.render_dashboard()?;
+            self.window.present()
+        });
+        if let Err(e) = drawn.and_then(|drawn| drawn) {
+            error!("could not draw the dashboard: {}", e);
+        }
+    }
+
+    /// Closes the window, and with it the dashboard of `scenes`.
+    pub fn close(self, surface: &Surface, scenes: &mut Scenes<FontCanvas<OpenGl>>) {
+        // its images have to go while its context is current
+        if let Err(e) = self.on_window(surface, || scenes.close_dashboard()) {
+            error!("could not close the dashboard: {}", e);
+        }
+        info!("dashboard closed");
+    }
+
+    /// Runs `f` with what is drawn going to the dashboard window, then to the
+    /// main `surface` again.
+    fn on_window<T>(&self, surface: &Surface, f: impl FnOnce() -> T) -> error::Result<T> {
+        self.window.make_current()?;
+        let result = f();
+        surface.make_current()?;
+        Ok(result)
+    }
+}
+
+/// Passes `event` of the dashboard window on to the dashboard of `scenes`, if
+/// it comes from the keyboard or the mouse.
+pub fn send_input(scenes: &mut Scenes<FontCanvas<OpenGl>>, event: &WindowEvent) {
+    let input = match event {
+        WindowEvent::KeyboardInput {
+            input:
+                KeyboardInput {
+                    state,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+            ..
+        } => Input::Key {
+            key: key_name(*key),
+            pressed: *state == ElementState::Pressed,
+        },
+        event => match pointer_input(event) {
+            Some(input) => input,
+            None => return,
+        },
+    };
+    if let Err(e) = scenes.dashboard_input(&input) {
+        error!(scene = DASHBOARD, "lua error handling input: {}", e);
+    }
+}
@@ -0,0 +1,305 @@
+//! NES frames drawn into pixels on a thread of their own after every tick, so
+//! the render loop only has to upload them.
+//!
+//! The render loop asks for the layers it draws of every instance, and the
+//! worker draws those for the next ticks. Until it has, the render loop draws
+//! them itself. The worker also stitches the [level maps](LevelMaps).
+
+use std::{
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::Receiver,
+        Arc, Mutex, PoisonError,
+    },
+};
+
+use fastnes::{
+    cart::NROM,
+    nes::NES,
+    ppu::{Color, DrawOptions, FastPPU},
+};
+use femtovg::rgb::RGBA8;
+
+use crate::{
+    levelmap::LevelMaps,
+    mario::{self, Mario},
+    smb::{in_level, scroll},
+};
+
+use super::atlas::{downscale, THUMBNAIL_FACTOR};
+
+/// What of a frame is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layer {
+    Background,
+    Sprites,
+    /// The sprites over the background, scaled down by [`THUMBNAIL_FACTOR`].
+    Thumbnail,
+}
+
+impl Layer {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// The layer drawn by the NES for this layer, if it is drawn directly.
+    fn options(self) -> Option<DrawOptions> {
+        match self {
+            Layer::Background => Some(DrawOptions::Background),
+            Layer::Sprites => Some(DrawOptions::Sprites),
+            Layer::Thumbnail => None,
+        }
+    }
+}
+
+/// A frame drawn into pixels, with the layers that were asked for.
+pub struct Frame {
+    /// Frame number of the NES it was drawn from.
+    pub number: u64,
+    /// Position in the game of the left edge of the screen, see [`scroll`].
+    pub scroll: u32,
+    layers: [Option<Vec<RGBA8>>; 3],
+}
+
+impl Frame {
+    /// Draws the `layers` asked for of the frame `nes` shows. Only the drawing
+    /// needs the NES, the rest is done by [`Frame::new`].
+    fn draw(nes: &NES<NROM, FastPPU>, layers: u8) -> (Option<Vec<RGBA8>>, Option<Vec<RGBA8>>) {
+        let thumbnail = layers & Layer::Thumbnail.bit() != 0;
+        let draw = |layer: Layer| {
+            let wanted = layers & layer.bit() != 0 || thumbnail;
+            let options = layer.options().filter(|_| wanted)?;
+            Some(unsafe { as_rgba(&nes.draw_frame(options)) }.to_vec())
+        };
+        (draw(Layer::Background), draw(Layer::Sprites))
+    }
+
+    /// The frame with number `number` made of what [`Frame::draw`] drew.
+    fn new(
+        number: u64,
+        scroll: u32,
+        layers: u8,
+        (background, sprites): (Option<Vec<RGBA8>>, Option<Vec<RGBA8>>),
+    ) -> Frame {
+        let thumbnail = match (&background, &sprites) {
+            (Some(background), Some(sprites)) if layers & Layer::Thumbnail.bit() != 0 => {
+                Some(downscale(&composite(background, sprites), THUMBNAIL_FACTOR))
+            }
+            _ => None,
+        };
+        let keep =
+            |layer: Layer, pixels: Option<Vec<RGBA8>>| pixels.filter(|_| layers & layer.bit() != 0);
+        Frame {
+            number,
+            scroll,
+            layers: [
+                keep(Layer::Background, background),
+                keep(Layer::Sprites, sprites),
+                thumbnail,
+            ],
+        }
+    }
+
+    pub fn layer(&self, layer: Layer) -> Option<&[RGBA8]> {
+        self.layers[layer as usize].as_deref()
+    }
+
+    fn has(&self, layers: u8) -> bool {
+        [Layer::Background, Layer::Sprites, Layer::Thumbnail]
+            .into_iter()
+            .all(|layer| layers & layer.bit() == 0 || self.layer(layer).is_some())
+    }
+}
+
+/// The pixels of one layer of a frame.
+pub struct Pixels {
+    frame: Arc<Frame>,
+    layer: Layer,
+}
+
+impl Pixels {
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+}
+
+impl Deref for Pixels {
+    type Target = [RGBA8];
+
+    fn deref(&self) -> &[RGBA8] {
+        self.frame.layer(self.layer).unwrap_or_default()
+    }
+}
+
+/// The latest frame drawn of every instance.
+pub struct Frames {
+    ready: Vec<Mutex<Option<Arc<Frame>>>>,
+    /// Layers asked for of every instance since the worker last went through.
+    wanted: Vec<AtomicU8>,
+}
+
+impl Frames {
+    pub fn new(instances: usize) -> Frames {
+        Frames {
+            ready: (0..instances).map(|_| Mutex::new(None)).collect(),
+            wanted: (0..instances).map(|_| AtomicU8::new(0)).collect(),
+        }
+    }
+
+    /// The latest frame of the zero-based instance `index` with `layer`
+    /// drawn, if the worker drew one. Asks the worker for the layer of the
+    /// frames to come either way.
+    fn get(&self, index: usize, layer: Layer) -> Option<Arc<Frame>> {
+        self.wanted[index].fetch_or(layer.bit(), Ordering::Relaxed);
+        self.ready[index]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .filter(|frame| frame.layer(layer).is_some())
+    }
+
+    /// The pixels of `layer` of the latest frame of the zero-based instance
+    /// `index`, unless they are of frame `uploaded` already. Draws them from
+    /// `mario` right away if the worker hasn't yet.
+    pub fn latest(
+        &self,
+        index: usize,
+        layer: Layer,
+        mario: &Mutex<Mario>,
+        uploaded: Option<u64>,
+    ) -> Option<Pixels> {
+        let frame = match self.get(index, layer) {
+            Some(frame) => frame,
+            None => {
+                let mut mario = mario::lock(mario);
+                mario.shown = true;
+                let number = mario.nes().frame_number() as u64;
+                if uploaded == Some(number) {
+                    return None;
+                }
+                let scroll = scroll(mario.nes_mut());
+                let drawn = Frame::draw(mario.nes(), layer.bit());
+                drop(mario);
+                Arc::new(Frame::new(number, scroll, layer.bit(), drawn))
+            }
+        };
+        if uploaded == Some(frame.number) {
+            return None;
+        }
+        Some(Pixels { frame, layer })
+    }
+
+    /// Draws the layers asked for of every Mario whose frame changed since it
+    /// was last drawn, counting those Marios as shown.
+    pub fn prepare(&self, marios: &[Arc<Mutex<Mario>>]) {
+        for (i, mario) in marios.iter().enumerate() {
+            let layers = self.wanted[i].swap(0, Ordering::Relaxed);
+            if layers == 0 {
+                continue;
+            }
+            let ready = self.ready[i]
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+
+            let mut mario = mario::lock(mario);
+            mario.shown = true;
+            if mario.errored.is_some() {
+                // keeps showing the last frame drawn before it panicked
+                continue;
+            }
+            let nes = mario.nes_mut();
+            let number = nes.frame_number() as u64;
+            if ready.is_some_and(|ready| ready.number == number && ready.has(layers)) {
+                continue;
+            }
+            let scroll = scroll(nes);
+            let drawn = Frame::draw(nes, layers);
+            drop(mario);
+
+            let frame = Frame::new(number, scroll, layers, drawn);
+            *self.ready[i].lock().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(frame));
+        }
+    }
+}
+
+/// Stitches what the Mario furthest ahead in a level sees onto the map of
+/// his area, unless his screen is where it was last time.
+fn stitch_leader(marios: &[Arc<Mutex<Mario>>], maps: &Mutex<LevelMaps>) {
+    let leader = marios
+        .iter()
+        .filter_map(|leader| {
+            let mut mario = mario::lock(leader);
+            if mario.errored.is_some() {
+                return None;
+            }
+            let nes = mario.nes_mut();
+            in_level(nes).then(|| (scroll(nes), leader))
+        })
+        .max_by_key(|&(position, _)| position);
+    let leader = match leader {
+        Some((position, leader)) if maps.lock().unwrap().wants(position) => leader,
+        _ => return,
+    };
+
+    let mut leader = mario::lock(leader);
+    let nes = leader.nes_mut();
+    if !in_level(nes) {
+        return;
+    }
+    let position = scroll(nes);
+    let background = nes.draw_frame(DrawOptions::Background);
+    drop(leader);
+
+    // the backdrop is transparent in the background layer
+    let pixels: Vec<RGBA8> = unsafe { as_rgba(&background) }
+        .iter()
+        .map(|pixel| RGBA8 { a: 255, ..*pixel })
+        .collect();
+    maps.lock().unwrap().stitch(position, &pixels);
+}
+
+/// Draws the frames asked for, and stitches the level maps, after every tick
+/// sent on `ticks`, until nothing can be sent anymore.
+pub fn prepare_all(
+    frames: &Frames,
+    marios: &[Arc<Mutex<Mario>>],
+    maps: &Mutex<LevelMaps>,
+    ticks: Receiver<()>,
+) {
+    while ticks.recv().is_ok() {
+        // ticks that came in while drawing are caught up on at once
+        while ticks.try_recv().is_ok() {}
+        frames.prepare(marios);
+        stitch_leader(marios, maps);
+    }
+}
+
+unsafe fn as_rgba<const N: usize>(p: &[Color; N]) -> &[RGBA8] {
+    ::core::slice::from_raw_parts(
+        (p as *const [Color; N]) as *const RGBA8,
+        ::core::mem::size_of::<[Color; N]>() / ::core::mem::size_of::<RGBA8>(),
+    )
+}
+
+/// What `nes` shows right now, the sprites over the background, without any
+/// transparent pixels.
+pub fn frame_pixels(nes: &NES<NROM, FastPPU>) -> Vec<RGBA8> {
+    let background = nes.draw_frame(DrawOptions::Background);
+    let sprites = nes.draw_frame(DrawOptions::Sprites);
+    unsafe { composite(as_rgba(&background), as_rgba(&sprites)) }
+}
+
+/// The `sprites` layer of a frame over its `background` layer, without any
+/// transparent pixels.
+fn composite(background: &[RGBA8], sprites: &[RGBA8]) -> Vec<RGBA8> {
+    background
+        .iter()
+        .zip(sprites)
+        .map(|(background, sprite)| {
+            let color = if sprite.a > 0 { sprite } else { background };
+            RGBA8::new(color.r, color.g, color.b, 255)
+        })
+        .collect()
+}
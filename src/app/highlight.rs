@@ -0,0 +1,88 @@
+//! Clips of every level clear, played again from the inputs of the run and
+//! encoded without going through the canvas.
+
+use std::{
+    fs::create_dir_all,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc::Receiver,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{error, info};
+
+use crate::mario::Run;
+
+use super::frames::frame_pixels;
+
+/// Encodes every run received from `clears`, with the instance that played
+/// it, to an MP4 in `dir` at the `fps` of the game, one after the other.
+/// Returns once nothing can be sent anymore.
+pub fn encode_all(dir: &Path, fps: u32, clears: Receiver<(usize, Run)>) {
+    if let Err(e) = create_dir_all(dir) {
+        error!(
+            "could not create highlight directory {}: {}",
+            dir.display(),
+            e
+        );
+        return;
+    }
+    for (instance, run) in clears {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let name = format!(
+            "mario-{}-{}-{}-{}.mp4",
+            instance,
+            run.depth / 4 + 1,
+            run.depth % 4 + 1,
+            timestamp
+        );
+        let path = dir.join(name);
+        match encode(&run, fps, &path) {
+            Ok(()) => info!(path = %path.display(), frames = run.inputs.len(), "wrote highlight"),
+            Err(e) => error!("could not encode highlight {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// Plays `run` again and pipes its frames through ffmpeg into `path`, at
+/// `fps` frames per second.
+fn encode(run: &Run, fps: u32, path: &Path) -> io::Result<()> {
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-s", "256x240"])
+        .args(["-r", &fps.to_string(), "-i", "-"])
+        .args(["-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = ffmpeg.stdin.take().expect("stdin is piped");
+
+    let mut written = Ok(());
+    let mut buffer = Vec::with_capacity(256 * 240 * 4);
+    run.replay(|nes| {
+        if written.is_err() {
+            return;
+        }
+        buffer.clear();
+        for pixel in frame_pixels(nes) {
+            buffer.extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+        written = stdin.write_all(&buffer);
+    });
+    // closing stdin tells ffmpeg the clip is over
+    drop(stdin);
+
+    let status = ffmpeg.wait()?;
+    written?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("ffmpeg exited with {}", status),
+        ));
+    }
+    Ok(())
+}
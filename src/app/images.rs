@@ -0,0 +1,177 @@
+//! Images of what the NES frames in the [atlas](super::atlas) don't show:
+//! the state the rewind viewer looks at, and the maps of the levels.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use femtovg::{
+    imgref::Img, renderer::OpenGl, Canvas, ImageFlags, ImageId, Paint, Path, PixelFormat,
+};
+
+use crate::{
+    error,
+    levelmap::{self, LevelMaps},
+    luanim::{FontCanvas, Screen, Vec2},
+    mario::State,
+};
+
+use super::{frames::frame_pixels, script::ScriptState};
+
+/// The state shown by the [rewind viewer](ScriptState::rewind), drawn only
+/// when another one is shown, since the worker only draws the newest.
+pub struct RewindImage {
+    image: ImageId,
+    /// When the state last drawn was saved.
+    shown: Option<u64>,
+    /// Time spent drawing and uploading frames, see [`ScriptState::uploads`].
+    uploads: Arc<Mutex<Duration>>,
+}
+
+impl RewindImage {
+    pub fn new(canvas: &mut Canvas<OpenGl>, state: &ScriptState) -> error::Result<RewindImage> {
+        let image = canvas.create_image_empty(256, 240, PixelFormat::Rgba8, ImageFlags::NEAREST)?;
+        Ok(RewindImage {
+            image,
+            shown: None,
+            uploads: state.uploads.clone(),
+        })
+    }
+
+    /// Draws `state` with its top left corner at `origin`, at `pixel` units
+    /// per NES pixel.
+    pub fn draw(
+        &mut self,
+        screen: &mut Screen<FontCanvas<OpenGl>>,
+        origin: Vec2,
+        pixel: f32,
+        state: &State,
+    ) -> mlua::Result<()> {
+        if self.shown != Some(state.saved) {
+            let started = Instant::now();
+            let pixels = frame_pixels(&state.nes);
+            screen
+                .canvas
+                .update_image(self.image, Img::new(&pixels[..], 256, 240), 0, 0)
+                .map_err(mlua::Error::external)?;
+            self.shown = Some(state.saved);
+            *self.uploads.lock().unwrap() += started.elapsed();
+        }
+
+        let (width, height) = (256.0 * pixel, 240.0 * pixel);
+        let paint = Paint::image(self.image, origin.x, origin.y, width, height, 0.0, 1.0);
+        let mut path = Path::new();
+        path.rect(origin.x, origin.y, width, height);
+
+        let transform = screen.transform();
+        screen.canvas.set_transform(&transform.into());
+        screen.canvas.fill_path(&mut path, &paint);
+        screen.canvas.reset_transform();
+        Ok(())
+    }
+}
+
+/// A texture for every level map drawn, as wide as a map gets, with the pages
+/// stitched onto it.
+pub struct LevelImages {
+    images: HashMap<u16, LevelImage>,
+    maps: Arc<Mutex<LevelMaps>>,
+    /// Time spent drawing and uploading frames, see [`ScriptState::uploads`].
+    uploads: Arc<Mutex<Duration>>,
+}
+
+struct LevelImage {
+    image: ImageId,
+    /// Version of every page last uploaded.
+    uploaded: Vec<Option<u64>>,
+}
+
+impl LevelImages {
+    pub fn new(state: &ScriptState) -> LevelImages {
+        LevelImages {
+            images: HashMap::new(),
+            maps: state.level_maps.clone(),
+            uploads: state.uploads.clone(),
+        }
+    }
+
+    /// Draws the map of `area`, or of the area of the Mario furthest ahead,
+    /// with its top left corner at `origin` and `pixel` units per NES pixel.
+    /// Uploads the pages stitched onto it since it was last drawn first.
+    pub fn draw(
+        &mut self,
+        screen: &mut Screen<FontCanvas<OpenGl>>,
+        origin: Vec2,
+        pixel: f32,
+        area: Option<u16>,
+    ) -> mlua::Result<()> {
+        let maps = self.maps.lock().unwrap_or_else(PoisonError::into_inner);
+        let (area, map) = match area.or(maps.current()) {
+            Some(area) => match maps.get(area) {
+                Some(map) => (area, map),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        let level = match self.images.entry(area) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let image = screen
+                    .canvas
+                    .create_image_empty(
+                        levelmap::MAX_WIDTH,
+                        levelmap::HEIGHT,
+                        PixelFormat::Rgba8,
+                        ImageFlags::NEAREST,
+                    )
+                    .map_err(mlua::Error::external)?;
+                entry.insert(LevelImage {
+                    image,
+                    uploaded: Vec::new(),
+                })
+            }
+        };
+
+        let started = Instant::now();
+        level.uploaded.resize(map.pages().len(), None);
+        for (i, page) in map.pages().iter().enumerate() {
+            if level.uploaded[i] == Some(page.version) {
+                continue;
+            }
+            let img = Img::new(&page.pixels[..], levelmap::PAGE_WIDTH, levelmap::HEIGHT);
+            screen
+                .canvas
+                .update_image(level.image, img, i * levelmap::PAGE_WIDTH, 0)
+                .map_err(mlua::Error::external)?;
+            level.uploaded[i] = Some(page.version);
+        }
+        let width = map.width();
+        drop(maps);
+        *self.uploads.lock().unwrap() += started.elapsed();
+
+        let paint = Paint::image(
+            level.image,
+            origin.x,
+            origin.y,
+            levelmap::MAX_WIDTH as f32 * pixel,
+            levelmap::HEIGHT as f32 * pixel,
+            0.0,
+            1.0,
+        );
+        let mut path = Path::new();
+        path.rect(
+            origin.x,
+            origin.y,
+            width as f32 * pixel,
+            levelmap::HEIGHT as f32 * pixel,
+        );
+
+        let transform = screen.transform();
+        screen.canvas.set_transform(&transform.into());
+        screen.canvas.fill_path(&mut path, &paint);
+        screen.canvas.reset_transform();
+        Ok(())
+    }
+}
@@ -0,0 +1,204 @@
+//! What the mouse, the keyboard and the hotkeys of the main window do to the
+//! scenes and the Marios.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use femtovg::renderer::OpenGl;
+use tracing::{error, info, warn};
+use winit::event::{
+    ElementState, ModifiersState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::{
+    hotkeys::Action,
+    luanim::{FontCanvas, Input, Vec2},
+    mario::{self, Mario, Reset},
+    rewind::Viewer,
+    scene::Scenes,
+};
+
+use super::animate::{rewound, saved};
+
+/// Scroll distance of one wheel notch on devices that report pixels.
+const PIXELS_PER_LINE: f64 = 40.0;
+
+pub fn send_input(scenes: &mut Scenes<FontCanvas<OpenGl>>, input: Input) {
+    if let Err(e) = scenes.input(&input) {
+        error!(scene = %scenes.current().name, "lua error handling input: {}", e);
+    }
+}
+
+/// What scripts see of `event` if it comes from the mouse.
+pub fn pointer_input(event: &WindowEvent) -> Option<Input> {
+    match event {
+        WindowEvent::CursorMoved { position, .. } => Some(Input::CursorMoved(Vec2::new(
+            position.x as f32,
+            position.y as f32,
+        ))),
+        WindowEvent::MouseInput { state, button, .. } => Some(Input::MouseButton {
+            button: match button {
+                MouseButton::Left => "left",
+                MouseButton::Right => "right",
+                MouseButton::Middle => "middle",
+                MouseButton::Other(_) => "other",
+            }
+            .to_owned(),
+            pressed: *state == ElementState::Pressed,
+        }),
+        WindowEvent::MouseWheel { delta, .. } => {
+            let lines = match *delta {
+                MouseScrollDelta::LineDelta(x, y) => Vec2::new(x, y),
+                MouseScrollDelta::PixelDelta(p) => Vec2::new(
+                    (p.x / PIXELS_PER_LINE) as f32,
+                    (p.y / PIXELS_PER_LINE) as f32,
+                ),
+            };
+            Some(Input::Scroll(lines))
+        }
+        _ => None,
+    }
+}
+
+/// Name of `key` as seen by scripts, like `a`, `key1` or `space`.
+pub fn key_name(key: VirtualKeyCode) -> String {
+    format!("{:?}", key).to_lowercase()
+}
+
+/// What typing the number of a Mario with `modifiers` held does to him:
+/// control resets him to his oldest state, shift to the start of his level,
+/// and both to a fresh NES. Alt pauses him, for F10 to step.
+pub fn instance_hotkey(modifiers: ModifiersState) -> Option<Action> {
+    match (modifiers.ctrl(), modifiers.shift(), modifiers.alt()) {
+        (true, true, _) => Some(Action::Reset(Reset::Boot)),
+        (true, false, true) => Some(Action::Rewind),
+        (true, false, false) => Some(Action::Reset(Reset::Oldest)),
+        (false, true, _) => Some(Action::Reset(Reset::Level)),
+        (false, false, true) => Some(Action::Pause),
+        (false, false, false) => None,
+    }
+}
+
+pub fn digit_key(key: VirtualKeyCode) -> Option<usize> {
+    match key {
+        VirtualKeyCode::Key0 => Some(0),
+        key => scene_hotkey(key).map(|index| index + 1),
+    }
+}
+
+pub fn scene_hotkey(key: VirtualKeyCode) -> Option<usize> {
+    match key {
+        VirtualKeyCode::Key1 => Some(0),
+        VirtualKeyCode::Key2 => Some(1),
+        VirtualKeyCode::Key3 => Some(2),
+        VirtualKeyCode::Key4 => Some(3),
+        VirtualKeyCode::Key5 => Some(4),
+        VirtualKeyCode::Key6 => Some(5),
+        VirtualKeyCode::Key7 => Some(6),
+        VirtualKeyCode::Key8 => Some(7),
+        VirtualKeyCode::Key9 => Some(8),
+        _ => None,
+    }
+}
+
+/// Does `action` to Mario number `instance`, telling the scenes so they can
+/// show it.
+pub fn act_on(
+    marios: &[Arc<Mutex<Mario>>],
+    scenes: &mut Scenes<FontCanvas<OpenGl>>,
+    rewind: &Mutex<Option<Viewer>>,
+    instance: usize,
+    action: Action,
+) {
+    let mut mario = mario::lock(&marios[instance - 1]);
+    match action {
+        Action::Rewind => {
+            drop(mario);
+            let mut rewind = rewind.lock().unwrap_or_else(PoisonError::into_inner);
+            let viewer = match *rewind {
+                Some(viewer) if viewer.instance == instance => None,
+                _ => Some(Viewer::new(instance)),
+            };
+            *rewind = viewer;
+            drop(rewind);
+            info!(instance, open = viewer.is_some(), "rewind viewer toggled");
+            emit_rewind(marios, scenes, viewer);
+        }
+        Action::Reset(reset) => {
+            if !mario.reset_to(reset) {
+                warn!(instance, "not in a level to go back to the start of");
+                return;
+            }
+            drop(mario);
+            info!(instance, reset = reset.name(), "reset by hand");
+            for scene in scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("reset", (instance, reset.name())) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+        Action::Pause => {
+            mario.paused = !mario.paused;
+            mario.steps = 0;
+            let paused = mario.paused;
+            drop(mario);
+            info!(instance, paused, "pause toggled");
+            for scene in scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("pause", (instance, paused)) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+    }
+}
+
+/// Moves the rewind viewer through the states of its Mario with `key`, or
+/// closes it with Escape.
+pub fn scrub_rewind(
+    marios: &[Arc<Mutex<Mario>>],
+    scenes: &mut Scenes<FontCanvas<OpenGl>>,
+    rewind: &Mutex<Option<Viewer>>,
+    key: VirtualKeyCode,
+) {
+    let mut rewind = rewind.lock().unwrap_or_else(PoisonError::into_inner);
+    let viewer = match rewind.as_mut() {
+        Some(viewer) => viewer,
+        None => return,
+    };
+    if key == VirtualKeyCode::Escape {
+        *rewind = None;
+    } else {
+        let saved = saved(&mario::lock(&marios[viewer.instance - 1]));
+        match key {
+            VirtualKeyCode::PageUp => viewer.scrub(&saved, -1),
+            VirtualKeyCode::PageDown => viewer.scrub(&saved, 1),
+            VirtualKeyCode::Home => viewer.seek(&saved, 0),
+            _ => viewer.seek(&saved, saved.len()),
+        }
+    }
+    let viewer = *rewind;
+    drop(rewind);
+    emit_rewind(marios, scenes, viewer);
+}
+
+/// Tells the scenes what the rewind viewer shows: the Mario, which of his
+/// states counting from 1, how many he keeps and the frame number of the one
+/// shown, or nothing once it is closed.
+fn emit_rewind(
+    marios: &[Arc<Mutex<Mario>>],
+    scenes: &mut Scenes<FontCanvas<OpenGl>>,
+    viewer: Option<Viewer>,
+) {
+    let shown = viewer.map(|viewer| {
+        let (index, count, frame) = rewound(&mario::lock(&marios[viewer.instance - 1]), &viewer);
+        (viewer.instance, index, count, frame)
+    });
+    for scene in scenes.iter_mut() {
+        let emitted = match shown {
+            Some(shown) => scene.animation.emit("rewind", shown),
+            None => scene.animation.emit("rewind", ()),
+        };
+        if let Err(e) = emitted {
+            error!("lua error in scene {}: {}", scene.name, e);
+        }
+    }
+}
@@ -0,0 +1,950 @@
+//! The show itself: the windows, the scenes drawn to them and the
+//! simulation behind them, and the commands that run scripts without a
+//! window.
+
+pub mod animate;
+pub mod atlas;
+pub mod commands;
+pub mod dashboard;
+pub mod frames;
+pub mod highlight;
+pub mod images;
+pub mod input;
+pub mod nes_draw;
+pub mod output;
+pub mod platform;
+pub mod readings;
+pub mod script;
+pub mod simulation;
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::{self, create_dir_all},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
+    time::Instant,
+};
+
+use anyhow::Context;
+use femtovg::renderer::OpenGl;
+use mlua::{Table, Value};
+use notify::{Event, EventKind};
+use spin_sleep::LoopHelper;
+use tracing::{error, info, trace, warn};
+use winit::{
+    event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode},
+    event_loop::{ControlFlow, EventLoop},
+};
+
+use crate::{
+    console::Console,
+    hotkeys::InstanceKeys,
+    luanim::{DrawTime, FontCanvas, Input},
+    mario::{self, Mario},
+    obstacles::Obstacles,
+    recap::{self, Recap},
+    savestate,
+    scaling::Scaling,
+    scene::Scenes,
+    sfx::{Player, Sound},
+    smb::scroll,
+    sram::{self, SaveRam},
+    timings::{self, Phase, Spans},
+    vote::Trait,
+};
+
+use self::{
+    animate::animate,
+    dashboard::{self, Dashboard, DASHBOARD},
+    frames::frame_pixels,
+    input::{
+        act_on, digit_key, instance_hotkey, key_name, pointer_input, scene_hotkey, scrub_rewind,
+        send_input,
+    },
+    output::Sink,
+    platform::Surface,
+    readings::{Reading, ViewOnly},
+    script::{ScriptState, Visible},
+    simulation::Simulation,
+};
+
+pub const WIDTH: usize = 1920;
+pub const HEIGHT: usize = 1080;
+
+/// Seconds the arrow keys move the timeline of the current scene.
+const SEEK_STEP: f32 = 5.0;
+
+/// What the show is started with.
+pub struct Setup {
+    pub marios: Vec<Arc<Mutex<Mario>>>,
+    pub state: ScriptState,
+    pub surface: Surface,
+    pub scenes: Scenes<FontCanvas<OpenGl>>,
+    /// The files the scenes were started from, by name.
+    pub scene_files: Vec<(String, PathBuf)>,
+    pub scaling: Scaling,
+    pub dashboard: Option<Dashboard>,
+    pub simulation: Simulation,
+    /// Changes to the scripts, to start their scenes over.
+    pub script_changes: mpsc::Receiver<notify::Result<Event>>,
+    pub sfx: Option<Player>,
+    pub sink: Option<Sink>,
+    pub obstacles: Option<(PathBuf, Arc<Mutex<Obstacles>>)>,
+    pub save_ram: Option<SaveRam>,
+    pub recap_dir: Option<PathBuf>,
+    pub profile_dir: Option<PathBuf>,
+    pub states_dir: PathBuf,
+    /// The most frames drawn per second, if there is a limit.
+    pub max_fps: Option<f64>,
+    /// Whether to pause while nobody is watching.
+    pub pause_unfocused: bool,
+    /// Set to pause the simulation, shared with whoever else may pause it.
+    pub pause_held: Arc<AtomicBool>,
+}
+
+/// What the render loop works with: the windows, the scenes drawn to them,
+/// and what it hears of from the simulation.
+pub struct App {
+    marios: Vec<Arc<Mutex<Mario>>>,
+    state: ScriptState,
+    surface: Surface,
+    scenes: Scenes<FontCanvas<OpenGl>>,
+    /// The files the scenes were started from, by name.
+    scene_files: Vec<(String, PathBuf)>,
+    scaling: Scaling,
+    dashboard: Option<Dashboard>,
+    simulation: Simulation,
+    /// Changes to the scripts, to start their scenes over.
+    script_changes: mpsc::Receiver<notify::Result<Event>>,
+    sfx: Option<Player>,
+    sink: Option<Sink>,
+    obstacles: Option<(PathBuf, Arc<Mutex<Obstacles>>)>,
+    save_ram: Option<SaveRam>,
+    recap_dir: Option<PathBuf>,
+    profile_dir: Option<PathBuf>,
+    states_dir: PathBuf,
+    /// The last state saved with F5 and whose it was.
+    quick_state: Option<(usize, PathBuf)>,
+    console: Console,
+    /// Whether F3 shows how long drawing and simulating take.
+    show_timings: bool,
+    /// The number of the Mario being typed to reset or pause him.
+    instance_keys: InstanceKeys,
+    modifiers: ModifiersState,
+    limiter: Option<LoopHelper>,
+    pause_unfocused: bool,
+    /// Paused with the hotkey or over HTTP.
+    pause_held: Arc<AtomicBool>,
+    /// Whether the main window has focus and whether it is hidden, and
+    /// whether the dashboard has focus, to tell if anybody is watching.
+    focused: bool,
+    occluded: bool,
+    dashboard_focused: bool,
+    /// What every scene was last sent, to only write what changed since.
+    sent: HashMap<String, Vec<Reading>>,
+}
+
+impl App {
+    /// Sets up the show, with nothing typed, saved or paused for yet.
+    pub fn new(setup: Setup) -> App {
+        App {
+            marios: setup.marios,
+            state: setup.state,
+            surface: setup.surface,
+            scenes: setup.scenes,
+            scene_files: setup.scene_files,
+            scaling: setup.scaling,
+            dashboard: setup.dashboard,
+            simulation: setup.simulation,
+            script_changes: setup.script_changes,
+            sfx: setup.sfx,
+            sink: setup.sink,
+            obstacles: setup.obstacles,
+            save_ram: setup.save_ram,
+            recap_dir: setup.recap_dir,
+            profile_dir: setup.profile_dir,
+            states_dir: setup.states_dir,
+            quick_state: None,
+            console: Console::default(),
+            show_timings: false,
+            instance_keys: InstanceKeys::default(),
+            modifiers: ModifiersState::empty(),
+            limiter: setup
+                .max_fps
+                .map(|fps| LoopHelper::builder().build_with_target_rate(fps)),
+            pause_unfocused: setup.pause_unfocused,
+            pause_held: setup.pause_held,
+            focused: true,
+            occluded: false,
+            dashboard_focused: false,
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Runs the show on `el` until its window is closed.
+    pub fn run(mut self, el: EventLoop<()>) -> ! {
+        el.run(move |event, _, cf| match event {
+            winit::event::Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == self.surface.window.id() => self.window_event(event, cf),
+            winit::event::Event::WindowEvent {
+                ref event,
+                window_id,
+            } if self
+                .dashboard
+                .as_ref()
+                .is_some_and(|dashboard| dashboard.owns(window_id)) =>
+            {
+                self.dashboard_event(event)
+            }
+            winit::event::Event::MainEventsCleared => self.frame(cf),
+            winit::event::Event::LoopDestroyed => self.exit(),
+            _ => {}
+        })
+    }
+
+    /// Handles `event` of the main window.
+    fn window_event(&mut self, event: &winit::event::WindowEvent, cf: &mut ControlFlow) {
+        match event {
+            winit::event::WindowEvent::CloseRequested => *cf = ControlFlow::Exit,
+            winit::event::WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                self.store_paused();
+            }
+            winit::event::WindowEvent::Occluded(occluded) => {
+                self.occluded = *occluded;
+                self.store_paused();
+            }
+            winit::event::WindowEvent::ModifiersChanged(held) => {
+                self.modifiers = *held;
+                if instance_hotkey(self.modifiers).is_none() {
+                    if let Some((instance, action)) = self.instance_keys.finish() {
+                        let rewind = &self.state.rewind;
+                        act_on(&self.marios, &mut self.scenes, rewind, instance, action);
+                    }
+                }
+            }
+            winit::event::WindowEvent::ReceivedCharacter(c) if self.console.open => {
+                if self.console.type_char(*c) {
+                    self.console
+                        .submit(&mut self.scenes.current_mut().animation);
+                }
+            }
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if self.console.open => match key {
+                VirtualKeyCode::Grave | VirtualKeyCode::Escape => self.console.toggle(),
+                VirtualKeyCode::Up => self.console.recall_previous(),
+                VirtualKeyCode::Down => self.console.recall_next(),
+                _ => {}
+            },
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => self.key_pressed(*key),
+            winit::event::WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Released,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if !self.console.open => send_input(
+                &mut self.scenes,
+                Input::Key {
+                    key: key_name(*key),
+                    pressed: false,
+                },
+            ),
+            event => {
+                if let Some(input) = pointer_input(event) {
+                    send_input(&mut self.scenes, input);
+                }
+            }
+        }
+    }
+
+    /// Handles `key` pressed in the main window while the console is closed.
+    fn key_pressed(&mut self, key: VirtualKeyCode) {
+        send_input(
+            &mut self.scenes,
+            Input::Key {
+                key: key_name(key),
+                pressed: true,
+            },
+        );
+        match key {
+            VirtualKeyCode::Grave => self.console.toggle(),
+            VirtualKeyCode::Tab => self.scenes.show_next(),
+            VirtualKeyCode::F3 => self.show_timings = !self.show_timings,
+            VirtualKeyCode::F5 => match quick_save(&self.states_dir, &self.marios) {
+                Ok(saved) => self.quick_state = Some(saved),
+                Err(e) => error!("could not save state: {:#}", e),
+            },
+            VirtualKeyCode::F9 => match &self.quick_state {
+                Some((instance, path)) => {
+                    let mut mario = mario::lock(&self.marios[instance - 1]);
+                    match savestate::load(&mut mario, path) {
+                        Ok(()) => info!(instance, path = %path.display(), "loaded state"),
+                        Err(e) => error!("could not load state {}: {}", path.display(), e),
+                    }
+                }
+                None => warn!("no state saved with F5 to load"),
+            },
+            VirtualKeyCode::R => match &self.recap_dir {
+                Some(dir) => write_recap(dir, &self.simulation.session, &self.marios),
+                None => warn!("no --recap directory to write the recap to"),
+            },
+            VirtualKeyCode::P => {
+                let held = !self.pause_held.fetch_xor(true, Ordering::Relaxed);
+                self.store_paused();
+                info!(paused = held, "simulation pause toggled");
+            }
+            VirtualKeyCode::Space => {
+                let animation = &mut self.scenes.current_mut().animation;
+                if animation.is_paused() {
+                    animation.resume();
+                } else {
+                    animation.pause();
+                }
+            }
+            VirtualKeyCode::Left | VirtualKeyCode::Right => {
+                let scene = self.scenes.current_mut();
+                let step = if key == VirtualKeyCode::Left {
+                    -SEEK_STEP
+                } else {
+                    SEEK_STEP
+                };
+                let time = scene.animation.time() + step;
+                if let Err(e) = scene.animation.seek(time) {
+                    error!(scene = %scene.name, "could not seek: {}", e);
+                }
+            }
+            VirtualKeyCode::Up => {
+                let animation = &mut self.scenes.current_mut().animation;
+                animation.set_rate(animation.rate() * 2.0);
+            }
+            VirtualKeyCode::Down => {
+                let animation = &mut self.scenes.current_mut().animation;
+                animation.set_rate(animation.rate() / 2.0);
+            }
+            VirtualKeyCode::PageUp
+            | VirtualKeyCode::PageDown
+            | VirtualKeyCode::Home
+            | VirtualKeyCode::End
+            | VirtualKeyCode::Escape => {
+                scrub_rewind(&self.marios, &mut self.scenes, &self.state.rewind, key)
+            }
+            VirtualKeyCode::F10 => {
+                for mario in self.marios.iter() {
+                    let mut mario = mario::lock(mario);
+                    if mario.paused {
+                        mario.steps += 1;
+                    }
+                }
+            }
+            key => match (instance_hotkey(self.modifiers), digit_key(key)) {
+                (Some(action), Some(digit)) => {
+                    if let Some((instance, action)) =
+                        self.instance_keys.digit(digit, action, self.marios.len())
+                    {
+                        let rewind = &self.state.rewind;
+                        act_on(&self.marios, &mut self.scenes, rewind, instance, action);
+                    }
+                }
+                _ => {
+                    if let Some(index) = scene_hotkey(key) {
+                        self.scenes.show(index);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Holds the simulation while it is paused with the hotkey or over HTTP,
+    /// or with --pause-unfocused while nobody is watching: neither window has
+    /// focus, or the main one is hidden and the dashboard doesn't have focus.
+    fn store_paused(&self) {
+        let watched = self.dashboard_focused || (self.focused && !self.occluded);
+        let unwatched = self.pause_unfocused && !watched;
+        let paused = self.pause_held.load(Ordering::Relaxed) || unwatched;
+        self.state.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// Handles `event` of the dashboard window.
+    fn dashboard_event(&mut self, event: &winit::event::WindowEvent) {
+        match event {
+            winit::event::WindowEvent::CloseRequested => {
+                if let Some(dashboard) = self.dashboard.take() {
+                    dashboard.close(&self.surface, &mut self.scenes);
+                }
+                self.dashboard_focused = false;
+                self.store_paused();
+            }
+            // looking at the dashboard is watching too
+            winit::event::WindowEvent::Focused(focused) => {
+                self.dashboard_focused = *focused;
+                self.store_paused();
+            }
+            event => dashboard::send_input(&mut self.scenes, event),
+        }
+    }
+
+    /// Tells the scenes what happened since the last frame and what the
+    /// Marios are like now, then draws them.
+    fn frame(&mut self, cf: &mut ControlFlow) {
+        if let Some(limiter) = self.limiter.as_mut() {
+            limiter.loop_sleep();
+            limiter.loop_start();
+        }
+        // paused over HTTP since the last frame
+        self.store_paused();
+        self.reload_scripts();
+        self.hear_simulation();
+        let running = self.expire_timers();
+        self.hear_pokes();
+        let voting = self.close_vote();
+        let results = self.read_marios();
+
+        let values_started = Instant::now();
+        self.send_values(&results, &voting, &running);
+        let mut spans = Spans::default();
+        spans.add(Phase::Values, values_started.elapsed());
+        trace!(
+            time = ?values_started.elapsed(),
+            lua_kib = self.scenes.current_mut().animation.used_memory() / 1024,
+            "sent values to scenes"
+        );
+        *self
+            .state
+            .readings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = results;
+
+        self.draw(spans, cf);
+    }
+
+    /// Starts the scenes over if any script changed.
+    fn reload_scripts(&mut self) {
+        let mut refresh = false;
+        while let Ok(event) = self.script_changes.try_recv() {
+            match event {
+                Ok(Event {
+                    kind: EventKind::Modify(_),
+                    ..
+                }) => refresh = true,
+                Ok(_) => {}
+                Err(e) => warn!("watch error: {:?}", e),
+            }
+        }
+        if !refresh {
+            return;
+        }
+        for (name, path) in self.scene_files.iter() {
+            match animate(path, &self.surface, &self.marios, &self.state, self.scaling) {
+                Ok(animation) => {
+                    info!(scene = %name, "reloaded script");
+                    self.scenes.replace(name, animation);
+                    self.sent.remove(name);
+                }
+                Err(e) => error!("lua error in scene {}: {}", name, e),
+            }
+        }
+        if let Some(dashboard) = &self.dashboard {
+            let reloaded = dashboard.reload(
+                &self.surface,
+                &self.marios,
+                &self.state,
+                self.scaling,
+                &mut self.scenes,
+            );
+            match reloaded {
+                Ok(()) => {
+                    info!(scene = DASHBOARD, "reloaded script");
+                    self.sent.remove(DASHBOARD);
+                }
+                Err(e) => error!("lua error in scene {}: {}", DASHBOARD, e),
+            }
+        }
+    }
+
+    /// Tells the scenes what the simulation heard of since the last frame,
+    /// and plays the sounds that go with it.
+    fn hear_simulation(&mut self) {
+        let simulation = &self.simulation;
+        while let Ok(intervention) = simulation.stagnation.try_recv() {
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("stagnation", intervention.name()) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+
+        while let Ok((sample, intervened)) = simulation.diversity.try_recv() {
+            let args = (
+                sample.personalities,
+                sample.inputs,
+                intervened.map(|intervention| intervention.name()),
+            );
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("diversity", args) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+
+        while let Ok(winner) = simulation.predictions.try_recv() {
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("prediction", winner) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+
+        while let Ok(change) = simulation.ranks.try_recv() {
+            let args = (change.instance + 1, change.old + 1, change.new + 1);
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("rank", args) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+
+        while let Ok(sound) = simulation.sounds.try_recv() {
+            play(&mut self.sfx, sound);
+        }
+
+        while let Ok(instance) = simulation.victories.try_recv() {
+            play(&mut self.sfx, Sound::Clear);
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("victory", instance) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+
+        while let Ok(stalled) = simulation.stalls.try_recv() {
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("stalled", stalled) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+    }
+
+    /// Tells the scenes of the timers that ran out, returning the ones still
+    /// running with the seconds they have left.
+    fn expire_timers(&mut self) -> Vec<(String, f64)> {
+        // unlocked before scripts hear of them, as they may start timers again
+        let now = Instant::now();
+        let mut timers = self
+            .state
+            .timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let expired = timers.expire(now);
+        let running: Vec<(String, f64)> = timers
+            .all(now)
+            .map(|(name, remaining)| (name.to_owned(), remaining.as_secs_f64()))
+            .collect();
+        drop(timers);
+        for name in expired {
+            info!(timer = %name, "timer ran out");
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("timer", name.as_str()) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+        running
+    }
+
+    /// Tells the scenes of the writes to RAM scripts made since the last
+    /// frame.
+    fn hear_pokes(&mut self) {
+        let pokes = std::mem::take(
+            &mut *self
+                .state
+                .pokes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        for poke in pokes {
+            let args = (
+                poke.instance + 1,
+                poke.target.as_str(),
+                poke.bytes.clone(),
+                poke.old.clone(),
+            );
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("poke", args.clone()) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+    }
+
+    /// Applies the vote that is over, if one is, and tells the scenes.
+    /// Returns the vote still open as scripts see it: the Mario counting from
+    /// 1, the seconds left and the votes so far.
+    fn close_vote(&mut self) -> Option<(usize, f64, [(Trait, usize); 4])> {
+        let mut vote = self
+            .state
+            .vote
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let closed = match &*vote {
+            Some(open) if open.is_over() => vote.take(),
+            _ => None,
+        };
+        if let Some(closed) = closed {
+            let leader = closed.leader();
+            if let Some(choice) = leader {
+                choice.apply(&mut mario::lock(&self.marios[closed.instance]).personality);
+            }
+            let (instance, choice) = (closed.instance + 1, leader.map(|c| c.name()));
+            info!(instance, choice, "vote closed");
+            for scene in self.scenes.iter_mut() {
+                if let Err(e) = scene.animation.emit("vote", (instance, choice)) {
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+        let voting = vote.as_ref().map(|vote| {
+            (
+                vote.instance + 1,
+                vote.remaining().as_secs_f64(),
+                vote.tally(),
+            )
+        });
+        drop(vote);
+        voting
+    }
+
+    /// Reads every Mario for the scenes, simulating the ones no scene draws
+    /// as off-screen.
+    fn read_marios(&self) -> Vec<Reading> {
+        // the Marios drawn, if every scene being drawn declared them
+        let visible = RefCell::new(Some(vec![false; self.marios.len()]));
+        for scene in self.scenes.drawn() {
+            let declared = scene.animation.values(|lua, _| {
+                let mut visible = visible.borrow_mut();
+                match (lua.app_data_ref::<Visible>(), visible.as_mut()) {
+                    (Some(declared), Some(visible)) => {
+                        for (visible, &declared) in visible.iter_mut().zip(&declared.0) {
+                            *visible |= declared;
+                        }
+                    }
+                    (None, _) => *visible = None,
+                    _ => {}
+                }
+                Ok(())
+            });
+            if let Err(e) = declared {
+                error!("lua error in scene {}: {}", scene.name, e);
+            }
+        }
+        let visible = visible.into_inner();
+
+        let watches = self
+            .state
+            .ram_watch
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let last = self
+            .state
+            .readings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let results = self
+            .marios
+            .iter()
+            .enumerate()
+            .map(|(i, mario)| {
+                let mut mario = mario::lock(mario);
+                mario.offscreen = visible.as_ref().is_some_and(|visible| !visible[i]);
+                Reading::of(&mut mario, i, &watches, last.get(i))
+            })
+            .collect();
+        drop(last);
+        drop(watches);
+        results
+    }
+
+    /// Writes the `frame`, `vote`, `timers` and `marios` values of every
+    /// scene, the last only where it changed since it was last sent.
+    fn send_values(
+        &mut self,
+        results: &[Reading],
+        voting: &Option<(usize, f64, [(Trait, usize); 4])>,
+        running: &[(String, f64)],
+    ) {
+        for scene in self.scenes.iter_mut() {
+            let last_sent = self.sent.get(&scene.name);
+            let values = scene.animation.values(|lua, table| {
+                let frame: u32 = table.get("frame")?;
+                table.set("frame", frame + 1)?;
+
+                // an open vote keeps its table, so only the numbers change
+                let last: Value = table.get("vote")?;
+                let vote = match voting {
+                    Some((instance, remaining, tally)) => {
+                        let vote = match last {
+                            Value::Table(vote) => vote,
+                            _ => lua.create_table()?,
+                        };
+                        vote.set("instance", *instance)?;
+                        vote.set("remaining", *remaining)?;
+                        let votes = match vote.get("votes")? {
+                            Value::Table(votes) => votes,
+                            _ => lua.create_table()?,
+                        };
+                        for (choice, count) in tally {
+                            votes.set(choice.name(), *count)?;
+                        }
+                        vote.set("votes", votes)?;
+                        Value::Table(vote)
+                    }
+                    None => Value::Boolean(false),
+                };
+                table.set("vote", vote)?;
+                table.set("timers", lua.create_table_from(running.iter().cloned())?)?;
+
+                if lua.app_data_ref::<ViewOnly>().is_some() {
+                    return Ok(());
+                }
+                let marios: Table = table.get("marios")?;
+                for (i, result) in results.iter().enumerate() {
+                    let last = last_sent.and_then(|sent| sent.get(i));
+                    if last != Some(result) {
+                        result.write(lua, &marios.get(i + 1)?, last)?;
+                    }
+                }
+                table.set("marios", marios)?;
+                Ok(())
+            });
+            match values {
+                Ok(()) => {
+                    self.sent.insert(scene.name.clone(), results.to_vec());
+                }
+                Err(e) => {
+                    // the tables may be half written, so write all of them next time
+                    self.sent.remove(&scene.name);
+                    error!("lua error in scene {}: {}", scene.name, e);
+                }
+            }
+        }
+    }
+
+    /// Draws the scenes to their windows, with the output, console and
+    /// timings, adding the time every phase took to `spans`.
+    fn draw(&mut self, mut spans: Spans, cf: &mut ControlFlow) {
+        // Programs that draw graphics continuously can render here unconditionally for simplicity.
+        self.scenes
+            .render()
+            .unwrap_or_else(|e| error!("lua error: {}", e));
+        let mut drawn = DrawTime::default();
+        for scene in self.scenes.iter_mut() {
+            drawn += scene.animation.draw_time();
+        }
+        let uploads = std::mem::take(&mut *self.state.uploads.lock().unwrap());
+        spans.add(Phase::Lua, drawn.script.saturating_sub(drawn.instructions));
+        spans.add(
+            Phase::Instructions,
+            drawn.instructions.saturating_sub(uploads),
+        );
+        spans.add(Phase::Upload, uploads);
+        spans.add(Phase::Flush, drawn.flush);
+        if let Some(out) = self.sink.as_mut() {
+            let canvas = self.scenes.current_mut().animation.canvas_mut();
+            let sent = canvas
+                .screenshot()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))
+                .and_then(|frame| out.send(frame.as_ref()));
+            if let Err(e) = sent {
+                error!("could not send frame, stopping output: {}", e);
+                self.sink = None;
+            }
+        }
+        self.console
+            .draw(self.scenes.current_mut().animation.canvas_mut());
+        if self.show_timings {
+            let frame = *self.state.timings.lock().unwrap();
+            let tick = self
+                .state
+                .stats
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .timings;
+            timings::draw(
+                self.scenes.current_mut().animation.canvas_mut(),
+                &frame,
+                &tick,
+            );
+        }
+        let swapping = Instant::now();
+        if let Err(e) = self.surface.present() {
+            error!("could not swap buffers: {}", e);
+            *cf = ControlFlow::Exit;
+        }
+        spans.add(Phase::Swap, swapping.elapsed());
+        self.state.timings.lock().unwrap().update(&spans);
+
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.draw(&self.surface, &mut self.scenes);
+        }
+    }
+
+    /// Writes what is kept once the window closes: the recap, the obstacles
+    /// and where the scripts spent their time.
+    fn exit(&mut self) {
+        if let Some(dir) = &self.recap_dir {
+            write_recap(dir, &self.simulation.session, &self.marios);
+        }
+        if let Some((dir, obstacles)) = &self.obstacles {
+            let obstacles = obstacles.lock().unwrap_or_else(PoisonError::into_inner);
+            match obstacles.save(dir) {
+                Ok(()) => info!(obstacles = obstacles.len(), "saved obstacles"),
+                Err(e) => error!("could not save obstacles: {}", e),
+            }
+        }
+        if let Some(dir) = &self.profile_dir {
+            write_profiles(dir, &mut self.scenes);
+        }
+        if let Some(save_ram) = &self.save_ram {
+            store_save_ram(save_ram, &self.marios);
+        }
+    }
+}
+
+/// Gives every Mario what was stored in `save_ram` to boot with, if anything.
+pub fn load_save_ram(save_ram: &SaveRam, marios: &[Arc<Mutex<Mario>>]) -> anyhow::Result<()> {
+    let path = save_ram.path().display();
+    let data = match save_ram.load() {
+        Ok(Some(data)) => data,
+        Ok(None) => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("could not load save RAM {}", path)),
+    };
+    info!(%path, bytes = data.len(), "loaded save RAM");
+    let data: Arc<[u8]> = data.into();
+    for mario in marios {
+        mario::lock(mario).set_save_ram(data.clone());
+    }
+    Ok(())
+}
+
+/// Stores the save RAM of the Mario furthest ahead in `save_ram`.
+fn store_save_ram(save_ram: &SaveRam, marios: &[Arc<Mutex<Mario>>]) {
+    let leader = marios
+        .iter()
+        .max_by_key(|mario| scroll(mario::lock(mario).nes_mut()));
+    let data = match leader {
+        Some(leader) => sram::read_from(mario::lock(leader).nes_mut()),
+        None => return,
+    };
+    match save_ram.store(&data) {
+        Ok(()) => info!(path = %save_ram.path().display(), "stored save RAM"),
+        Err(e) => error!("could not store save RAM: {}", e),
+    }
+}
+
+/// Writes where the script of every scene spent its time since it was last
+/// loaded to `dir`.
+fn write_profiles(dir: &Path, scenes: &mut Scenes<FontCanvas<OpenGl>>) {
+    if let Err(e) = create_dir_all(dir) {
+        error!("could not write lua profiles: {}", e);
+        return;
+    }
+    for scene in scenes.iter_mut() {
+        let profile = match scene.animation.profile() {
+            Some(profile) => profile,
+            None => continue,
+        };
+        let path = dir.join(format!("{}.folded", scene.name));
+        match fs::write(&path, profile) {
+            Ok(()) => info!(scene = %scene.name, path = %path.display(), "wrote lua profile"),
+            Err(e) => error!("could not write lua profile of scene {}: {}", scene.name, e),
+        }
+    }
+}
+
+/// Writes the recap of the session so far to `dir`, with snapshots of the
+/// Marios that got the furthest.
+fn write_recap(dir: &Path, session: &Mutex<Recap>, marios: &[Arc<Mutex<Mario>>]) {
+    let session = session.lock().unwrap_or_else(PoisonError::into_inner);
+    let written = session
+        .write(dir)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| {
+            for instance in session.top(recap::TOP) {
+                let path = dir.join(Recap::snapshot_name(instance));
+                save_snapshot(&marios[instance], &path)
+                    .with_context(|| format!("could not save {}", path.display()))?;
+            }
+            Ok(())
+        });
+    match written {
+        Ok(()) => info!(dir = %dir.display(), "wrote session recap"),
+        Err(e) => error!("could not write session recap: {:#}", e),
+    }
+}
+
+/// Saves the state of the Mario that got the furthest to a new file in `dir`,
+/// returning which instance that was and where it went.
+fn quick_save(dir: &Path, marios: &[Arc<Mutex<Mario>>]) -> anyhow::Result<(usize, PathBuf)> {
+    let (index, mario) = marios
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, mario)| scroll(mario::lock(mario).nes_mut()))
+        .context("no Marios to save")?;
+    let instance = index + 1;
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = dir.join(format!("mario-{}-{}.state", instance, timestamp));
+
+    create_dir_all(dir)?;
+    savestate::save(&mut mario::lock(mario), &path)
+        .with_context(|| format!("could not write {}", path.display()))?;
+    info!(instance, path = %path.display(), "saved state");
+    Ok((instance, path))
+}
+
+/// Saves what `mario` shows right now as a PNG.
+fn save_snapshot(mario: &Mutex<Mario>, path: &Path) -> anyhow::Result<()> {
+    let pixels = frame_pixels(mario::lock(mario).nes());
+    let mut pixmap = tiny_skia::Pixmap::new(256, 240).context("could not allocate snapshot")?;
+    for (pixel, color) in pixmap.pixels_mut().iter_mut().zip(pixels) {
+        *pixel = tiny_skia::ColorU8::from_rgba(color.r, color.g, color.b, color.a).premultiply();
+    }
+    pixmap.save_png(path)?;
+    Ok(())
+}
+
+/// Saves every `every`th state `mario` keeps side by side as a PNG, counted
+
+/// Plays `sound` if there are sound effects.
+fn play(sfx: &mut Option<Player>, sound: Sound) {
+    if let Some(Err(e)) = sfx.as_mut().map(|sfx| sfx.play(sound)) {
+        warn!(sound = sound.name(), "could not play sound: {}", e);
+    }
+}
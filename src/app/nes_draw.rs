@@ -0,0 +1,190 @@
+//! Drawing NES frames from the [atlas](super::atlas) the way scripts ask
+//! for them: scaled, shifted, cropped, and two of them lined up to compare.
+
+use std::sync::Mutex;
+
+use femtovg::{renderer::OpenGl, ImageId, Paint, Path};
+
+use crate::{
+    luanim::{FontCanvas, Mat3, Raster, Screen, Vec2},
+    mario::Mario,
+    scaling::{self, Scaling},
+};
+
+use super::{
+    atlas::{Atlas, Crop},
+    frames::Layer,
+};
+
+/// The scaling a script asked for by name, or `default` if it didn't.
+pub fn scaling_mode(mode: Option<String>, default: Scaling) -> mlua::Result<Scaling> {
+    match mode {
+        Some(mode) => mode.parse().map_err(mlua::Error::RuntimeError),
+        None => Ok(default),
+    }
+}
+
+/// NES pixels left between the two frames of `nes_compare` by default.
+const COMPARE_GAP: f32 = 8.0;
+
+/// One frame, or the sprites of one frame, to draw.
+pub struct NesDraw {
+    /// Top left corner.
+    pub origin: Vec2,
+    /// Units per NES pixel.
+    pub pixel: f32,
+    /// NES pixels to shift the frame by, cutting off what ends up outside of
+    /// where it would be unshifted.
+    pub offset: Vec2,
+    pub crop: Crop,
+    pub alpha: f32,
+    pub scaling: Scaling,
+}
+
+impl NesDraw {
+    pub fn run(
+        &self,
+        screen: &mut Screen<FontCanvas<OpenGl>>,
+        atlas: &mut Atlas,
+        mario: &Mutex<Mario>,
+        instance: usize,
+        layer: Layer,
+    ) -> mlua::Result<()> {
+        let source = self.upload(screen, atlas, mario, instance, layer)?;
+        self.fill(screen, atlas, source);
+        Ok(())
+    }
+
+    /// Where the frame goes on screen and the units per NES pixel it is drawn
+    /// at, after snapping.
+    fn placement(&self, transform: Mat3) -> (Vec2, f32) {
+        match self.scaling {
+            Scaling::Snap => scaling::snap(transform, self.origin, self.pixel, 256.0, 240.0),
+            _ => (self.origin, self.pixel),
+        }
+    }
+
+    /// Uploads the current frame of `mario` unless it is already there,
+    /// returning what to paint it from.
+    fn upload(
+        &self,
+        screen: &mut Screen<FontCanvas<OpenGl>>,
+        atlas: &mut Atlas,
+        mario: &Mutex<Mario>,
+        instance: usize,
+        layer: Layer,
+    ) -> mlua::Result<Source> {
+        let transform = screen.transform();
+        let (_, pixel) = self.placement(transform);
+        let canvas = &mut screen.canvas;
+        Ok(match self.scaling {
+            Scaling::Sharp => {
+                let factor = scaling::prescale(transform, pixel);
+                Source::Prescaled(
+                    atlas.prescaled(canvas, instance, mario, layer, self.crop, factor)?,
+                )
+            }
+            _ => Source::Tile(atlas.tile(canvas, instance, mario, layer, self.crop)?),
+        })
+    }
+
+    /// Draws the frame uploaded to `source`.
+    fn fill(&self, screen: &mut Screen<FontCanvas<OpenGl>>, atlas: &Atlas, source: Source) {
+        let transform = screen.transform();
+        let (origin, pixel) = self.placement(transform);
+        let width = 256.0 * pixel;
+        let height = 240.0 * pixel;
+        let shifted = origin + pixel * self.offset;
+
+        let paint = match source {
+            Source::Prescaled(image) => {
+                Paint::image(image, shifted.x, shifted.y, width, height, 0.0, self.alpha)
+            }
+            Source::Tile(tile) => atlas.paint(shifted.x, shifted.y, pixel, tile, self.alpha),
+        };
+        // both where the frame would be and where it is shifted to are cropped
+        let rows = self.crop.rows(1);
+        let mut path = Path::new();
+        path.rect(
+            f32::max(origin.x, shifted.x),
+            f32::max(origin.y, shifted.y) + rows.start as f32 * pixel,
+            f32::max(width - (pixel * self.offset.x).abs(), 0.0),
+            f32::max(
+                rows.len() as f32 * pixel - (pixel * self.offset.y).abs(),
+                0.0,
+            ),
+        );
+
+        screen.canvas.set_transform(&transform.into());
+        screen.canvas.fill_path(&mut path, &paint);
+        screen.canvas.reset_transform();
+    }
+}
+
+/// Where an uploaded frame can be painted from.
+#[derive(Clone, Copy)]
+enum Source {
+    /// A tile of the atlas.
+    Tile((f32, f32)),
+    /// An image of its own, scaled up.
+    Prescaled(ImageId),
+}
+
+/// NES pixels to shift the frames of two Marios at `first` and `second` in
+/// the game by, so the same spot of the level lines up in both. Marios in
+/// different areas, or too far apart to share any of the screen, aren't
+/// shifted.
+fn lock_scroll(first: u32, second: u32) -> (f32, f32) {
+    let apart = i64::from(second) - i64::from(first);
+    if first >> 16 != second >> 16 || apart.abs() >= 256 {
+        return (0.0, 0.0);
+    }
+    let half = apart as f32 / 2.0;
+    (-half, half)
+}
+
+/// Draws the frames of two Marios as `nes_compare` does, each by its own
+/// [`NesDraw`], shifted so the same spot of the level lines up in both.
+pub fn compare(
+    screen: &mut Screen<FontCanvas<OpenGl>>,
+    background: &mut Atlas,
+    sprites: &mut Atlas,
+    draws: [(usize, &Mutex<Mario>, NesDraw); 2],
+) -> mlua::Result<()> {
+    // upload both first, so they are lined up by the frames drawn
+    let mut sources = Vec::with_capacity(draws.len());
+    for (instance, mario, draw) in draws.iter() {
+        sources.push((
+            draw.upload(screen, background, mario, *instance, Layer::Background)?,
+            draw.upload(screen, sprites, mario, *instance, Layer::Sprites)?,
+        ));
+    }
+    let (first, second) = (draws[0].0, draws[1].0);
+    let (first_xo, second_xo) = lock_scroll(background.scroll(first), background.scroll(second));
+
+    for (((_, _, draw), xo), (bg, spr)) in draws.into_iter().zip([first_xo, second_xo]).zip(sources)
+    {
+        let draw = NesDraw {
+            offset: Vec2::new(xo, 0.0),
+            ..draw
+        };
+        draw.fill(screen, background, bg);
+        draw.fill(screen, sprites, spr);
+    }
+    Ok(())
+}
+
+/// NES pixels from the top of the first frame of `nes_compare` to the top of
+/// the second, which leaves `gap` between what is left of them after `crop`.
+pub fn compare_spacing(crop: Crop, gap: Option<f32>) -> f32 {
+    crop.rows(1).len() as f32 + gap.unwrap_or(COMPARE_GAP)
+}
+
+/// Draws the box a frame takes up when rendering without an emulator.
+pub fn raster_frame(screen: &mut Screen<Raster>, origin: Vec2, pixel: f32, crop: Crop) {
+    let rows = crop.rows(1);
+    let origin = origin + Vec2::new(0.0, rows.start as f32 * pixel);
+    let size = pixel * Vec2::new(256.0, rows.len() as f32);
+    let transform = screen.transform();
+    screen.canvas.fill_rect(transform, origin, size, 64);
+}
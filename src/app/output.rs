@@ -0,0 +1,121 @@
+//! Publishing rendered frames to other programs, so they can be captured
+//! without the window around them.
+//!
+//! With the `ndi` feature, frames can go out as an NDI source that OBS adds
+//! with its NDI plugin. Otherwise they go out as raw RGBA through a file or
+//! named pipe, and ffmpeg is what turns them into whatever the capturing
+//! program reads. Spout and Syphon senders are left to it rather than linked
+//! in, as each needs its own SDK. To get frames into OBS as a media source,
+//! for example:
+//!
+//! ```text
+//! mkfifo /tmp/shellkick
+//! shellkick --output pipe:/tmp/shellkick &
+//! ffmpeg -f rawvideo -pix_fmt rgba -s 1920x1080 -r 60 -i /tmp/shellkick \
+//!     -f mpegts udp://127.0.0.1:9000
+//! ```
+//!
+//! where `-s` is the size of the canvas, whatever the size of the window, and
+//! OBS opens `udp://127.0.0.1:9000`.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use femtovg::{imgref::ImgRef, rgb::RGBA8};
+
+#[cfg(feature = "ndi")]
+use crate::ndi::Sender;
+
+/// Where rendered frames are sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// Raw RGBA frames written one after the other to a file or named pipe,
+    /// for example for ffmpeg to turn into a stream OBS can read.
+    Pipe(PathBuf),
+    /// An NDI source with this name, for shellkick built with the `ndi`
+    /// feature and the NDI runtime installed.
+    Ndi(String),
+}
+
+impl FromStr for Output {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("pipe", path)) => Ok(Output::Pipe(path.into())),
+            Some(("ndi", name)) if !name.is_empty() => Ok(Output::Ndi(name.to_owned())),
+            _ if ["spout", "syphon"].contains(&s) => Err(format!(
+                "{} output isn't built in, send frames to ndi:NAME, or to pipe:PATH and \
+                 through ffmpeg, instead",
+                s
+            )),
+            _ => Err(format!(
+                "unknown output {:?}, expected ndi:NAME or pipe:PATH",
+                s
+            )),
+        }
+    }
+}
+
+/// An open [`Output`].
+pub struct Sink {
+    target: Target,
+    buffer: Vec<u8>,
+}
+
+enum Target {
+    Pipe(BufWriter<File>),
+    #[cfg(feature = "ndi")]
+    Ndi(Sender),
+}
+
+impl Sink {
+    /// Opens `output`. Opening a named pipe waits until something reads it.
+    pub fn open(output: &Output) -> io::Result<Sink> {
+        let target = match output {
+            Output::Pipe(path) => {
+                let file = OpenOptions::new().write(true).create(true).open(path)?;
+                Target::Pipe(BufWriter::new(file))
+            }
+            #[cfg(feature = "ndi")]
+            Output::Ndi(name) => {
+                Target::Ndi(Sender::new(name).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?)
+            }
+            #[cfg(not(feature = "ndi"))]
+            Output::Ndi(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "NDI output needs shellkick built with the ndi feature",
+                ))
+            }
+        };
+        Ok(Sink {
+            target,
+            buffer: Vec::new(),
+        })
+    }
+
+    /// Sends one frame, which blocks while a pipe is full.
+    pub fn send(&mut self, frame: ImgRef<RGBA8>) -> io::Result<()> {
+        self.buffer.clear();
+        for pixel in frame.pixels() {
+            self.buffer
+                .extend_from_slice(&[pixel.r, pixel.g, pixel.b, pixel.a]);
+        }
+        match &mut self.target {
+            Target::Pipe(writer) => {
+                writer.write_all(&self.buffer)?;
+                writer.flush()
+            }
+            #[cfg(feature = "ndi")]
+            Target::Ndi(sender) => {
+                sender.send(frame.width(), frame.height(), &self.buffer);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! The window and the surface scenes are rendered to, kept apart from the rest
+//! of the show so a renderer only has to provide a [`Surface`].
+
+use std::num::NonZeroU32;
+
+use anyhow::Context;
+use femtovg::renderer::OpenGl;
+use glutin::{
+    config::{Config, ConfigTemplateBuilder},
+    context::{ContextApi, ContextAttributesBuilder, PossiblyCurrentContext},
+    display::GetGlDisplay,
+    prelude::{
+        GlDisplay, NotCurrentGlContextSurfaceAccessor, PossiblyCurrentContextGlSurfaceAccessor,
+    },
+    surface::{GlSurface, SurfaceAttributesBuilder, SwapInterval, WindowSurface},
+};
+use glutin_winit::{DisplayBuilder, GlWindow};
+use raw_window_handle::HasRawWindowHandle;
+use winit::{
+    dpi::PhysicalSize,
+    event_loop::EventLoop,
+    window::{Window, WindowBuilder},
+};
+
+use crate::error::{self, Error};
+
+/// A window with a surface that can be drawn to and presented.
+pub struct Surface {
+    pub window: Window,
+    /// Whether what is left undrawn shows what is behind the window.
+    pub transparent: bool,
+    config: Config,
+    surface: glutin::surface::Surface<WindowSurface>,
+    context: PossiblyCurrentContext,
+}
+
+impl Surface {
+    /// Opens a `width` by `height` window called `title` drawn to with
+    /// OpenGL, with per-pixel alpha if `transparent`. The new window's
+    /// surface is the one drawn to until another is made current.
+    pub fn new(
+        el: &EventLoop<()>,
+        title: &str,
+        width: u32,
+        height: u32,
+        transparent: bool,
+    ) -> anyhow::Result<Surface> {
+        let template = ConfigTemplateBuilder::new().with_transparency(transparent);
+        let (window, config) = DisplayBuilder::new()
+            .with_window_builder(Some(
+                WindowBuilder::new()
+                    .with_title(title)
+                    .with_inner_size(PhysicalSize::new(width, height))
+                    .with_resizable(false)
+                    .with_transparent(transparent),
+            ))
+            .build(el, template, |mut it| {
+                it.next().expect("no OpenGL configs available")
+            })
+            .map_err(|e| Error::Window(e.to_string()))?;
+
+        let window = window.ok_or_else(|| Error::Window("no window was built".to_owned()))?;
+        let attrs = window.build_surface_attributes(SurfaceAttributesBuilder::new());
+
+        let display = config.display();
+        let surface = unsafe { display.create_window_surface(&config, &attrs) }
+            .map_err(Error::from)
+            .context("could not create window surface")?;
+
+        let context = unsafe {
+            display.create_context(
+                &config,
+                &ContextAttributesBuilder::new()
+                    .with_context_api(ContextApi::OpenGl(None))
+                    .build(Some(window.raw_window_handle())),
+            )
+        }
+        .and_then(|context| context.make_current(&surface))
+        .map_err(Error::from)
+        .context("could not create OpenGL context")?;
+
+        Ok(Surface {
+            window,
+            transparent,
+            config,
+            surface,
+            context,
+        })
+    }
+
+    /// A new renderer for a canvas that draws to this surface.
+    pub fn renderer(&self) -> error::Result<OpenGl> {
+        Ok(OpenGl::new_from_glutin_display(&self.config.display())?)
+    }
+
+    /// Whether presenting waits for the display to refresh. Not every
+    /// platform lets this be changed.
+    pub fn set_vsync(&self, vsync: bool) -> error::Result<()> {
+        let interval = if vsync {
+            SwapInterval::Wait(NonZeroU32::new(1).unwrap())
+        } else {
+            SwapInterval::DontWait
+        };
+        Ok(self.surface.set_swap_interval(&self.context, interval)?)
+    }
+
+    /// Has what is drawn from now on go to this surface, for when there is
+    /// more than one window.
+    pub fn make_current(&self) -> error::Result<()> {
+        Ok(self.context.make_current(&self.surface)?)
+    }
+
+    /// Shows what was drawn since the last call.
+    pub fn present(&self) -> error::Result<()> {
+        Ok(self.surface.swap_buffers(&self.context)?)
+    }
+}
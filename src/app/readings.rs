@@ -0,0 +1,227 @@
+//! What scripts see of every Mario: the readings copied into the `marios`
+//! value every frame, and the `marios_view` global reading them one field at
+//! a time.
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+use mlua::{MetaMethod, Table, ToLua, UserData, UserDataMethods, Value};
+
+use crate::{
+    mario::{Mario, Personality},
+    observation::Position,
+    ramwatch::Watches,
+    smb::{scroll, Memory, Powerup, Warp},
+};
+
+use super::script::{check_instance, instances};
+
+/// The state of a Mario that is copied into the `marios` value every frame.
+#[derive(Clone, PartialEq)]
+pub struct Reading {
+    fitness: u32,
+    /// Where Mario is in his area, by the top left of his sprite like an
+    /// [`Observation`](crate::observation::Observation).
+    position: Position,
+    /// Changes when viewers vote on it.
+    personality: Personality,
+    effective: Personality,
+    powerup: Powerup,
+    lives: u8,
+    errored: Option<String>,
+    /// The level Mario started in, if not 1-1.
+    start: Option<Warp>,
+    /// Whether Mario plays with a neural network.
+    neat: bool,
+    /// The bytes of every address of RAM watched for Mario, by name.
+    pub ram: Vec<(String, Vec<u8>)>,
+}
+
+impl Reading {
+    /// Reads zero-based Mario number `instance`, with his `watches`. An
+    /// errored Mario keeps his `last` reading, as his state is whatever the
+    /// frame that panicked left behind.
+    pub fn of(
+        mario: &mut Mario,
+        instance: usize,
+        watches: &Watches,
+        last: Option<&Reading>,
+    ) -> Reading {
+        if let (Some(errored), Some(last)) = (&mario.errored, last) {
+            return Reading {
+                errored: Some(errored.clone()),
+                ..last.clone()
+            };
+        }
+        let nes = mario.nes_mut();
+        let (fitness, powerup, lives) = (scroll(nes), nes.powerup(), nes.lives());
+        let ram = watches.read(instance, nes);
+        Reading {
+            fitness,
+            position: Position::player(nes),
+            personality: mario.personality.clone(),
+            effective: mario.effective.clone(),
+            powerup,
+            lives,
+            errored: mario.errored.clone(),
+            start: mario.warp,
+            neat: mario.controller.name() == "neat",
+            ram,
+        }
+    }
+
+    /// Writes the reading into the table of its Mario, leaving out what is
+    /// the same as in `last`, the reading written before.
+    pub fn write(
+        &self,
+        lua: &mlua::Lua,
+        table: &Table,
+        last: Option<&Reading>,
+    ) -> mlua::Result<()> {
+        let changed =
+            |same: fn(&Reading, &Reading) -> bool| !last.is_some_and(|last| same(last, self));
+        if changed(|a, b| a.fitness == b.fitness) {
+            table.set("fitness", self.fitness)?;
+        }
+        if changed(|a, b| a.position == b.position) {
+            table.set("x", self.position.x)?;
+            table.set("y", self.position.y)?;
+        }
+        if changed(|a, b| a.powerup == b.powerup) {
+            table.set("powerup", self.powerup.name())?;
+        }
+        if changed(|a, b| a.lives == b.lives) {
+            table.set("lives", self.lives)?;
+        }
+        if changed(|a, b| a.errored == b.errored) {
+            table.set("errored", self.errored.clone())?;
+        }
+        if changed(|a, b| a.start == b.start) {
+            table.set("start", self.start.map(|warp| warp.to_string()))?;
+        }
+        if changed(|a, b| a.neat == b.neat) {
+            table.set("neat", self.neat)?;
+        }
+        if changed(|a, b| a.ram == b.ram) {
+            let ram = lua.create_table()?;
+            for (name, bytes) in self.ram.iter() {
+                ram.set(name.as_str(), bytes_value(lua, bytes)?)?;
+            }
+            table.set("ram", ram)?;
+        }
+        for (key, personality, changed) in [
+            (
+                "personality",
+                &self.personality,
+                changed(|a, b| a.personality == b.personality),
+            ),
+            (
+                "effective",
+                &self.effective,
+                changed(|a, b| a.effective == b.effective),
+            ),
+        ] {
+            if !changed {
+                continue;
+            }
+            let table: Table = table.get(key)?;
+            table.set("patient", personality.patient)?;
+            table.set("bold", personality.bold)?;
+            table.set("playful", personality.playful)?;
+            table.set("twitchy", personality.twitchy)?;
+            table.set("jumpy", personality.jumpy)?;
+        }
+        Ok(())
+    }
+}
+
+/// Watched bytes of RAM as scripts see them: a single byte is a number, more
+/// are a sequence of them.
+fn bytes_value<'lua>(lua: &'lua mlua::Lua, bytes: &[u8]) -> mlua::Result<Value<'lua>> {
+    match bytes {
+        &[byte] => byte.to_lua(lua),
+        _ => lua.create_sequence_from(bytes.iter().copied())?.to_lua(lua),
+    }
+}
+
+/// Set in the Lua state of a script that reads the Marios only through
+/// [`MariosView`], so the `marios` value isn't kept up to date for it.
+pub struct ViewOnly;
+
+/// The readings of the last frame, read by scripts through the `marios_view`
+/// global one field at a time instead of copied into the `marios` value.
+pub struct MariosView(pub Arc<Mutex<Vec<Reading>>>);
+
+impl MariosView {
+    /// Applies `f` to the reading of `instance`, if there is one yet.
+    fn read<T>(&self, instance: usize, f: impl FnOnce(&Reading) -> T) -> mlua::Result<Option<T>> {
+        check_instance(instance)?;
+        let readings = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(readings.get(instance - 1).map(f))
+    }
+}
+
+/// A trait of `personality` by the name scripts know it by.
+fn personality_trait(personality: &Personality, name: &str) -> Option<f64> {
+    match name {
+        "patient" => Some(personality.patient.into()),
+        "bold" => Some(personality.bold.into()),
+        "playful" => Some(personality.playful.into()),
+        "twitchy" => Some(personality.twitchy.into()),
+        "jumpy" => Some(personality.jumpy.into()),
+        _ => None,
+    }
+}
+
+impl UserData for MariosView {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Len, |_, _, ()| Ok(instances()));
+        methods.add_method("fitness", |_, view, instance: usize| {
+            view.read(instance, |reading| reading.fitness)
+        });
+        methods.add_method("name", |_, _, instance: usize| {
+            check_instance(instance)?;
+            Ok(format!("Mario {}", instance))
+        });
+        methods.add_method("stat", |lua, view, (instance, key): (usize, String)| {
+            let stat = view.read(instance, |reading| -> mlua::Result<Value> {
+                let (personality, name) = match key.split_once('.') {
+                    Some(("effective", name)) => (&reading.effective, name),
+                    _ => (&reading.personality, key.as_str()),
+                };
+                Ok(match key.as_str() {
+                    "fitness" => reading.fitness.to_lua(lua)?,
+                    "x" => reading.position.x.to_lua(lua)?,
+                    "y" => reading.position.y.to_lua(lua)?,
+                    "powerup" => reading.powerup.name().to_lua(lua)?,
+                    "lives" => reading.lives.to_lua(lua)?,
+                    "errored" => reading.errored.clone().to_lua(lua)?,
+                    "start" => reading.start.map(|warp| warp.to_string()).to_lua(lua)?,
+                    "neat" => reading.neat.to_lua(lua)?,
+                    key if key.starts_with("ram.") => {
+                        let name = &key["ram.".len()..];
+                        match reading.ram.iter().find(|(watched, _)| watched == name) {
+                            Some((_, bytes)) => bytes_value(lua, bytes)?,
+                            None => Value::Nil,
+                        }
+                    }
+                    _ => match personality_trait(personality, name) {
+                        Some(value) => value.to_lua(lua)?,
+                        None => {
+                            return Err(mlua::Error::RuntimeError(format!(
+                                "unknown stat {:?}, expected fitness, x, y, powerup, lives, \
+                                 errored, start, neat, a watch like ram.speed or a trait like \
+                                 patient or effective.patient",
+                                key
+                            )))
+                        }
+                    },
+                })
+            })?;
+            stat.unwrap_or(Ok(Value::Nil))
+        });
+        methods.add_method("exclusive", |lua, _, ()| {
+            lua.set_app_data(ViewOnly);
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,911 @@
+//! What scripts get to see besides the Marios themselves: the state every
+//! scene shares, and the values and globals every script starts with.
+
+use std::{
+    fs::read,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use mlua::{FromLua, FromLuaMulti, Table, ToLua, UserData, UserDataMethods, Value};
+
+use crate::{
+    captions::Captions,
+    diversity::Diversity,
+    error::{self, Error},
+    history::FitnessHistory,
+    layout::{Cell, Glide, Grid},
+    levelmap::{self, LevelMaps},
+    logbook::Logbook,
+    luanim::{script_time, Backend, EnvValue, Options},
+    mario::Personality,
+    neat::Pool,
+    poke::{Allowlist, Poke},
+    population::Stats,
+    prediction::Predictions,
+    ramwatch::{self, Watch, Watches},
+    rewind::Viewer,
+    scene::SceneSwitch,
+    smb::Powerup,
+    teams::{Scoreboard, Team},
+    ticker::Ticker,
+    timers::Timers,
+    timings::{self, Phase, Timings},
+    vote::{Trait, Vote},
+    widgets,
+    worldmap::WorldMap,
+};
+
+use super::{
+    frames::Frames,
+    readings::{MariosView, Reading},
+    HEIGHT, WIDTH,
+};
+
+/// Most Marios in the show, as many as fit in the [`Atlas`](super::atlas::Atlas).
+pub const MAX_INSTANCES: usize = 256;
+
+/// Marios in the show, set once it is known.
+pub static INSTANCES: OnceLock<usize> = OnceLock::new();
+
+/// Fitness values kept per instance for the sparkline widget.
+const HISTORY: usize = 120;
+
+/// Simulated frames between two fitness values in the history.
+const HISTORY_INTERVAL: u32 = 60;
+
+/// Script units a ticker scrolls per second, unless the script says otherwise.
+const TICKER_SPEED: f32 = 64.0;
+
+/// How long a ticker message waits to be shown before it is dropped, unless
+/// the script says otherwise.
+const TICKER_LIFETIME: Duration = Duration::from_secs(120);
+
+/// Seconds a screen takes to glide to its new place in a layout.
+const GLIDE_DURATION: f32 = 0.5;
+
+/// Marios in the show, or as many as there can be while that isn't known.
+pub fn instances() -> usize {
+    INSTANCES.get().copied().unwrap_or(MAX_INSTANCES)
+}
+
+/// Fails if the script declared the instances it draws and `instance` isn't
+/// one of them.
+pub fn check_visible(lua: &mlua::Lua, instance: usize) -> mlua::Result<()> {
+    match lua.app_data_ref::<Visible>() {
+        Some(visible) if !visible.0[instance - 1] => Err(mlua::Error::RuntimeError(format!(
+            "instance {} is not visible, add it with visible()",
+            instance
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Arguments of `nes_frame`: x, y, scale, instance, scaling and crop.
+pub type NesFrameArgs = (
+    f32,
+    f32,
+    f32,
+    usize,
+    Option<String>,
+    Option<usize>,
+    Option<usize>,
+);
+
+/// Arguments of `nes_sprites`: x, y, scale, instance, offset, opacity, scaling
+/// and crop.
+pub type NesSpritesArgs = (
+    f32,
+    f32,
+    f32,
+    usize,
+    f32,
+    f32,
+    f32,
+    Option<String>,
+    Option<usize>,
+    Option<usize>,
+);
+
+/// Arguments of `nes_thumbnail`: x, y, scale, instance, opacity and crop.
+pub type NesThumbnailArgs = (
+    f32,
+    f32,
+    f32,
+    usize,
+    Option<f32>,
+    Option<usize>,
+    Option<usize>,
+);
+
+/// Arguments of `nes_compare`: x, y, scale, the two instances, the gap
+/// between them, scaling and crop.
+pub type NesCompareArgs = (
+    f32,
+    f32,
+    f32,
+    usize,
+    usize,
+    Option<f32>,
+    Option<String>,
+    Option<usize>,
+    Option<usize>,
+);
+
+/// Arguments of `ticker`: x, y, width, size, speed and font.
+type TickerArgs = (f32, f32, f32, f32, Option<f32>, Option<String>);
+
+/// Arguments of `ram_watch`: x, y, size, instance and font.
+type RamWatchArgs = (f32, f32, f32, usize, Option<String>);
+
+/// `seconds` as a `Duration`, if it is a number of seconds one can hold.
+fn check_seconds(seconds: f64) -> mlua::Result<Duration> {
+    Duration::try_from_secs_f64(seconds).map_err(|_| {
+        mlua::Error::RuntimeError(format!("expected a number of seconds, got {}", seconds))
+    })
+}
+
+pub fn check_instance(instance: usize) -> mlua::Result<()> {
+    if (1..=instances()).contains(&instance) {
+        Ok(())
+    } else {
+        Err(mlua::Error::RuntimeError(format!(
+            "instance {} out of range 1..={}",
+            instance,
+            instances()
+        )))
+    }
+}
+
+/// Cells for the `grid_layout` global, from a table of options that all have
+/// defaults: a cell for every instance on the whole canvas, shaped like a frame.
+fn grid_layout<'lua>(
+    lua: &'lua mlua::Lua,
+    options: Option<Table<'lua>>,
+) -> mlua::Result<Table<'lua>> {
+    let options = match options {
+        Some(options) => options,
+        None => lua.create_table()?,
+    };
+    // the canvas is always 512 units wide
+    let width = options.get::<_, Option<f32>>("width")?.unwrap_or(512.0);
+    let count = options
+        .get::<_, Option<usize>>("count")?
+        .unwrap_or_else(instances);
+    let featured = options.get::<_, Option<usize>>("featured")?;
+    if let Some(featured) = featured.filter(|featured| !(1..=count).contains(featured)) {
+        return Err(mlua::Error::RuntimeError(format!(
+            "featured cell {} out of range 1..={}",
+            featured, count
+        )));
+    }
+    let grid = Grid {
+        count,
+        width,
+        height: options
+            .get::<_, Option<f32>>("height")?
+            .unwrap_or(width * HEIGHT as f32 / WIDTH as f32),
+        aspect: options
+            .get::<_, Option<f32>>("aspect")?
+            .unwrap_or(256.0 / 240.0),
+        margin: options.get::<_, Option<f32>>("margin")?.unwrap_or(0.0),
+        gap: options.get::<_, Option<f32>>("gap")?.unwrap_or(0.0),
+        featured: featured.map(|featured| featured - 1),
+        featured_share: options
+            .get::<_, Option<f32>>("featured_share")?
+            .unwrap_or(0.5),
+    };
+    if grid.aspect <= 0.0 {
+        return Err(mlua::Error::RuntimeError(
+            "aspect of cells has to be positive".to_owned(),
+        ));
+    }
+    let cells = grid.cells().iter().map(|cell| cell_table(lua, cell));
+    lua.create_sequence_from(cells.collect::<mlua::Result<Vec<_>>>()?)
+}
+
+/// A cell of a layout as scripts see it.
+fn cell_table<'lua>(lua: &'lua mlua::Lua, cell: &Cell) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set("x", cell.x)?;
+    table.set("y", cell.y)?;
+    table.set("width", cell.width)?;
+    table.set("height", cell.height)?;
+    // the size of a frame that fills the cell, 256 / 3.75 units wide at 1
+    table.set("scale", cell.width / (256.0 / 3.75))?;
+    Ok(table)
+}
+
+/// A cell of a layout from a script, as [`cell_table`] makes them.
+fn table_cell(table: &Table) -> mlua::Result<Cell> {
+    Ok(Cell {
+        x: table.get("x")?,
+        y: table.get("y")?,
+        width: table.get("width")?,
+        height: table.get("height")?,
+    })
+}
+
+/// Set in the Lua state of a script that declared the instances it draws
+/// with `visible()`, by zero-based instance. Only those can be drawn, and the
+/// rest are simulated as off-screen.
+pub struct Visible(pub Vec<bool>);
+
+/// A [`Glide`] a script made with the `glide` global, moving the cells of
+/// instances as the script's time goes on.
+struct GlideView(Glide);
+
+impl UserData for GlideView {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("move", |lua, glide, targets: Table| {
+            let cells = targets
+                .pairs::<usize, Table>()
+                .map(|pair| {
+                    let (instance, cell) = pair?;
+                    Ok((instance, table_cell(&cell)?))
+                })
+                .collect::<mlua::Result<Vec<_>>>()?;
+            glide.0.retarget(script_time(lua), cells);
+            Ok(())
+        });
+        methods.add_method("cell", |lua, glide, instance: usize| {
+            match glide.0.get(script_time(lua), instance) {
+                Some(cell) => Ok(Some(cell_table(lua, &cell)?)),
+                None => Ok(None),
+            }
+        });
+    }
+}
+
+/// A font file read into memory, to be added to every new canvas.
+pub struct Font {
+    pub name: String,
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+}
+
+pub fn load_fonts(fonts: &[(String, PathBuf)]) -> error::Result<Vec<Font>> {
+    fonts
+        .iter()
+        .map(|(name, path)| {
+            let data = read(path).map_err(|e| Error::Font {
+                path: path.clone(),
+                source: femtovg::ErrorKind::IoError(e),
+            })?;
+            Ok(Font {
+                name: name.clone(),
+                path: path.clone(),
+                data,
+            })
+        })
+        .collect()
+}
+
+/// What scripts get to see besides the Marios themselves, shared by every
+/// scene.
+#[derive(Clone)]
+pub struct ScriptState {
+    pub env: Vec<(String, EnvValue)>,
+    pub fonts: Arc<Vec<Font>>,
+    pub history: Arc<Mutex<FitnessHistory>>,
+    pub stats: Arc<Mutex<Stats>>,
+    pub predictions: Arc<Mutex<Predictions>>,
+    pub diversity: Arc<Mutex<Diversity>>,
+    /// How both teams are doing, when the Marios are split into teams.
+    pub scoreboard: Arc<Mutex<Option<Scoreboard>>>,
+    /// The networks Marios play with, when any do.
+    pub neat: Arc<OnceLock<Arc<Mutex<Pool>>>>,
+    /// The vote on a Mario's personality that is open, if any.
+    pub vote: Arc<Mutex<Option<Vote>>>,
+    /// Countdowns started from the command line or by scripts.
+    pub timers: Arc<Mutex<Timers>>,
+    /// Messages scrolling by on the ticker of every scene.
+    pub ticker: Arc<Mutex<Ticker>>,
+    /// Captions from --captions, and when they started.
+    pub captions: Arc<Mutex<(Captions, Instant)>>,
+    /// Addresses of RAM watched from the command line or by scripts.
+    pub ram_watch: Arc<Mutex<Watches>>,
+    /// Addresses of RAM scripts may write to, from --allow-write.
+    pub writable: Arc<Allowlist>,
+    /// Writes to RAM by scripts since the last frame, for the scenes to hear
+    /// of.
+    pub pokes: Arc<Mutex<Vec<Poke>>>,
+    /// The Mario whose states are looked back through, and which one is
+    /// shown, if any.
+    pub rewind: Arc<Mutex<Option<Viewer>>>,
+    /// The warnings and errors logged lately.
+    pub logbook: Arc<Mutex<Logbook>>,
+    /// Set to hold the simulation where it is.
+    pub paused: Arc<AtomicBool>,
+    pub switch: SceneSwitch,
+    /// What every Mario was like on the last frame.
+    pub readings: Arc<Mutex<Vec<Reading>>>,
+    /// Whether scripts keep track of where they spend their time.
+    pub profile: bool,
+    /// Time every phase of drawing a frame takes.
+    pub timings: Arc<Mutex<Timings>>,
+    /// Time spent drawing NES frames and uploading them as images since the
+    /// last frame was drawn.
+    pub uploads: Arc<Mutex<Duration>>,
+    /// NES frames drawn ahead of time, for the render loop to upload.
+    pub frames: Arc<Frames>,
+    /// Maps of the areas the Mario furthest ahead went through.
+    pub level_maps: Arc<Mutex<LevelMaps>>,
+    /// Which level every Mario is in, and the best times through them.
+    pub world_map: Arc<Mutex<WorldMap>>,
+}
+
+impl ScriptState {
+    pub fn new(env: Vec<(String, EnvValue)>, fonts: Vec<Font>) -> ScriptState {
+        ScriptState {
+            env,
+            fonts: Arc::new(fonts),
+            history: Arc::new(Mutex::new(FitnessHistory::new(
+                instances(),
+                HISTORY,
+                HISTORY_INTERVAL,
+            ))),
+            stats: Arc::default(),
+            predictions: Arc::default(),
+            diversity: Arc::new(Mutex::new(Diversity::new(HISTORY))),
+            scoreboard: Arc::default(),
+            neat: Arc::default(),
+            vote: Arc::default(),
+            timers: Arc::default(),
+            ticker: Arc::default(),
+            captions: Arc::new(Mutex::new((Captions::default(), Instant::now()))),
+            ram_watch: Arc::default(),
+            writable: Arc::default(),
+            pokes: Arc::default(),
+            rewind: Arc::default(),
+            logbook: Arc::default(),
+            paused: Arc::default(),
+            switch: SceneSwitch::default(),
+            readings: Arc::default(),
+            profile: false,
+            timings: Arc::default(),
+            uploads: Arc::default(),
+            frames: Arc::new(Frames::new(instances())),
+            level_maps: Arc::default(),
+            world_map: Arc::default(),
+        }
+    }
+}
+
+/// Options shared by every script: startup parameters, the widget
+/// instructions, the `frame` and `marios` values and the `scenes`, `fonts`,
+/// `fitness_history`, `simulation`, `predict`, `predictions`, `vote`, `timers`,
+/// `ticker`, `captions`, `ram_watch` and `marios_view` globals.
+pub fn script_options<B: Backend>(
+    personalities: Vec<Personality>,
+    state: &ScriptState,
+) -> Options<B> {
+    let switch = state.switch.clone();
+    let fonts = state.fonts.clone();
+    let history = state.history.clone();
+    let stats = state.stats.clone();
+    let paused = state.paused.clone();
+    let predictions = state.predictions.clone();
+    let standings = state.predictions.clone();
+    let scoreboard = state.scoreboard.clone();
+    let neat = state.neat.clone();
+    let diversity = state.diversity.clone();
+    let teamed = state.scoreboard.lock().unwrap().is_some();
+    let vote = state.vote.clone();
+    let timers = state.timers.clone();
+    let ticker = state.ticker.clone();
+    let queue = state.ticker.clone();
+    let captions = state.captions.clone();
+    let readings = state.readings.clone();
+    let watched = state.readings.clone();
+    let watching = state.ram_watch.clone();
+    let logbook = state.logbook.clone();
+    let frame_timings = state.timings.clone();
+    let level_maps = state.level_maps.clone();
+    let world_map = state.world_map.clone();
+    let options = state
+        .env
+        .iter()
+        .fold(Options::new(), |options, (key, value)| {
+            options.env(key.clone(), value.clone())
+        })
+        .profile(state.profile);
+    let options = widgets::register_diversity(options, diversity.clone());
+    widgets::register(options, personalities.clone(), history.clone())
+        .instruction("ticker", move |lua, args, screen| {
+            let (x, y, width, size, speed, font): TickerArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            if size <= 0.0 {
+                return Err(mlua::Error::RuntimeError(
+                    "size of the ticker has to be positive".to_owned(),
+                ));
+            }
+            let font = font.as_deref();
+            let mut ticker = ticker.lock().unwrap_or_else(PoisonError::into_inner);
+            let speed = speed.unwrap_or(TICKER_SPEED) / size;
+            let measure = |text: &str| -> mlua::Result<f32> {
+                Ok(screen.measure_text(text, 1.0, font)?.width)
+            };
+            ticker.scroll(Instant::now(), width / size, speed, measure)?;
+            for (offset, text) in ticker.visible(width / size) {
+                screen.draw_text(x + offset * size, y, size, text, font)?;
+            }
+            Ok(())
+        })
+        .instruction("ram_watch", move |lua, args, screen| {
+            let (x, y, size, instance, font): RamWatchArgs =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            check_instance(instance)?;
+            let font = font.as_deref();
+            let readings = watched.lock().unwrap_or_else(PoisonError::into_inner);
+            // nothing was read before the first frame
+            let ram = match readings.get(instance - 1) {
+                Some(reading) => &reading.ram,
+                None => return Ok(()),
+            };
+            let mut column: f32 = 0.0;
+            for (name, _) in ram.iter() {
+                column = column.max(screen.measure_text(name, size, font)?.width);
+            }
+            let space = screen.measure_text("  ", size, font)?;
+            for (i, (name, bytes)) in ram.iter().enumerate() {
+                let y = y + space.height * i as f32;
+                screen.draw_text(x, y, size, name, font)?;
+                let value = ramwatch::format(bytes);
+                screen.draw_text(x + column + space.width, y, size, &value, font)?;
+            }
+            Ok(())
+        })
+        .value("frame", |_lua| Ok(Value::Integer(0)))
+        // the open vote, updated every frame
+        .value("vote", |_lua| Ok(Value::Boolean(false)))
+        // seconds left on every countdown by name, updated every frame
+        .value("timers", |lua| lua.create_table().map(Value::Table))
+        .value("marios", move |lua| {
+            let marios_data = lua.create_table()?;
+            let personality_table = |mario: &Personality| -> mlua::Result<Table> {
+                let personality = lua.create_table()?;
+                personality.set("patient", mario.patient)?;
+                personality.set("bold", mario.bold)?;
+                personality.set("playful", mario.playful)?;
+                personality.set("twitchy", mario.twitchy)?;
+                personality.set("jumpy", mario.jumpy)?;
+                Ok(personality)
+            };
+            for (i, mario) in personalities.iter().enumerate() {
+                let data = lua.create_table()?;
+                data.set("personality", personality_table(mario)?)?;
+                // updated every frame with the values annealing changed
+                data.set("effective", personality_table(mario)?)?;
+                data.set("fitness", 0)?;
+                data.set("x", 0)?;
+                data.set("y", 0)?;
+                data.set("powerup", Powerup::Small.name())?;
+                data.set("lives", 0)?;
+                data.set("ram", lua.create_table()?)?;
+                if teamed {
+                    data.set("team", Team::of(i, personalities.len()).name())?;
+                }
+
+                let index = i + 1;
+                marios_data.set(index, data)?;
+            }
+            marios_data.to_lua(lua)
+        })
+        .global("fonts", move |lua| {
+            let names = fonts.iter().map(|font| font.name.as_str());
+            lua.create_sequence_from(names)?.to_lua(lua)
+        })
+        .global("scenes", move |lua| {
+            let scenes = lua.create_table()?;
+            let switch = switch.clone();
+            scenes.set(
+                "switch",
+                lua.create_function(move |_, name: String| {
+                    switch.request(name);
+                    Ok(())
+                })?,
+            )?;
+            scenes.to_lua(lua)
+        })
+        .global("fitness_history", move |lua| {
+            let history = history.clone();
+            let get = lua.create_function(move |lua, instance: usize| {
+                let history = history.lock().unwrap_or_else(PoisonError::into_inner);
+                let values = match instance.checked_sub(1).and_then(|i| history.get(i)) {
+                    Some(values) => Value::Table(lua.create_sequence_from(values.iter().copied())?),
+                    None => Value::Nil,
+                };
+                Ok(values)
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("level_map_info", move |lua| {
+            let level_maps = level_maps.clone();
+            let info = lua.create_function(move |lua, area: Option<u16>| {
+                let maps = level_maps.lock().unwrap_or_else(PoisonError::into_inner);
+                let (area, map) = match area.or(maps.current()) {
+                    Some(area) => match maps.get(area) {
+                        Some(map) => (area, map),
+                        None => return Ok(Value::Nil),
+                    },
+                    None => return Ok(Value::Nil),
+                };
+                let table = lua.create_table()?;
+                table.set("area", area)?;
+                table.set("width", map.width())?;
+                table.set("height", levelmap::HEIGHT)?;
+                table.set("status_bar", levelmap::STATUS_BAR)?;
+                Ok(Value::Table(table))
+            })?;
+            Ok(Value::Function(info))
+        })
+        .global("world_map", move |lua| {
+            let world_map = world_map.clone();
+            let get = lua.create_function(move |lua, ()| {
+                let map = world_map.lock().unwrap_or_else(PoisonError::into_inner);
+                let levels = lua.create_table()?;
+                let counts = map.counts();
+                let best = map.best();
+                for level in counts.keys().chain(best.keys()) {
+                    let table = lua.create_table()?;
+                    table.set("marios", counts.get(level).copied().unwrap_or(0))?;
+                    table.set("best_frames", best.get(level).copied())?;
+                    levels.set(level.to_string(), table)?;
+                }
+                let marios = lua.create_table()?;
+                for i in 0..instances() {
+                    marios.set(i + 1, map.level(i).map(|level| level.to_string()))?;
+                }
+                let table = lua.create_table()?;
+                table.set("levels", levels)?;
+                table.set("instances", marios)?;
+                Ok(Value::Table(table))
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("simulation", move |lua| {
+            let stats = stats.clone();
+            let paused = paused.clone();
+            let frame_timings = frame_timings.clone();
+            let get = lua.create_function(move |lua, ()| {
+                let stats = *stats.lock().unwrap_or_else(PoisonError::into_inner);
+                let table = lua.create_table()?;
+                table.set("rate", stats.rate)?;
+                table.set("missed", stats.missed)?;
+                table.set("behind", stats.behind)?;
+                table.set("slowest", stats.slowest.0 + 1)?;
+                table.set("slowest_ms", stats.slowest.1.as_secs_f64() * 1000.0)?;
+                table.set("crashes", stats.crashes)?;
+                table.set("restarts", stats.restarts)?;
+                table.set("respawns", stats.respawns)?;
+                table.set("cache_hit_rate", stats.cache_hit_rate())?;
+                table.set("stalled", stats.stalled().as_secs_f64())?;
+                table.set("paused", paused.load(Ordering::Relaxed))?;
+                let frame = *frame_timings.lock().unwrap_or_else(PoisonError::into_inner);
+                let phases = lua.create_table()?;
+                for phase in Phase::DRAW {
+                    phases.set(phase.name(), timings::millis(frame.get(phase)))?;
+                }
+                for phase in Phase::TICK {
+                    phases.set(phase.name(), timings::millis(stats.timings.get(phase)))?;
+                }
+                table.set("timings_ms", phases)?;
+                Ok(table)
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("predict", move |lua| {
+            let predictions = predictions.clone();
+            let predict = lua.create_function(move |_, (viewer, instance): (String, usize)| {
+                check_instance(instance)?;
+                predictions
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .predict(&viewer, instance - 1);
+                Ok(())
+            })?;
+            Ok(Value::Function(predict))
+        })
+        .global("predictions", move |lua| {
+            let standings = standings.clone();
+            let get = lua.create_function(move |lua, ()| {
+                let predictions = standings.lock().unwrap_or_else(PoisonError::into_inner);
+                let table = lua.create_table()?;
+                table.set("round", predictions.round())?;
+                table.set("depth", predictions.target())?;
+                table.set("winner", predictions.last_winner().map(|i| i + 1))?;
+                let picks = lua.create_table()?;
+                for (viewer, instance) in predictions.picks() {
+                    picks.set(viewer, instance + 1)?;
+                }
+                table.set("picks", picks)?;
+                let ranking = lua.create_table()?;
+                for (i, (viewer, points)) in predictions.standings().into_iter().enumerate() {
+                    let entry = lua.create_table()?;
+                    entry.set("viewer", viewer)?;
+                    entry.set("points", points)?;
+                    ranking.set(i + 1, entry)?;
+                }
+                table.set("standings", ranking)?;
+                Ok(table)
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("diversity", move |lua| {
+            let diversity = diversity.clone();
+            let get = lua.create_function(move |lua, ()| {
+                let latest = diversity
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .latest();
+                let sample = match latest {
+                    Some(sample) => sample,
+                    None => return Ok(Value::Nil),
+                };
+                let table = lua.create_table()?;
+                table.set("personalities", sample.personalities)?;
+                table.set("inputs", sample.inputs)?;
+                Ok(Value::Table(table))
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("teams", move |lua| {
+            let scoreboard = scoreboard.clone();
+            let get = lua.create_function(move |lua, ()| {
+                let scoreboard = scoreboard.lock().unwrap_or_else(PoisonError::into_inner);
+                let scoreboard = match scoreboard.as_ref() {
+                    Some(scoreboard) => scoreboard,
+                    None => return Ok(Value::Nil),
+                };
+                let table = lua.create_table()?;
+                for team in Team::ALL {
+                    let aggregate = scoreboard.team(team);
+                    let entry = lua.create_table()?;
+                    entry.set("members", aggregate.members)?;
+                    entry.set("best", aggregate.best)?;
+                    entry.set("mean", aggregate.mean())?;
+                    entry.set("deepest", aggregate.deepest)?;
+                    entry.set("clears", aggregate.clears)?;
+                    table.set(team.name(), entry)?;
+                }
+                Ok(Value::Table(table))
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("grid_layout", |lua| {
+            let layout = lua.create_function(grid_layout)?;
+            Ok(Value::Function(layout))
+        })
+        .global("glide", |lua| {
+            let new = lua.create_function(|_, duration: Option<f32>| {
+                Ok(GlideView(Glide::new(duration.unwrap_or(GLIDE_DURATION))))
+            })?;
+            Ok(Value::Function(new))
+        })
+        .global("visible", |lua| {
+            let declare = lua.create_function(|lua, declared: Option<Vec<usize>>| {
+                let declared = match declared {
+                    Some(declared) => declared,
+                    None => {
+                        lua.remove_app_data::<Visible>();
+                        return Ok(());
+                    }
+                };
+                let mut visible = vec![false; instances()];
+                for instance in declared {
+                    check_instance(instance)?;
+                    visible[instance - 1] = true;
+                }
+                lua.set_app_data(Visible(visible));
+                Ok(())
+            })?;
+            Ok(Value::Function(declare))
+        })
+        .global("marios_view", move |lua| {
+            let view = lua.create_userdata(MariosView(readings.clone()))?;
+            Ok(Value::UserData(view))
+        })
+        .global("neat", move |lua| {
+            let neat = neat.clone();
+            let get = lua.create_function(move |lua, ()| {
+                let pool = match neat.get() {
+                    Some(pool) => pool.lock().unwrap_or_else(PoisonError::into_inner),
+                    None => return Ok(Value::Nil),
+                };
+                let table = lua.create_table()?;
+                table.set("generation", pool.generation())?;
+                table.set("species", pool.species())?;
+                table.set("best", pool.best().map(|(_, fitness)| *fitness))?;
+                Ok(Value::Table(table))
+            })?;
+            Ok(Value::Function(get))
+        })
+        .global("vote", move |lua| {
+            let table = lua.create_table()?;
+            let start_vote = vote.clone();
+            table.set(
+                "start",
+                lua.create_function(move |_, (instance, seconds): (usize, f64)| {
+                    check_instance(instance)?;
+                    let duration = check_seconds(seconds)?;
+                    let opened = Vote::new(instance - 1, duration).ok_or_else(|| {
+                        mlua::Error::RuntimeError(format!(
+                            "{} seconds is too long for a vote",
+                            seconds
+                        ))
+                    })?;
+                    *start_vote.lock().unwrap_or_else(PoisonError::into_inner) = Some(opened);
+                    Ok(())
+                })?,
+            )?;
+            let cast_vote = vote.clone();
+            table.set(
+                "cast",
+                lua.create_function(move |_, (viewer, choice): (String, String)| {
+                    let choice: Trait = choice.parse().map_err(mlua::Error::RuntimeError)?;
+                    let mut vote = cast_vote.lock().unwrap_or_else(PoisonError::into_inner);
+                    match vote.as_mut() {
+                        Some(vote) => vote.cast(&viewer, choice),
+                        None => {
+                            return Err(mlua::Error::RuntimeError("no vote is open".to_owned()))
+                        }
+                    }
+                    Ok(())
+                })?,
+            )?;
+            table.to_lua(lua)
+        })
+        .global("ticker", move |lua| {
+            let table = lua.create_table()?;
+            let push = queue.clone();
+            table.set(
+                "push",
+                lua.create_function(move |_, (text, seconds): (String, Option<f64>)| {
+                    let lifetime = match seconds {
+                        Some(seconds) => check_seconds(seconds)?,
+                        None => TICKER_LIFETIME,
+                    };
+                    let mut ticker = push.lock().unwrap_or_else(PoisonError::into_inner);
+                    if !ticker.push(text, lifetime, Instant::now()) {
+                        return Err(mlua::Error::RuntimeError(format!(
+                            "{} seconds is too long for a ticker message",
+                            lifetime.as_secs_f64()
+                        )));
+                    }
+                    Ok(())
+                })?,
+            )?;
+            let clear = queue.clone();
+            table.set(
+                "clear",
+                lua.create_function(move |_, ()| {
+                    clear.lock().unwrap_or_else(PoisonError::into_inner).clear();
+                    Ok(())
+                })?,
+            )?;
+            table.to_lua(lua)
+        })
+        .global("ram_watch", move |lua| {
+            let table = lua.create_table()?;
+            let add = watching.clone();
+            table.set(
+                "add",
+                lua.create_function(
+                    move |lua, (name, target, instance): (String, Value, Option<usize>)| {
+                        if let Some(instance) = instance {
+                            check_instance(instance)?;
+                        }
+                        let instance = instance.map(|instance| instance - 1);
+                        let watch = match target {
+                            Value::String(target) => Watch::new(name, target.to_str()?, instance),
+                            address => Watch::at(name, u16::from_lua(address, lua)?, 1, instance),
+                        }
+                        .map_err(mlua::Error::RuntimeError)?;
+                        add.lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .add(watch);
+                        Ok(())
+                    },
+                )?,
+            )?;
+            let remove = watching.clone();
+            table.set(
+                "remove",
+                lua.create_function(move |_, name: String| {
+                    Ok(remove
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .remove(&name))
+                })?,
+            )?;
+            table.to_lua(lua)
+        })
+        .global("captions", move |lua| {
+            let table = lua.create_table()?;
+            let current = captions.clone();
+            table.set(
+                "current",
+                lua.create_function(move |lua, ()| {
+                    let captions = current.lock().unwrap_or_else(PoisonError::into_inner);
+                    let (captions, started) = &*captions;
+                    let (cue, opacity) = match captions.at(started.elapsed().as_secs_f32()) {
+                        Some(showing) => showing,
+                        None => return Ok(Value::Nil),
+                    };
+                    let table = lua.create_table()?;
+                    table.set("text", cue.text.as_str())?;
+                    table.set("opacity", opacity)?;
+                    table.set("start", cue.start)?;
+                    table.set("finish", cue.end)?;
+                    Ok(Value::Table(table))
+                })?,
+            )?;
+            let restart = captions.clone();
+            table.set(
+                "restart",
+                lua.create_function(move |_, ()| {
+                    restart.lock().unwrap_or_else(PoisonError::into_inner).1 = Instant::now();
+                    Ok(())
+                })?,
+            )?;
+            table.to_lua(lua)
+        })
+        .global("timers", move |lua| {
+            let table = lua.create_table()?;
+            let start_timer = timers.clone();
+            table.set(
+                "start",
+                lua.create_function(move |_, (name, seconds): (String, f64)| {
+                    let duration = check_seconds(seconds)?;
+                    let mut timers = start_timer.lock().unwrap_or_else(PoisonError::into_inner);
+                    if !timers.start(&name, duration, Instant::now()) {
+                        return Err(mlua::Error::RuntimeError(format!(
+                            "{} seconds is too long for a timer",
+                            seconds
+                        )));
+                    }
+                    Ok(())
+                })?,
+            )?;
+            let cancel_timer = timers.clone();
+            table.set(
+                "cancel",
+                lua.create_function(move |_, name: String| {
+                    let mut timers = cancel_timer.lock().unwrap_or_else(PoisonError::into_inner);
+                    Ok(timers.cancel(&name))
+                })?,
+            )?;
+            let read_timer = timers.clone();
+            table.set(
+                "remaining",
+                lua.create_function(move |_, name: String| {
+                    let timers = read_timer.lock().unwrap_or_else(PoisonError::into_inner);
+                    let remaining = timers.remaining(&name, Instant::now());
+                    Ok(remaining.map(|remaining| remaining.as_secs_f64()))
+                })?,
+            )?;
+            table.to_lua(lua)
+        })
+        .global("logbook", move |lua| {
+            let logbook = logbook.clone();
+            let lines = lua.create_function(move |lua, ()| {
+                let logbook = logbook.lock().unwrap_or_else(PoisonError::into_inner);
+                let lines = lua.create_table()?;
+                for (i, line) in logbook.lines().enumerate() {
+                    let table = lua.create_table()?;
+                    table.set("level", line.level.as_str().to_lowercase())?;
+                    table.set("message", line.message.as_str())?;
+                    table.set("repeats", line.repeats)?;
+                    table.set("age", line.at.elapsed().as_secs_f64())?;
+                    lines.set(i + 1, table)?;
+                }
+                Ok(lines)
+            })?;
+            Ok(Value::Function(lines))
+        })
+}
@@ -0,0 +1,415 @@
+//! Running the simulation for the show: the Marios tick on worker threads,
+//! and after every tick what scripts see is kept up to date and the render
+//! loop hears of what happened.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use rand::seq::SliceRandom;
+use tracing::{error, info};
+
+use crate::{
+    affinity::Placement,
+    diversity::{self, RECENT_INPUTS},
+    fitness_log::{FitnessLog, Sample},
+    mario::{self, Mario, Settings},
+    population::{self, Stats, Supervisor},
+    ranking::{RankChange, Ranking},
+    recap::{Progress, Recap},
+    sfx::Sound,
+    smb::{depth, fitness, scroll, title_menu, Fitness, Memory, Warp},
+    stagnation::{Intervention, Stagnation},
+    teams::{Team, Teams},
+    worldmap::Sighting,
+};
+
+use super::{frames, highlight, script::ScriptState};
+
+/// Seconds between two measurements of the diversity of the population.
+const DIVERSITY_INTERVAL: f32 = 60.0;
+
+/// Pixels a Mario has to be further than the one above him on the leaderboard
+/// before he overtakes him.
+const RANK_MARGIN: u32 = 16;
+
+/// Ticks in a row the simulation has to fall behind before --throttle kicks in.
+const THROTTLE_AFTER: u32 = 300;
+
+/// How often input logs are written to disk.
+const INPUT_LOG_FLUSH: Duration = Duration::from_secs(1);
+
+/// How long the simulation can go without a tick before it is reported as
+/// stalled.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the render loop hears of from the simulation running on other
+/// threads.
+pub struct Simulation {
+    /// How far every Mario got this session, for the recap.
+    pub session: Arc<Mutex<Recap>>,
+    pub stagnation: mpsc::Receiver<Intervention>,
+    pub diversity: mpsc::Receiver<(diversity::Sample, Option<Intervention>)>,
+    /// The Mario predicted to win, counting from 1.
+    pub predictions: mpsc::Receiver<usize>,
+    pub ranks: mpsc::Receiver<RankChange>,
+    pub sounds: mpsc::Receiver<Sound>,
+    /// Marios that cleared a level, counting from 1.
+    pub victories: mpsc::Receiver<usize>,
+    /// Whether the simulation stalled, whenever it stalls or recovers.
+    pub stalls: mpsc::Receiver<bool>,
+}
+
+/// What is done after every tick besides keeping what scripts see up to
+/// date.
+pub struct Options {
+    /// The file to log the fitness of every Mario to, and how often.
+    pub fitness_log: Option<(PathBuf, Duration)>,
+    /// Whether Marios plan less far ahead while the simulation falls behind.
+    pub throttle: bool,
+    /// Seconds without any Mario getting further before intervening.
+    pub stagnation: Option<f32>,
+    /// How alike the personalities may get before intervening.
+    pub diversity_floor: Option<f32>,
+    pub intervention: Intervention,
+    /// The directory level clears are encoded to.
+    pub highlights: Option<PathBuf>,
+}
+
+/// Starts simulating `marios` on `threads` worker threads, with a watchdog
+/// reporting when it stalls and a thread drawing their frames ahead.
+/// After every tick it keeps what scripts see up to date, logs the fitness if
+/// asked to, and steps in when the Marios stagnate.
+pub fn start(
+    marios: &[Arc<Mutex<Mario>>],
+    settings: Settings,
+    threads: usize,
+    workers: Placement,
+    teams: Option<Teams>,
+    state: &ScriptState,
+    options: Options,
+) -> anyhow::Result<Simulation> {
+    let mut fitness_log = match &options.fitness_log {
+        Some((path, interval)) => Some(
+            FitnessLog::open(path, *interval)
+                .with_context(|| format!("could not open fitness log {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let region = settings.region;
+
+    let mut stagnation = options
+        .stagnation
+        .map(|seconds| Stagnation::new(region.frames(seconds)));
+    let intervention = options.intervention;
+    let (tx_stagnation, rx_stagnation) = mpsc::channel();
+
+    let throttle = options.throttle;
+
+    let sim_marios = marios.to_vec();
+    let sim_history = state.history.clone();
+    let sim_stats = state.stats.clone();
+    let sim_paused = state.paused.clone();
+    let sim_predictions = state.predictions.clone();
+    let sim_scoreboard = state.scoreboard.clone();
+    let sim_diversity = state.diversity.clone();
+    let sim_world_map = state.world_map.clone();
+    let diversity_floor = options.diversity_floor;
+    let diversity_interval = region.frames(DIVERSITY_INTERVAL);
+    let mut diversity_ticks = 0;
+    let (tx_diversity, rx_diversity) = mpsc::channel();
+    let (tx_prediction, rx_prediction) = mpsc::channel();
+    let mut ranking = Ranking::new(RANK_MARGIN);
+    let (tx_rank, rx_rank) = mpsc::channel();
+    let (tx_sound, rx_sound) = mpsc::channel();
+    let mut dying = vec![false; marios.len()];
+    let mut record = None;
+    let session = Arc::new(Mutex::new(Recap::new(marios.len())));
+    let mut logs_flushed = Instant::now();
+    let (tx_victory, rx_victory) = mpsc::channel();
+    let tx_highlight = options.highlights.map(|dir| {
+        let (tx, rx) = mpsc::channel();
+        let fps = region.fps();
+        thread::spawn(move || highlight::encode_all(&dir, fps, rx));
+        tx
+    });
+    let (tx_frames, rx_frames) = mpsc::channel();
+    let frames_marios = marios.to_vec();
+    let frames = state.frames.clone();
+    let level_maps = state.level_maps.clone();
+    thread::spawn(move || frames::prepare_all(&frames, &frames_marios, &level_maps, rx_frames));
+    let sim_session = session.clone();
+    let mut supervisor = Supervisor::new(
+        marios.to_vec(),
+        threads,
+        workers,
+        settings,
+        sim_paused,
+        move |stats| {
+            *sim_stats.lock().unwrap_or_else(PoisonError::into_inner) = *stats;
+            // the worker only stops by panicking, frames are drawn on demand then
+            let _ = tx_frames.send(());
+            if throttle && stats.behind > 0 && stats.behind % THROTTLE_AFTER == 0 {
+                population::throttle(&sim_marios);
+            }
+
+            if let Some(stagnation) = stagnation.as_mut() {
+                let best = sim_marios
+                    .iter()
+                    .map(|mario| scroll(mario::lock(mario).nes_mut()))
+                    .max()
+                    .unwrap_or(0);
+                if stagnation.update(best) {
+                    population::intervene(&sim_marios, intervention);
+                    if let Some(teams) = &teams {
+                        shape_teams(&sim_marios, teams);
+                    }
+                    // the window is gone once the event loop exits
+                    let _ = tx_stagnation.send(intervention);
+                }
+            }
+
+            diversity_ticks += 1;
+            if diversity_ticks >= diversity_interval {
+                diversity_ticks = 0;
+                let sample = measure_diversity(&sim_marios);
+                info!(
+                    personalities = sample.personalities,
+                    inputs = sample.inputs,
+                    "population diversity"
+                );
+                sim_diversity
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(sample);
+                let intervened = match diversity_floor {
+                    Some(floor) if sample.personalities < floor => {
+                        population::intervene(&sim_marios, intervention);
+                        if let Some(teams) = &teams {
+                            shape_teams(&sim_marios, teams);
+                        }
+                        Some(intervention)
+                    }
+                    _ => None,
+                };
+                let _ = tx_diversity.send((sample, intervened));
+            }
+
+            let mut history = sim_history.lock().unwrap_or_else(PoisonError::into_inner);
+            if history.due() {
+                history.push(
+                    sim_marios
+                        .iter()
+                        .map(|mario| scroll(mario::lock(mario).nes_mut())),
+                );
+            }
+            drop(history);
+
+            let flush_logs = logs_flushed.elapsed() >= INPUT_LOG_FLUSH;
+            if flush_logs {
+                logs_flushed = Instant::now();
+            }
+            let mut progress = Vec::with_capacity(sim_marios.len());
+            let mut sightings = Vec::with_capacity(sim_marios.len());
+            for (i, mario) in sim_marios.iter().enumerate() {
+                let mut mario = mario::lock(mario);
+                if flush_logs {
+                    mario.flush_log();
+                }
+                if let Some(run) = mario.cleared.take() {
+                    if let Some(scoreboard) = sim_scoreboard.lock().unwrap().as_mut() {
+                        scoreboard.cleared(i, sim_marios.len());
+                    }
+                    let _ = tx_victory.send(i + 1);
+                    if let Some(tx) = &tx_highlight {
+                        let _ = tx.send((i + 1, run));
+                    }
+                }
+                let nes = mario.nes_mut();
+                progress.push(Progress {
+                    position: scroll(nes),
+                    depth: depth(nes),
+                    dying: matches!(fitness(nes), Fitness::Dying(_)),
+                });
+                sightings.push(Sighting {
+                    level: (!title_menu(nes)).then(|| Warp::of(nes)),
+                    lives: nes.lives(),
+                    frame: nes.frame_number() as u64,
+                });
+            }
+            sim_world_map
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .update(&sightings);
+            for (was, progress) in dying.iter_mut().zip(&progress) {
+                if progress.dying && !*was {
+                    let _ = tx_sound.send(Sound::Death);
+                }
+                *was = progress.dying;
+            }
+            let depths: Vec<u32> = progress.iter().map(|progress| progress.depth).collect();
+            let deepest = depths.iter().copied().max().unwrap_or(0);
+            // the first tick only sets the record
+            if record.is_some_and(|record| deepest > record) {
+                let _ = tx_sound.send(Sound::Record);
+            }
+            record = record.max(Some(deepest));
+            let mut predictions = sim_predictions
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            if let Some(winner) = predictions.update(&depths) {
+                let _ = tx_prediction.send(winner + 1);
+            }
+            drop(predictions);
+            let scrolls: Vec<u32> = progress.iter().map(|progress| progress.position).collect();
+            for change in ranking.update(&scrolls) {
+                let _ = tx_rank.send(change);
+            }
+            if let Some(scoreboard) = sim_scoreboard.lock().unwrap().as_mut() {
+                let positions: Vec<(u32, u32)> = progress
+                    .iter()
+                    .map(|progress| (progress.position, progress.depth))
+                    .collect();
+                scoreboard.update(&positions);
+            }
+            sim_session
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .update(progress);
+
+            if let Some(log) = fitness_log.as_mut().filter(|log| log.due()) {
+                let samples = sim_marios.iter().enumerate().filter_map(|(i, mario)| {
+                    let mut mario = mario::lock(mario);
+                    if mario.errored.is_some() {
+                        return None;
+                    }
+                    let nes = mario.nes_mut();
+                    Some(Sample {
+                        instance: i + 1,
+                        frame: nes.frame_number() as u64,
+                        state: fitness(nes).label(),
+                        position: scroll(nes),
+                    })
+                });
+                if let Err(e) = log.write(samples) {
+                    error!("fitness log error: {}", e);
+                }
+            }
+        },
+    );
+    supervisor.start(Stats::default());
+
+    let watchdog_stats = state.stats.clone();
+    let watchdog_paused = state.paused.clone();
+    let (tx_stalled, rx_stalled) = mpsc::channel();
+    thread::spawn(move || {
+        watchdog(
+            &watchdog_stats,
+            &watchdog_paused,
+            &mut supervisor,
+            tx_stalled,
+        )
+    });
+
+    Ok(Simulation {
+        session,
+        stagnation: rx_stagnation,
+        diversity: rx_diversity,
+        predictions: rx_prediction,
+        ranks: rx_rank,
+        sounds: rx_sound,
+        victories: rx_victory,
+        stalls: rx_stalled,
+    })
+}
+
+/// Logs when the simulation stops ticking, and when it picks up again, and
+/// sends whether it is stalled to `tx` whenever that changes. A paused
+/// simulation doesn't count as stalled. If the thread running it died,
+/// `supervisor` starts it over from wherever the Marios got to.
+fn watchdog(
+    stats: &Mutex<Stats>,
+    paused: &AtomicBool,
+    supervisor: &mut Supervisor,
+    tx: mpsc::Sender<bool>,
+) -> ! {
+    let mut stalled = false;
+    let mut was_paused = false;
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        // give the first tick after a pause time to happen
+        let is_paused = paused.load(Ordering::Relaxed);
+        if std::mem::replace(&mut was_paused, is_paused) || is_paused {
+            continue;
+        }
+
+        let mut stats = stats.lock().unwrap_or_else(PoisonError::into_inner);
+        if supervisor.died() {
+            error!(
+                ticks = stats.ticks,
+                "simulation thread died, starting it over"
+            );
+            stats.respawns += 1;
+            supervisor.start(*stats);
+        }
+        let since = stats.stalled();
+        if since > STALL_TIMEOUT && !stalled {
+            error!(?since, ticks = stats.ticks, "simulation stalled");
+            stalled = true;
+            let _ = tx.send(stalled);
+        } else if since <= STALL_TIMEOUT && stalled {
+            info!(ticks = stats.ticks, "simulation recovered");
+            stalled = false;
+            let _ = tx.send(stalled);
+        }
+    }
+}
+
+/// Has the Marios given in `starts`, and `random` others, start in another
+/// level than 1-1.
+pub fn start_warped(marios: &[Arc<Mutex<Mario>>], starts: &[(usize, Warp)], random: usize) {
+    let mut rng = rand::thread_rng();
+    let mut rest: Vec<usize> = (1..=marios.len())
+        .filter(|instance| starts.iter().all(|(given, _)| given != instance))
+        .collect();
+    rest.shuffle(&mut rng);
+    let random = rest
+        .into_iter()
+        .take(random)
+        .map(|instance| (instance, Warp::random(&mut rng)));
+
+    for (instance, warp) in starts.iter().copied().chain(random) {
+        info!(instance, level = %warp, "starting in another level");
+        mario::lock(&marios[instance - 1]).warp = Some(warp);
+    }
+}
+
+/// Gives every Mario what sets its team apart.
+pub fn shape_teams(marios: &[Arc<Mutex<Mario>>], teams: &Teams) {
+    for (i, mario) in marios.iter().enumerate() {
+        teams.shape(
+            Team::of(i, marios.len()),
+            &mut mario::lock(mario).personality,
+        );
+    }
+}
+
+/// How alike the personalities and recent inputs of `marios` are.
+fn measure_diversity(marios: &[Arc<Mutex<Mario>>]) -> diversity::Sample {
+    let (personalities, recent): (Vec<_>, Vec<_>) = marios
+        .iter()
+        .map(|mario| {
+            let mario = mario::lock(mario);
+            let from = mario.played.len().saturating_sub(RECENT_INPUTS);
+            (mario.personality.clone(), mario.played[from..].to_vec())
+        })
+        .unzip();
+    diversity::Sample::of(&personalities, recent.iter().map(Vec::as_slice))
+}
@@ -0,0 +1,199 @@
+//! Marios that play with a small neural network instead of planning ahead,
+//! evolved with [NEAT](crate::neat) in a pool the Marios share.
+//!
+//! Every genome gets one attempt at the level: from where the attempt starts
+//! until Mario dies, stops getting further or clears the level. Its fitness is
+//! how far it got.
+
+use std::sync::{Arc, Mutex};
+
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+use rand::RngCore;
+use tracing::info;
+
+use crate::{
+    controller::{Controller, InputPlan, Turn},
+    neat::{Network, Pool, Ticket},
+    observation::{Observation, FEATURES},
+    smb::{depth, fitness, scroll, Fitness},
+};
+
+/// The network sees an [`Observation`] of the game.
+pub const INPUTS: usize = FEATURES;
+/// Right, left, A and B, pressed when their output is positive.
+const BUTTONS: [u8; 4] = [0b1000_0000, 0b0100_0000, 0b0000_0001, 0b0000_0010];
+pub const OUTPUTS: usize = BUTTONS.len();
+
+/// Frames an attempt gets to get further before it is over.
+const PATIENCE: u32 = 150;
+/// Fitness for clearing the level, on top of the distance.
+const CLEAR_BONUS: f64 = 4096.0;
+
+/// The input the outputs of a network press.
+pub fn buttons(outputs: &[f32]) -> u8 {
+    BUTTONS
+        .iter()
+        .zip(outputs)
+        .filter(|&(_, &output)| output > 0.0)
+        .fold(0, |input, (button, _)| input | button)
+}
+
+/// Where the attempts of a brain start.
+struct Start {
+    nes: NES<NROM, FastPPU>,
+    depth: u32,
+    scroll: u32,
+}
+
+/// The network a Mario plays with, and how its attempt is going.
+pub struct Brain {
+    pool: Arc<Mutex<Pool>>,
+    /// The genome being evaluated, or none when playing the best one while
+    /// the rest of a generation is.
+    ticket: Option<Ticket>,
+    network: Network,
+    start: Option<Start>,
+    /// Furthest [`scroll`] of the attempt so far.
+    best: u32,
+    stalled: u32,
+}
+
+impl Brain {
+    pub fn new(pool: Arc<Mutex<Pool>>) -> Brain {
+        let (ticket, genome) = pool.lock().unwrap().take();
+        Brain {
+            network: Network::new(&genome),
+            pool,
+            ticket,
+            start: None,
+            best: 0,
+            stalled: 0,
+        }
+    }
+
+    /// The input for the next frame, after putting Mario back at the start
+    /// once the attempt is over.
+    fn step(&mut self, turn: &mut Turn, rng: &mut dyn RngCore) -> u8 {
+        let (start_depth, start_scroll) = match &self.start {
+            Some(start) => (start.depth, start.scroll),
+            None => {
+                if !matches!(fitness(turn.nes), Fitness::Level(..)) {
+                    return 0;
+                }
+                let start = Start {
+                    depth: depth(turn.nes),
+                    scroll: scroll(turn.nes),
+                    nes: turn.nes.clone(),
+                };
+                self.best = start.scroll;
+                self.stalled = 0;
+                let start = self.start.insert(start);
+                (start.depth, start.scroll)
+            }
+        };
+
+        let cleared = depth(turn.nes) != start_depth;
+        match fitness(turn.nes) {
+            Fitness::Dying(_) => {}
+            _ if cleared => {}
+            Fitness::Level(..) => {
+                let position = scroll(turn.nes);
+                if position > self.best {
+                    self.best = position;
+                    self.stalled = 0;
+                } else {
+                    self.stalled += 1;
+                }
+                if self.stalled < PATIENCE {
+                    return self.press(&turn.observation);
+                }
+            }
+            // let cutscenes play out
+            _ => return 0,
+        }
+
+        let mut score = f64::from(self.best.saturating_sub(start_scroll));
+        if cleared {
+            score += CLEAR_BONUS;
+        }
+        self.next_genome(score, rng);
+        self.best = start_scroll;
+        self.stalled = 0;
+        match &self.start {
+            Some(start) if !cleared => {
+                *turn.nes = start.nes.clone();
+                self.press(&Observation::read(turn.nes))
+            }
+            _ => {
+                // the next attempt starts in the new level
+                self.start = None;
+                0
+            }
+        }
+    }
+
+    fn press(&mut self, observation: &Observation) -> u8 {
+        buttons(&self.network.activate(&observation.vector()))
+    }
+
+    /// Reports how the current genome did and takes the next one.
+    fn next_genome(&mut self, score: f64, mut rng: &mut dyn RngCore) {
+        let mut pool = self.pool.lock().unwrap();
+        if let Some(ticket) = self.ticket.take() {
+            if pool.report(ticket, score, &mut rng) {
+                info!(
+                    generation = pool.generation(),
+                    species = pool.species(),
+                    best = pool.best().map(|(_, fitness)| *fitness),
+                    "bred a new generation"
+                );
+            }
+        }
+        let (ticket, genome) = pool.take();
+        self.ticket = ticket;
+        self.network = Network::new(&genome);
+    }
+}
+
+impl Controller for Brain {
+    fn decide(&mut self, turn: &mut Turn, rng: &mut dyn RngCore) -> InputPlan {
+        InputPlan::from([self.step(turn, rng)])
+    }
+
+    fn name(&self) -> &'static str {
+        "neat"
+    }
+
+    /// Attempts end when Mario dies, and start over where they started.
+    fn reverts(&self) -> bool {
+        false
+    }
+
+    /// Forgets where attempts start. The attempt carries on from the next
+    /// frame in a level.
+    fn forget(&mut self) {
+        self.start = None;
+    }
+}
+
+impl Drop for Brain {
+    /// Hands an unfinished genome back to the pool for another Mario.
+    fn drop(&mut self) {
+        if let Some(ticket) = self.ticket.take() {
+            if let Ok(mut pool) = self.pool.lock() {
+                pool.abandon(ticket);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positive_outputs_press_buttons() {
+        assert_eq!(buttons(&[0.5, -0.5, 0.1, 0.0]), 0b1000_0001);
+        assert_eq!(buttons(&[-1.0; OUTPUTS]), 0);
+    }
+}
@@ -0,0 +1,132 @@
+//! Captions read from a SubRip (`.srt`) file, for narrating what the Marios
+//! are doing. Scripts get the caption showing at any time, with how far it
+//! has faded in or out.
+
+use std::str::FromStr;
+
+/// Seconds a caption takes to fade in and out.
+pub const FADE: f32 = 0.25;
+
+/// Text shown from `start` to `end` seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    pub start: f32,
+    pub end: f32,
+    pub text: String,
+}
+
+/// Cues in the order they start.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Captions {
+    cues: Vec<Cue>,
+}
+
+impl Captions {
+    /// The cue showing `time` seconds in, with its opacity from 0 to 1. The
+    /// one that started last wins when cues overlap.
+    pub fn at(&self, time: f32) -> Option<(&Cue, f32)> {
+        let cue = self
+            .cues
+            .iter()
+            .rev()
+            .find(|cue| cue.start <= time && time < cue.end)?;
+        let opacity = ((time - cue.start) / FADE)
+            .min((cue.end - time) / FADE)
+            .min(1.0);
+        Some((cue, opacity))
+    }
+
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+}
+
+impl FromStr for Captions {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut cues = Vec::new();
+        let mut lines = s.lines().map(str::trim_end).enumerate().peekable();
+        loop {
+            // blank lines between cues, then the number of the cue
+            while lines.next_if(|(_, line)| line.trim().is_empty()).is_some() {}
+            if lines.next().is_none() {
+                break;
+            }
+            let (number, timing) = match lines.next() {
+                Some(timing) => timing,
+                None => return Err("cue without a time at the end".to_owned()),
+            };
+            let (start, end) = timing
+                .split_once("-->")
+                .and_then(|(start, end)| Some((timestamp(start)?, timestamp(end)?)))
+                .ok_or_else(|| {
+                    format!(
+                        "expected HH:MM:SS,mmm --> HH:MM:SS,mmm on line {}, got {:?}",
+                        number + 1,
+                        timing
+                    )
+                })?;
+            let mut text = Vec::new();
+            while let Some((_, line)) = lines.next_if(|(_, line)| !line.trim().is_empty()) {
+                text.push(line);
+            }
+            cues.push(Cue {
+                start,
+                end,
+                text: text.join("\n"),
+            });
+        }
+        cues.sort_by(|a, b| a.start.total_cmp(&b.start));
+        Ok(Captions { cues })
+    }
+}
+
+/// Seconds of a timestamp like `01:02:03,456`.
+fn timestamp(s: &str) -> Option<f32> {
+    let (time, millis) = s.trim().split_once([',', '.'])?;
+    let mut parts = time.split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let seconds = hours.parse::<u32>().ok()? * 3600
+        + minutes.parse::<u32>().ok()? * 60
+        + seconds.parse::<u32>().ok()?;
+    Some(seconds as f32 + millis.parse::<u32>().ok()? as f32 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_subrip_and_fades() {
+        let captions: Captions = "1\n\
+            00:00:01,000 --> 00:00:03,500\n\
+            Mario 3 found a shortcut\n\
+            through the pipe\n\
+            \n\
+            2\n\
+            00:01:00,000 --> 00:01:02,000\n\
+            Bowser awaits\n"
+            .parse()
+            .unwrap();
+        assert_eq!(captions.cues().len(), 2);
+        assert_eq!(
+            captions.cues()[0].text,
+            "Mario 3 found a shortcut\nthrough the pipe"
+        );
+        assert_eq!(captions.cues()[1].start, 60.0);
+
+        assert_eq!(captions.at(0.5), None);
+        let (cue, opacity) = captions.at(1.125).unwrap();
+        assert_eq!((cue.end, opacity), (3.5, 0.5));
+        assert_eq!(captions.at(2.0).unwrap().1, 1.0);
+        assert_eq!(captions.at(3.5), None);
+
+        assert!("1\n00:00:01 --> 00:00:02\nno millis\n"
+            .parse::<Captions>()
+            .is_err());
+    }
+}
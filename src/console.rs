@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use crate::luanim::{Animation, Backend, Mat3};
+
+const TEXT_SIZE: f32 = 20.0;
+const LINE_HEIGHT: f32 = TEXT_SIZE * 1.25;
+const MARGIN: f32 = 16.0;
+
+/// Lines kept in the scrollback.
+const SCROLLBACK: usize = 200;
+
+/// A Lua console drawn over the current scene, evaluating what is typed in
+/// the state of that scene's animation.
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    lines: VecDeque<String>,
+    history: Vec<String>,
+    recalled: Option<usize>,
+}
+
+impl Console {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Handles a typed character. Returns true when enter was pressed and the
+    /// input should be run with [`Console::submit`].
+    pub fn type_char(&mut self, c: char) -> bool {
+        match c {
+            '\r' | '\n' => return true,
+            '\u{8}' => {
+                self.input.pop();
+            }
+            // the toggle key
+            '`' => {}
+            c if !c.is_control() => self.input.push(c),
+            _ => {}
+        }
+        false
+    }
+
+    /// Replaces the input with an earlier one, going further back with every
+    /// call.
+    pub fn recall_previous(&mut self) {
+        let index = match self.recalled {
+            Some(index) => index.saturating_sub(1),
+            None if !self.history.is_empty() => self.history.len() - 1,
+            None => return,
+        };
+        self.recalled = Some(index);
+        self.input = self.history[index].clone();
+    }
+
+    /// Moves forward through earlier inputs, ending at an empty line.
+    pub fn recall_next(&mut self) {
+        match self.recalled {
+            Some(index) if index + 1 < self.history.len() => {
+                self.recalled = Some(index + 1);
+                self.input = self.history[index + 1].clone();
+            }
+            _ => {
+                self.recalled = None;
+                self.input.clear();
+            }
+        }
+    }
+
+    /// Evaluates the current input in `animation` and shows the result.
+    pub fn submit<B: Backend>(&mut self, animation: &mut Animation<B>) {
+        let code = std::mem::take(&mut self.input);
+        self.recalled = None;
+        if code.trim().is_empty() {
+            return;
+        }
+
+        self.push(format!("> {}", code));
+        match animation.eval(&code) {
+            Ok(output) => output.into_iter().for_each(|line| self.push(line)),
+            Err(e) => self.push(format!("error: {}", e)),
+        }
+        self.history.push(code);
+    }
+
+    fn push(&mut self, text: String) {
+        for line in text.lines() {
+            if self.lines.len() == SCROLLBACK {
+                self.lines.pop_front();
+            }
+            self.lines.push_back(line.to_owned());
+        }
+    }
+
+    /// Draws the scrollback and input line at the bottom of `canvas`.
+    pub fn draw(&self, canvas: &mut impl Backend) {
+        if !self.open {
+            return;
+        }
+
+        let (_, height) = canvas.size();
+        let rows = ((height - MARGIN * 2.0) / LINE_HEIGHT) as usize;
+        let prompt = format!("> {}_", self.input);
+
+        let shown = self.lines.len().min(rows.saturating_sub(1));
+        let lines = self
+            .lines
+            .iter()
+            .skip(self.lines.len() - shown)
+            .chain(std::iter::once(&prompt));
+
+        let top = height - MARGIN - (shown + 1) as f32 * LINE_HEIGHT;
+        for (i, line) in lines.enumerate() {
+            let y = top + (i + 1) as f32 * LINE_HEIGHT;
+            // a line that can't be drawn is not worth reporting from the console
+            let _ = canvas.fill_text(Mat3::identity(), MARGIN, y, TEXT_SIZE, line, None);
+        }
+        canvas.flush();
+    }
+}
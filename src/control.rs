@@ -0,0 +1,158 @@
+//! Controlling the show over HTTP, from a stream deck, a chat bot or anything
+//! else that can send a request.
+//!
+//! `POST /scene/NAME` switches to the scene called NAME, as `scenes.switch`
+//! does from a script, and `POST /pause` pauses the simulation or resumes it,
+//! as the P key does:
+//!
+//! ```text
+//! shellkick --control 127.0.0.1:8080 &
+//! curl -X POST http://127.0.0.1:8080/scene/race
+//! curl -X POST http://127.0.0.1:8080/pause
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use tracing::{info, warn};
+
+use crate::scene::SceneSwitch;
+
+/// How long a client gets to send its request.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The most of a request that is read, headers included.
+const MAX_REQUEST: u64 = 8 * 1024;
+
+/// What requests can change.
+pub struct Control {
+    /// The names of the scenes that can be switched to.
+    pub scenes: Vec<String>,
+    pub switch: SceneSwitch,
+    /// Set while the simulation is held, by the P key or a request.
+    pub pause: Arc<AtomicBool>,
+}
+
+impl Control {
+    /// Listens on `addr` and answers requests one after the other on a thread
+    /// of its own. Returns the address listened on.
+    pub fn serve(self, addr: impl ToSocketAddrs) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        thread::Builder::new()
+            .name("control".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if let Err(e) = stream.and_then(|stream| self.answer(stream)) {
+                        warn!("could not answer control request: {}", e);
+                    }
+                }
+            })?;
+        Ok(local)
+    }
+
+    fn answer(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new((&stream).take(MAX_REQUEST));
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+
+        // skip the headers, nothing here needs them
+        let mut header = String::new();
+        loop {
+            header.clear();
+            if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+                break;
+            }
+        }
+
+        let (status, body) = self.handle(request.trim_end());
+        write!(
+            &stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// Carries out the request starting with the line `request`, and returns
+    /// the status and body to answer with.
+    fn handle(&self, request: &str) -> (&'static str, String) {
+        let mut parts = request.split(' ');
+        let method = parts.next().unwrap_or_default();
+        let path = parts.next().unwrap_or_default();
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+
+        match (method, segments.as_slice()) {
+            ("POST", ["scene", name]) if self.scenes.iter().any(|scene| scene == name) => {
+                info!(scene = name, "scene switch requested");
+                self.switch.request(*name);
+                ("200 OK", "ok\n".to_owned())
+            }
+            ("POST", ["scene", name]) => ("404 Not Found", format!("no scene called {:?}\n", name)),
+            ("POST", ["pause"]) => {
+                let paused = !self.pause.fetch_xor(true, Ordering::Relaxed);
+                info!(paused, "simulation pause toggled");
+                let state = if paused { "paused" } else { "running" };
+                ("200 OK", format!("{}\n", state))
+            }
+            (_, ["scene", _] | ["pause"]) => ("405 Method Not Allowed", "use POST\n".to_owned()),
+            _ => ("404 Not Found", format!("nothing at {:?}\n", path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control() -> Control {
+        Control {
+            scenes: vec!["race".to_owned(), "break".to_owned()],
+            switch: SceneSwitch::default(),
+            pause: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn switches_scene() {
+        let control = control();
+        let (status, _) = control.handle("POST /scene/break HTTP/1.1");
+        assert_eq!(status, "200 OK");
+        assert_eq!(control.switch.take().as_deref(), Some("break"));
+    }
+
+    #[test]
+    fn toggles_pause() {
+        let control = control();
+        assert_eq!(control.handle("POST /pause HTTP/1.1").1, "paused\n");
+        assert!(control.pause.load(Ordering::Relaxed));
+        assert_eq!(control.handle("POST /pause HTTP/1.1").1, "running\n");
+        assert!(!control.pause.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn rejects_unknown_scenes_and_methods() {
+        let control = control();
+        assert_eq!(
+            control.handle("POST /scene/intro HTTP/1.1").0,
+            "404 Not Found"
+        );
+        assert_eq!(
+            control.handle("GET /scene/race HTTP/1.1").0,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(control.handle("POST /resume HTTP/1.1").0, "404 Not Found");
+        assert_eq!(control.switch.take(), None);
+    }
+}
@@ -0,0 +1,208 @@
+//! What decides the inputs a Mario plays. The simulation boots the game,
+//! reverts after deaths, keeps save states and hurries through cutscenes;
+//! between all that it asks the Mario's controller for the next inputs.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use fastnes::{cart::NROM, input::Controllers, nes::NES, ppu::FastPPU};
+use rand::{seq::SliceRandom, Rng, RngCore};
+use tracing::debug;
+
+use crate::{
+    mario::{Cost, Personality},
+    observation::Observation,
+    rollout_cache::{self, RolloutCache},
+    smb::{objective_fitness, Fitness, Objective, Ram},
+};
+
+/// Inputs for the next frames, played in order.
+pub type InputPlan = VecDeque<u8>;
+
+/// What a controller decides on.
+pub struct Turn<'a> {
+    /// The state the plan is played from. A controller may put it back at an
+    /// earlier state, after which the inputs since are forgotten.
+    pub nes: &'a mut NES<NROM, FastPPU>,
+    pub observation: Observation,
+    /// How Mario is doing now, by his objective.
+    pub score: Fitness,
+    /// The input played on the last frame.
+    pub last_input: u8,
+    /// The personality after annealing.
+    pub personality: &'a Personality,
+    pub objective: Objective,
+    /// See [`Settings::powerup_bonus`](crate::mario::Settings::powerup_bonus).
+    pub powerup_bonus: u64,
+    /// Inputs that got Marios past the [obstacle](crate::obstacles) Mario is
+    /// at, with how many times they did. Empty when he isn't at one.
+    pub known: Vec<(Vec<u8>, u32)>,
+    /// Where time spent playing ahead is counted.
+    pub cost: &'a mut Cost,
+}
+
+pub trait Controller: Send {
+    /// Plans the inputs of at least the next frame.
+    fn decide(&mut self, turn: &mut Turn, rng: &mut dyn RngCore) -> InputPlan;
+
+    /// Shown to scripts and in logs.
+    fn name(&self) -> &'static str;
+
+    /// Whether the simulation reverts Mario when he dies, and keeps save
+    /// states to revert to. Controllers that deal with dying themselves turn
+    /// this off.
+    fn reverts(&self) -> bool {
+        true
+    }
+
+    /// Called when Mario is put somewhere else, like after a reset or when a
+    /// state is loaded.
+    fn forget(&mut self) {}
+}
+
+/// Chance of a plan at an obstacle being one that got past it before.
+const KNOWN_BIAS: f64 = 0.5;
+
+/// Plans ahead by trying a few random plans on copies of the game and playing
+/// the best, and moves randomly for a while when none get further.
+#[derive(Debug, Default)]
+pub struct Planner {
+    /// Random plans left to play before planning again.
+    being_random: Option<u32>,
+    /// Plans in a row that didn't get further.
+    stuck_count: u32,
+    cache: RolloutCache,
+    /// The objective and powerup bonus the cached rollouts were scored by.
+    scored_by: Option<(Objective, u64)>,
+}
+
+fn next_input(prev: u8, personality: &Personality, rng: &mut dyn RngCore) -> u8 {
+    let mut next = prev;
+    if rng.gen_range(0.0..1.0) < personality.twitchy {
+        let dir = 1 << rng.gen_range(4..8);
+        next = (next & 0b00001111) | dir;
+    }
+    if rng.gen_range(0.0..1.0) < personality.jumpy {
+        next ^= 0b1
+    }
+    next | 0b10 // always press B
+}
+
+impl Controller for Planner {
+    fn decide(&mut self, turn: &mut Turn, rng: &mut dyn RngCore) -> InputPlan {
+        let personality = turn.personality;
+
+        if let Some(num) = self.being_random.as_mut() {
+            // Random input
+            *num -= 1;
+            if *num == 0 {
+                self.being_random = None;
+            }
+
+            let mut plan = InputPlan::new();
+            let mut last = turn.last_input;
+            for _ in 0..personality.playful {
+                last = next_input(last, personality, rng);
+                plan.push_back(last);
+            }
+            return plan;
+        }
+
+        // Regular input
+        let mut best_result = Fitness::Dying(false);
+        let mut best_plan = InputPlan::new();
+        let input = Arc::new(AtomicU8::new(0));
+        let rollouts = Instant::now();
+        let mut cloning = Duration::ZERO;
+        let scoring = (turn.objective, turn.powerup_bonus);
+        if self.scored_by != Some(scoring) {
+            self.cache.clear();
+            self.scored_by = Some(scoring);
+        }
+        let state = Ram::of(turn.nes).checksum();
+
+        for _ in 0..personality.rollouts.max(1) {
+            // generate inputs, or take some that got past here before
+            let known = if !turn.known.is_empty() && rng.gen_bool(KNOWN_BIAS) {
+                turn.known.choose_weighted(rng, |(_, times)| *times).ok()
+            } else {
+                None
+            };
+            let mut list: InputPlan = match known {
+                Some((inputs, _)) if !inputs.is_empty() => inputs.iter().copied().collect(),
+                _ => {
+                    let mut list = InputPlan::new();
+                    let mut last = turn.last_input;
+                    for _ in 0..personality.playful {
+                        last = next_input(last, personality, rng);
+                        list.push_back(last);
+                    }
+                    list
+                }
+            };
+
+            // run, unless it was before
+            let key = rollout_cache::key(state, list.make_contiguous());
+            let score = match self.cache.get(key) {
+                Some(score) => {
+                    turn.cost.cache_hits += 1;
+                    score
+                }
+                None => {
+                    turn.cost.cache_misses += 1;
+                    let clone = Instant::now();
+                    let mut cloned = turn.nes.clone();
+                    cloning += clone.elapsed();
+                    cloned.controllers = Controllers::standard(&input);
+
+                    for item in list.iter().copied() {
+                        input.store(item, Ordering::Relaxed);
+                        cloned.next_frame();
+                    }
+
+                    let score = objective_fitness(&mut cloned, turn.objective, turn.powerup_bonus);
+                    self.cache.insert(key, score);
+                    score
+                }
+            };
+
+            // get results
+            if score >= best_result {
+                best_result = score;
+                best_plan = list;
+            }
+        }
+        turn.cost.rollouts += rollouts.elapsed().saturating_sub(cloning);
+        turn.cost.clone += cloning;
+
+        // test against current score
+        if best_result <= turn.score
+            && !matches!(best_result, Fitness::Cutscene | Fitness::Flagpole(_))
+        {
+            self.stuck_count += 1;
+            if self.stuck_count >= personality.patient {
+                debug!(turns = personality.bold, "stuck, moving randomly");
+                self.stuck_count = 0;
+                self.being_random = Some(personality.bold);
+            }
+        }
+        best_plan
+    }
+
+    fn name(&self) -> &'static str {
+        "planner"
+    }
+
+    /// Starts planning afresh, but keeps the cached rollouts: they are keyed
+    /// by the state they start from.
+    fn forget(&mut self) {
+        self.being_random = None;
+        self.stuck_count = 0;
+    }
+}
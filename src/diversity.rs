@@ -0,0 +1,121 @@
+//! How different the Marios of a population still are from each other, to
+//! see it collapse into a monoculture.
+
+use std::collections::VecDeque;
+
+use crate::mario::Personality;
+
+/// Frames of recent input compared between Marios.
+pub const RECENT_INPUTS: usize = 600;
+
+/// Mean distance between every pair of `points`, scaled to 0 for identical
+/// points and 1 for points at opposite corners of the unit cube.
+pub fn spread<const N: usize>(points: &[[f32; N]]) -> f32 {
+    let mut total = 0.0;
+    let mut pairs = 0;
+    for (i, a) in points.iter().enumerate() {
+        for b in points[i + 1..].iter() {
+            let squared: f32 = a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+            total += squared.sqrt();
+            pairs += 1;
+        }
+    }
+    if pairs == 0 {
+        0.0
+    } else {
+        total / pairs as f32 / (N as f32).sqrt()
+    }
+}
+
+/// How often each of the eight buttons is held in `inputs`, from 0 to 1.
+pub fn input_profile(inputs: &[u8]) -> [f32; 8] {
+    let mut held = [0.0; 8];
+    for input in inputs {
+        for (bit, held) in held.iter_mut().enumerate() {
+            if input & (1 << bit) != 0 {
+                *held += 1.0;
+            }
+        }
+    }
+    held.map(|held| held / inputs.len().max(1) as f32)
+}
+
+/// The diversity of a population at one point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sample {
+    /// [`spread`] of the [traits](Personality::traits).
+    pub personalities: f32,
+    /// [`spread`] of the [`input_profile`] of recent inputs.
+    pub inputs: f32,
+}
+
+impl Sample {
+    /// Measures the diversity of Marios with `personalities` that played
+    /// `recent` inputs.
+    pub fn of<'a>(
+        personalities: impl IntoIterator<Item = &'a Personality>,
+        recent: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Sample {
+        let traits: Vec<_> = personalities.into_iter().map(Personality::traits).collect();
+        let profiles: Vec<_> = recent.into_iter().map(input_profile).collect();
+        Sample {
+            personalities: spread(&traits),
+            inputs: spread(&profiles),
+        }
+    }
+}
+
+/// The most recent samples of diversity, in a ring buffer.
+pub struct Diversity {
+    capacity: usize,
+    samples: VecDeque<Sample>,
+}
+
+impl Diversity {
+    pub fn new(capacity: usize) -> Diversity {
+        Diversity {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, sample: Sample) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn latest(&self) -> Option<Sample> {
+        self.samples.back().copied()
+    }
+
+    /// Samples oldest first.
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spread_goes_from_same_to_opposite() {
+        assert_eq!(spread(&[[0.5, 0.5], [0.5, 0.5]]), 0.0);
+        assert_eq!(spread(&[[0.0, 0.0], [1.0, 1.0]]), 1.0);
+        assert_eq!(spread::<2>(&[[0.3, 0.1]]), 0.0);
+    }
+
+    #[test]
+    fn profiles_count_held_buttons() {
+        let profile = input_profile(&[0b11, 0b10, 0b10, 0]);
+        assert_eq!(profile[0], 0.25);
+        assert_eq!(profile[1], 0.75);
+        assert_eq!(profile[7], 0.0);
+    }
+}
@@ -0,0 +1,37 @@
+use std::{io, path::PathBuf};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not read ROM {}", .path.display())]
+    Rom {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("cannot run ROM {}", .path.display())]
+    InvalidRom {
+        path: PathBuf,
+        #[source]
+        source: crate::rom::RomError,
+    },
+    #[cfg(feature = "femtovg")]
+    #[error("could not load font {}", .path.display())]
+    Font {
+        path: PathBuf,
+        #[source]
+        source: femtovg::ErrorKind,
+    },
+    #[error("could not create window: {0}")]
+    Window(String),
+    #[error("OpenGL error")]
+    Gl(#[from] glutin::error::Error),
+    #[cfg(feature = "femtovg")]
+    #[error("renderer error: {0:?}")]
+    Renderer(#[from] femtovg::ErrorKind),
+    #[error(transparent)]
+    Lua(#[from] mlua::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
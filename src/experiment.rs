@@ -0,0 +1,311 @@
+//! Sweeping personality and planner parameters headlessly, to see which
+//! settings get Marios furthest in a fixed number of frames.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use rand::Rng;
+use threadpool::ThreadPool;
+use tracing::info;
+
+use crate::{
+    mario::{self, Mario, Personality, Settings, ROLLOUTS},
+    population,
+    smb::{depth, scroll, Objective},
+};
+
+/// Something about how Marios play that can be swept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    Patient,
+    Bold,
+    Playful,
+    Twitchy,
+    Jumpy,
+    Confident,
+    Rollouts,
+    /// [`RevertPolicy::checkpoint`](crate::mario::RevertPolicy::checkpoint).
+    Checkpoint,
+    /// [`RevertPolicy::death`](crate::mario::RevertPolicy::death).
+    Death,
+}
+
+impl Param {
+    pub const ALL: [Param; 9] = [
+        Param::Patient,
+        Param::Bold,
+        Param::Playful,
+        Param::Twitchy,
+        Param::Jumpy,
+        Param::Confident,
+        Param::Rollouts,
+        Param::Checkpoint,
+        Param::Death,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Param::Patient => "patient",
+            Param::Bold => "bold",
+            Param::Playful => "playful",
+            Param::Twitchy => "twitchy",
+            Param::Jumpy => "jumpy",
+            Param::Confident => "confident",
+            Param::Rollouts => "rollouts",
+            Param::Checkpoint => "checkpoint",
+            Param::Death => "death",
+        }
+    }
+
+    /// A value from the range random Marios are given, or a sensible one for
+    /// what isn't randomized.
+    pub fn random(self, rng: &mut impl Rng) -> f64 {
+        match self {
+            Param::Patient | Param::Bold => f64::from(rng.gen_range(1..10u32)),
+            Param::Twitchy | Param::Jumpy => rng.gen_range(0.01..0.2),
+            Param::Playful => f64::from(rng.gen_range(1..=40u32)),
+            Param::Confident => f64::from(rng.gen_range(1..=10u32)),
+            Param::Rollouts => f64::from(rng.gen_range(1..=8u32)),
+            Param::Checkpoint => f64::from(rng.gen_range(1..=64u32)),
+            Param::Death => f64::from(rng.gen_range(0..=120u32)),
+        }
+    }
+
+    /// Whether the parameter is part of a personality, rather than of the
+    /// settings every Mario shares.
+    pub fn personal(self) -> bool {
+        !matches!(self, Param::Checkpoint | Param::Death)
+    }
+
+    fn apply(self, value: f64, personality: &mut Personality, settings: &mut Settings) {
+        let whole = value.round().max(0.0);
+        match self {
+            Param::Checkpoint => settings.revert.checkpoint = whole as u64,
+            Param::Death => settings.revert.death = whole as u64,
+            _ => self.shape(value, personality),
+        }
+    }
+
+    /// Sets the parameter in `personality`, if it is [personal](Param::personal).
+    pub fn shape(self, value: f64, personality: &mut Personality) {
+        let whole = value.round().max(0.0);
+        match self {
+            Param::Patient => personality.patient = whole as u32,
+            Param::Bold => personality.bold = (whole as u32).max(1),
+            Param::Playful => personality.playful = (whole as u32).max(1),
+            Param::Twitchy => personality.twitchy = value as f32,
+            Param::Jumpy => personality.jumpy = value as f32,
+            Param::Confident => personality.confident = whole as u32,
+            Param::Rollouts => personality.rollouts = (whole as u32).max(1),
+            Param::Checkpoint | Param::Death => {}
+        }
+    }
+}
+
+impl FromStr for Param {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Param::ALL.into_iter().find(|param| param.name() == s) {
+            Some(param) => Ok(param),
+            None => Err(format!(
+                "unknown parameter {:?}, expected patient, bold, playful, twitchy, jumpy, \
+                 confident, rollouts, checkpoint or death",
+                s
+            )),
+        }
+    }
+}
+
+/// The values a parameter takes in a grid sweep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Axis {
+    pub param: Param,
+    pub values: Vec<f64>,
+}
+
+impl FromStr for Axis {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (param, values) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected PARAM=VALUE,VALUE,..., got {:?}", s))?;
+        let values = values
+            .split(',')
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("expected a number for {}, got {:?}", param, value))
+            })
+            .collect::<Result<Vec<f64>, String>>()?;
+        Ok(Axis {
+            param: param.parse()?,
+            values,
+        })
+    }
+}
+
+/// Values for some of the parameters, the rest being left as they are.
+pub type Config = Vec<(Param, f64)>;
+
+/// Every combination of the values of `axes`.
+pub fn grid(axes: &[Axis]) -> Vec<Config> {
+    axes.iter().fold(vec![Config::new()], |configs, axis| {
+        configs
+            .iter()
+            .flat_map(|config| {
+                axis.values.iter().map(move |&value| {
+                    let mut config = config.clone();
+                    config.push((axis.param, value));
+                    config
+                })
+            })
+            .collect()
+    })
+}
+
+/// `count` configurations with random values for every one of `params`.
+pub fn random(params: &[Param], count: usize, rng: &mut impl Rng) -> Vec<Config> {
+    (0..count)
+        .map(|_| {
+            params
+                .iter()
+                .map(|&param| (param, param.random(rng)))
+                .collect()
+        })
+        .collect()
+}
+
+/// The personality swept parameters start from, in the middle of the range
+/// random Marios are given.
+fn baseline() -> Personality {
+    Personality {
+        patient: 5,
+        bold: 5,
+        playful: 10,
+        twitchy: 0.1,
+        jumpy: 0.1,
+        confident: 1,
+        rollouts: ROLLOUTS,
+    }
+}
+
+/// How far a single run got.
+#[derive(Clone, Copy, Debug)]
+pub struct Outcome {
+    pub depth: u32,
+    pub position: u32,
+}
+
+/// How every configuration of a sweep is played.
+#[derive(Clone, Copy, Debug)]
+pub struct Experiment {
+    pub settings: Settings,
+    pub objective: Objective,
+    /// Marios played with every configuration. They play randomly, so the
+    /// runs differ.
+    pub runs: usize,
+    /// Frames every run lasts.
+    pub frames: u32,
+    /// Workers to run the Marios of a configuration on.
+    pub threads: usize,
+}
+
+impl Experiment {
+    /// Plays the runs of `config` and returns how far each got.
+    pub fn run(&self, rom: &[u8], config: &Config) -> Vec<Outcome> {
+        let mut personality = baseline();
+        let mut settings = self.settings;
+        for &(param, value) in config {
+            param.apply(value, &mut personality, &mut settings);
+        }
+
+        let marios: Vec<_> = (0..self.runs)
+            .map(|_| {
+                let mut mario = Mario::new(personality.clone(), rom.to_vec());
+                mario.objective = self.objective;
+                Arc::new(Mutex::new(mario))
+            })
+            .collect();
+        let pool = ThreadPool::new(self.threads);
+        for ticks in 0..self.frames {
+            population::tick(&pool, &marios, &settings, ticks);
+        }
+
+        marios
+            .iter()
+            .map(|mario| {
+                let mut mario = mario::lock(mario);
+                let nes = mario.nes_mut();
+                Outcome {
+                    depth: depth(nes),
+                    position: scroll(nes),
+                }
+            })
+            .collect()
+    }
+
+    /// Runs every one of `configs` and writes a CSV line per run to `out`,
+    /// with a column for every parameter swept.
+    pub fn sweep(&self, rom: &[u8], configs: &[Config], out: &mut impl Write) -> io::Result<()> {
+        let params: Vec<Param> = match configs.first() {
+            Some(config) => config.iter().map(|&(param, _)| param).collect(),
+            None => Vec::new(),
+        };
+        let mut header = String::from("config,run");
+        for param in params.iter() {
+            write!(header, ",{}", param.name()).unwrap();
+        }
+        writeln!(out, "{},depth,position", header)?;
+
+        for (i, config) in configs.iter().enumerate() {
+            info!(
+                config = i + 1,
+                of = configs.len(),
+                ?config,
+                "running configuration"
+            );
+            for (run, outcome) in self.run(rom, config).iter().enumerate() {
+                let mut line = format!("{},{}", i + 1, run + 1);
+                for (_, value) in config {
+                    write!(line, ",{}", value).unwrap();
+                }
+                writeln!(out, "{},{},{}", line, outcome.depth, outcome.position)?;
+            }
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grids_cover_every_combination() {
+        let axes: Vec<Axis> = ["twitchy=0.05,0.1", "bold=1,5,9"]
+            .iter()
+            .map(|axis| axis.parse().unwrap())
+            .collect();
+        let configs = grid(&axes);
+        assert_eq!(configs.len(), 6);
+        assert_eq!(configs[1], vec![(Param::Twitchy, 0.05), (Param::Bold, 5.0)]);
+        assert!("brave=1".parse::<Axis>().is_err());
+        assert!("bold=one".parse::<Axis>().is_err());
+    }
+
+    #[test]
+    fn random_configs_set_every_param() {
+        let configs = random(&Param::ALL, 3, &mut rand::thread_rng());
+        assert_eq!(configs.len(), 3);
+        assert!(configs
+            .iter()
+            .all(|config| config.len() == Param::ALL.len()));
+    }
+}
@@ -0,0 +1,121 @@
+//! Frames of a Mario laid side by side in a single image, oldest on the left,
+//! each with its frame number under it, to show at a glance how a run went.
+
+/// Width of a frame in pixels.
+const FRAME_WIDTH: usize = 256;
+/// Height of a frame in pixels.
+const FRAME_HEIGHT: usize = 240;
+/// Pixels between two frames.
+const GAP: usize = 4;
+/// Pixels every pixel of a digit is drawn as.
+const DIGIT_SCALE: usize = 2;
+/// Pixels around the frame number.
+const LABEL_PADDING: usize = 4;
+/// Height of the strip under the frames the frame numbers are written on.
+const LABEL_HEIGHT: usize = 5 * DIGIT_SCALE + 2 * LABEL_PADDING;
+
+const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+const INK: [u8; 4] = [255, 255, 255, 255];
+
+/// The digits 0 to 9, three pixels wide and five high, a row of three bits at
+/// a time from the top.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b011, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Which of `len` states, oldest first, go on a filmstrip of every `every`th
+/// one. Counted back from the newest, so the last moment is always on it.
+pub fn picked(len: usize, every: usize) -> Vec<usize> {
+    let mut picked: Vec<usize> = (0..len).rev().step_by(every.max(1)).collect();
+    picked.reverse();
+    picked
+}
+
+/// An image of RGBA pixels, row by row, to paste frames onto.
+pub struct Filmstrip {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Filmstrip {
+    /// An empty filmstrip with room for `frames` frames.
+    pub fn new(frames: usize) -> Filmstrip {
+        let width = (frames * (FRAME_WIDTH + GAP)).saturating_sub(GAP);
+        let height = FRAME_HEIGHT + LABEL_HEIGHT;
+        Filmstrip {
+            width,
+            height,
+            pixels: vec![BACKGROUND; width * height],
+        }
+    }
+
+    /// Pastes the `pixels` of a frame, row by row, in place `index` from the
+    /// left, with its frame `number` under it.
+    pub fn paste(&mut self, index: usize, pixels: &[[u8; 4]], number: u64) {
+        let left = index * (FRAME_WIDTH + GAP);
+        for (y, row) in pixels.chunks(FRAME_WIDTH).take(FRAME_HEIGHT).enumerate() {
+            let start = y * self.width + left;
+            self.pixels[start..start + row.len()].copy_from_slice(row);
+        }
+
+        let top = FRAME_HEIGHT + LABEL_PADDING;
+        let mut x = left + LABEL_PADDING;
+        for digit in number.to_string().bytes() {
+            let rows = DIGITS[usize::from(digit - b'0')];
+            for (y, bits) in rows.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & 0b100 >> column != 0 {
+                        self.dot(x + column * DIGIT_SCALE, top + y * DIGIT_SCALE);
+                    }
+                }
+            }
+            x += 4 * DIGIT_SCALE;
+        }
+    }
+
+    /// Fills a pixel of a digit at `x`, `y`, unless it would spill into the
+    /// next frame.
+    fn dot(&mut self, x: usize, y: usize) {
+        for y in y..y + DIGIT_SCALE {
+            for x in x..(x + DIGIT_SCALE).min(self.width) {
+                self.pixels[y * self.width + x] = INK;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_pasted_side_by_side() {
+        assert_eq!(picked(10, 4), [1, 5, 9]);
+        assert_eq!(picked(3, 1), [0, 1, 2]);
+        assert!(picked(0, 4).is_empty());
+
+        let mut filmstrip = Filmstrip::new(2);
+        assert_eq!((filmstrip.width, filmstrip.height), (516, 258));
+        let red = [255, 0, 0, 255];
+        filmstrip.paste(1, &vec![red; FRAME_WIDTH * FRAME_HEIGHT], 10);
+        assert_eq!(filmstrip.pixels[FRAME_WIDTH + GAP], red);
+        assert_eq!(filmstrip.pixels[filmstrip.width - 1], red);
+        assert_eq!(filmstrip.pixels[FRAME_WIDTH], BACKGROUND);
+
+        // the top left pixel of the 1 is blank, the one right of it isn't
+        let label = (FRAME_HEIGHT + LABEL_PADDING) * filmstrip.width;
+        let left = FRAME_WIDTH + GAP + LABEL_PADDING;
+        assert_eq!(filmstrip.pixels[label + left], BACKGROUND);
+        assert_eq!(filmstrip.pixels[label + left + DIGIT_SCALE], INK);
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+enum Format {
+    Csv,
+    Json,
+}
+
+pub struct Sample {
+    pub instance: usize,
+    pub frame: u64,
+    pub state: &'static str,
+    pub position: u32,
+}
+
+pub struct FitnessLog {
+    out: BufWriter<File>,
+    format: Format,
+    interval: Duration,
+    next: Instant,
+}
+
+impl FitnessLog {
+    /// Opens `path` for appending. Files ending in `.json` or `.jsonl` get one
+    /// JSON object per line, anything else is written as CSV.
+    pub fn open(path: &Path, interval: Duration) -> io::Result<FitnessLog> {
+        let format = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") | Some("jsonl") => Format::Json,
+            _ => Format::Csv,
+        };
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let empty = file.metadata()?.len() == 0;
+
+        let mut out = BufWriter::new(file);
+        if empty {
+            if let Format::Csv = format {
+                writeln!(out, "timestamp,instance,frame,state,position")?;
+                out.flush()?;
+            }
+        }
+
+        Ok(FitnessLog {
+            out,
+            format,
+            interval,
+            next: Instant::now(),
+        })
+    }
+
+    pub fn due(&self) -> bool {
+        Instant::now() >= self.next
+    }
+
+    pub fn write(&mut self, samples: impl IntoIterator<Item = Sample>) -> io::Result<()> {
+        self.next = Instant::now() + self.interval;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        for sample in samples {
+            match self.format {
+                Format::Csv => writeln!(
+                    self.out,
+                    "{:.3},{},{},{},{}",
+                    timestamp, sample.instance, sample.frame, sample.state, sample.position
+                )?,
+                Format::Json => writeln!(
+                    self.out,
+                    "{{\"timestamp\":{:.3},\"instance\":{},\"frame\":{},\"state\":\"{}\",\"position\":{}}}",
+                    timestamp, sample.instance, sample.frame, sample.state, sample.position
+                )?,
+            }
+        }
+
+        self.out.flush()
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::VecDeque;
+
+/// The most recent fitness values of every instance, sampled every few
+/// simulated frames into fixed-size ring buffers.
+pub struct FitnessHistory {
+    capacity: usize,
+    interval: u32,
+    ticks: u32,
+    instances: Vec<VecDeque<u32>>,
+}
+
+impl FitnessHistory {
+    /// Keeps `capacity` values for each of `instances`, one every `interval`
+    /// frames.
+    pub fn new(instances: usize, capacity: usize, interval: u32) -> FitnessHistory {
+        FitnessHistory {
+            capacity,
+            interval: interval.max(1),
+            ticks: 0,
+            instances: vec![VecDeque::with_capacity(capacity); instances],
+        }
+    }
+
+    /// Counts a simulated frame. Returns true when a sample should be taken.
+    pub fn due(&mut self) -> bool {
+        self.ticks += 1;
+        if self.ticks == self.interval {
+            self.ticks = 0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Adds a value for every instance, dropping the oldest ones once full.
+    pub fn push(&mut self, values: impl IntoIterator<Item = u32>) {
+        for (history, value) in self.instances.iter_mut().zip(values) {
+            if history.len() == self.capacity {
+                history.pop_front();
+            }
+            history.push_back(value);
+        }
+    }
+
+    /// Values of the zero-based `instance`, oldest first.
+    pub fn get(&self, instance: usize) -> Option<&VecDeque<u32>> {
+        self.instances.get(instance)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -0,0 +1,83 @@
+//! Picking a single Mario from the keyboard, by typing his number on the
+//! number keys with a modifier held. Numbers of more than one digit are typed
+//! one digit at a time, and count as soon as no other Mario's number starts
+//! with them, so picking one never waits for more than it has to.
+
+use crate::mario::Reset;
+
+/// What happens to the Mario picked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Reset(Reset),
+    /// Pauses him, or lets him go on if he was paused.
+    Pause,
+    /// Looks back through his states, or stops if they were already being
+    /// looked through.
+    Rewind,
+}
+
+/// The digits typed so far, and what they were typed for.
+#[derive(Default)]
+pub struct InstanceKeys {
+    typed: Option<(usize, Action)>,
+}
+
+impl InstanceKeys {
+    /// Adds `digit` to the number of one of `instances` Marios, typed for
+    /// `action`. Returns the number and the action once no further digit
+    /// could make it another Mario's. Typing for another action starts over.
+    pub fn digit(
+        &mut self,
+        digit: usize,
+        action: Action,
+        instances: usize,
+    ) -> Option<(usize, Action)> {
+        let number = match self.typed.take() {
+            Some((number, typed)) if typed == action => number * 10 + digit,
+            _ => digit,
+        };
+        if number > instances {
+            None
+        } else if number * 10 > instances {
+            (number > 0).then_some((number, action))
+        } else {
+            self.typed = Some((number, action));
+            None
+        }
+    }
+
+    /// Takes the number typed so far, for when the modifier is let go.
+    pub fn finish(&mut self) -> Option<(usize, Action)> {
+        self.typed.take().filter(|&(number, _)| number > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_count_once_they_are_complete() {
+        let level = Action::Reset(Reset::Level);
+        let mut keys = InstanceKeys::default();
+        assert_eq!(keys.digit(4, level, 9), Some((4, level)));
+
+        assert_eq!(keys.digit(4, Action::Pause, 100), None);
+        assert_eq!(keys.digit(2, Action::Pause, 100), Some((42, Action::Pause)));
+        assert_eq!(keys.finish(), None);
+
+        // 1 could still become 10 to 12
+        let boot = Action::Reset(Reset::Boot);
+        assert_eq!(keys.digit(1, boot, 12), None);
+        assert_eq!(keys.finish(), Some((1, boot)));
+        assert_eq!(keys.digit(1, boot, 12), None);
+        assert_eq!(keys.digit(5, boot, 12), None);
+        assert_eq!(keys.finish(), None);
+
+        // switching actions halfway starts the number over
+        assert_eq!(keys.digit(1, boot, 12), None);
+        assert_eq!(keys.digit(2, level, 12), Some((2, level)));
+        assert_eq!(keys.digit(0, level, 12), None);
+        assert_eq!(keys.finish(), None);
+    }
+}
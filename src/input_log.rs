@@ -0,0 +1,277 @@
+//! A compact record of every input a Mario played, written as it plays, so
+//! any moment of its life can be played again afterwards.
+//!
+//! A log starts with [`MAGIC`], followed by records of a tag byte and its
+//! payload:
+//!
+//! - [`INPUT`], an input byte and a count: the next `count` frames were
+//!   played with that input.
+//! - [`REVERT`], a little-endian `u64` frame: the emulator went back to the
+//!   state at that frame, so the inputs from there on are replaced. Starting
+//!   over from the title screen is a revert to frame 0.
+//! - [`CHECK`], a little-endian `u64` frame, `u32` position and `u64` RAM
+//!   checksum: what the game looked like at that frame, to check a replay
+//!   against.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+pub const MAGIC: &[u8; 4] = b"SKI1";
+pub const INPUT: u8 = 0;
+pub const REVERT: u8 = 1;
+pub const CHECK: u8 = 2;
+
+/// Writes a log as a Mario plays, merging frames with the same input.
+pub struct InputLog {
+    out: BufWriter<File>,
+    /// The input being repeated and for how many frames, not written yet.
+    pending: Option<(u8, u8)>,
+}
+
+impl InputLog {
+    /// Creates the log at `path`, replacing what was there.
+    pub fn create(path: &Path) -> io::Result<InputLog> {
+        InputLog::new(BufWriter::new(File::create(path)?))
+    }
+
+    /// Starts a log at the current position of `out`.
+    pub fn new(mut out: BufWriter<File>) -> io::Result<InputLog> {
+        out.write_all(MAGIC)?;
+        Ok(InputLog { out, pending: None })
+    }
+
+    /// Records that the next frame was played with `input`.
+    pub fn input(&mut self, input: u8) -> io::Result<()> {
+        match self.pending.as_mut() {
+            Some((pending, count)) if *pending == input && *count < u8::MAX => *count += 1,
+            _ => {
+                self.write_pending()?;
+                self.pending = Some((input, 1));
+            }
+        }
+        Ok(())
+    }
+
+    /// Records going back to the state at `frame`.
+    pub fn revert(&mut self, frame: u64) -> io::Result<()> {
+        self.write_pending()?;
+        self.out.write_all(&[REVERT])?;
+        self.out.write_all(&frame.to_le_bytes())
+    }
+
+    /// Records that at `frame` Mario was at `position`, see
+    /// [`crate::smb::scroll`], with RAM that sums to `ram`, see
+    /// [`crate::smb::Ram::checksum`].
+    pub fn check(&mut self, frame: u64, position: u32, ram: u64) -> io::Result<()> {
+        self.write_pending()?;
+        self.out.write_all(&[CHECK])?;
+        self.out.write_all(&frame.to_le_bytes())?;
+        self.out.write_all(&position.to_le_bytes())?;
+        self.out.write_all(&ram.to_le_bytes())
+    }
+
+    /// Writes everything recorded so far to the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.out.flush()
+    }
+
+    fn write_pending(&mut self) -> io::Result<()> {
+        match self.pending.take() {
+            Some((input, count)) => self.out.write_all(&[INPUT, input, count]),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for InputLog {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// A record of a log, with the repeated inputs spelled out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entry {
+    /// `input` was played on the frame starting at `frame`.
+    Input {
+        frame: u64,
+        input: u8,
+    },
+    Revert {
+        frame: u64,
+    },
+    Check {
+        frame: u64,
+        position: u32,
+        ram: u64,
+    },
+}
+
+/// Reads the entries of a log one by one.
+pub struct Reader<R> {
+    input: R,
+    frame: u64,
+    /// Frames of the last input record not returned yet.
+    repeating: Option<(u8, u8)>,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open(path: &Path) -> io::Result<Reader<BufReader<File>>> {
+        Reader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Reads a log from `input`, failing if it doesn't start with [`MAGIC`].
+    pub fn new(mut input: R) -> io::Result<Reader<R>> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an input log",
+            ));
+        }
+        Ok(Reader {
+            input,
+            frame: 0,
+            repeating: None,
+        })
+    }
+
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        if let Some((input, count)) = self.repeating.take() {
+            if count > 1 {
+                self.repeating = Some((input, count - 1));
+            }
+            let entry = Entry::Input {
+                frame: self.frame,
+                input,
+            };
+            self.frame += 1;
+            return Ok(Some(entry));
+        }
+
+        let mut tag = [0];
+        if self.input.read(&mut tag)? == 0 {
+            return Ok(None);
+        }
+        match tag[0] {
+            INPUT => {
+                let mut record = [0; 2];
+                self.input.read_exact(&mut record)?;
+                if record[1] > 0 {
+                    self.repeating = Some((record[0], record[1]));
+                }
+                self.next_entry()
+            }
+            REVERT => {
+                let mut frame = [0; 8];
+                self.input.read_exact(&mut frame)?;
+                self.frame = u64::from_le_bytes(frame);
+                Ok(Some(Entry::Revert { frame: self.frame }))
+            }
+            CHECK => {
+                let mut record = [0; 20];
+                self.input.read_exact(&mut record)?;
+                let (frame, rest) = record.split_at(8);
+                let (position, ram) = rest.split_at(4);
+                Ok(Some(Entry::Check {
+                    frame: u64::from_le_bytes(frame.try_into().unwrap()),
+                    position: u32::from_le_bytes(position.try_into().unwrap()),
+                    ram: u64::from_le_bytes(ram.try_into().unwrap()),
+                }))
+            }
+            tag => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown record {}", tag),
+            )),
+        }
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+/// The inputs from frame 0 that get a freshly booted NES to where the Mario
+/// was after the last of `entries`, with everything reverted left out.
+pub fn timeline(entries: impl IntoIterator<Item = Entry>) -> Vec<u8> {
+    let mut inputs = Vec::new();
+    for entry in entries {
+        match entry {
+            Entry::Input { frame, input } => {
+                inputs.truncate(frame as usize);
+                inputs.push(input);
+            }
+            Entry::Revert { frame } => inputs.truncate(frame as usize),
+            Entry::Check { .. } => {}
+        }
+    }
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    #[test]
+    fn reads_back_what_was_written() {
+        let path = temp_dir().join(format!("shellkick-input-log-{}", std::process::id()));
+        {
+            let mut log = InputLog::create(&path).unwrap();
+            for input in [1, 1, 1, 2, 3, 3] {
+                log.input(input).unwrap();
+            }
+            log.revert(4).unwrap();
+            log.input(5).unwrap();
+            log.check(5, 1234, u64::MAX).unwrap();
+        }
+        let entries: Vec<Entry> = Reader::open(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 9);
+        assert_eq!(entries[4], Entry::Input { frame: 4, input: 3 });
+        assert_eq!(entries[6], Entry::Revert { frame: 4 });
+        assert_eq!(entries[7], Entry::Input { frame: 4, input: 5 });
+        assert_eq!(
+            entries[8],
+            Entry::Check {
+                frame: 5,
+                position: 1234,
+                ram: u64::MAX
+            }
+        );
+        assert_eq!(timeline(entries), vec![1, 1, 1, 2, 5]);
+    }
+
+    #[test]
+    fn long_repeats_are_split() {
+        let path = temp_dir().join(format!("shellkick-input-repeats-{}", std::process::id()));
+        {
+            let mut log = InputLog::create(&path).unwrap();
+            for _ in 0..300 {
+                log.input(7).unwrap();
+            }
+        }
+        let size = std::fs::metadata(&path).unwrap().len();
+        let inputs = timeline(Reader::open(&path).unwrap().map(Result::unwrap));
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(size, 4 + 2 * 3);
+        assert_eq!(inputs, vec![7; 300]);
+    }
+}
@@ -0,0 +1,302 @@
+//! Laying out a screen for every instance, so scenes that show the whole
+//! population adapt to how many there are, and screens glide to their new
+//! places when the layout changes.
+
+use std::collections::BTreeMap;
+
+/// What to lay out and where. Lengths are in whatever units the caller uses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+    /// Number of cells.
+    pub count: usize,
+    pub width: f32,
+    pub height: f32,
+    /// Width of a cell divided by its height.
+    pub aspect: f32,
+    /// Space kept free around the whole grid.
+    pub margin: f32,
+    /// Space between cells.
+    pub gap: f32,
+    /// Zero-based cell shown larger, on the left of the others.
+    pub featured: Option<usize>,
+    /// Part of the width the featured cell may take up.
+    pub featured_share: f32,
+}
+
+/// Where a cell goes, by its top left corner, relative to the center of the
+/// area.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Cell {
+    /// A cell of no size at the center of this one.
+    fn center(&self) -> Cell {
+        Cell {
+            x: self.x + self.width / 2.0,
+            y: self.y + self.height / 2.0,
+            width: 0.0,
+            height: 0.0,
+        }
+    }
+
+    fn lerp(&self, other: &Cell, t: f32) -> Cell {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Cell {
+            x: lerp(self.x, other.x),
+            y: lerp(self.y, other.y),
+            width: lerp(self.width, other.width),
+            height: lerp(self.height, other.height),
+        }
+    }
+}
+
+impl Grid {
+    /// A cell for every one of `count`, in order, with the featured cell in
+    /// its own place.
+    pub fn cells(&self) -> Vec<Cell> {
+        let left = -self.width / 2.0 + self.margin;
+        let top = -self.height / 2.0 + self.margin;
+        let width = (self.width - self.margin * 2.0).max(0.0);
+        let height = (self.height - self.margin * 2.0).max(0.0);
+
+        let featured = match self.featured {
+            Some(featured) if featured < self.count && self.count > 1 => featured,
+            _ => return self.fit(self.count, left, top, width, height),
+        };
+        let share = width * self.featured_share.clamp(0.0, 1.0);
+        let mut cells = self.fit(
+            self.count - 1,
+            left + share + self.gap,
+            top,
+            (width - share - self.gap).max(0.0),
+            height,
+        );
+        let big = self.fit(1, left, top, share, height)[0];
+        cells.insert(featured, big);
+        cells
+    }
+
+    /// Fits `count` cells as large as possible in the given area, in rows from
+    /// the top left, with the grid as a whole centered.
+    fn fit(&self, count: usize, left: f32, top: f32, width: f32, height: f32) -> Vec<Cell> {
+        if count == 0 {
+            return Vec::new();
+        }
+        // widest cells of any number of columns, the fewest columns on a tie
+        let (columns, cell) = (1..=count)
+            .map(|columns| {
+                let rows = count.div_ceil(columns);
+                let across = (width - self.gap * (columns - 1) as f32) / columns as f32;
+                let down = (height - self.gap * (rows - 1) as f32) / rows as f32;
+                (columns, across.min(down * self.aspect).max(0.0))
+            })
+            .fold(
+                (1, f32::MIN),
+                |best, next| if next.1 > best.1 { next } else { best },
+            );
+
+        let rows = count.div_ceil(columns);
+        let cell_height = cell / self.aspect;
+        let used_width = cell * columns as f32 + self.gap * (columns - 1) as f32;
+        let used_height = cell_height * rows as f32 + self.gap * (rows - 1) as f32;
+        let left = left + (width - used_width) / 2.0;
+        let top = top + (height - used_height) / 2.0;
+        (0..count)
+            .map(|i| Cell {
+                x: left + (i % columns) as f32 * (cell + self.gap),
+                y: top + (i / columns) as f32 * (cell_height + self.gap),
+                width: cell,
+                height: cell_height,
+            })
+            .collect()
+    }
+}
+
+/// A cell on its way from one place to another.
+#[derive(Clone, Copy, Debug)]
+struct Tween {
+    from: Cell,
+    to: Cell,
+    started: f32,
+    /// Whether the cell shrinks away, gone once it gets there.
+    leaving: bool,
+}
+
+/// Cells by key, usually the instance, that glide to where they were last
+/// moved instead of jumping there. Times are in seconds.
+#[derive(Clone, Debug)]
+pub struct Glide {
+    duration: f32,
+    tweens: BTreeMap<usize, Tween>,
+}
+
+impl Glide {
+    /// Cells that take `duration` seconds to get anywhere.
+    pub fn new(duration: f32) -> Glide {
+        Glide {
+            duration,
+            tweens: BTreeMap::new(),
+        }
+    }
+
+    /// Sends every cell to its new place from `time` on. New cells grow from
+    /// the center of theirs, and cells that are left out shrink away.
+    pub fn retarget(&mut self, time: f32, cells: impl IntoIterator<Item = (usize, Cell)>) {
+        let mut tweens = BTreeMap::new();
+        for (key, to) in cells {
+            // already on its way there
+            if let Some(&tween) = self
+                .tweens
+                .get(&key)
+                .filter(|tween| !tween.leaving && tween.to == to)
+            {
+                tweens.insert(key, tween);
+                continue;
+            }
+            let from = match self.get(time, key) {
+                Some(from) => from,
+                None => to.center(),
+            };
+            let tween = Tween {
+                from,
+                to,
+                started: time,
+                leaving: false,
+            };
+            tweens.insert(key, tween);
+        }
+        for (&key, tween) in &self.tweens {
+            if tweens.contains_key(&key) {
+                continue;
+            }
+            if tween.leaving {
+                if !self.done(time, tween) {
+                    tweens.insert(key, *tween);
+                }
+                continue;
+            }
+            let from = self.at(time, tween);
+            let tween = Tween {
+                from,
+                to: from.center(),
+                started: time,
+                leaving: true,
+            };
+            tweens.insert(key, tween);
+        }
+        self.tweens = tweens;
+    }
+
+    /// Where the cell of `key` is at `time`, unless it is gone.
+    pub fn get(&self, time: f32, key: usize) -> Option<Cell> {
+        let tween = self.tweens.get(&key)?;
+        if tween.leaving && self.done(time, tween) {
+            return None;
+        }
+        Some(self.at(time, tween))
+    }
+
+    fn done(&self, time: f32, tween: &Tween) -> bool {
+        time - tween.started >= self.duration
+    }
+
+    fn at(&self, time: f32, tween: &Tween) -> Cell {
+        let t = if self.duration > 0.0 {
+            ((time - tween.started) / self.duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        // eases out, so a cell sent somewhere else halfway doesn't stop first
+        tween.from.lerp(&tween.to, 1.0 - (1.0 - t).powi(3))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(x: f32, y: f32, size: f32) -> Cell {
+        Cell {
+            x,
+            y,
+            width: size,
+            height: size,
+        }
+    }
+
+    fn grid(count: usize) -> Grid {
+        Grid {
+            count,
+            width: 400.0,
+            height: 200.0,
+            aspect: 1.0,
+            margin: 0.0,
+            gap: 0.0,
+            featured: None,
+            featured_share: 0.5,
+        }
+    }
+
+    #[test]
+    fn cells_are_as_large_as_fits() {
+        let cells = grid(8).cells();
+        assert_eq!(cells.len(), 8);
+        // four columns of two rows
+        assert_eq!(cells[0], cell(-200.0, -100.0, 100.0));
+        assert_eq!(cells[5], cell(-100.0, 0.0, 100.0));
+
+        // a single row, centered
+        let cells = Grid {
+            margin: 10.0,
+            gap: 10.0,
+            ..grid(2)
+        }
+        .cells();
+        assert_eq!(cells[0], cell(-185.0, -90.0, 180.0));
+        assert_eq!(cells[1].x, 5.0);
+        assert!(grid(0).cells().is_empty());
+    }
+
+    #[test]
+    fn the_featured_cell_keeps_its_place() {
+        let cells = Grid {
+            featured: Some(2),
+            ..grid(5)
+        }
+        .cells();
+        assert_eq!(cells.len(), 5);
+        assert_eq!(cells[2], cell(-200.0, -100.0, 200.0));
+        // the others in two rows of two on the right
+        assert_eq!(cells[0], cell(0.0, -100.0, 100.0));
+        assert_eq!(cells[3], cell(0.0, 0.0, 100.0));
+        assert_eq!(cells[4].x, 100.0);
+    }
+
+    #[test]
+    fn cells_glide_in_and_shrink_away() {
+        let mut glide = Glide::new(1.0);
+        glide.retarget(0.0, [(1, cell(0.0, 0.0, 100.0))]);
+        assert_eq!(glide.get(0.0, 1), Some(cell(50.0, 50.0, 0.0)));
+        assert_eq!(glide.get(1.0, 1), Some(cell(0.0, 0.0, 100.0)));
+
+        glide.retarget(2.0, [(2, cell(100.0, 0.0, 100.0))]);
+        // eased out, so past halfway at half the time
+        let halfway = glide.get(2.5, 1).unwrap();
+        assert!(halfway.width < 50.0 && halfway.width > 0.0);
+        assert_eq!(glide.get(3.0, 1), None);
+        assert_eq!(glide.get(3.0, 2), Some(cell(100.0, 0.0, 100.0)));
+
+        // sent elsewhere halfway, from where it is
+        glide.retarget(3.0, [(2, cell(200.0, 0.0, 100.0))]);
+        glide.retarget(3.5, [(2, cell(0.0, 0.0, 100.0))]);
+        glide.retarget(4.0, [(2, cell(0.0, 0.0, 100.0))]);
+        assert_eq!(glide.get(3.5, 2).unwrap().x, 100.0 + 100.0 * 0.875);
+        assert_eq!(glide.get(4.5, 2), Some(cell(0.0, 0.0, 100.0)));
+        assert_eq!(glide.tweens.len(), 1);
+    }
+}
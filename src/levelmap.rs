@@ -0,0 +1,164 @@
+//! Whole levels pieced together from the frames of the Mario furthest ahead,
+//! for scenes that show a level with every Mario plotted on it.
+//!
+//! Maps are kept by area, the world and area number [`crate::smb::scroll`]
+//! keeps above the position, and cut into pages as wide as the screen so only
+//! the pages that changed have to be uploaded again.
+
+use std::collections::BTreeMap;
+
+use femtovg::rgb::RGBA8;
+
+/// Rows at the top of the screen the status bar takes up. It stays put while
+/// the level scrolls, so it is left out of maps.
+pub const STATUS_BAR: usize = 32;
+/// Rows of a map.
+pub const HEIGHT: usize = 240 - STATUS_BAR;
+/// Pixels of the level a page of a map holds, a screen's width.
+pub const PAGE_WIDTH: usize = 256;
+/// Widest a map gets, to stay within the texture sizes of most graphics
+/// cards. Levels longer than this are cut off at the end.
+pub const MAX_WIDTH: usize = 8192;
+
+/// Part of a map as wide as the screen.
+#[derive(Clone)]
+pub struct Page {
+    pub pixels: Vec<RGBA8>,
+    /// Counts up whenever anything is stitched onto the page.
+    pub version: u64,
+}
+
+/// A single area, stitched together as far as anyone has gotten into it.
+#[derive(Clone, Default)]
+pub struct LevelMap {
+    pages: Vec<Page>,
+    /// Pixels from the start of the area up to the furthest stitched.
+    width: usize,
+}
+
+impl LevelMap {
+    /// Copies the screen `frame` shows, at `screen_x` into the area, onto the
+    /// map.
+    pub fn stitch(&mut self, screen_x: u16, frame: &[RGBA8]) {
+        let start = usize::from(screen_x);
+        let end = (start + 256).min(MAX_WIDTH);
+        if start >= end {
+            return;
+        }
+        let pages = end.div_ceil(PAGE_WIDTH);
+        while self.pages.len() < pages {
+            self.pages.push(Page {
+                pixels: vec![RGBA8::default(); PAGE_WIDTH * HEIGHT],
+                version: 0,
+            });
+        }
+
+        for page in start / PAGE_WIDTH..pages {
+            let from = start.max(page * PAGE_WIDTH);
+            let to = end.min((page + 1) * PAGE_WIDTH);
+            let page = &mut self.pages[page];
+            for row in 0..HEIGHT {
+                let source = (STATUS_BAR + row) * 256 + from - start;
+                let target = row * PAGE_WIDTH + from % PAGE_WIDTH;
+                page.pixels[target..target + to - from]
+                    .copy_from_slice(&frame[source..source + to - from]);
+            }
+            page.version += 1;
+        }
+        self.width = self.width.max(end);
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn pages(&self) -> &[Page] {
+        &self.pages
+    }
+}
+
+/// The map of every area someone has been the furthest in.
+#[derive(Default)]
+pub struct LevelMaps {
+    maps: BTreeMap<u16, LevelMap>,
+    /// Area and position of the screen last stitched.
+    last: Option<(u16, u16)>,
+}
+
+impl LevelMaps {
+    /// Whether a screen at `scroll`, as [`crate::smb::scroll`] gives it, would
+    /// add anything that wasn't stitched the last time.
+    pub fn wants(&self, scroll: u32) -> bool {
+        self.last != Some(split(scroll))
+    }
+
+    /// Stitches the screen `frame` shows at `scroll` onto the map of its area.
+    pub fn stitch(&mut self, scroll: u32, frame: &[RGBA8]) {
+        let (area, screen_x) = split(scroll);
+        self.maps.entry(area).or_default().stitch(screen_x, frame);
+        self.last = Some((area, screen_x));
+    }
+
+    pub fn get(&self, area: u16) -> Option<&LevelMap> {
+        self.maps.get(&area)
+    }
+
+    /// The area last stitched, which the Mario furthest ahead is in.
+    pub fn current(&self) -> Option<u16> {
+        self.last.map(|(area, _)| area)
+    }
+}
+
+/// The area and position of the screen in it of `scroll`.
+fn split(scroll: u32) -> (u16, u16) {
+    ((scroll >> 16) as u16, scroll as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A screen where every pixel has the column it is in as its red value,
+    /// and the status bar is blue.
+    fn screen() -> Vec<RGBA8> {
+        (0..256 * 240)
+            .map(|i| {
+                if i / 256 < STATUS_BAR {
+                    RGBA8::new(0, 0, 255, 255)
+                } else {
+                    RGBA8::new((i % 256) as u8, 0, 0, 255)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn screens_are_stitched_across_pages() {
+        let mut maps = LevelMaps::default();
+        let scroll = 0x0102_0000 | 0x180;
+        assert!(maps.wants(scroll));
+        maps.stitch(scroll, &screen());
+        assert!(!maps.wants(scroll));
+        assert_eq!(maps.current(), Some(0x0102));
+
+        let map = maps.get(0x0102).unwrap();
+        assert_eq!(map.width(), 0x280);
+        assert_eq!(map.pages().len(), 3);
+        assert_eq!(map.pages()[0].version, 0);
+        // the screen starts halfway into the second page
+        let second = &map.pages()[1].pixels;
+        assert_eq!(second[0x7f], RGBA8::default());
+        assert_eq!(second[0x80], RGBA8::new(0, 0, 0, 255));
+        assert_eq!(second[HEIGHT * PAGE_WIDTH - 1].r, 0x7f);
+        assert_eq!(map.pages()[2].pixels[0x7f].r, 0xff);
+        assert!(second.iter().all(|pixel| pixel.b == 0));
+    }
+
+    #[test]
+    fn maps_stop_at_the_widest_texture() {
+        let mut map = LevelMap::default();
+        map.stitch((MAX_WIDTH - 16) as u16, &screen());
+        assert_eq!(map.width(), MAX_WIDTH);
+        assert_eq!(map.pages().len(), MAX_WIDTH / PAGE_WIDTH);
+    }
+}
@@ -0,0 +1,51 @@
+pub mod affinity;
+#[cfg(all(feature = "femtovg", feature = "raster"))]
+pub mod app;
+pub mod brain;
+pub mod captions;
+pub mod console;
+pub mod control;
+pub mod controller;
+pub mod diversity;
+pub mod error;
+pub mod experiment;
+pub mod filmstrip;
+pub mod fitness_log;
+pub mod history;
+pub mod hotkeys;
+pub mod input_log;
+pub mod layout;
+pub mod levelmap;
+pub mod logbook;
+pub mod luanim;
+pub mod mario;
+#[cfg(feature = "ndi")]
+pub mod ndi;
+pub mod neat;
+pub mod observation;
+pub mod obstacles;
+pub mod poke;
+pub mod population;
+pub mod practice;
+pub mod prediction;
+pub mod ramdiff;
+pub mod ramwatch;
+pub mod ranking;
+pub mod recap;
+pub mod rewind;
+pub mod rollout_cache;
+pub mod rom;
+pub mod savestate;
+pub mod scaling;
+pub mod scene;
+pub mod sfx;
+pub mod smb;
+pub mod sram;
+pub mod stagnation;
+pub mod teams;
+pub mod ticker;
+pub mod timers;
+pub mod timings;
+pub mod vote;
+pub mod widgets;
+pub mod worldmap;
@@ -0,0 +1,79 @@
+//! The warnings and errors logged lately, kept for scripts to show on the
+//! operator's dashboard instead of having to watch the terminal. The same
+//! message logged over and over, like a script failing every frame, takes up
+//! a single line.
+
+use std::{collections::VecDeque, time::Instant};
+
+use tracing::Level;
+
+/// Lines kept before the oldest are dropped.
+const CAPACITY: usize = 100;
+
+/// A message logged, and how often it was logged in a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub level: Level,
+    pub message: String,
+    pub repeats: u32,
+    /// When it was last logged.
+    pub at: Instant,
+}
+
+/// The latest lines logged, oldest first.
+#[derive(Debug, Default)]
+pub struct Logbook {
+    lines: VecDeque<Line>,
+}
+
+impl Logbook {
+    /// Keeps `message` logged at `level` at `at`, as another repeat of the
+    /// last line if it is the same.
+    pub fn push(&mut self, level: Level, message: String, at: Instant) {
+        if let Some(last) = self
+            .lines
+            .back_mut()
+            .filter(|last| last.level == level && last.message == message)
+        {
+            last.repeats += 1;
+            last.at = at;
+            return;
+        }
+        if self.lines.len() == CAPACITY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(Line {
+            level,
+            message,
+            repeats: 1,
+            at,
+        });
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &Line> {
+        self.lines.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_lines_are_counted() {
+        let mut logbook = Logbook::default();
+        let now = Instant::now();
+        logbook.push(Level::ERROR, "lua error".to_owned(), now);
+        logbook.push(Level::ERROR, "lua error".to_owned(), now);
+        logbook.push(Level::WARN, "lua error".to_owned(), now);
+        logbook.push(Level::ERROR, "lua error".to_owned(), now);
+        let repeats: Vec<u32> = logbook.lines().map(|line| line.repeats).collect();
+        assert_eq!(repeats, [2, 1, 1]);
+
+        for i in 0..CAPACITY {
+            logbook.push(Level::WARN, i.to_string(), now);
+        }
+        assert_eq!(logbook.lines().count(), CAPACITY);
+        assert_eq!(logbook.lines().next().unwrap().message, "0");
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+};
+
+use femtovg::{Canvas, Color, ErrorKind, FontId, Paint, Path, Renderer, Transform2D};
+use mlua::{Error, Result};
+
+use super::{Backend, Mat3, PathCmd, TextMetrics, Vec2};
+
+impl From<Mat3> for Transform2D {
+    fn from(value: Mat3) -> Self {
+        Transform2D([value.a, value.b, value.c, value.d, value.e, value.f])
+    }
+}
+
+/// A femtovg canvas that knows its fonts by name, so scripts can choose one.
+/// Everything else is done on the canvas it derefs to.
+pub struct FontCanvas<T: Renderer> {
+    canvas: Canvas<T>,
+    fonts: HashMap<String, FontId>,
+    /// The font text is drawn in when no font is asked for, which is the
+    /// first one added.
+    default: Option<FontId>,
+    /// What the canvas is cleared to.
+    background: Color,
+}
+
+impl<T: Renderer> FontCanvas<T> {
+    pub fn new(canvas: Canvas<T>) -> FontCanvas<T> {
+        FontCanvas {
+            canvas,
+            fonts: HashMap::new(),
+            default: None,
+            background: Color::black(),
+        }
+    }
+
+    /// Clears the canvas to `color` from now on instead of black, such as a
+    /// transparent color for a window that is shown on top of something.
+    pub fn set_background(&mut self, color: Color) {
+        self.background = color;
+    }
+
+    /// Registers the font file `data` under `name`.
+    pub fn add_font_mem(&mut self, name: &str, data: &[u8]) -> std::result::Result<(), ErrorKind> {
+        let id = self.canvas.add_font_mem(data)?;
+        self.fonts.insert(name.to_owned(), id);
+        self.default.get_or_insert(id);
+        Ok(())
+    }
+
+    fn paint(&self, size: f32, font: Option<&str>) -> Result<Paint> {
+        let id = match font {
+            Some(name) => match self.fonts.get(name) {
+                Some(&id) => Some(id),
+                None => return Err(Error::RuntimeError(format!("unknown font {:?}", name))),
+            },
+            None => self.default,
+        };
+        let paint = Paint::color(Color::white()).with_font_size(size);
+        Ok(match id {
+            Some(id) => paint.with_font(&[id]),
+            None => paint,
+        })
+    }
+}
+
+impl<T: Renderer> Deref for FontCanvas<T> {
+    type Target = Canvas<T>;
+
+    fn deref(&self) -> &Canvas<T> {
+        &self.canvas
+    }
+}
+
+impl<T: Renderer> DerefMut for FontCanvas<T> {
+    fn deref_mut(&mut self) -> &mut Canvas<T> {
+        &mut self.canvas
+    }
+}
+
+impl<T: Renderer> Backend for FontCanvas<T> {
+    fn size(&self) -> (f32, f32) {
+        (self.canvas.width() as f32, self.canvas.height() as f32)
+    }
+
+    fn clear(&mut self) {
+        let width = self.canvas.width() as u32;
+        let height = self.canvas.height() as u32;
+        self.canvas.clear_rect(0, 0, width, height, self.background);
+    }
+
+    fn flush(&mut self) {
+        self.canvas.flush();
+    }
+
+    fn set_alpha(&mut self, alpha: f32) {
+        self.canvas.set_global_alpha(alpha);
+    }
+
+    fn fill_circle(&mut self, center: Vec2, radius: f32) {
+        let mut circle = Path::new();
+        circle.circle(center.x, center.y, radius);
+        self.canvas
+            .fill_path(&mut circle, &Paint::color(Color::white()))
+    }
+
+    fn fill_path(&mut self, path: &[PathCmd]) {
+        self.canvas
+            .fill_path(&mut to_path(path), &Paint::color(Color::white()))
+    }
+
+    fn stroke_path(&mut self, path: &[PathCmd], width: f32) {
+        self.canvas.stroke_path(
+            &mut to_path(path),
+            &Paint::color(Color::white()).with_line_width(width),
+        );
+    }
+
+    fn fill_text(
+        &mut self,
+        transform: Mat3,
+        x: f32,
+        y: f32,
+        size: f32,
+        text: &str,
+        font: Option<&str>,
+    ) -> Result<()> {
+        let paint = self.paint(size, font)?;
+        self.canvas.set_transform(&transform.into());
+        let result = self.canvas.fill_text(x, y, text, &paint);
+        self.canvas.reset_transform();
+        result.map(|_| ()).map_err(Error::external)
+    }
+
+    fn measure_text(&self, text: &str, size: f32, font: Option<&str>) -> Result<TextMetrics> {
+        let paint = self.paint(size, font)?;
+        let text_metrics = self
+            .canvas
+            .measure_text(0.0, 0.0, text, &paint)
+            .map_err(Error::external)?;
+        let font_metrics = self.canvas.measure_font(&paint).map_err(Error::external)?;
+        Ok(TextMetrics {
+            width: text_metrics.width(),
+            height: font_metrics.height(),
+            ascender: font_metrics.ascender(),
+        })
+    }
+}
+
+fn to_path(path: &[PathCmd]) -> Path {
+    let mut to = Path::new();
+    for cmd in path {
+        match *cmd {
+            PathCmd::MoveTo(p) => to.move_to(p.x, p.y),
+            PathCmd::LineTo(p) => to.line_to(p.x, p.y),
+            PathCmd::Close => to.close(),
+        }
+    }
+    to
+}
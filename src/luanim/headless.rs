@@ -0,0 +1,57 @@
+use mlua::Result;
+
+use super::{Backend, Mat3, PathCmd, TextMetrics, Vec2};
+
+/// A backend that draws nothing, for running scripts without a window.
+///
+/// Text is measured as if every character were a square, which is exact for
+/// the monospaced font used on stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Headless {
+    width: f32,
+    height: f32,
+}
+
+impl Headless {
+    pub fn new(width: f32, height: f32) -> Headless {
+        Headless { width, height }
+    }
+}
+
+impl Backend for Headless {
+    fn size(&self) -> (f32, f32) {
+        (self.width, self.height)
+    }
+
+    fn clear(&mut self) {}
+
+    fn flush(&mut self) {}
+
+    fn set_alpha(&mut self, _alpha: f32) {}
+
+    fn fill_circle(&mut self, _center: Vec2, _radius: f32) {}
+
+    fn fill_path(&mut self, _path: &[PathCmd]) {}
+
+    fn stroke_path(&mut self, _path: &[PathCmd], _width: f32) {}
+
+    fn fill_text(
+        &mut self,
+        _transform: Mat3,
+        _x: f32,
+        _y: f32,
+        _size: f32,
+        _text: &str,
+        _font: Option<&str>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn measure_text(&self, text: &str, size: f32, _font: Option<&str>) -> Result<TextMetrics> {
+        Ok(TextMetrics {
+            width: text.chars().count() as f32 * size,
+            height: size,
+            ascender: size,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::{Add, Mul, Sub};
+
+/// A 2D affine transform, laid out like a canvas transform:
+///
+/// ```text
+/// | a c e |
+/// | b d f |
+/// | 0 0 1 |
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3 {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Mat3 {
+    pub fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Mat3 {
+        Mat3 { a, b, c, d, e, f }
+    }
+    pub fn identity() -> Mat3 {
+        Mat3::new(1.0, 0.0, 0.0, 1.0, 0.0, 0.0)
+    }
+    pub fn scale(x: f32, y: f32) -> Mat3 {
+        Mat3::new(x, 0.0, 0.0, y, 0.0, 0.0)
+    }
+    pub fn translate(x: f32, y: f32) -> Mat3 {
+        Mat3::new(1.0, 0.0, 0.0, 1.0, x, y)
+    }
+    /// The transform undoing this one, or `None` if it collapses the plane.
+    pub fn inverse(self) -> Option<Mat3> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 {
+            return None;
+        }
+        Some(Mat3::new(
+            self.d / det,
+            -self.b / det,
+            -self.c / det,
+            self.a / det,
+            (self.c * self.f - self.d * self.e) / det,
+            (self.b * self.e - self.a * self.f) / det,
+        ))
+    }
+}
+
+impl Mul<Mat3> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, b: Mat3) -> Self::Output {
+        let a = self;
+        Mat3::new(
+            a.a * b.a + a.c * b.b,
+            a.b * b.a + a.d * b.b,
+            a.a * b.c + a.c * b.d,
+            a.b * b.c + a.d * b.d,
+            a.a * b.e + a.c * b.f + a.e,
+            a.b * b.e + a.d * b.f + a.f,
+        )
+    }
+}
+
+impl Mul<Vec2> for Mat3 {
+    type Output = Vec2;
+
+    fn mul(self, b: Vec2) -> Self::Output {
+        let a = self;
+        Vec2::new(a.a * b.x + a.c * b.y + a.e, a.b * b.x + a.d * b.y + a.f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vec2 {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Vec2 {
+    pub fn new(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+    pub fn len_squared(self) -> f32 {
+        self.x * self.x + self.y * self.y
+    }
+}
+
+impl Add<Vec2> for Vec2 {
+    type Output = Vec2;
+
+    fn add(self, rhs: Vec2) -> Self::Output {
+        Vec2::new(self.x + rhs.x, self.y + rhs.y)
+    }
+}
+
+impl Sub<Vec2> for Vec2 {
+    type Output = Vec2;
+
+    fn sub(self, rhs: Vec2) -> Self::Output {
+        Vec2::new(self.x - rhs.x, self.y - rhs.y)
+    }
+}
+
+impl Mul<Vec2> for f32 {
+    type Output = Vec2;
+
+    fn mul(self, rhs: Vec2) -> Self::Output {
+        Vec2::new(self * rhs.x, self * rhs.y)
+    }
+}
@@ -0,0 +1,1448 @@
+//! Renders [luanim](https://github.com/Astavie/luanim) animation scripts.
+//!
+//! A script returns an animation function which is called every frame with the
+//! current time and an `emit` callback. Every call to `emit` is an instruction:
+//! an opcode followed by its arguments. Opcodes below 128 are the core luanim
+//! instructions listed in [`ir`], together with a few extensions of this renderer
+//! from 64 up; custom instructions are registered by name and get the next free
+//! opcode from 128 up. Scripts find the opcodes of all
+//! instructions in the `canvas.instructions` table.
+
+use std::{
+    cell::{Cell, RefCell},
+    fs::read_to_string,
+    ops::AddAssign,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use mlua::{
+    Debug, DebugEvent, Error, FromLua, FromLuaMulti, Function, HookTriggers, Lua, LuaOptions,
+    MultiValue, Result, Scope, StdLib, Table, ToLua, ToLuaMulti, Value,
+};
+
+#[cfg(feature = "femtovg")]
+mod femtovg;
+mod headless;
+mod math;
+mod profile;
+#[cfg(feature = "raster")]
+mod raster;
+
+#[cfg(feature = "femtovg")]
+pub use self::femtovg::FontCanvas;
+pub use headless::Headless;
+pub use math::{Mat3, Vec2};
+use profile::Profiler;
+#[cfg(feature = "raster")]
+pub use raster::Raster;
+
+const TEXT_SCALE: f32 = 8.0 / 15.0;
+const FONT_SIZE: f32 = 16.0;
+
+/// Seconds between the frames that are evaluated, without drawing, when an
+/// animation is replayed up to an earlier point.
+const REPLAY_STEP: f32 = 1.0 / 60.0;
+
+/// Instructions between two checks of the script budget.
+const HOOK_INTERVAL: u32 = 10_000;
+
+/// Straight lines an ellipse that isn't a circle is drawn with.
+const ELLIPSE_SEGMENTS: usize = 64;
+
+const ERROR_SIZE: f32 = 24.0;
+const ERROR_MARGIN: f32 = 16.0;
+
+const ANIM_KEY: &str = "luanim.anim";
+const VALUES_KEY: &str = "luanim.values";
+const HANDLERS_KEY: &str = "luanim.handlers";
+const REQUIRED_KEY: &str = "luanim.required";
+
+/// Opcodes of the core instructions, matching `ir.lua`.
+pub mod ir {
+    pub const PUSH_TRANSFORM: u8 = 0;
+    pub const POP_TRANSFORM: u8 = 1;
+    pub const LINE_WIDTH: u8 = 3;
+    pub const CIRCLE: u8 = 4;
+    pub const MOVE_TO: u8 = 7;
+    pub const LINE_TO: u8 = 9;
+    pub const CLOSE_PATH: u8 = 10;
+    pub const TEXT: u8 = 13;
+    pub const ELLIPSE: u8 = 19;
+    pub const STROKE_PATH: u8 = 20;
+
+    /// Text broken into lines no wider than a maximum width. Not part of
+    /// luanim itself.
+    pub const TEXT_WRAP: u8 = 64;
+
+    /// Opcode of the first custom instruction.
+    pub const CUSTOM: u8 = 128;
+
+    pub(super) const NAMES: [(&str, u8); 11] = [
+        ("push_transform", PUSH_TRANSFORM),
+        ("pop_transform", POP_TRANSFORM),
+        ("line_width", LINE_WIDTH),
+        ("circle", CIRCLE),
+        ("move_to", MOVE_TO),
+        ("line_to", LINE_TO),
+        ("close_path", CLOSE_PATH),
+        ("text", TEXT),
+        ("ellipse", ELLIPSE),
+        ("stroke_path", STROKE_PATH),
+        ("text_wrap", TEXT_WRAP),
+    ];
+}
+
+/// A segment of a stroked path, in screen coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCmd {
+    MoveTo(Vec2),
+    LineTo(Vec2),
+    Close,
+}
+
+/// The drawing operations needed by the core instructions.
+///
+/// A femtovg canvas with named fonts is available with the `femtovg` feature,
+/// a software rasterizer with the `raster` feature, and [`Headless`] draws
+/// nothing at all.
+pub trait Backend {
+    /// Size of the drawing surface in pixels.
+    fn size(&self) -> (f32, f32);
+    /// Clears the whole surface to black, or the background the backend was
+    /// set up with.
+    fn clear(&mut self);
+    /// Submits all queued draw calls.
+    fn flush(&mut self);
+    /// Opacity applied to everything drawn afterwards.
+    fn set_alpha(&mut self, alpha: f32);
+
+    fn fill_circle(&mut self, center: Vec2, radius: f32);
+    fn fill_path(&mut self, path: &[PathCmd]);
+    fn stroke_path(&mut self, path: &[PathCmd], width: f32);
+    /// Draws `text` at `(x, y)` in the coordinate space given by `transform`,
+    /// in the font registered as `font`, or the default font for `None`.
+    fn fill_text(
+        &mut self,
+        transform: Mat3,
+        x: f32,
+        y: f32,
+        size: f32,
+        text: &str,
+        font: Option<&str>,
+    ) -> Result<()>;
+    /// Size of `text` when drawn at font size `size` without any transform.
+    fn measure_text(&self, text: &str, size: f32, font: Option<&str>) -> Result<TextMetrics>;
+}
+
+/// Size of a piece of text, see [`Backend::measure_text`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TextMetrics {
+    /// Advance width of the whole text.
+    pub width: f32,
+    /// Distance between the baselines of two lines.
+    pub height: f32,
+    /// Distance from the baseline to the top of the tallest glyph.
+    pub ascender: f32,
+}
+
+impl TextMetrics {
+    fn scale(self, scale: f32) -> TextMetrics {
+        TextMetrics {
+            width: self.width * scale,
+            height: self.height * scale,
+            ascender: self.ascender * scale,
+        }
+    }
+}
+
+/// Handler for a custom instruction. Receives the arguments that were emitted
+/// after the opcode.
+pub type Instruction<B> =
+    Box<dyn for<'lua> Fn(&'lua Lua, MultiValue<'lua>, &mut Screen<B>) -> Result<()>>;
+
+type ValueInit = Box<dyn for<'lua> Fn(&'lua Lua) -> Result<Value<'lua>>>;
+
+/// Placement of a whole frame when compositing several animations onto one
+/// surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layer {
+    /// Offset of the frame in pixels.
+    pub offset: Vec2,
+    /// Opacity of the frame.
+    pub alpha: f32,
+}
+
+/// Time spent in [`Animation::draw`] since the last [`Animation::tick`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DrawTime {
+    /// Running the animation function, its instructions included.
+    pub script: Duration,
+    /// Handling the instructions it emitted.
+    pub instructions: Duration,
+    /// Flushing the canvas.
+    pub flush: Duration,
+}
+
+impl AddAssign for DrawTime {
+    fn add_assign(&mut self, other: DrawTime) {
+        self.script += other.script;
+        self.instructions += other.instructions;
+        self.flush += other.flush;
+    }
+}
+
+impl Default for Layer {
+    fn default() -> Self {
+        Layer {
+            offset: Vec2::new(0.0, 0.0),
+            alpha: 1.0,
+        }
+    }
+}
+
+/// User input forwarded to a script with [`Animation::input`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// The cursor moved to this position in pixels.
+    CursorMoved(Vec2),
+    /// A mouse button (`left`, `right`, `middle` or `other`) was pressed or
+    /// released.
+    MouseButton { button: String, pressed: bool },
+    /// The mouse wheel scrolled by this many lines.
+    Scroll(Vec2),
+    /// A key was pressed or released.
+    Key { key: String, pressed: bool },
+}
+
+/// A startup parameter passed to the script through the `env` global.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvValue {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+}
+
+impl From<bool> for EnvValue {
+    fn from(value: bool) -> Self {
+        EnvValue::Boolean(value)
+    }
+}
+
+impl From<i64> for EnvValue {
+    fn from(value: i64) -> Self {
+        EnvValue::Integer(value)
+    }
+}
+
+impl From<u32> for EnvValue {
+    fn from(value: u32) -> Self {
+        EnvValue::Integer(value.into())
+    }
+}
+
+impl From<f64> for EnvValue {
+    fn from(value: f64) -> Self {
+        EnvValue::Number(value)
+    }
+}
+
+impl From<&str> for EnvValue {
+    fn from(value: &str) -> Self {
+        EnvValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> Self {
+        EnvValue::String(value)
+    }
+}
+
+impl<'lua> ToLua<'lua> for EnvValue {
+    fn to_lua(self, lua: &'lua Lua) -> Result<Value<'lua>> {
+        match self {
+            EnvValue::Boolean(b) => Ok(Value::Boolean(b)),
+            EnvValue::Integer(i) => i.to_lua(lua),
+            EnvValue::Number(n) => Ok(Value::Number(n)),
+            EnvValue::String(s) => s.to_lua(lua),
+        }
+    }
+}
+
+/// Custom instructions by opcode, starting at [`ir::CUSTOM`].
+struct Instructions<B>(Vec<(String, Instruction<B>)>);
+
+impl<B> Instructions<B> {
+    /// Fails once every opcode from [`ir::CUSTOM`] on is taken.
+    fn register(&mut self, name: String, handler: Instruction<B>) -> Result<u8> {
+        if let Some(opcode) = self.opcode(&name) {
+            self.0[usize::from(opcode - ir::CUSTOM)].1 = handler;
+            return Ok(opcode);
+        }
+        let opcode = u8::try_from(usize::from(ir::CUSTOM) + self.0.len()).map_err(|_| {
+            Error::RuntimeError(format!(
+                "can't register instruction {}, all {} custom opcodes are taken",
+                name,
+                self.0.len()
+            ))
+        })?;
+        self.0.push((name, handler));
+        Ok(opcode)
+    }
+
+    fn opcode(&self, name: &str) -> Option<u8> {
+        self.0
+            .iter()
+            .position(|(n, _)| n == name)
+            .map(|i| ir::CUSTOM + i as u8)
+    }
+
+    fn get(&self, opcode: u8) -> Option<&Instruction<B>> {
+        let index = usize::from(opcode.checked_sub(ir::CUSTOM)?);
+        self.0.get(index).map(|(_, handler)| handler)
+    }
+
+    fn names(&self) -> impl Iterator<Item = (&str, u8)> {
+        ir::NAMES.into_iter().chain(
+            self.0
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.as_str(), ir::CUSTOM + i as u8)),
+        )
+    }
+}
+
+/// Configuration for [`Animation::new`].
+pub struct Options<B> {
+    lib_path: PathBuf,
+    instructions: Instructions<B>,
+    /// The first instruction that couldn't be registered, returned by
+    /// [`Animation::new`].
+    error: Option<Error>,
+    values: Vec<(String, ValueInit)>,
+    globals: Vec<(String, ValueInit)>,
+    env: Vec<(String, EnvValue)>,
+    instruction_limit: u64,
+    time_limit: Duration,
+    memory_limit: usize,
+    profile: bool,
+}
+
+impl<B> Default for Options<B> {
+    fn default() -> Self {
+        Options {
+            lib_path: PathBuf::from("luanim/src"),
+            instructions: Instructions(Vec::new()),
+            error: None,
+            values: Vec::new(),
+            globals: Vec::new(),
+            env: Vec::new(),
+            instruction_limit: 100_000_000,
+            time_limit: Duration::from_millis(100),
+            memory_limit: 256 * 1024 * 1024,
+            profile: false,
+        }
+    }
+}
+
+impl<B> Options<B> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Directory containing the luanim Lua libraries. Defaults to `luanim/src`.
+    pub fn lib_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.lib_path = path.into();
+        self
+    }
+
+    /// Registers a custom instruction under `name`, available to the script
+    /// from the moment it is loaded. Registering more than there are opcodes
+    /// for makes [`Animation::new`] fail.
+    pub fn instruction(
+        mut self,
+        name: impl Into<String>,
+        handler: impl for<'lua> Fn(&'lua Lua, MultiValue<'lua>, &mut Screen<B>) -> Result<()> + 'static,
+    ) -> Self {
+        if let Err(e) = self.instructions.register(name.into(), Box::new(handler)) {
+            self.error.get_or_insert(e);
+        }
+        self
+    }
+
+    /// Adds a value the script can read through `canvas.signal(key)`. `init`
+    /// creates its starting value; it can be changed later with
+    /// [`Animation::values`].
+    pub fn value(
+        mut self,
+        key: impl Into<String>,
+        init: impl for<'lua> Fn(&'lua Lua) -> Result<Value<'lua>> + 'static,
+    ) -> Self {
+        self.values.push((key.into(), Box::new(init)));
+        self
+    }
+
+    /// Sets `env[key]` for the script. The script sees an `env` table even if
+    /// no values were set.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<EnvValue>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Maximum number of Lua instructions a single frame (or loading the
+    /// script) may take before it is aborted. Defaults to 100 million.
+    pub fn instruction_limit(mut self, limit: u64) -> Self {
+        self.instruction_limit = limit;
+        self
+    }
+
+    /// Maximum time a single frame (or loading the script) may take before it
+    /// is aborted. Defaults to 100ms.
+    pub fn time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = limit;
+        self
+    }
+
+    /// Maximum bytes the script may have allocated at once, past which
+    /// allocating fails with an error. Defaults to 256 MiB. LuaJIT manages its
+    /// own memory, so there is no limit under it.
+    pub fn memory_limit(mut self, limit: usize) -> Self {
+        self.memory_limit = limit;
+        self
+    }
+
+    /// Keeps track of the time the script spends in every function and
+    /// instruction, see [`Animation::profile`]. Slows the script down.
+    pub fn profile(mut self, enabled: bool) -> Self {
+        self.profile = enabled;
+        self
+    }
+
+    /// Sets the global variable `name` before the script is loaded.
+    pub fn global(
+        mut self,
+        name: impl Into<String>,
+        init: impl for<'lua> Fn(&'lua Lua) -> Result<Value<'lua>> + 'static,
+    ) -> Self {
+        self.globals.push((name.into(), Box::new(init)));
+        self
+    }
+}
+
+/// Drawing state shared by all instructions of a frame.
+pub struct Screen<B> {
+    root: Mat3,
+    transform_stack: Vec<Mat3>,
+    path: Option<Vec<PathCmd>>,
+
+    pub line_width: f32,
+    pub canvas: B,
+}
+
+/// A loaded animation script together with the surface it draws on.
+///
+/// Every animation keeps its own timeline, which is moved along with
+/// [`Animation::tick`] and can be paused, slowed down or sped up, and scrubbed
+/// with [`Animation::seek`].
+///
+/// Scripts run without access to files or other processes, and are aborted
+/// when a frame exceeds the limits set in [`Options`]. A script that failed
+/// shows its error instead of drawing until it is seeked back or reloaded.
+pub struct Animation<B> {
+    instructions: Instructions<B>,
+    lua: Lua,
+    screen: Screen<B>,
+    budget: Arc<Mutex<Budget>>,
+    error: Option<String>,
+    drawn: DrawTime,
+
+    name: String,
+    source: String,
+    time: f32,
+    rate: f32,
+    paused: bool,
+}
+
+/// Work done by the script call in progress, checked by the instruction hook.
+struct Budget {
+    instruction_limit: u64,
+    time_limit: Duration,
+    instructions: u64,
+    started: Option<Instant>,
+    profile: Option<Profiler>,
+}
+
+impl Budget {
+    fn start(&mut self) {
+        self.instructions = 0;
+        self.started = Some(Instant::now());
+        if let Some(profile) = &mut self.profile {
+            profile.clear_stack();
+        }
+    }
+
+    fn stop(&mut self) {
+        self.started = None;
+    }
+
+    fn spend(&mut self, instructions: u32) -> Result<()> {
+        let started = match self.started {
+            Some(started) => started,
+            None => return Ok(()),
+        };
+        self.instructions += u64::from(instructions);
+        if self.instructions > self.instruction_limit {
+            return Err(Error::RuntimeError(format!(
+                "script aborted after {} instructions",
+                self.instruction_limit
+            )));
+        }
+        if started.elapsed() > self.time_limit {
+            return Err(Error::RuntimeError(format!(
+                "script aborted after running for {:?}",
+                self.time_limit
+            )));
+        }
+        Ok(())
+    }
+
+    /// Passes a function call or return on to the profiler, while the script
+    /// is being run.
+    fn trace(&mut self, debug: &Debug) {
+        let profile = match &mut self.profile {
+            Some(profile) if self.started.is_some() => profile,
+            _ => return,
+        };
+        let now = Instant::now();
+        match debug.event() {
+            DebugEvent::Call => profile.enter(&function_label(debug), now),
+            DebugEvent::TailCall => {
+                // the calling function is done, and returns with the called one
+                profile.leave(now);
+                profile.enter(&function_label(debug), now);
+            }
+            DebugEvent::Ret => profile.leave(now),
+            _ => {}
+        }
+    }
+
+    /// Counts the time an instruction took to the function that emitted it.
+    fn instruction(&mut self, name: &str, time: Duration) {
+        if let Some(profile) = &mut self.profile {
+            profile.record(&format!("instruction {}", name), time);
+        }
+    }
+}
+
+/// The function being called or returned from, with where it is defined if
+/// it is a Lua function.
+fn function_label(debug: &Debug) -> String {
+    let name = debug.names().name.map(String::from_utf8_lossy);
+    let name = name.as_deref().unwrap_or("?");
+    let source = debug.source();
+    match source.short_src {
+        Some(src) if source.line_defined >= 0 => format!(
+            "{} ({}:{})",
+            name,
+            String::from_utf8_lossy(src),
+            source.line_defined
+        ),
+        _ => name.to_owned(),
+    }
+}
+
+/// Handle to the values of an animation, see [`Animation::values`].
+pub struct AnimationValues<'lua>(&'lua Lua, Table<'lua>);
+
+impl<'lua> AnimationValues<'lua> {
+    pub fn set(&self, key: impl ToLua<'lua>, value: impl ToLua<'lua>) -> Result<()> {
+        let signal: Function = self.1.get(key)?;
+        signal.call(value)?;
+        Ok(())
+    }
+    pub fn get<V: FromLua<'lua>>(&self, key: impl ToLua<'lua>) -> Result<V> {
+        let signal: Function = self.1.get(key)?;
+        let val: Value = signal.call(())?;
+        V::from_lua(val, self.0)
+    }
+}
+
+/// Time of the frame a script is being evaluated at.
+struct ScriptTime(f32);
+
+/// Seconds into the animation of the frame `lua` is evaluating, for custom
+/// globals that animate by themselves. Zero while the script loads.
+pub fn script_time(lua: &Lua) -> f32 {
+    lua.app_data_ref::<ScriptTime>().map_or(0.0, |time| time.0)
+}
+
+fn read_source(path: &Path) -> Result<String> {
+    read_to_string(path)
+        .map_err(|e| Error::external(format!("could not read {}: {}", path.display(), e)))
+}
+
+fn load_file<'lua>(lua: &'lua Lua, lib_path: &Path, name: &str) -> Result<Table<'lua>> {
+    lua.load(&read_source(&lib_path.join(name.to_owned() + ".lua"))?)
+        .set_name(&(name.to_owned() + ".lua"))?
+        .eval::<Table>()
+}
+
+fn load_libs(lua: &Lua, lib_path: &Path) -> Result<()> {
+    let globals = lua.globals();
+    globals.set("ir", load_file(lua, lib_path, "ir")?)?;
+
+    globals.set("tweens", load_file(lua, lib_path, "tweens")?)?;
+    globals.set("vector", load_file(lua, lib_path, "vector")?)?;
+    globals.set("signal", load_file(lua, lib_path, "signal")?)?;
+    globals.set("luanim", load_file(lua, lib_path, "luanim")?)?;
+
+    globals.set("shapes", load_file(lua, lib_path, "shapes")?)?;
+    Ok(())
+}
+
+impl<B: Backend> Animation<B> {
+    /// Loads the luanim libraries and evaluates `script`, which must return the
+    /// animation function.
+    pub fn new(script: impl AsRef<Path>, canvas: B, options: Options<B>) -> Result<Animation<B>> {
+        if let Some(e) = options.error {
+            return Err(e);
+        }
+        let script = script.as_ref();
+        let name = script.to_string_lossy().into_owned();
+        let source = read_source(script)?;
+        let screen = Screen::new(canvas);
+        let lua = Lua::new_with(StdLib::ALL_SAFE, LuaOptions::new())?;
+        match lua.set_memory_limit(options.memory_limit) {
+            Ok(_) | Err(Error::MemoryLimitNotAvailable) => {}
+            Err(e) => return Err(e),
+        }
+
+        let budget = Arc::new(Mutex::new(Budget {
+            instruction_limit: options.instruction_limit,
+            time_limit: options.time_limit,
+            instructions: 0,
+            started: None,
+            profile: options.profile.then(Profiler::default),
+        }));
+        let hook_budget = Arc::clone(&budget);
+        lua.set_hook(
+            HookTriggers {
+                on_calls: options.profile,
+                on_returns: options.profile,
+                every_nth_instruction: Some(HOOK_INTERVAL),
+                ..Default::default()
+            },
+            move |_lua, debug| {
+                let mut budget = hook_budget.lock().unwrap();
+                match debug.event() {
+                    DebugEvent::Count => budget.spend(HOOK_INTERVAL),
+                    _ => {
+                        budget.trace(&debug);
+                        Ok(())
+                    }
+                }
+            },
+        )?;
+
+        {
+            // libs
+            load_libs(&lua, &options.lib_path)?;
+            sandbox(&lua, script.parent().unwrap_or(Path::new("")))?;
+
+            let globals = lua.globals();
+            let g_canvas = lua.create_table()?;
+
+            // canvas signals
+            let signal: Table = globals.get("signal")?;
+            let signal: Function = signal.get("signal")?;
+
+            let signals = lua.create_table()?;
+            for (key, init) in options.values.iter() {
+                let value: Function = signal.call(init(&lua)?)?;
+                signals.set(key.as_str(), value)?;
+            }
+
+            lua.set_named_registry_value(VALUES_KEY, signals)?;
+
+            g_canvas.set(
+                "signal",
+                lua.create_function(|lua, name: String| {
+                    lua.create_function(move |lua, ()| {
+                        lua.named_registry_value::<_, Table>(VALUES_KEY)?
+                            .get::<_, Function>(name.clone())?
+                            .call::<_, Value>(())
+                    })
+                })?,
+            )?;
+
+            // instruction opcodes
+            let opcodes = lua.create_table()?;
+            for (name, opcode) in options.instructions.names() {
+                opcodes.set(name, opcode)?;
+            }
+            g_canvas.set("instructions", opcodes)?;
+
+            // input
+            let mouse = lua.create_table()?;
+            mouse.set("x", 0.0)?;
+            mouse.set("y", 0.0)?;
+            mouse.set("buttons", lua.create_table()?)?;
+            g_canvas.set("mouse", mouse)?;
+            g_canvas.set("keys", lua.create_table()?)?;
+            g_canvas.set(
+                "on",
+                lua.create_function(|lua, (event, handler): (String, Function)| {
+                    let handlers: Table = lua.named_registry_value(HANDLERS_KEY)?;
+                    let list = match handlers.get::<_, Option<Table>>(event.as_str())? {
+                        Some(list) => list,
+                        None => {
+                            let list = lua.create_table()?;
+                            handlers.set(event, list.clone())?;
+                            list
+                        }
+                    };
+                    list.set(list.len()? + 1, handler)
+                })?,
+            )?;
+
+            // startup parameters
+            let env = lua.create_table()?;
+            for (key, value) in options.env.iter() {
+                env.set(key.as_str(), value.clone())?;
+            }
+            globals.set("env", env)?;
+
+            // animation
+            globals.set("canvas", g_canvas)?;
+            for (name, init) in options.globals.iter() {
+                globals.set(name.as_str(), init(&lua)?)?;
+            }
+
+            budget.lock().unwrap().start();
+            let result = load_anim(&lua, &screen, &source, &name);
+            budget.lock().unwrap().stop();
+            result?;
+        }
+
+        Ok(Animation {
+            lua,
+            instructions: options.instructions,
+            screen,
+            budget,
+            error: None,
+            drawn: DrawTime::default(),
+            name,
+            source,
+            time: 0.0,
+            rate: 1.0,
+            paused: false,
+        })
+    }
+
+    /// Registers a custom instruction under `name` and returns its opcode. An
+    /// instruction registered under an existing name replaces the old handler
+    /// and keeps its opcode. Fails once all custom opcodes are taken.
+    pub fn register_instruction(
+        &mut self,
+        name: &str,
+        handler: impl for<'lua> Fn(&'lua Lua, MultiValue<'lua>, &mut Screen<B>) -> Result<()> + 'static,
+    ) -> Result<u8> {
+        let opcode = self
+            .instructions
+            .register(name.to_owned(), Box::new(handler))?;
+        let canvas: Table = self.lua.globals().get("canvas")?;
+        let opcodes: Table = canvas.get("instructions")?;
+        opcodes.set(name, opcode)?;
+        Ok(opcode)
+    }
+
+    /// Size of the canvas in pixels.
+    pub fn size(&self) -> (f32, f32) {
+        self.screen.canvas.size()
+    }
+
+    /// Clears the canvas.
+    pub fn clear(&mut self) {
+        self.screen.canvas.clear();
+    }
+
+    /// Current position on the timeline, in seconds.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Seconds of animation that pass per second given to [`Animation::tick`].
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    /// Sets the playback rate. Negative rates are clamped to zero, play
+    /// backwards by seeking instead.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate.max(0.0);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops the timeline, [`Animation::tick`] does nothing until resumed.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Moves the timeline forward by `dt` seconds of wall-clock time, scaled
+    /// by the playback rate, unless paused.
+    pub fn tick(&mut self, dt: f32) {
+        if !self.paused {
+            self.time += dt * self.rate;
+        }
+        self.drawn = DrawTime::default();
+    }
+
+    /// How long drawing took since the last [`Animation::tick`].
+    pub fn draw_time(&self) -> DrawTime {
+        self.drawn
+    }
+
+    /// Jumps to `time` seconds. Script state can't be rewound, so seeking
+    /// backwards evaluates the script again and replays it up to `time`
+    /// without drawing.
+    pub fn seek(&mut self, time: f32) -> Result<()> {
+        let time = time.max(0.0);
+        if time < self.time {
+            self.budget.lock().unwrap().start();
+            let result = load_anim(&self.lua, &self.screen, &self.source, &self.name);
+            self.budget.lock().unwrap().stop();
+            result?;
+            self.error = None;
+
+            let mut replay = 0.0;
+            while replay < time {
+                self.call(replay, false)?;
+                replay += REPLAY_STEP;
+            }
+        }
+        self.time = time;
+        Ok(())
+    }
+
+    /// Clears the canvas and draws the frame at `time` seconds, seeking there
+    /// first.
+    pub fn advance_time(&mut self, time: f32) -> Result<()> {
+        self.seek(time)?;
+        self.clear();
+        self.draw(Layer::default())
+    }
+
+    /// Draws the frame at the current time on top of what is already on the
+    /// canvas, placed according to `layer`. An error is only returned by the
+    /// frame it happened in, later frames show it on the canvas instead.
+    pub fn draw(&mut self, layer: Layer) -> Result<()> {
+        self.screen
+            .begin_frame(Mat3::translate(layer.offset.x, layer.offset.y) * self.screen.root);
+        self.screen.canvas.set_alpha(layer.alpha);
+
+        let started = Instant::now();
+        let result = match self.error {
+            Some(_) => Ok(()),
+            None => self
+                .call(self.time, true)
+                .and_then(|_| self.screen.end_frame()),
+        };
+        self.drawn.script += started.elapsed();
+        if let Err(e) = &result {
+            self.error = Some(e.to_string());
+        }
+
+        if let Some(error) = &self.error {
+            let transform = Mat3::translate(layer.offset.x, layer.offset.y);
+            for (i, line) in error.lines().enumerate() {
+                let y = ERROR_MARGIN + (i + 1) as f32 * ERROR_SIZE * 1.25;
+                let _ = self.screen.canvas.fill_text(
+                    transform,
+                    ERROR_MARGIN,
+                    y,
+                    ERROR_SIZE,
+                    line,
+                    None,
+                );
+            }
+        }
+
+        let flushing = Instant::now();
+        self.screen.canvas.flush();
+        self.drawn.flush += flushing.elapsed();
+        self.screen.canvas.set_alpha(1.0);
+        result
+    }
+
+    /// Calls the animation function, passing emitted instructions on to the
+    /// canvas only if `draw` is set.
+    fn call(&mut self, time: f32, draw: bool) -> Result<()> {
+        self.budget.lock().unwrap().start();
+        let lua = &self.lua;
+        let screen = RefCell::new(&mut self.screen);
+        let instructions = &self.instructions;
+        let budget = &self.budget;
+        let profiling = budget.lock().unwrap().profile.is_some();
+        let handling = Cell::new(Duration::ZERO);
+
+        let result = lua.scope(|scope| {
+            // create canvas global
+            set_measure(lua, scope, |text, font| {
+                measure(&screen.borrow().canvas, text, font)
+            })?;
+
+            // create emit function
+            let emit = if draw {
+                scope.create_function_mut(|lua, (instr, args): (u8, MultiValue)| {
+                    let screen = &mut screen.borrow_mut();
+                    let started = Instant::now();
+                    let result = instruction(lua, instr, args, screen, instructions);
+                    let time = started.elapsed();
+                    handling.set(handling.get() + time);
+                    if profiling {
+                        let name = instructions
+                            .names()
+                            .find(|&(_, opcode)| opcode == instr)
+                            .map_or_else(|| instr.to_string(), |(name, _)| name.to_owned());
+                        budget.lock().unwrap().instruction(&name, time);
+                    }
+                    result
+                })?
+            } else {
+                scope.create_function(|_, _: MultiValue| Ok(()))?
+            };
+
+            // call animation
+            lua.set_app_data(ScriptTime(time));
+            let anim: Function = lua.named_registry_value(ANIM_KEY)?;
+            anim.call((time, emit))
+        });
+        self.budget.lock().unwrap().stop();
+        self.drawn.instructions += handling.get();
+        result
+    }
+
+    /// Where the script spent its time so far, in the folded format flame
+    /// graph tools read, if it is being profiled.
+    pub fn profile(&self) -> Option<String> {
+        let budget = self.budget.lock().unwrap();
+        budget.profile.as_ref().map(Profiler::folded)
+    }
+
+    /// Bytes the script's Lua state takes up.
+    pub fn used_memory(&self) -> usize {
+        self.lua.used_memory()
+    }
+
+    /// Gives access to the values registered through [`Options::value`].
+    pub fn values(
+        &self,
+        f: impl for<'lua> Fn(&'lua Lua, AnimationValues<'lua>) -> Result<()>,
+    ) -> Result<()> {
+        let lua = &self.lua;
+        f(
+            lua,
+            AnimationValues(lua, lua.named_registry_value(VALUES_KEY)?),
+        )
+    }
+
+    /// Evaluates `code` in the script's state, as an expression if it is one
+    /// and as statements otherwise, and returns everything it printed
+    /// followed by its results. Besides the script's globals, `value(key)` and
+    /// `value(key, new)` read and change the values registered through
+    /// [`Options::value`].
+    pub fn eval(&mut self, code: &str) -> Result<Vec<String>> {
+        let lua = &self.lua;
+        let screen = &self.screen;
+        let output = RefCell::new(Vec::new());
+
+        self.budget.lock().unwrap().start();
+        let result = lua.scope(|scope| {
+            set_measure(lua, scope, |text, font| measure(&screen.canvas, text, font))?;
+
+            // globals are shared with the script, console helpers are not
+            let env = lua.create_table()?;
+            let meta = lua.create_table()?;
+            meta.set("__index", lua.globals())?;
+            meta.set("__newindex", lua.globals())?;
+            env.set_metatable(Some(meta));
+
+            env.set(
+                "print",
+                scope.create_function(|lua, args: MultiValue| {
+                    let line = args
+                        .into_iter()
+                        .map(|value| display(lua, value))
+                        .collect::<Result<Vec<_>>>()?;
+                    output.borrow_mut().push(line.join("\t"));
+                    Ok(())
+                })?,
+            )?;
+            env.set(
+                "value",
+                lua.create_function(|lua, (key, value): (String, Option<Value>)| {
+                    let signal: Function =
+                        lua.named_registry_value::<_, Table>(VALUES_KEY)?.get(key)?;
+                    match value {
+                        Some(value) => signal.call::<_, Value>(value),
+                        None => signal.call::<_, Value>(()),
+                    }
+                })?,
+            )?;
+
+            let chunk = match lua
+                .load(&format!("return {}", code))
+                .set_name("console")?
+                .set_environment(env.clone())?
+                .into_function()
+            {
+                Ok(chunk) => chunk,
+                Err(_) => lua
+                    .load(code)
+                    .set_name("console")?
+                    .set_environment(env)?
+                    .into_function()?,
+            };
+
+            let results: MultiValue = chunk.call(())?;
+            for value in results {
+                let line = display(lua, value)?;
+                output.borrow_mut().push(line);
+            }
+            Ok(())
+        });
+        self.budget.lock().unwrap().stop();
+
+        result?;
+        Ok(output.into_inner())
+    }
+
+    /// Updates `canvas.mouse` and `canvas.keys` and calls the handlers the
+    /// script registered for the event with `canvas.on(event, handler)`:
+    ///
+    /// - `mousemove` with the cursor position in script coordinates;
+    /// - `mousedown` and `mouseup` with the button and the cursor position;
+    /// - `scroll` with the horizontal and vertical distance in lines;
+    /// - `keydown` and `keyup` with the name of the key.
+    pub fn input(&mut self, input: &Input) -> Result<()> {
+        let lua = &self.lua;
+        let canvas: Table = lua.globals().get("canvas")?;
+        let mouse: Table = canvas.get("mouse")?;
+
+        let (event, args) = match input {
+            Input::CursorMoved(position) => {
+                let inverse = self.screen.root.inverse().unwrap_or_else(Mat3::identity);
+                let p = inverse * *position;
+                mouse.set("x", p.x)?;
+                mouse.set("y", p.y)?;
+                ("mousemove", (p.x, p.y).to_lua_multi(lua)?)
+            }
+            Input::MouseButton { button, pressed } => {
+                let buttons: Table = mouse.get("buttons")?;
+                buttons.set(button.as_str(), *pressed)?;
+                let x: f32 = mouse.get("x")?;
+                let y: f32 = mouse.get("y")?;
+                let event = if *pressed { "mousedown" } else { "mouseup" };
+                (event, (button.as_str(), x, y).to_lua_multi(lua)?)
+            }
+            Input::Scroll(delta) => ("scroll", (delta.x, delta.y).to_lua_multi(lua)?),
+            Input::Key { key, pressed } => {
+                let keys: Table = canvas.get("keys")?;
+                keys.set(key.as_str(), *pressed)?;
+                let event = if *pressed { "keydown" } else { "keyup" };
+                (event, key.as_str().to_lua_multi(lua)?)
+            }
+        };
+
+        self.emit(event, args)
+    }
+
+    /// Calls the handlers the script registered for `event` with
+    /// `canvas.on(event, handler)`, passing them `args`.
+    pub fn emit<'lua>(&'lua self, event: &str, args: impl ToLuaMulti<'lua>) -> Result<()> {
+        let lua = &self.lua;
+        let handlers: Table = lua.named_registry_value(HANDLERS_KEY)?;
+        let list = match handlers.get::<_, Option<Table>>(event)? {
+            Some(list) => list,
+            None => return Ok(()),
+        };
+        let args = args.to_lua_multi(lua)?;
+
+        self.budget.lock().unwrap().start();
+        let result = list
+            .sequence_values::<Function>()
+            .try_for_each(|handler| handler?.call::<_, ()>(args.clone()));
+        self.budget.lock().unwrap().stop();
+        result
+    }
+
+    /// The surface this animation draws on, for drawing overlays on top of it.
+    pub fn canvas_mut(&mut self) -> &mut B {
+        &mut self.screen.canvas
+    }
+}
+
+/// Formats `value` the way Lua's `tostring` does.
+fn display<'lua>(lua: &'lua Lua, value: Value<'lua>) -> Result<String> {
+    let tostring: Function = lua.globals().get("tostring")?;
+    tostring.call(value)
+}
+
+fn instruction<'lua, B: Backend>(
+    lua: &'lua Lua,
+    instr: u8,
+    args: MultiValue<'lua>,
+    screen: &mut Screen<B>,
+    custom: &Instructions<B>,
+) -> Result<()> {
+    match instr {
+        ir::PUSH_TRANSFORM => {
+            let (_, _, a, b, c, d, e, f): (String, bool, f32, f32, f32, f32, f32, f32) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            screen.push_transform(Mat3::new(a, b, c, d, e, f))
+        }
+        ir::POP_TRANSFORM => screen.pop_transform()?,
+        ir::LINE_WIDTH => {
+            let width: f32 = FromLuaMulti::from_lua_multi(args, lua)?;
+            screen.line_width = width * screen.root_scale()
+        }
+        ir::CIRCLE => {
+            let (x, y, r): (f32, f32, f32) = FromLuaMulti::from_lua_multi(args, lua)?;
+            let middle = screen.point_at(x, y);
+            let radius = screen.root_scale() * r;
+            screen.draw_circle(middle, radius);
+        }
+        ir::MOVE_TO => {
+            let (x, y) = FromLuaMulti::from_lua_multi(args, lua)?;
+            let p = screen.point_at(x, y);
+            let path = screen.path_start();
+            path.push(PathCmd::MoveTo(p));
+        }
+        ir::LINE_TO => {
+            let (x, y) = FromLuaMulti::from_lua_multi(args, lua)?;
+            let p = screen.point_at(x, y);
+            screen.path_op(|path| path.push(PathCmd::LineTo(p)));
+        }
+        ir::CLOSE_PATH => screen.path_op(|path| path.push(PathCmd::Close)),
+        ir::STROKE_PATH => screen.path_draw(),
+        ir::TEXT => {
+            let (x, y, size, text, font): (f32, f32, f32, String, Option<String>) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            screen.draw_text(x, y, size, &text, font.as_deref())?;
+        }
+        ir::TEXT_WRAP => {
+            let (x, y, size, width, text, font): (f32, f32, f32, f32, String, Option<String>) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            let font = font.as_deref();
+            let height = measure(&screen.canvas, &text, font)?.height * size * screen.rough_scale();
+            let lines = wrap(&text, width / size, |line| {
+                Ok(measure(&screen.canvas, line, font)?.width)
+            })?;
+            for (i, line) in lines.iter().enumerate() {
+                screen.draw_text(x, y + i as f32 * height, size, line, font)?;
+            }
+        }
+        ir::ELLIPSE => {
+            let (x, y, r): (f32, f32, f32) = FromLuaMulti::from_lua_multi(args, lua)?;
+            let middle = screen.point_at(x, y);
+            let horz = screen.point_at(x + r, y) - middle;
+            let vert = screen.point_at(x, y + r) - middle;
+            screen.draw_ellipse(middle, horz, vert);
+        }
+        _ => match custom.get(instr) {
+            Some(handler) => handler(lua, args, screen)?,
+            None => {
+                return Err(Error::RuntimeError(format!(
+                    "unknown instruction {}",
+                    instr
+                )))
+            }
+        },
+    };
+    Ok(())
+}
+
+impl<B: Backend> Screen<B> {
+    /// Wraps `canvas`, mapping script coordinates so that x runs from -256 to
+    /// 256 across the width with the origin in the center.
+    pub fn new(canvas: B) -> Screen<B> {
+        let (width, height) = canvas.size();
+        let root = Mat3::new(
+            width / 2.0 / 256.0,
+            0.0,
+            0.0,
+            width / 2.0 / 256.0,
+            width / 2.0,
+            height / 2.0,
+        );
+        Screen {
+            canvas,
+            root,
+            transform_stack: vec![root],
+            line_width: 1.0,
+            path: None,
+        }
+    }
+
+    pub fn point_at(&self, x: f32, y: f32) -> Vec2 {
+        self.transform() * Vec2::new(x, y)
+    }
+    pub fn rough_scale(&self) -> f32 {
+        let trans = self.transform();
+        let rough_scale = (Vec2::new(trans.a, trans.b).len_squared().sqrt()
+            + Vec2::new(trans.c, trans.d).len_squared().sqrt())
+            / 2.0;
+        rough_scale
+    }
+    pub fn root_scale(&self) -> f32 {
+        self.root.a
+    }
+    pub fn transform(&self) -> Mat3 {
+        self.transform_stack.last().copied().unwrap_or(self.root)
+    }
+
+    pub fn push_transform(&mut self, mat: Mat3) {
+        self.transform_stack.push(self.transform() * mat);
+    }
+    /// Pops a transform pushed during this frame. The transform the frame
+    /// started with can't be popped.
+    pub fn pop_transform(&mut self) -> Result<()> {
+        if self.transform_stack.len() <= 1 {
+            return Err(Error::RuntimeError(
+                "pop_transform without a matching push_transform".to_owned(),
+            ));
+        }
+        self.transform_stack.pop();
+        Ok(())
+    }
+
+    /// Starts a frame drawn at `transform`, dropping anything left over from
+    /// the last one.
+    fn begin_frame(&mut self, transform: Mat3) {
+        self.transform_stack = vec![transform];
+        self.path = None;
+    }
+
+    /// Checks that the frame left no transforms pushed and no path unstroked.
+    fn end_frame(&mut self) -> Result<()> {
+        let depth = self.transform_stack.len().saturating_sub(1);
+        if depth > 0 {
+            return Err(Error::RuntimeError(format!(
+                "frame ended with {} transform(s) still pushed",
+                depth
+            )));
+        }
+        if self.path.take().is_some() {
+            return Err(Error::RuntimeError(
+                "frame ended with a path that was never stroked".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Draws `text` at text size `size` with its baseline at `(x, y)`, in the
+    /// coordinate space of the text instruction.
+    pub fn draw_text(
+        &mut self,
+        x: f32,
+        y: f32,
+        size: f32,
+        text: &str,
+        font: Option<&str>,
+    ) -> Result<()> {
+        let rough_scale = self.rough_scale();
+        let font_size = size * TEXT_SCALE * FONT_SIZE * rough_scale;
+
+        let transform = self.transform() * Mat3::scale(1.0 / rough_scale, 1.0 / rough_scale);
+        self.canvas
+            .fill_text(transform, x, y, font_size, text, font)
+    }
+
+    /// Size of `text` drawn by [`Screen::draw_text`] at `size`, in script
+    /// units before the current transform.
+    pub fn measure_text(&self, text: &str, size: f32, font: Option<&str>) -> Result<TextMetrics> {
+        Ok(measure(&self.canvas, text, font)?.scale(size))
+    }
+
+    pub fn draw_circle(&mut self, center: Vec2, radius: f32) {
+        self.canvas.fill_circle(center, radius)
+    }
+    /// Fills the ellipse around `center` through `center + horz` and
+    /// `center + vert`, which is a circle unless the transform scales x and y
+    /// differently or shears.
+    pub fn draw_ellipse(&mut self, center: Vec2, horz: Vec2, vert: Vec2) {
+        let (a2, b2) = (horz.len_squared(), vert.len_squared());
+        let dot = horz.x * vert.x + horz.y * vert.y;
+        if (a2 - b2).abs() < 1.0 && dot.abs() < 1.0 {
+            self.draw_circle(center, a2.sqrt());
+            return;
+        }
+
+        let mut path = Vec::with_capacity(ELLIPSE_SEGMENTS + 1);
+        for i in 0..ELLIPSE_SEGMENTS {
+            let angle = i as f32 / ELLIPSE_SEGMENTS as f32 * std::f32::consts::TAU;
+            let point = center + angle.cos() * horz + angle.sin() * vert;
+            path.push(match i {
+                0 => PathCmd::MoveTo(point),
+                _ => PathCmd::LineTo(point),
+            });
+        }
+        path.push(PathCmd::Close);
+        self.canvas.fill_path(&path);
+    }
+
+    pub fn path_start(&mut self) -> &mut Vec<PathCmd> {
+        self.path.insert(Vec::new())
+    }
+    pub fn path_op(&mut self, op: impl FnOnce(&mut Vec<PathCmd>)) {
+        if let Some(path) = self.path.as_mut() {
+            op(path);
+        }
+    }
+    pub fn path_draw(&mut self) {
+        if let Some(path) = self.path.take() {
+            self.canvas.stroke_path(&path, self.line_width);
+        }
+    }
+}
+
+/// Removes everything that reaches outside the animation: files, processes and
+/// native modules. What is left of `require` only loads the Lua files in `dir`,
+/// see [`require`]. The debug library is never loaded.
+fn sandbox(lua: &Lua, dir: &Path) -> Result<()> {
+    let globals = lua.globals();
+    for name in ["io", "os", "dofile", "loadfile", "package"] {
+        globals.set(name, Value::Nil)?;
+    }
+
+    let dir = dir.to_owned();
+    globals.set(
+        "require",
+        lua.create_function(move |lua, name: String| require(lua, &dir, &name))?,
+    )
+}
+
+/// Loads the module `name` once per evaluation of the script, from the file in
+/// `dir` it names with dots between directories, like `require` does from the
+/// current directory. Only letters, digits, `_` and `-` make up names, so it
+/// can't reach outside `dir`.
+fn require<'lua>(lua: &'lua Lua, dir: &Path, name: &str) -> Result<Value<'lua>> {
+    let required: Table = lua.named_registry_value(REQUIRED_KEY)?;
+    if let Some(module) = required.get::<_, Option<Value>>(name)? {
+        return Ok(module);
+    }
+
+    let valid = name.split('.').all(|part| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    });
+    if !valid {
+        return Err(Error::RuntimeError(format!(
+            "can't require {:?}, expected names separated by dots",
+            name
+        )));
+    }
+    let path = dir.join(name.replace('.', "/") + ".lua");
+    let module = lua
+        .load(&read_source(&path)?)
+        .set_name(&(name.to_owned() + ".lua"))?
+        .call::<_, Value>(name)?;
+    // like require, remember modules that return nothing as loaded all the same
+    let module = match module {
+        Value::Nil => Value::Boolean(true),
+        module => module,
+    };
+    required.set(name, module.clone())?;
+    Ok(module)
+}
+
+/// Evaluates the script `source` and stores the animation function it returns.
+fn load_anim<B: Backend>(lua: &Lua, screen: &Screen<B>, source: &str, name: &str) -> Result<()> {
+    // handlers registered by an earlier evaluation are gone with its state,
+    // and the modules it required are loaded again in case they changed
+    lua.set_named_registry_value(HANDLERS_KEY, lua.create_table()?)?;
+    lua.set_named_registry_value(REQUIRED_KEY, lua.create_table()?)?;
+    lua.set_app_data(ScriptTime(0.0));
+
+    lua.scope(|scope| {
+        set_measure(lua, scope, |text, font| measure(&screen.canvas, text, font))?;
+        let anim = lua.load(source).set_name(name)?.eval::<Function>()?;
+        lua.set_named_registry_value(ANIM_KEY, anim)
+    })
+}
+
+/// Sets `canvas.measure`, returning the width, height and ascender of a text,
+/// and `canvas.wrap`, returning the lines a text is broken into by the
+/// `text_wrap` instruction.
+fn set_measure<'lua, 'scope>(
+    lua: &'lua Lua,
+    scope: &Scope<'lua, 'scope>,
+    measure: impl Fn(&str, Option<&str>) -> Result<TextMetrics> + 'scope,
+) -> Result<()> {
+    let measure = Rc::new(measure);
+    let globals = lua.globals();
+    let table: Table = globals.get("canvas")?;
+
+    let measure_text = Rc::clone(&measure);
+    table.set(
+        "measure",
+        scope.create_function(move |_, (text, font): (String, Option<String>)| {
+            let metrics = measure_text(&text, font.as_deref())?;
+            Ok((metrics.width, metrics.height, metrics.ascender))
+        })?,
+    )?;
+    table.set(
+        "wrap",
+        scope.create_function(
+            move |lua, (text, size, width, font): (String, f32, f32, Option<String>)| {
+                let font = font.as_deref();
+                let lines = wrap(&text, width / size, |line| Ok(measure(line, font)?.width))?;
+                lua.create_sequence_from(lines)
+            },
+        )?,
+    )?;
+    Ok(())
+}
+
+/// Metrics of `text` in `font` in script units, at text size 1.
+fn measure<B: Backend>(canvas: &B, text: &str, font: Option<&str>) -> Result<TextMetrics> {
+    Ok(canvas
+        .measure_text(text, FONT_SIZE, font)?
+        .scale(TEXT_SCALE))
+}
+
+/// Splits `text` at newlines and wherever a line would get wider than
+/// `max_width`. Words wider than that get a line of their own.
+fn wrap(text: &str, max_width: f32, width: impl Fn(&str) -> Result<f32>) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if line.is_empty() {
+                line.push_str(word);
+                continue;
+            }
+            let longer = format!("{} {}", line, word);
+            if width(&longer)? <= max_width {
+                line = longer;
+            } else {
+                lines.push(std::mem::replace(&mut line, word.to_owned()));
+            }
+        }
+        lines.push(line);
+    }
+    Ok(lines)
+}
@@ -0,0 +1,112 @@
+//! Where a script spends its time, by the stack of functions it was in.
+//!
+//! The instruction hook tells the profiler when functions are called and
+//! return. Time is attributed to the stack it was spent in, not counting the
+//! functions called from it, and reported in the folded format flame graph
+//! tools like `inferno-flamegraph` and `flamegraph.pl` read.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    time::{Duration, Instant},
+};
+
+/// A function being run, and how long the functions it called took.
+struct Frame {
+    label: String,
+    entered: Instant,
+    children: Duration,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    stack: Vec<Frame>,
+    /// Time spent in every stack, by its labels joined with `;`.
+    totals: BTreeMap<String, Duration>,
+}
+
+impl Profiler {
+    /// Forgets the functions being run, which are left behind when a script
+    /// errors out of them.
+    pub fn clear_stack(&mut self) {
+        self.stack.clear();
+    }
+
+    pub fn enter(&mut self, label: &str, now: Instant) {
+        self.stack.push(Frame {
+            // the folded format separates frames with semicolons and the
+            // count with a space
+            label: label.replace([';', ' '], "_"),
+            entered: now,
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Counts the time spent in the function on top since it was entered.
+    pub fn leave(&mut self, now: Instant) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let time = now.saturating_duration_since(frame.entered);
+        let key = self.key(&frame.label);
+        *self.totals.entry(key).or_default() += time.saturating_sub(frame.children);
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += time;
+        }
+    }
+
+    /// Counts `time` spent in something that isn't a function, like handling
+    /// an instruction, as if called from the function on top.
+    pub fn record(&mut self, label: &str, time: Duration) {
+        let key = self.key(&label.replace([';', ' '], "_"));
+        *self.totals.entry(key).or_default() += time;
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += time;
+        }
+    }
+
+    fn key(&self, label: &str) -> String {
+        let mut key = String::new();
+        for frame in self.stack.iter() {
+            key.push_str(&frame.label);
+            key.push(';');
+        }
+        key.push_str(label);
+        key
+    }
+
+    /// Every stack with the microseconds spent in it, a line each.
+    pub fn folded(&self) -> String {
+        let mut out = String::new();
+        for (stack, time) in self.totals.iter() {
+            writeln!(out, "{} {}", stack, time.as_micros()).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time_goes_to_the_innermost_function() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut profiler = Profiler::default();
+        profiler.enter("anim (mario.lua:3)", at(0));
+        profiler.enter("draw", at(2));
+        profiler.record("instruction nes_frame", Duration::from_millis(1));
+        profiler.leave(at(5));
+        profiler.leave(at(10));
+        profiler.leave(at(11));
+
+        assert_eq!(
+            profiler.folded(),
+            "anim_(mario.lua:3) 7000\n\
+             anim_(mario.lua:3);draw 2000\n\
+             anim_(mario.lua:3);draw;instruction_nes_frame 1000\n"
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+
+use mlua::Result;
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+use super::{Backend, Mat3, PathCmd, TextMetrics, Vec2};
+
+/// A backend that draws on the CPU, for rendering scripts where there is no
+/// GPU at all.
+///
+/// Shapes are drawn in white on black like on the real canvas. Text is drawn
+/// as one box per character whatever the font, which is enough to check
+/// layout without depending on a font rasterizer.
+pub struct Raster {
+    pixmap: Pixmap,
+    alpha: f32,
+}
+
+impl Raster {
+    /// A black surface of `width` by `height` pixels, or `None` if either is
+    /// zero.
+    pub fn new(width: u32, height: u32) -> Option<Raster> {
+        Some(Raster {
+            pixmap: Pixmap::new(width, height)?,
+            alpha: 1.0,
+        })
+    }
+
+    /// What has been drawn so far.
+    pub fn pixmap(&self) -> &Pixmap {
+        &self.pixmap
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.pixmap
+            .save_png(path)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+    }
+
+    /// Fills a rectangle in the coordinate space given by `transform` with
+    /// `gray`, for standing in for images.
+    pub fn fill_rect(&mut self, transform: Mat3, origin: Vec2, size: Vec2, gray: u8) {
+        if let Some(rect) = Rect::from_xywh(origin.x, origin.y, size.x, size.y) {
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(gray, gray, gray, (self.alpha * 255.0) as u8);
+            self.pixmap
+                .fill_rect(rect, &paint, to_transform(transform), None);
+        }
+    }
+
+    fn paint(&self) -> Paint<'static> {
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(255, 255, 255, (self.alpha * 255.0) as u8);
+        paint
+    }
+
+    fn fill(&mut self, path: Option<tiny_skia::Path>, transform: Transform) {
+        if let Some(path) = path {
+            let paint = self.paint();
+            self.pixmap
+                .fill_path(&path, &paint, FillRule::Winding, transform, None);
+        }
+    }
+}
+
+fn to_path(path: &[PathCmd]) -> Option<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+    for cmd in path {
+        match *cmd {
+            PathCmd::MoveTo(p) => builder.move_to(p.x, p.y),
+            PathCmd::LineTo(p) => builder.line_to(p.x, p.y),
+            PathCmd::Close => builder.close(),
+        }
+    }
+    builder.finish()
+}
+
+fn to_transform(transform: Mat3) -> Transform {
+    Transform::from_row(
+        transform.a,
+        transform.b,
+        transform.c,
+        transform.d,
+        transform.e,
+        transform.f,
+    )
+}
+
+impl Backend for Raster {
+    fn size(&self) -> (f32, f32) {
+        (self.pixmap.width() as f32, self.pixmap.height() as f32)
+    }
+
+    fn clear(&mut self) {
+        self.pixmap.fill(tiny_skia::Color::BLACK);
+    }
+
+    fn flush(&mut self) {}
+
+    fn set_alpha(&mut self, alpha: f32) {
+        self.alpha = alpha;
+    }
+
+    fn fill_circle(&mut self, center: Vec2, radius: f32) {
+        let circle = PathBuilder::from_circle(center.x, center.y, radius);
+        self.fill(circle, Transform::identity());
+    }
+
+    fn fill_path(&mut self, path: &[PathCmd]) {
+        self.fill(to_path(path), Transform::identity());
+    }
+
+    fn stroke_path(&mut self, path: &[PathCmd], width: f32) {
+        if let Some(path) = to_path(path) {
+            let stroke = Stroke {
+                width,
+                ..Stroke::default()
+            };
+            let paint = self.paint();
+            self.pixmap
+                .stroke_path(&path, &paint, &stroke, Transform::identity(), None);
+        }
+    }
+
+    fn fill_text(
+        &mut self,
+        transform: Mat3,
+        x: f32,
+        y: f32,
+        size: f32,
+        text: &str,
+        _font: Option<&str>,
+    ) -> Result<()> {
+        let transform = to_transform(transform);
+        for (i, c) in text.chars().enumerate() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let left = x + i as f32 * size + size * 0.1;
+            let glyph = Rect::from_xywh(left, y - size * 0.9, size * 0.8, size * 0.8);
+            self.fill(glyph.map(PathBuilder::from_rect), transform);
+        }
+        Ok(())
+    }
+
+    fn measure_text(&self, text: &str, size: f32, _font: Option<&str>) -> Result<TextMetrics> {
+        Ok(TextMetrics {
+            width: text.chars().count() as f32 * size,
+            height: size,
+            ascender: size * 0.9,
+        })
+    }
+}
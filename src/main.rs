@@ -1,584 +1,1191 @@
 use std::{
-    collections::VecDeque,
-    fs::read,
-    sync::{
-        atomic::{AtomicU8, Ordering},
-        mpsc, Arc, Mutex,
-    },
-    thread,
-    time::Instant,
+    fs::{create_dir_all, read, File},
+    io,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, mpsc, Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
 
-use fastnes::{
-    cart::{Cartridge, NROM},
-    input::Controllers,
-    nes::NES,
-    ppu::{Color, DrawOptions, FastPPU},
-};
-use femtovg::{imgref::Img, renderer::OpenGl, rgb::RGBA8, Canvas, ImageFlags, Paint, Path};
-use glutin::{
-    config::{Config, ConfigTemplateBuilder},
-    context::{ContextApi, ContextAttributesBuilder},
-    display::GetGlDisplay,
-    prelude::{GlDisplay, NotCurrentGlContextSurfaceAccessor},
-    surface::{GlSurface, SurfaceAttributesBuilder},
-};
-use glutin_winit::{DisplayBuilder, GlWindow};
-use luanim::Animation;
-use notify::{Event, EventKind, RecursiveMode, Watcher};
-use rand::Rng;
-use raw_window_handle::HasRawWindowHandle;
-use rlua::{FromLuaMulti, Result, Table};
-use spin_sleep::LoopHelper;
-use threadpool::ThreadPool;
-use winit::{
-    dpi::PhysicalSize,
-    event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use femtovg::renderer::OpenGl;
+use notify::{RecursiveMode, Watcher};
+use shellkick::{
+    affinity::{Cores, Placement},
+    app::{
+        animate::animate,
+        commands::{bench, diff_states, practice, render, test_script, verify},
+        dashboard::{Dashboard, DASHBOARD},
+        load_save_ram,
+        output::{Output, Sink},
+        platform::Surface,
+        script::{instances, load_fonts, Font, ScriptState, INSTANCES, MAX_INSTANCES},
+        simulation::{self, shape_teams, start_warped},
+        App, Setup, HEIGHT, WIDTH,
+    },
+    brain::{self, Brain},
+    captions::Captions,
+    control::Control,
+    error::{self, Error},
+    experiment::{self, Axis, Experiment, Param},
+    input_log::InputLog,
+    logbook::Logbook,
+    luanim::{EnvValue, FontCanvas},
+    mario::{self, Annealing, FrameSkip, Mario, RevertPolicy, Settings},
+    neat::Pool,
+    obstacles::Obstacles,
+    poke::Allowlist,
+    population,
+    practice::Segment,
+    ramwatch::{self, Watch},
+    rom::{self, Header, Identity, Region},
+    savestate,
+    scaling::Scaling,
+    scene::{Scene, Scenes, Transition},
+    sfx::{Player, Sound},
+    smb::{
+        map::{self, MemoryMap},
+        Objective, Warp,
+    },
+    sram::SaveRam,
+    stagnation::Intervention,
+    teams::{Scoreboard, Teams},
 };
+use tracing::{error, info, warn, Level};
+use tracing_subscriber::{filter::LevelFilter, fmt, layer, prelude::*};
+use winit::event_loop::EventLoop;
+
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Append fitness samples of every instance to this file (CSV, or JSON lines for .json)
+    #[arg(long, value_name = "FILE")]
+    log_fitness: Option<PathBuf>,
+
+    /// Seconds between fitness samples
+    #[arg(long, value_name = "SECONDS", default_value = "1", value_parser = parse_seconds)]
+    log_interval: Duration,
+
+    /// Most verbose log level to output (error, warn, info, debug, trace)
+    #[arg(long, value_name = "LEVEL", default_value_t = Level::INFO)]
+    log_level: Level,
+
+    /// Also write logs to this file as JSON lines
+    #[arg(long, value_name = "FILE")]
+    log_json: Option<PathBuf>,
+
+    /// Load an animation script as a named scene, can be given multiple times.
+    /// Number keys switch between scenes in the order given, tab goes to the next one.
+    /// Space pauses the current scene, left and right seek, up and down change its speed.
+    /// Backtick opens a Lua console for the current scene
+    #[arg(long = "scene", value_name = "NAME=FILE", value_parser = parse_key_value::<PathBuf>)]
+    scenes: Vec<(String, PathBuf)>,
+
+    /// Open a second window drawing this script, for what only the operator should see. It hears
+    /// the same events as the scenes and gets input of its own
+    #[arg(long, value_name = "FILE")]
+    dashboard: Option<PathBuf>,
+
+    /// Load a font scripts can draw text in by name, can be given multiple times. The first one
+    /// is used when a script doesn't name one, the stream font when none are given
+    #[arg(long = "font", value_name = "NAME=FILE", value_parser = parse_key_value::<PathBuf>)]
+    fonts: Vec<(String, PathBuf)>,
+
+    /// How to switch between scenes (cut, fade or slide)
+    #[arg(long, value_name = "STYLE", default_value = "fade")]
+    transition: Transition,
+
+    /// Seconds a scene transition takes
+    #[arg(long, value_name = "SECONDS", default_value = "1", value_parser = parse_seconds)]
+    transition_time: Duration,
+
+    /// Go to the next scene every this many seconds
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    scene_rotate: Option<Duration>,
+
+    /// Answer HTTP requests on this address, like 127.0.0.1:8080. POST /scene/NAME switches to
+    /// the scene called NAME, POST /pause pauses or resumes the simulation like P does
+    #[arg(long, value_name = "ADDR")]
+    control: Option<String>,
+
+    /// Stream title, available to scripts as env.title
+    #[arg(long, default_value = "")]
+    title: String,
+
+    /// Theme name, available to scripts as env.theme
+    #[arg(long, default_value = "default")]
+    theme: String,
+
+    /// Extra string available to scripts as env.KEY, can be given multiple times
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_key_value::<String>)]
+    env: Vec<(String, String)>,
+
+    /// Pixels a Mario has to get further into a level before saving its state again
+    #[arg(long, value_name = "PIXELS", default_value_t = 16)]
+    checkpoint_distance: u64,
+
+    /// Frames a Mario goes back after dying, on top of its latest saved state
+    #[arg(long, value_name = "FRAMES", default_value_t = 0)]
+    revert_death: u64,
+
+    /// Frames a Mario goes back after running out of time, two minutes' worth by default
+    #[arg(long, value_name = "FRAMES")]
+    revert_timeout: Option<u64>,
+
+    /// Extra frames to go back after dying at the same spot again, doubled for every further death
+    #[arg(long, value_name = "FRAMES", default_value_t = 0)]
+    revert_backoff: u64,
+
+    /// Most frames a single revert goes back
+    #[arg(long, value_name = "FRAMES")]
+    revert_max: Option<u64>,
+
+    /// What Marios go for besides distance (distance, score, coins or speed)
+    #[arg(long, value_name = "OBJECTIVE", default_value = "distance")]
+    objective: Objective,
+
+    /// Make Marios calmer the deeper into the game they get
+    #[arg(long)]
+    anneal: bool,
+
+    /// Factor twitchy and jumpy are multiplied by for every level in, with --anneal
+    #[arg(long, value_name = "FACTOR", default_value_t = 0.9)]
+    anneal_calm: f32,
+
+    /// Stuck iterations added to patient for every level in, with --anneal
+    #[arg(long, value_name = "ITERATIONS", default_value_t = 1)]
+    anneal_patience: u32,
+
+    /// Frames to emulate at once while a Mario is in a cutscene, 1 to play them at normal speed
+    #[arg(long, value_name = "FRAMES", default_value_t = 1)]
+    cutscene_speed: u32,
+
+    /// Run Marios that haven't been drawn for a while this many frames at a time, every this many
+    /// frames, to leave more time for the ones on screen
+    #[arg(long, value_name = "FRAMES")]
+    frame_skip: Option<u32>,
+
+    /// Seconds a Mario has to go without being drawn for --frame-skip to apply
+    #[arg(long, value_name = "SECONDS", default_value_t = 2.0)]
+    hidden_after: f32,
+
+    /// Pixels of distance each powerup is worth to Marios, 0 to not care about powerups
+    #[arg(long, value_name = "PIXELS", default_value_t = 0)]
+    powerup_bonus: u64,
+
+    /// Start a Mario over from the title screen when it crashes, instead of leaving it stopped
+    #[arg(long)]
+    reset_errored: bool,
+
+    /// Mebibytes the saved states of all Marios may take up, dropping the oldest past it
+    #[arg(long, value_name = "MIB")]
+    max_memory: Option<u64>,
+
+    /// Cores the simulation workers may run on, like 0-5,7 (Linux only)
+    #[arg(long, value_name = "CORES")]
+    worker_cores: Option<Cores>,
+
+    /// Niceness of the simulation workers, from -20 to 19 with higher giving way (Linux only)
+    #[arg(long, value_name = "NICE", allow_negative_numbers = true)]
+    worker_nice: Option<i32>,
+
+    /// Cores the render loop may run on, like 6-7 (Linux only)
+    #[arg(long, value_name = "CORES")]
+    render_cores: Option<Cores>,
+
+    /// Niceness of the render loop, from -20 to 19 with higher giving way (Linux only)
+    #[arg(long, value_name = "NICE", allow_negative_numbers = true)]
+    render_nice: Option<i32>,
+
+    /// Wait for the display to refresh before showing a frame (on or off)
+    #[arg(long, value_name = "on|off", default_value = "on", value_parser = parse_on_off)]
+    vsync: bool,
+
+    /// Marios in the show, up to 256
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = MAX_INSTANCES,
+        value_parser = parse_population
+    )]
+    instances: usize,
+
+    /// Time a short run before starting and show as many Marios as keep up with the frame rate,
+    /// up to --instances
+    #[arg(long)]
+    auto: bool,
+
+    /// Worker threads to run the Marios on, one less than the cores by default
+    #[arg(long, value_name = "COUNT")]
+    threads: Option<usize>,
+
+    /// Draw at most this many frames per second
+    #[arg(long, value_name = "FPS")]
+    max_fps: Option<f64>,
+
+    /// Clear to transparent in a window with per-pixel alpha, to show only what scripts draw on
+    /// top of another capture. Scripts see this as env.transparent
+    #[arg(long)]
+    transparent: bool,
+
+    /// Also send every rendered frame here, without the console (ndi:NAME is an NDI source OBS can
+    /// add, with the ndi feature; pipe:PATH writes raw 1920x1080 RGBA frames to a file or named
+    /// pipe, for ffmpeg to pass on to OBS or a stream)
+    #[arg(long, value_name = "OUTPUT")]
+    output: Option<Output>,
+
+    /// How NES frames are resampled at scales that aren't whole (nearest, snap or sharp), unless
+    /// a script asks for another
+    #[arg(long, value_name = "MODE", default_value = "nearest")]
+    scaling: Scaling,
+
+    /// Pause the simulation while neither the window nor the dashboard has focus, or the window
+    /// is minimized and the dashboard doesn't have focus
+    #[arg(long)]
+    pause_unfocused: bool,
+
+    /// Halve how far ahead Marios plan whenever the simulation is behind for five seconds
+    #[arg(long)]
+    throttle: bool,
+
+    /// Intervene when no Mario got further than the best so far for this many seconds.
+    /// Scripts are told with a "stagnation" event
+    #[arg(long, value_name = "SECONDS")]
+    stagnation: Option<f32>,
+
+    /// Intervene when the personalities of the Marios get more alike than this, from 0 when
+    /// they are all the same to 1. Measured every minute and sent to scripts as a "diversity" event
+    #[arg(long, value_name = "FRACTION")]
+    diversity_floor: Option<f32>,
+
+    /// What to do when the population stagnates (mutate, inject or lookahead)
+    #[arg(long, value_name = "STYLE", default_value = "inject")]
+    intervention: Intervention,
+
+    /// Write a recap of the session with snapshots of the best Marios to this directory on exit,
+    /// or earlier with the R key
+    #[arg(long, value_name = "DIR")]
+    recap: Option<PathBuf>,
+
+    /// Play every level clear again in the background and encode it to an MP4 in this directory,
+    /// which needs ffmpeg
+    #[arg(long, value_name = "DIR")]
+    highlights: Option<PathBuf>,
+
+    /// Write every input each Mario plays and every time it reverts to mario-N.inputs in this
+    /// directory, to play any moment again later
+    #[arg(long, value_name = "DIR")]
+    input_log: Option<PathBuf>,
+
+    /// Keep track of where every scene's script spends its time, and write it as a flame graph to
+    /// SCENE.folded in this directory on exit
+    #[arg(long, value_name = "DIR")]
+    profile_lua: Option<PathBuf>,
+
+    /// TV system to run the game at the frame rate of (ntsc or pal), by default the one the ROM's
+    /// header names
+    #[arg(long, value_name = "REGION")]
+    region: Option<Region>,
+
+    /// Read the addresses of the game's state from this Lua file instead of maps/SHA1.lua, for
+    /// ROM hacks that keep it elsewhere
+    #[arg(long, value_name = "FILE")]
+    memory_map: Option<PathBuf>,
+
+    /// Directory F5 saves the state of the Mario that got the furthest to, for F9 to load again
+    #[arg(long, value_name = "DIR", default_value = "states")]
+    states: PathBuf,
+
+    /// Start a Mario in another level than 1-1 by writing it into RAM after the title screen, can
+    /// be given more than once. Its input log and states won't replay
+    #[arg(long = "start", value_name = "INSTANCE=LEVEL", value_parser = parse_instance_warp)]
+    starts: Vec<(usize, Warp)>,
+
+    /// Start this many Marios not given a --start in random levels
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    random_starts: usize,
+
+    /// Give the first half of the Marios this personality trait (patient, bold, playful, twitchy,
+    /// jumpy, confident or rollouts), can be given more than once. Scripts compare the halves with
+    /// teams()
+    #[arg(long, value_name = "PARAM=VALUE", value_parser = parse_team_param)]
+    team_a: Vec<(Param, f64)>,
+
+    /// Give the second half of the Marios this personality trait, like --team-a
+    #[arg(long, value_name = "PARAM=VALUE", value_parser = parse_team_param)]
+    team_b: Vec<(Param, f64)>,
+
+    /// Play the last COUNT Marios with small neural networks evolved with NEAT instead of planning
+    /// ahead. Scripts follow their evolution with neat()
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    neat: usize,
+
+    /// Networks in every generation of the --neat Marios
+    #[arg(long, value_name = "SIZE", default_value_t = 60)]
+    neat_population: usize,
+
+    /// Learn where Marios keep dying and which inputs got them past, and have them try those
+    /// again. Kept per level in this directory across runs
+    #[arg(long, value_name = "DIR")]
+    obstacles: Option<PathBuf>,
+
+    /// Keep the battery-backed save RAM of a ROM whose header says it has one in this directory
+    /// between runs
+    #[arg(long, value_name = "DIR")]
+    save_ram: Option<PathBuf>,
+
+    /// Start a Mario from a state saved earlier, can be given more than once
+    #[arg(long = "load-state", value_name = "INSTANCE=FILE", value_parser = parse_instance_path)]
+    load_states: Vec<(usize, PathBuf)>,
+
+    /// Start a countdown scripts see in the timers value and are told about with a "timer" event
+    /// when it runs out, can be given more than once
+    #[arg(long = "timer", value_name = "NAME=SECONDS", value_parser = parse_timer)]
+    timers: Vec<(String, Duration)>,
+
+    /// Watch an address of RAM, or a field of the memory map like lives, as NAME for every Mario.
+    /// Scripts see it in the ram table of every Mario and draw it with ram_watch, can be given
+    /// more than once
+    #[arg(long = "watch", value_name = "NAME=ADDRESS", value_parser = parse_watch)]
+    watches: Vec<Watch>,
+
+    /// Let scripts write to an address of RAM, or a field of the memory map like powerup, with
+    /// poke. Can be given more than once, nothing can be written without it
+    #[arg(long = "allow-write", value_name = "ADDRESS", value_parser = ramwatch::target)]
+    allow_writes: Vec<(u16, u16)>,
+
+    /// Show the captions in this SubRip (.srt) file to scripts through captions.current(), timed
+    /// from when the show starts
+    #[arg(long, value_name = "FILE")]
+    captions: Option<PathBuf>,
+
+    /// Play this sound file when a Mario dies, clears a level or gets further than anyone before
+    /// (death, clear or record), can be given more than once. Needs the audio feature
+    #[arg(long = "sfx", value_name = "SOUND=FILE", value_parser = parse_sound)]
+    sfx: Vec<(Sound, PathBuf)>,
+
+    /// Volume of the --sfx sounds, from 0 for silent to 1 for as loud as the files
+    #[arg(long, value_name = "FRACTION", default_value_t = 1.0)]
+    sfx_volume: f32,
+}
 
-mod luanim;
-
-const WIDTH: usize = 1920;
-const HEIGHT: usize = 1080;
+#[derive(Subcommand)]
+enum Command {
+    /// Run a script without a window or emulator for a number of frames and
+    /// report any Lua errors
+    TestScript {
+        file: PathBuf,
 
-unsafe fn as_rgba<const N: usize>(p: &[Color; N]) -> &[RGBA8] {
-    ::core::slice::from_raw_parts(
-        (p as *const [Color; N]) as *const RGBA8,
-        ::core::mem::size_of::<[Color; N]>(),
-    )
+        /// Frames to run, at 60 per second
+        #[arg(long, default_value_t = 600)]
+        frames: u32,
+    },
+    /// Render a script without a window or GPU to numbered PNG files, with gray boxes standing
+    /// in for NES frames
+    Render {
+        file: PathBuf,
+
+        /// Frames to render, at 60 per second
+        #[arg(long, default_value_t = 60)]
+        frames: u32,
+
+        /// Directory to write the frames to
+        #[arg(long, default_value = "frames")]
+        out: PathBuf,
+    },
+    /// Run Marios without a window as fast as possible and report how long
+    /// their frames take
+    Bench {
+        /// Marios to run
+        #[arg(long, default_value_t = 64)]
+        instances: usize,
+
+        /// Frames to run every Mario for
+        #[arg(long, default_value_t = 600)]
+        frames: u32,
+
+        /// Worker threads to run them on, one less than the cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Have every Mario play the same stretch of a level over and over, from a save state to a
+    /// position in its level, and report how often each personality gets through
+    Practice {
+        /// Save state to start every attempt from, as saved with F5
+        state: PathBuf,
+
+        /// Pixels into the level that count as getting through
+        #[arg(long, value_name = "PIXELS")]
+        target: u16,
+
+        /// Attempts every Mario makes
+        #[arg(long, default_value_t = 20)]
+        attempts: u32,
+
+        /// Seconds an attempt gets before it counts as failed
+        #[arg(long, value_name = "SECONDS", default_value_t = 60.0)]
+        timeout: f32,
+
+        /// Marios to run
+        #[arg(long, default_value_t = 64)]
+        instances: usize,
+
+        /// Worker threads to run them on, one less than the cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+    },
+    /// Play Marios with every combination of the given values, or with random ones, for a number
+    /// of frames and write how far each got as CSV
+    Experiment {
+        /// Values to try for a parameter (patient, bold, playful, twitchy, jumpy, confident,
+        /// rollouts, checkpoint or death), can be given once per parameter
+        #[arg(long = "grid", value_name = "PARAM=VALUE,...")]
+        axes: Vec<Axis>,
+
+        /// Try this many random configurations instead of a grid
+        #[arg(long, value_name = "COUNT")]
+        random: Option<usize>,
+
+        /// Parameters random configurations vary, all of them if not given
+        #[arg(long = "vary", value_name = "PARAM")]
+        vary: Vec<Param>,
+
+        /// Marios to play every configuration with
+        #[arg(long, default_value_t = 4)]
+        runs: usize,
+
+        /// Frames every Mario plays
+        #[arg(long, default_value_t = 60 * 60 * 5)]
+        frames: u32,
+
+        /// Worker threads to run them on, one less than the cores by default
+        #[arg(long)]
+        threads: Option<usize>,
+
+        /// File to write the CSV to instead of standard output
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Play an input log written with --input-log again on a fresh NES and check that it ends up
+    /// where the Mario that wrote it was
+    Verify { log: PathBuf },
+    /// Compare the RAM of two save states and list the addresses that differ, named after the
+    /// fields of the memory map they are in
+    Diff {
+        /// Save state from before, as saved with F5
+        before: PathBuf,
+
+        /// Save state from after
+        after: PathBuf,
+
+        /// File to write the differences to as CSV instead of listing them
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
 }
 
-#[derive(Clone, Debug)]
-struct Personality {
-    patient: u32, // stuck iterations before random movement
-    bold: u32,    // frames per random movement
-    playful: u32, // frames per regular movement
-    twitchy: f32, // likelyhood of direction switch per frame
-    jumpy: f32,   // likelyhood of A switch per frame
+fn parse_key_value<T: From<String>>(s: &str) -> Result<(String, T), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {:?}", s))?;
+    Ok((key.to_owned(), value.to_owned().into()))
+}
 
-    confident: u32, // iterations per save state
+fn parse_instance(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(instance) if (1..=MAX_INSTANCES).contains(&instance) => Ok(instance),
+        _ => Err(format!(
+            "expected an instance from 1 to {}, got {:?}",
+            MAX_INSTANCES, s
+        )),
+    }
 }
 
-struct Mario {
-    personality: Personality,
-    being_random: Option<u32>,
+fn parse_population(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(count) if (1..=MAX_INSTANCES).contains(&count) => Ok(count),
+        _ => Err(format!(
+            "expected from 1 to {} Marios, got {:?}",
+            MAX_INSTANCES, s
+        )),
+    }
+}
 
-    stuck_count: u32,
-    inputs_future: VecDeque<u8>,
-    last_input: u8,
-    next_state: u32,
+fn parse_instance_path(s: &str) -> Result<(usize, PathBuf), String> {
+    let (instance, path) = parse_key_value::<PathBuf>(s)?;
+    Ok((parse_instance(&instance)?, path))
+}
 
-    states: VecDeque<NES<NROM, FastPPU>>,
+fn parse_instance_warp(s: &str) -> Result<(usize, Warp), String> {
+    let (instance, warp) = parse_key_value::<String>(s)?;
+    Ok((parse_instance(&instance)?, warp.parse()?))
 }
 
-fn next_input(prev: u8, personality: &Personality) -> u8 {
-    let mut rng = rand::thread_rng();
-    let mut next = prev;
-    if rng.gen_range(0.0..1.0) < personality.twitchy {
-        let dir = 1 << rng.gen_range(4..8);
-        next = (next & 0b00001111) | dir;
+fn parse_team_param(s: &str) -> Result<(Param, f64), String> {
+    let (param, value) = parse_key_value::<String>(s)?;
+    let param: Param = param.parse()?;
+    if !param.personal() {
+        return Err(format!(
+            "{} is shared by every Mario, it can't differ between teams",
+            param.name()
+        ));
     }
-    if rng.gen_range(0.0..1.0) < personality.jumpy {
-        next ^= 0b1
+    match value.parse() {
+        Ok(value) => Ok((param, value)),
+        Err(_) => Err(format!("expected a number, got {:?}", value)),
     }
-    next | 0b10 // always press B
 }
 
-#[derive(PartialEq)]
-enum Fitness {
-    Dying(bool),
-    Cutscene,
-    Level(u64),
+fn parse_sound(s: &str) -> Result<(Sound, PathBuf), String> {
+    let (sound, path) = parse_key_value::<PathBuf>(s)?;
+    Ok((sound.parse()?, path))
 }
 
-impl PartialOrd for Fitness {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match (self, other) {
-            (Fitness::Dying(_), Fitness::Dying(_)) => Some(std::cmp::Ordering::Equal),
-            (Fitness::Dying(_), Fitness::Cutscene) => Some(std::cmp::Ordering::Less),
-            (Fitness::Dying(_), Fitness::Level(_)) => Some(std::cmp::Ordering::Less),
-            (Fitness::Cutscene, Fitness::Dying(_)) => Some(std::cmp::Ordering::Greater),
-            (Fitness::Cutscene, Fitness::Cutscene) => Some(std::cmp::Ordering::Equal),
-            (Fitness::Cutscene, Fitness::Level(_)) => Some(std::cmp::Ordering::Greater),
-            (Fitness::Level(_), Fitness::Dying(_)) => Some(std::cmp::Ordering::Greater),
-            (Fitness::Level(_), Fitness::Cutscene) => Some(std::cmp::Ordering::Less),
-            (Fitness::Level(a), Fitness::Level(b)) => u64::partial_cmp(a, b),
-        }
+fn parse_timer(s: &str) -> Result<(String, Duration), String> {
+    let (name, seconds) = parse_key_value::<String>(s)?;
+    let duration = seconds
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+    match duration {
+        Some(duration) if Instant::now().checked_add(duration).is_some() => Ok((name, duration)),
+        _ => Err(format!("expected a number of seconds, got {:?}", seconds)),
     }
 }
 
-fn fitness(nes: &mut NES<NROM, FastPPU>) -> Fitness {
-    let level_pos = u16::from(nes.read(0x6d)) << 8 // screen page
-                    | u16::from(nes.read(0x86)); // screen x
+/// A positive number of seconds, which `Duration` can hold.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    let duration = s
+        .parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok());
+    match duration {
+        Some(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(format!(
+            "expected a positive number of seconds, got {:?}",
+            s
+        )),
+    }
+}
 
-    let mario_position: u32 = u32::from(nes.read(0x075f)) << 24
-        | u32::from(nes.read(0x0760)) << 16
-        | u32::from(level_pos);
+fn parse_watch(s: &str) -> Result<Watch, String> {
+    let (name, target) = parse_key_value::<String>(s)?;
+    Watch::new(name, &target, None)
+}
 
-    let engine = nes.read(0x0e);
-    let task = nes.read(0x0772);
-    let mode = nes.read(0x0770);
+fn parse_on_off(s: &str) -> Result<bool, String> {
+    match s {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got {:?}", s)),
+    }
+}
 
-    let mario_y = u16::from(nes.read(0xb5)) << 8 | u16::from(nes.read(0xce));
-    let cutscene = engine <= 5 || engine == 7 || mode == 2 || (mode == 1 && task != 3);
-    let dying =
-        (mario_y > 456 || engine == 6 || engine == 11 || mode == 0 || mode == 3) && !cutscene;
+/// Sets up logging, returning where the warnings and errors logged are kept
+/// for scripts.
+fn init_logging(args: &Args) -> anyhow::Result<Arc<Mutex<Logbook>>> {
+    let json = match &args.log_json {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("could not create log file {}", path.display()))?;
+            Some(fmt::layer().json().with_writer(Mutex::new(file)))
+        }
+        None => None,
+    };
 
-    let time = u16::from(nes.read(0x07f8)) * 100
-        + u16::from(nes.read(0x07f9)) * 10
-        + u16::from(nes.read(0x07fa));
+    let logbook = Arc::new(Mutex::new(Logbook::default()));
+    tracing_subscriber::registry()
+        .with(LevelFilter::from_level(args.log_level))
+        .with(fmt::layer())
+        .with(json)
+        .with(Recorder(logbook.clone()))
+        .init();
+    Ok(logbook)
+}
 
-    let out_of_time = time == 0 && !cutscene;
+/// Keeps the warnings and errors logged in a [`Logbook`].
+struct Recorder(Arc<Mutex<Logbook>>);
 
-    if dying || out_of_time {
-        Fitness::Dying(out_of_time)
-    } else if cutscene {
-        Fitness::Cutscene
-    } else {
-        Fitness::Level(u64::from(mario_position))
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Recorder {
+    fn on_event(&self, event: &tracing::Event<'_>, _: layer::Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+        let mut message = Message::default();
+        event.record(&mut message);
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).push(
+            level,
+            message.0,
+            Instant::now(),
+        );
     }
 }
 
-fn victory(nes: &mut NES<NROM, FastPPU>) -> bool {
-    nes.read(0x0770) == 2
+/// The message of an event, followed by its fields.
+#[derive(Default)]
+struct Message(String);
+
+impl tracing::field::Visit for Message {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{:?}", value));
+        } else {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
 }
 
-fn scroll(nes: &mut NES<NROM, FastPPU>) -> u32 {
-    let level_pos = u16::from(nes.read(0x071a)) << 8 // screen page
-                    | u16::from(nes.read(0x071c)); // screen x
+/// Seconds a Mario goes back after running out of time, unless told otherwise.
+const REVERT_TIMEOUT: f32 = 120.0;
+
+const ROM: &str = "rom/smb.nes";
+/// Where memory map overrides for ROM hacks are looked for, named after the
+/// SHA-1 of the ROM.
+const MAPS: &str = "maps";
+const FONT: &str = "res/pressstart.ttf";
+const SCENES: [(&str, &str); 2] = [
+    ("spotlight", "script/mario.lua"),
+    ("wall", "script/wall.lua"),
+];
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let logbook = init_logging(&args)?;
+
+    let fonts = if args.fonts.is_empty() {
+        vec![("default".to_owned(), PathBuf::from(FONT))]
+    } else {
+        args.fonts.clone()
+    };
+    let fonts = load_fonts(&fonts)?;
+    if let Some(command) = &args.command {
+        return run_command(command, &args, fonts);
+    }
+
+    let (rom, settings) = load_game(&args)?;
+    let threads = args.threads.unwrap_or_else(population::default_threads);
+    let workers = Placement {
+        cores: args.worker_cores.clone(),
+        nice: args.worker_nice,
+    };
+    let instances = population_size(&args, &rom, threads, &workers, settings)?;
+    let _ = INSTANCES.set(instances);
+    let state = script_state(&args, fonts, logbook)?;
 
-    let mario_position: u32 = u32::from(nes.read(0x075f)) << 24
-        | u32::from(nes.read(0x0760)) << 16
-        | u32::from(level_pos);
+    let sfx = if args.sfx.is_empty() {
+        None
+    } else {
+        let volume = args.sfx_volume.clamp(0.0, 1.0);
+        Some(Player::new(&args.sfx, volume).context("could not set up sound effects")?)
+    };
 
-    mario_position
-}
+    let el = EventLoop::new();
+    let surface = Surface::new(
+        &el,
+        "shellkick",
+        WIDTH as u32,
+        HEIGHT as u32,
+        args.transparent,
+    )?;
+    if let Err(e) = surface.set_vsync(args.vsync) {
+        warn!(vsync = args.vsync, "could not set vsync: {}", e);
+    }
 
-fn next_frame(mario: &mut Mario) {
-    let input = Arc::new(AtomicU8::new(0));
-    let mut nes = mario.states.pop_back().unwrap();
-    nes.controllers = Controllers::standard(&input);
-    let mut score = fitness(&mut nes);
-
-    // get new inputs
-    if mario.inputs_future.is_empty() {
-        if score == Fitness::Dying(false) || score == Fitness::Dying(true) {
-            // do revert
-            let frames = if score == Fitness::Dying(true) {
-                360 * 20
-            } else {
-                0
-            };
-            let frame = nes.frame_number() - frames;
-            while nes.frame_number() >= frame && !mario.states.is_empty() {
-                nes = mario.states.pop_back().unwrap();
-            }
-            nes.controllers = Controllers::standard(&input);
-            score = fitness(&mut nes);
-
-            mario.next_state = mario.personality.confident;
-        } else if mario.next_state == 0 {
-            // remove previous states if we just cleared a level
-            if victory(&mut nes) {
-                mario.states.clear();
-            } else {
-                mario.states.push_back(nes.clone());
-                if mario.states.len() > 400 {
-                    mario.states.pop_front();
-                }
-            }
+    let Spawned {
+        marios,
+        teams,
+        obstacles,
+        save_ram,
+    } = spawn_marios(&args, &rom, instances, &state)?;
+    let options = simulation::Options {
+        fitness_log: args
+            .log_fitness
+            .clone()
+            .map(|path| (path, args.log_interval)),
+        throttle: args.throttle,
+        stagnation: args.stagnation,
+        diversity_floor: args.diversity_floor,
+        intervention: args.intervention,
+        highlights: args.highlights.clone(),
+    };
+    let simulation =
+        simulation::start(&marios, settings, threads, workers, teams, &state, options)?;
+
+    let scene_files = scene_files(&args);
+    let mut scenes = load_scenes(&args, &scene_files, &surface, &marios, &state)?;
+    let dashboard = match &args.dashboard {
+        Some(path) => Some(Dashboard::open(
+            &el,
+            &surface,
+            path,
+            &marios,
+            &state,
+            args.scaling,
+            &mut scenes,
+        )?),
+        None => None,
+    };
+    let pause_held = Arc::new(AtomicBool::new(false));
+    if let Some(addr) = &args.control {
+        let control = Control {
+            scenes: scene_files.iter().map(|(name, _)| name.clone()).collect(),
+            switch: state.switch.clone(),
+            pause: pause_held.clone(),
+        };
+        let addr = control
+            .serve(addr)
+            .with_context(|| format!("could not listen on {}", addr))?;
+        info!(%addr, "listening for control requests");
+    }
 
-            mario.next_state = mario.personality.confident;
-        } else {
-            mario.next_state -= 1;
+    let (tx_event, rx_event) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(tx_event).context("could not create file watcher")?;
+    watcher
+        .watch(Path::new("script"), RecursiveMode::Recursive)
+        .context("could not watch script directory")?;
+
+    let sink = match &args.output {
+        Some(output) => {
+            info!(?output, "opening output");
+            Some(Sink::open(output).context("could not open output")?)
         }
+        None => None,
+    };
 
-        if let Some(num) = mario.being_random.as_mut() {
-            // Random input
-            *num -= 1;
-            if *num == 0 {
-                mario.being_random = None;
-            }
+    let app = App::new(Setup {
+        marios,
+        state,
+        surface,
+        scenes,
+        scene_files,
+        scaling: args.scaling,
+        dashboard,
+        simulation,
+        script_changes: rx_event,
+        sfx,
+        sink,
+        obstacles,
+        save_ram,
+        recap_dir: args.recap.clone(),
+        profile_dir: args.profile_lua.clone(),
+        states_dir: args.states.clone(),
+        max_fps: args.max_fps,
+        pause_unfocused: args.pause_unfocused,
+        pause_held,
+    });
 
-            let mut last = mario.last_input;
-            for _ in 0..mario.personality.playful {
-                last = next_input(last, &mario.personality);
-                mario.inputs_future.push_back(last);
-            }
-        } else {
-            // Regular input
-            let mut best_result = Fitness::Dying(false);
-            let input = Arc::new(AtomicU8::new(0));
-
-            for _ in 0..3 {
-                // generate inputs
-                let mut list = VecDeque::new();
-                let mut last = mario.last_input;
-                for _ in 0..mario.personality.playful {
-                    last = next_input(last, &mario.personality);
-                    list.push_back(last);
-                }
+    // only now, so the threads spawned above don't inherit it
+    Placement {
+        cores: args.render_cores.clone(),
+        nice: args.render_nice,
+    }
+    .apply("render");
 
-                // run
-                let mut cloned = nes.clone();
-                cloned.controllers = Controllers::standard(&input);
+    app.run(el)
+}
 
-                for item in list.iter().copied() {
-                    input.store(item, Ordering::Relaxed);
-                    cloned.next_frame();
+/// Runs one of the commands that don't open a window.
+fn run_command(command: &Command, args: &Args, fonts: Vec<Font>) -> anyhow::Result<()> {
+    match command {
+        Command::TestScript { file, frames } => {
+            let _ = INSTANCES.set(args.instances);
+            test_script(file, *frames, &ScriptState::new(script_env(args), fonts))
+        }
+        Command::Render { file, frames, out } => {
+            let _ = INSTANCES.set(args.instances);
+            render(
+                file,
+                *frames,
+                out,
+                &ScriptState::new(script_env(args), fonts),
+            )
+        }
+        Command::Verify { log } => verify(&load_game(args)?.0, log),
+        Command::Diff { before, after, out } => {
+            diff_states(&load_game(args)?.0, before, after, out.as_deref())
+        }
+        Command::Experiment {
+            axes,
+            random,
+            vary,
+            runs,
+            frames,
+            threads,
+            out,
+        } => {
+            let (rom, settings) = load_game(args)?;
+            let configs = match random {
+                Some(count) if vary.is_empty() => {
+                    experiment::random(&Param::ALL, *count, &mut rand::thread_rng())
                 }
-
-                // get results
-                let score = fitness(&mut cloned);
-                if score >= best_result {
-                    best_result = score;
-                    mario.inputs_future = list;
+                Some(count) => experiment::random(vary, *count, &mut rand::thread_rng()),
+                None if axes.is_empty() => {
+                    anyhow::bail!("nothing to sweep, give --grid or --random")
                 }
-            }
-
-            // test against current score
-            if best_result <= score && best_result != Fitness::Cutscene {
-                mario.stuck_count += 1;
-                if mario.stuck_count >= mario.personality.patient {
-                    mario.stuck_count = 0;
-                    mario.being_random = Some(mario.personality.bold);
+                None => experiment::grid(axes),
+            };
+            let experiment = Experiment {
+                settings,
+                objective: args.objective,
+                runs: *runs,
+                frames: *frames,
+                threads: threads.unwrap_or_else(population::default_threads),
+            };
+            let written = match out {
+                Some(path) => {
+                    let mut file = File::create(path)
+                        .with_context(|| format!("could not create {}", path.display()))?;
+                    experiment.sweep(&rom, &configs, &mut file)
                 }
-            }
+                None => experiment.sweep(&rom, &configs, &mut io::stdout().lock()),
+            };
+            written.context("could not write experiment results")
+        }
+        Command::Practice {
+            state,
+            target,
+            attempts,
+            timeout,
+            instances,
+            threads,
+        } => {
+            let (rom, settings) = load_game(args)?;
+            let marios = population::spawn(&rom, *instances, args.objective);
+            let segment = Segment::load(
+                &mut mario::lock(&marios[0]),
+                state,
+                *target,
+                settings.region.frames(*timeout),
+            )
+            .with_context(|| format!("could not load state {}", state.display()))?;
+            let threads = threads.unwrap_or_else(population::default_threads);
+            practice(&marios, threads, settings, &segment, *attempts)
+        }
+        Command::Bench {
+            instances,
+            frames,
+            threads,
+        } => {
+            let (rom, settings) = load_game(args)?;
+            bench(
+                &rom,
+                *instances,
+                *frames,
+                threads.unwrap_or_else(population::default_threads),
+                settings,
+                args.objective,
+            )
         }
     }
+}
 
-    // set input
-    let item = mario.inputs_future.pop_front().unwrap();
-    mario.last_input = item;
-    input.store(item, Ordering::Relaxed);
-
-    // next frame
-    nes.next_frame();
+/// Reads the ROM and the memory map for it if there is one, and works out how
+/// the Marios play it.
+fn load_game(args: &Args) -> anyhow::Result<(Vec<u8>, Settings)> {
+    let rom = read(ROM).map_err(|source| Error::Rom {
+        path: ROM.into(),
+        source,
+    })?;
+    let identity = check_rom(&rom)?;
+    let map_path = args
+        .memory_map
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(MAPS).join(format!("{}.lua", identity.sha1)));
+    if args.memory_map.is_some() || map_path.exists() {
+        let source = std::fs::read_to_string(&map_path)
+            .with_context(|| format!("could not read memory map {}", map_path.display()))?;
+        let map = MemoryMap::from_lua(&source)
+            .with_context(|| format!("invalid memory map {}", map_path.display()))?;
+        // nothing has read the game's memory yet
+        let _ = map::install(map);
+        info!(path = %map_path.display(), "using memory map override");
+    }
 
-    // push nes back in
-    mario.states.push_back(nes);
+    let region = args.region.unwrap_or(identity.header.region());
+    info!(?region, fps = region.fps(), "timing the game");
+    let settings = Settings {
+        revert: RevertPolicy {
+            checkpoint: args.checkpoint_distance,
+            death: args.revert_death,
+            timeout: args
+                .revert_timeout
+                .unwrap_or(region.frames(REVERT_TIMEOUT).into()),
+            backoff: args.revert_backoff,
+            max: args.revert_max.unwrap_or(u64::MAX),
+        },
+        annealing: args.anneal.then_some(Annealing {
+            calm: args.anneal_calm,
+            patience: args.anneal_patience,
+        }),
+        cutscene_speed: args.cutscene_speed.max(1),
+        frame_skip: args.frame_skip.map(|batch| FrameSkip {
+            hidden_after: region.frames(args.hidden_after),
+            batch,
+        }),
+        powerup_bonus: args.powerup_bonus,
+        reset_errored: args.reset_errored,
+        region,
+        max_memory: args.max_memory.map(|mib| mib * 1024 * 1024),
+    };
+    Ok((rom, settings))
 }
 
-fn main() -> Result<()> {
-    let el = EventLoop::new();
-    let (window, config) = DisplayBuilder::new()
-        .with_window_builder(Some(
-            WindowBuilder::new()
-                .with_title("shellkick")
-                .with_inner_size(PhysicalSize::new(WIDTH as u32, HEIGHT as u32))
-                .with_resizable(false),
-        ))
-        .build(&el, ConfigTemplateBuilder::new(), |mut it| {
-            it.next().unwrap()
-        })
-        .unwrap();
-
-    let window = window.unwrap();
-    let attrs = window.build_surface_attributes(SurfaceAttributesBuilder::new());
-
-    let display = config.display();
-    let surface = unsafe { display.create_window_surface(&config, &attrs).unwrap() };
-
-    let gl_context = unsafe {
-        display
-            .create_context(
-                &config,
-                &ContextAttributesBuilder::new()
-                    .with_context_api(ContextApi::OpenGl(None))
-                    .build(Some(window.raw_window_handle())),
-            )
-            .unwrap()
-            .make_current(&surface)
-            .unwrap()
-    };
+/// Refuses ROMs that can't be booted, and warns about ones the memory map may
+/// not fit.
+fn check_rom(rom: &[u8]) -> error::Result<Identity> {
+    let identity = rom::identify(rom).map_err(|source| Error::InvalidRom {
+        path: ROM.into(),
+        source,
+    })?;
+    match identity.known {
+        Some(known) => info!(rom = known.name, "ROM recognized"),
+        None => warn!(
+            crc32 = %format!("{:08x}", identity.crc32),
+            sha1 = %identity.sha1,
+            "unknown ROM, if it is a hack or another revision Marios may misread it"
+        ),
+    }
+    if identity.header.region() == Region::Pal {
+        warn!("ROM is for PAL consoles, but the memory map is for the NTSC release");
+    }
+    Ok(identity)
+}
 
-    let mut marios = Vec::new();
-    let mut backgrounds = Vec::new();
-    let mut sprites = Vec::new();
-    let mut scores = Vec::new();
-
-    let mut rng = rand::thread_rng();
-    for _ in 0..256 {
-        let mut mario = Mario {
-            personality: Personality {
-                patient: rng.gen_range(1..10),
-                bold: rng.gen_range(1..10),
-                twitchy: rng.gen_range(0.01..0.2),
-                jumpy: rng.gen_range(0.01..0.2),
-
-                playful: 10,
-                confident: 1,
-            },
-            next_state: 0,
-            being_random: None,
-            stuck_count: 0,
-            last_input: 0,
-            inputs_future: vec![
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0b00001000, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            ]
-            .into(),
-            states: vec![NES::new(
-                NROM::from_ines(read("rom/smb.nes").unwrap()),
-                Controllers::disconnected(),
-                FastPPU::new(),
-            )]
-            .into(),
-        };
-        for _ in 0..rng.gen_range(0..20) {
-            mario.inputs_future.push_back(0)
-        }
-        marios.push(Arc::new(Mutex::new(mario)));
-
-        backgrounds.push(
-            [Color {
-                r: 127,
-                g: 127,
-                b: 127,
-                a: 255,
-            }; 61440],
+/// How many Marios to run, timing how many keep up with --auto. Fails if an
+/// instance given on the command line isn't one of them.
+fn population_size(
+    args: &Args,
+    rom: &[u8],
+    threads: usize,
+    workers: &Placement,
+    settings: Settings,
+) -> anyhow::Result<usize> {
+    let instances = if args.auto {
+        info!(threads, "timing how many Marios keep up");
+        let instances = population::calibrate(
+            rom,
+            args.instances,
+            threads,
+            workers,
+            settings,
+            args.objective,
         );
-        sprites.push(
-            [Color {
-                r: 127,
-                g: 127,
-                b: 127,
-                a: 0,
-            }; 61440],
+        info!(instances, "picked the population");
+        instances
+    } else {
+        args.instances
+    };
+    let given = args.starts.iter().map(|(instance, _)| *instance);
+    let given = given.chain(args.load_states.iter().map(|(instance, _)| *instance));
+    if let Some(instance) = given.filter(|&instance| instance > instances).max() {
+        anyhow::bail!(
+            "instance {} was given, but there are only {} Marios",
+            instance,
+            instances
         );
-        scores.push(0u32);
     }
+    Ok(instances)
+}
 
-    let marios_clone = marios.clone();
-    thread::spawn(move || {
-        let pool = ThreadPool::new(12);
-        let mut loop_helper = LoopHelper::builder().build_with_target_rate(60.0);
+/// What scripts get to see, with the timers, watches, writable addresses and
+/// captions given on the command line.
+fn script_state(
+    args: &Args,
+    fonts: Vec<Font>,
+    logbook: Arc<Mutex<Logbook>>,
+) -> anyhow::Result<ScriptState> {
+    let mut state = ScriptState::new(script_env(args), fonts);
+    state.profile = args.profile_lua.is_some();
+    state.logbook = logbook;
+    let mut timers = state.timers.lock().unwrap_or_else(PoisonError::into_inner);
+    for (name, duration) in args.timers.iter() {
+        timers.start(name, *duration, Instant::now());
+    }
+    drop(timers);
+    let mut watches = state
+        .ram_watch
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    for watch in args.watches.iter() {
+        watches.add(watch.clone());
+    }
+    drop(watches);
+    let mut writable = Allowlist::default();
+    for &(address, len) in args.allow_writes.iter() {
+        writable.allow(address, len);
+    }
+    state.writable = Arc::new(writable);
+    if let Some(path) = &args.captions {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read captions {}", path.display()))?;
+        let captions: Captions = source
+            .parse()
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("invalid captions {}", path.display()))?;
+        info!(path = %path.display(), cues = captions.cues().len(), "loaded captions");
+        *state
+            .captions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = (captions, Instant::now());
+    }
+    Ok(state)
+}
 
-        loop {
-            println!("{:?}", loop_helper.loop_start());
+fn script_env(args: &Args) -> Vec<(String, EnvValue)> {
+    let mut env: Vec<(String, EnvValue)> = vec![
+        ("width".to_owned(), (WIDTH as u32).into()),
+        ("height".to_owned(), (HEIGHT as u32).into()),
+        ("instances".to_owned(), (instances() as u32).into()),
+        ("title".to_owned(), args.title.clone().into()),
+        ("theme".to_owned(), args.theme.clone().into()),
+        ("transparent".to_owned(), args.transparent.into()),
+    ];
+    env.extend(
+        args.env
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone().into())),
+    );
+    env
+}
 
-            for mario in marios_clone.iter() {
-                let mario = mario.clone();
-                pool.execute(move || {
-                    let mut mario = mario.lock().unwrap();
-                    next_frame(&mut mario);
-                });
-            }
+/// The Marios, and what was set up around them from the command line.
+struct Spawned {
+    marios: Vec<Arc<Mutex<Mario>>>,
+    /// How the Marios are split into teams, if they are.
+    teams: Option<Teams>,
+    /// The obstacles every Mario shares, and the directory they are saved
+    /// back to on exit.
+    obstacles: Option<(PathBuf, Arc<Mutex<Obstacles>>)>,
+    /// Where the save RAM every Mario boots with is stored back to on exit.
+    save_ram: Option<SaveRam>,
+}
 
-            pool.join();
-            loop_helper.loop_sleep();
+/// Creates `instances` Marios playing `rom` and sets them up as the command
+/// line says: where they start, their teams and networks, their obstacles,
+/// and the states they load.
+fn spawn_marios(
+    args: &Args,
+    rom: &[u8],
+    instances: usize,
+    state: &ScriptState,
+) -> anyhow::Result<Spawned> {
+    let marios = population::spawn(rom, instances, args.objective);
+    if let Some(dir) = &args.input_log {
+        create_dir_all(dir).context("could not create input log directory")?;
+        for (i, mario) in marios.iter().enumerate() {
+            let path = dir.join(format!("mario-{}.inputs", i + 1));
+            let log = InputLog::create(&path)
+                .with_context(|| format!("could not create {}", path.display()))?;
+            mario::lock(mario).log = Some(log);
         }
-    });
-
-    let mut screen = animate("script/mario.lua", config.clone(), &marios)?;
-
-    let (tx_event, rx_event) = mpsc::channel();
-    let mut watcher = notify::recommended_watcher(tx_event).unwrap();
-    watcher
-        .watch(::std::path::Path::new("script"), RecursiveMode::Recursive)
-        .unwrap();
-
-    let mut time = Instant::now();
-
-    el.run(move |event, _, cf| match event {
-        winit::event::Event::WindowEvent {
-            ref event,
-            window_id,
-        } if window_id == window.id() => match event {
-            winit::event::WindowEvent::CloseRequested => *cf = ControlFlow::Exit,
-            _ => {}
-        },
-        winit::event::Event::MainEventsCleared => {
-            let mut refresh = false;
-            while let Ok(event) = rx_event.try_recv() {
-                match event {
-                    Ok(Event {
-                        kind: EventKind::Modify(_),
-                        ..
-                    }) => refresh = true,
-                    Ok(_) => {}
-                    Err(e) => println!("watch error: {:?}", e),
-                }
-            }
-            if refresh {
-                // refresh screen
-                match animate("script/mario.lua", config.clone(), &marios) {
-                    Ok(s) => {
-                        screen = s;
-                        time = Instant::now();
-                    }
-                    Err(e) => println!("lua error: {}", e),
-                }
+    }
+    start_warped(&marios, &args.starts, args.random_starts);
+    let teams = if args.team_a.is_empty() && args.team_b.is_empty() {
+        None
+    } else {
+        info!(a = ?args.team_a, b = ?args.team_b, "splitting Marios into teams");
+        *state.scoreboard.lock().unwrap() = Some(Scoreboard::default());
+        Some(Teams::new(args.team_a.clone(), args.team_b.clone()))
+    };
+    if let Some(teams) = &teams {
+        shape_teams(&marios, teams);
+    }
+    if args.neat > 0 {
+        let pool = Pool::new(
+            brain::INPUTS,
+            brain::OUTPUTS,
+            args.neat_population,
+            &mut rand::thread_rng(),
+        );
+        let pool = Arc::new(Mutex::new(pool));
+        for mario in marios.iter().rev().take(args.neat) {
+            mario::lock(mario).controller = Box::new(Brain::new(pool.clone()));
+        }
+        info!(
+            marios = args.neat.min(marios.len()),
+            population = args.neat_population,
+            "evolving neural networks"
+        );
+        let _ = state.neat.set(pool);
+    }
+    let obstacles = match &args.obstacles {
+        Some(dir) => {
+            let obstacles = Obstacles::load(dir)
+                .with_context(|| format!("could not load obstacles from {}", dir.display()))?;
+            info!(obstacles = obstacles.len(), "loaded obstacles");
+            let obstacles = Arc::new(Mutex::new(obstacles));
+            for mario in marios.iter() {
+                mario::lock(mario).obstacles = Some(obstacles.clone());
             }
-
-            screen
-                .values(|_ctx, table| {
-                    let frame: u32 = table.get("frame")?;
-                    table.set("frame", frame + 1)?;
-
-                    let results: Table = table.get("marios")?;
-                    for (i, mario) in marios.iter().enumerate() {
-                        let mut mario = mario.lock().unwrap();
-                        let len = mario.states.len();
-                        let nes = &mut mario.states[len - 1];
-                        let result = scroll(nes);
-
-                        let index = i + 1;
-                        let mario_table: Table = results.get(index)?;
-                        mario_table.set("fitness", result)?;
-                    }
-                    table.set("marios", results)?;
-                    Ok(())
-                })
-                .unwrap();
-
-            // Programs that draw graphics continuously can render here unconditionally for simplicity.
-            screen
-                .advance_time(time.elapsed().as_secs_f32())
-                .unwrap_or_else(|e| println!("lua error: {}", e));
-            surface.swap_buffers(&gl_context).unwrap();
+            Some((dir.clone(), obstacles))
         }
-        _ => {}
-    });
+        None => None,
+    };
+    let save_ram = match &args.save_ram {
+        Some(dir) if matches!(Header::parse(rom), Ok(header) if header.battery) => {
+            let save_ram = SaveRam::new(dir, rom);
+            load_save_ram(&save_ram, &marios)?;
+            Some(save_ram)
+        }
+        Some(_) => {
+            warn!("the ROM has no battery-backed save RAM to keep");
+            None
+        }
+        None => None,
+    };
+    if args.input_log.is_some() && (!args.starts.is_empty() || args.random_starts > 0) {
+        warn!("input logs of Marios started in another level won't replay");
+    }
+    for (instance, path) in args.load_states.iter() {
+        savestate::load(&mut mario::lock(&marios[instance - 1]), path)
+            .with_context(|| format!("could not load state {}", path.display()))?;
+        info!(instance, path = %path.display(), "loaded state");
+    }
+    Ok(Spawned {
+        marios,
+        teams,
+        obstacles,
+        save_ram,
+    })
 }
 
-fn animate(
-    path: &str,
-    config: Config,
-    marios: &Vec<Arc<Mutex<Mario>>>,
-) -> Result<Animation<OpenGl>> {
-    let opengl = OpenGl::new_from_glutin_display(&config.display()).unwrap();
-    let mut canvas = Canvas::new(opengl).unwrap();
-    canvas.set_size(WIDTH as u32, HEIGHT as u32, 1.0);
-    canvas.add_font("res/pressstart.ttf").unwrap();
-
-    let personalities: Vec<_> = marios
-        .iter()
-        .map(|mario| mario.lock().unwrap().personality.clone())
-        .collect();
-    let marios = marios.clone();
-    luanim::animate(
-        path.to_owned(),
-        canvas,
-        move |ctx, instr, args, screen| match instr {
-            // FASTNES
-            128 => {
-                let (x, y, scale, instance): (f32, f32, f32, usize) =
-                    FromLuaMulti::from_lua_multi(args, ctx)?;
-
-                let image = {
-                    let mario = marios[instance - 1].lock().unwrap();
-                    let nes = &mario.states[mario.states.len() - 1];
-                    let frame = nes.draw_frame(DrawOptions::Background);
-
-                    let img = Img::new(unsafe { as_rgba(&frame) }, 256, 240);
-                    screen
-                        .canvas
-                        .create_image(img, ImageFlags::NEAREST)
-                        .unwrap()
-                };
-
-                // divide by 3.75 to make it pixel perfect on full HD screens
-                let width = 256.0 / 3.75 * scale;
-                let height = 240.0 / 3.75 * scale;
-
-                let fill_paint = Paint::image(image, x, y, width, height, 0.0, 1.0);
-                let mut path = Path::new();
-                path.rect(x, y, width, height);
-
-                screen.canvas.set_transform(&screen.transform().into());
-                screen.canvas.fill_path(&mut path, &fill_paint);
-                screen.canvas.reset_transform();
-
-                // need to flush the canvas before being able to delete the image
-                screen.canvas.flush();
-                screen.canvas.delete_image(image);
-                Ok(())
-            }
-            129 => {
-                let (x, y, scale, instance, xo, yo, opacity): (
-                    f32,
-                    f32,
-                    f32,
-                    usize,
-                    f32,
-                    f32,
-                    f32,
-                ) = FromLuaMulti::from_lua_multi(args, ctx)?;
-
-                let image = {
-                    let mario = marios[instance - 1].lock().unwrap();
-                    let nes = &mario.states[mario.states.len() - 1];
-                    let frame = nes.draw_frame(DrawOptions::Sprites);
-
-                    let img = Img::new(unsafe { as_rgba(&frame) }, 256, 240);
-                    screen
-                        .canvas
-                        .create_image(img, ImageFlags::NEAREST)
-                        .unwrap()
-                };
-
-                // divide by 3.75 to make it pixel perfect on full HD screens
-                let pixel = 1.0 / 3.75 * scale;
-                let width = 256.0 * pixel;
-                let height = 240.0 * pixel;
-
-                let fill_paint = Paint::image(
-                    image,
-                    x + xo * pixel,
-                    y + yo * pixel,
-                    width,
-                    height,
-                    0.0,
-                    opacity,
-                );
-                let mut path = Path::new();
-                path.rect(
-                    f32::max(x, x + xo * pixel),
-                    f32::max(x, x + yo * pixel),
-                    f32::min(width - xo * pixel, width + xo * pixel),
-                    f32::min(height - yo * pixel, height + yo * pixel),
-                );
-
-                screen.canvas.set_transform(&screen.transform().into());
-                screen.canvas.fill_path(&mut path, &fill_paint);
-                screen.canvas.reset_transform();
-
-                // need to flush the canvas before being able to delete the image
-                screen.canvas.flush();
-                screen.canvas.delete_image(image);
-                Ok(())
-            }
-            _ => todo!("{}", instr),
-        },
-        |ctx| {
-            let values = ctx.create_table()?;
-            values.set("frame", 0)?;
-
-            let marios_data = ctx.create_table()?;
-            for (i, mario) in personalities.iter().enumerate() {
-                let personality = ctx.create_table()?;
-                personality.set("patient", mario.patient)?;
-                personality.set("bold", mario.bold)?;
-                personality.set("playful", mario.playful)?;
-                personality.set("twitchy", mario.twitchy)?;
-                personality.set("jumpy", mario.jumpy)?;
-
-                let data = ctx.create_table()?;
-                data.set("personality", personality)?;
-                data.set("fitness", 0)?;
-
-                let index = i + 1;
-                marios_data.set(index, data)?;
-            }
-            values.set("marios", marios_data)?;
+/// The scenes given with --scene by name, or the default ones.
+fn scene_files(args: &Args) -> Vec<(String, PathBuf)> {
+    if args.scenes.is_empty() {
+        SCENES
+            .iter()
+            .map(|&(name, path)| (name.to_owned(), PathBuf::from(path)))
+            .collect()
+    } else {
+        args.scenes.clone()
+    }
+}
 
-            Ok(values)
-        },
-    )
+/// Starts the script of every scene in `scene_files`, drawn to `surface`.
+fn load_scenes(
+    args: &Args,
+    scene_files: &[(String, PathBuf)],
+    surface: &Surface,
+    marios: &[Arc<Mutex<Mario>>],
+    state: &ScriptState,
+) -> anyhow::Result<Scenes<FontCanvas<OpenGl>>> {
+    if args.dashboard.is_some() && scene_files.iter().any(|(name, _)| name == DASHBOARD) {
+        anyhow::bail!("a scene can't be called {} next to --dashboard", DASHBOARD);
+    }
+    let mut loaded = Vec::new();
+    for (name, path) in scene_files.iter() {
+        let animation = animate(path, surface, marios, state, args.scaling)
+            .with_context(|| format!("could not start scene {} ({})", name, path.display()))?;
+        loaded.push(Scene::new(name, animation));
+    }
+
+    let mut scenes = Scenes::new(loaded, state.switch.clone());
+    scenes.transition = args.transition;
+    scenes.duration = args.transition_time;
+    scenes.rotate = args.scene_rotate;
+    Ok(scenes)
 }
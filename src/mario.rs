@@ -0,0 +1,971 @@
+use std::{
+    collections::VecDeque,
+    ops::AddAssign,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use fastnes::{
+    cart::{Cartridge, NROM},
+    input::Controllers,
+    nes::NES,
+    ppu::FastPPU,
+};
+use rand::Rng;
+use tracing::{debug, warn};
+
+use crate::{
+    controller::{Controller, Planner, Turn},
+    input_log::InputLog,
+    observation::Observation,
+    obstacles::{Obstacles, MACRO_FRAMES},
+    rom::Region,
+    smb::{
+        depth, fitness, in_level, objective_fitness, scroll, title_menu, victory, Fitness,
+        Objective, Ram, Warp,
+    },
+    sram,
+};
+
+const START: u8 = 0b00001000;
+/// Plans a Mario tries before picking the best one, unless told otherwise.
+pub const ROLLOUTS: u32 = 3;
+/// Most states a Mario keeps to revert to without a
+/// [memory budget](Settings::max_memory).
+const MAX_STATES: usize = 400;
+/// Rough memory a saved state takes up: the emulator with its own copy of the
+/// cartridge.
+pub const STATE_BYTES: u64 = 48 * 1024;
+
+/// Counts the states saved by every Mario, so they can be told apart by age.
+static SAVES: AtomicU64 = AtomicU64::new(0);
+
+/// When a Mario saves its state, and how far back it goes after dying.
+#[derive(Clone, Copy, Debug)]
+pub struct RevertPolicy {
+    /// Pixels Mario has to get further into a level before another state is
+    /// saved. A state is always saved on getting into a new level.
+    pub checkpoint: u64,
+    /// Frames to go back after dying, on top of the latest saved state.
+    pub death: u64,
+    /// Frames to go back after running out of time, to get far enough away
+    /// from whatever got it stuck.
+    pub timeout: u64,
+    /// Extra frames to go back after dying again at the same spot, doubled
+    /// for every further death there.
+    pub backoff: u64,
+    /// Most frames a single revert goes back.
+    pub max: u64,
+}
+
+impl Default for RevertPolicy {
+    fn default() -> Self {
+        RevertPolicy {
+            checkpoint: 16,
+            death: 0,
+            timeout: 360 * 20,
+            backoff: 0,
+            max: u64::MAX,
+        }
+    }
+}
+
+impl RevertPolicy {
+    /// Frames to go back for a death, `repeats` being the number of deaths at
+    /// the same spot right before this one.
+    pub fn depth(&self, timeout: bool, repeats: u32) -> u64 {
+        let base = if timeout { self.timeout } else { self.death };
+        let backoff = match repeats {
+            0 => 0,
+            n => self
+                .backoff
+                .saturating_mul(1u64.checked_shl(n - 1).unwrap_or(u64::MAX)),
+        };
+        base.saturating_add(backoff).min(self.max)
+    }
+
+    /// The frame to revert to from `frame`, which is never before the first
+    /// frame of the run.
+    pub fn target(&self, frame: u64, timeout: bool, repeats: u32) -> u64 {
+        frame.saturating_sub(self.depth(timeout, repeats))
+    }
+
+    /// Whether a state at `score` is worth saving after the last one, saved
+    /// at `last`.
+    pub fn checkpoint_due(&self, last: Option<Fitness>, score: &Fitness) -> bool {
+        match (last, score) {
+            (Some(Fitness::Level(last, _)), Fitness::Level(position, _)) => {
+                // the world and level are in the upper bits
+                position >> 16 != last >> 16 || *position >= last.saturating_add(self.checkpoint)
+            }
+            (_, Fitness::Level(..)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Personality {
+    pub patient: u32, // stuck iterations before random movement
+    pub bold: u32,    // frames per random movement
+    pub playful: u32, // frames per regular movement
+    pub twitchy: f32, // likelyhood of direction switch per frame
+    pub jumpy: f32,   // likelyhood of A switch per frame
+
+    pub confident: u32, // iterations per save state
+    pub rollouts: u32,  // plans tried per regular movement
+}
+
+impl Personality {
+    pub fn random(rng: &mut impl Rng) -> Personality {
+        Personality {
+            patient: rng.gen_range(1..10),
+            bold: rng.gen_range(1..10),
+            twitchy: rng.gen_range(0.01..0.2),
+            jumpy: rng.gen_range(0.01..0.2),
+
+            playful: 10,
+            confident: 1,
+            rollouts: ROLLOUTS,
+        }
+    }
+
+    /// The randomized traits scaled to 0..=1, in the order patient, bold,
+    /// twitchy and jumpy.
+    pub fn traits(&self) -> [f32; 4] {
+        [
+            self.patient as f32 / 10.0,
+            self.bold as f32 / 10.0,
+            (self.twitchy / 0.2).min(1.0),
+            (self.jumpy / 0.2).min(1.0),
+        ]
+    }
+}
+
+/// Makes Marios calmer the deeper into the game they get, as later levels
+/// punish random movement more.
+#[derive(Clone, Copy, Debug)]
+pub struct Annealing {
+    /// Factor twitchy and jumpy are multiplied by for every level in.
+    pub calm: f32,
+    /// Stuck iterations added to patient for every level in.
+    pub patience: u32,
+}
+
+impl Annealing {
+    /// The personality `personality` has at `depth` levels into the game.
+    pub fn apply(&self, personality: &Personality, depth: u32) -> Personality {
+        let calm = self.calm.powi(depth.min(i32::MAX as u32) as i32);
+        Personality {
+            twitchy: personality.twitchy * calm,
+            jumpy: personality.jumpy * calm,
+            patient: personality
+                .patient
+                .saturating_add(self.patience.saturating_mul(depth)),
+            ..personality.clone()
+        }
+    }
+}
+
+/// Runs Marios nobody is watching in batches of frames, to leave more time
+/// for the ones on screen.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameSkip {
+    /// Frames a Mario has to go without being drawn to count as off-screen.
+    pub hidden_after: u32,
+    /// Frames an off-screen Mario runs at once, on one tick out of this many.
+    pub batch: u32,
+}
+
+/// How every Mario in a population plays.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub revert: RevertPolicy,
+    pub annealing: Option<Annealing>,
+    /// Frames emulated at once during cutscenes, where inputs don't matter.
+    /// Cutscenes play at normal speed at 1.
+    pub cutscene_speed: u32,
+    pub frame_skip: Option<FrameSkip>,
+    /// Pixels of distance each powerup is worth, so keeping a mushroom counts
+    /// for something.
+    pub powerup_bonus: u64,
+    /// Start a Mario over from the title screen when its frame panics,
+    /// instead of leaving it stopped.
+    pub reset_errored: bool,
+    /// Sets how many frames are played per second.
+    pub region: Region,
+    /// Bytes all saved states together may take up, by [`STATE_BYTES`] each,
+    /// after which the oldest in the population are dropped. Without one,
+    /// every Mario keeps its latest 400 states.
+    pub max_memory: Option<u64>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            revert: RevertPolicy::default(),
+            annealing: None,
+            cutscene_speed: 1,
+            frame_skip: None,
+            powerup_bonus: 0,
+            reset_errored: false,
+            region: Region::Ntsc,
+            max_memory: None,
+        }
+    }
+}
+
+/// Time spent in [`next_frame`], summed over every call.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Cost {
+    pub frames: u64,
+    pub total: Duration,
+    /// Trying out inputs on clones of the emulator, not counting the cloning.
+    pub rollouts: Duration,
+    /// Emulating the frames actually played.
+    pub step: Duration,
+    /// Cloning the emulator for rollouts and saved states.
+    pub clone: Duration,
+    /// Rollouts whose result was [cached](crate::rollout_cache), and those
+    /// that had to be played.
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+}
+
+impl AddAssign for Cost {
+    fn add_assign(&mut self, other: Cost) {
+        self.frames += other.frames;
+        self.total += other.total;
+        self.rollouts += other.rollouts;
+        self.step += other.step;
+        self.clone += other.clone;
+        self.cache_hits += other.cache_hits;
+        self.cache_misses += other.cache_misses;
+    }
+}
+
+/// A state in [`Mario::states`], with when it was saved.
+#[derive(Clone)]
+pub struct State {
+    pub nes: NES<NROM, FastPPU>,
+    /// Increases with every state saved by any Mario.
+    pub saved: u64,
+}
+
+impl State {
+    fn new(nes: NES<NROM, FastPPU>) -> State {
+        State {
+            nes,
+            saved: SAVES.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+/// The inputs played since Mario got into a level, with the state they were
+/// played from, so the run can be played again without the reverts.
+#[derive(Clone)]
+pub struct Run {
+    pub start: NES<NROM, FastPPU>,
+    /// The level the run is in, see [`depth`].
+    pub depth: u32,
+    /// One input for every frame since `start`.
+    pub inputs: Vec<u8>,
+}
+
+impl Run {
+    fn new(nes: &mut NES<NROM, FastPPU>) -> Run {
+        Run {
+            depth: depth(nes),
+            start: nes.clone(),
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Plays the inputs again from the start, calling `frame` after every
+    /// frame.
+    pub fn replay(&self, mut frame: impl FnMut(&NES<NROM, FastPPU>)) {
+        let input = Arc::new(AtomicU8::new(0));
+        let mut nes = self.start.clone();
+        nes.controllers = Controllers::standard(&input);
+        for &item in self.inputs.iter() {
+            input.store(item, Ordering::Relaxed);
+            nes.next_frame();
+            frame(&nes);
+        }
+    }
+
+    /// Forgets the inputs from `frame` on, after reverting to it. Returns
+    /// false if that is before the run started.
+    fn rewind(&mut self, frame: u64) -> bool {
+        match frame.checked_sub(self.start.frame_number() as u64) {
+            Some(played) => {
+                self.inputs.truncate(played as usize);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// How far back a Mario is reset to by hand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reset {
+    /// The oldest state Mario still has.
+    Oldest,
+    /// Where Mario got into the level he is in.
+    Level,
+    /// A freshly booted NES.
+    Boot,
+}
+
+impl Reset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reset::Oldest => "oldest",
+            Reset::Level => "level",
+            Reset::Boot => "boot",
+        }
+    }
+}
+
+pub struct Mario {
+    pub personality: Personality,
+    /// What Mario tries to get the most of besides distance.
+    pub objective: Objective,
+    /// The level Mario starts in instead of 1-1.
+    pub warp: Option<Warp>,
+    /// The personality after annealing, which is what Mario plays with.
+    pub effective: Personality,
+    /// What decides the inputs Mario plays.
+    pub controller: Box<dyn Controller>,
+    /// Where Marios keep dying and what got them past, shared by the Marios
+    /// learning it.
+    pub obstacles: Option<Arc<Mutex<Obstacles>>>,
+    /// The page of the level Mario was on the last frame, to tell when he
+    /// gets past an obstacle.
+    spot: u32,
+
+    pub inputs_future: VecDeque<u8>,
+    pub last_input: u8,
+    pub next_state: u32,
+    /// Still getting from the title screen into the first level.
+    pub booting: bool,
+
+    /// Screen of the level the last death happened on, and how many times in
+    /// a row Mario died there.
+    pub death_spot: Option<u32>,
+    pub deaths: u32,
+
+    /// Set when a script draws this Mario, cleared by the simulation.
+    pub shown: bool,
+    /// Ticks since this Mario was last drawn.
+    pub hidden_for: u32,
+    /// Set while the scenes being drawn all declared which Marios they draw,
+    /// and this isn't one of them. Counts as off-screen right away instead of
+    /// after [`FrameSkip::hidden_after`].
+    pub offscreen: bool,
+    /// Skipped by the simulation while set, but for the frames asked for in
+    /// [`steps`](Mario::steps).
+    pub paused: bool,
+    /// Frames to play while paused, one every tick.
+    pub steps: u32,
+    pub cost: Cost,
+    /// What the last frame panicked with. An errored Mario is no longer run
+    /// until it is [`reset`](Mario::reset).
+    pub errored: Option<String>,
+
+    /// The level being played so far.
+    pub run: Option<Run>,
+    /// The run of the level that was just cleared, until someone takes it.
+    pub cleared: Option<Run>,
+    /// Where every input played and every revert is written to, if anywhere.
+    pub log: Option<InputLog>,
+    /// Every input from booting up to now, leaving out what was reverted, as
+    /// [save states](crate::savestate) are made of.
+    pub played: Vec<u8>,
+
+    /// States to revert to, oldest first, ending in the one being played.
+    pub states: VecDeque<State>,
+    rom: Vec<u8>,
+    /// What the battery-backed RAM of the cartridge holds when it boots.
+    save_ram: Option<Arc<[u8]>>,
+}
+
+impl Mario {
+    /// Creates a Mario on a freshly booted NES, which presses start once the
+    /// title screen is up.
+    pub fn new(personality: Personality, rom: Vec<u8>) -> Mario {
+        Mario {
+            effective: personality.clone(),
+            personality,
+            objective: Objective::Distance,
+            warp: None,
+            controller: Box::<Planner>::default(),
+            obstacles: None,
+            spot: 0,
+            next_state: 0,
+            last_input: 0,
+            death_spot: None,
+            deaths: 0,
+            booting: true,
+            shown: false,
+            hidden_for: 0,
+            offscreen: false,
+            paused: false,
+            steps: 0,
+            cost: Cost::default(),
+            errored: None,
+            run: None,
+            cleared: None,
+            log: None,
+            played: Vec::new(),
+            inputs_future: VecDeque::new(),
+            states: vec![State::new(boot(rom.clone()))].into(),
+            rom,
+            save_ram: None,
+        }
+    }
+
+    /// Boots with `data` in the battery-backed RAM of the cartridge, from now
+    /// on and after every reset. For a Mario that didn't play a frame yet.
+    pub fn set_save_ram(&mut self, data: Arc<[u8]>) {
+        self.save_ram = Some(data);
+        self.load_save_ram();
+    }
+
+    /// Puts the save RAM into the NES that just booted.
+    fn load_save_ram(&mut self) {
+        if let Some(data) = self.save_ram.clone() {
+            sram::write_to(self.nes_mut(), &data);
+        }
+    }
+
+    /// Starts over on a freshly booted NES, keeping the personality and
+    /// objective.
+    pub fn reset(&mut self) {
+        *self = self.start_over();
+        self.log_with(|log| log.revert(0));
+    }
+
+    /// A Mario on a freshly booted NES that takes over what this one keeps
+    /// across resets.
+    fn start_over(&mut self) -> Mario {
+        let mut mario = Mario::new(self.personality.clone(), std::mem::take(&mut self.rom));
+        mario.objective = self.objective;
+        mario.warp = self.warp;
+        mario.controller = std::mem::replace(&mut self.controller, Box::<Planner>::default());
+        mario.controller.forget();
+        mario.obstacles = self.obstacles.clone();
+        mario.shown = self.shown;
+        mario.offscreen = self.offscreen;
+        mario.paused = self.paused;
+        mario.cost = self.cost;
+        mario.log = self.log.take();
+        mario.save_ram = self.save_ram.take();
+        mario.load_save_ram();
+        mario
+    }
+
+    /// Writes to the input log, closing it if that fails.
+    fn log_with(&mut self, f: impl FnOnce(&mut InputLog) -> std::io::Result<()>) {
+        if let Some(log) = self.log.as_mut() {
+            if let Err(e) = f(log) {
+                warn!("could not write input log, closing it: {}", e);
+                self.log = None;
+            }
+        }
+    }
+
+    /// Continues from `nes`, which `inputs` got to from a freshly booted NES,
+    /// as if Mario had played them.
+    pub fn restore(&mut self, nes: NES<NROM, FastPPU>, inputs: Vec<u8>) {
+        let mut mario = self.start_over();
+        // wherever the inputs got to is where Mario is now
+        mario.warp = None;
+        mario.last_input = inputs.last().copied().unwrap_or(0);
+        mario.states = vec![State::new(nes)].into();
+        *self = mario;
+
+        self.log_with(|log| log.revert(0));
+        for input in inputs.iter().copied() {
+            self.log_with(|log| log.input(input));
+        }
+        self.played = inputs;
+    }
+
+    /// Goes back as far as `reset` says, forgetting what Mario planned and
+    /// whatever error stopped him. Returns false if he isn't in a level to go
+    /// back to the start of.
+    pub fn reset_to(&mut self, reset: Reset) -> bool {
+        match reset {
+            Reset::Oldest => self.states.truncate(1),
+            Reset::Level => {
+                let start = match &self.run {
+                    Some(run) => run.start.clone(),
+                    None => return false,
+                };
+                let frame = start.frame_number();
+                self.states.retain(|state| state.nes.frame_number() < frame);
+                self.states.push_back(State::new(start));
+            }
+            Reset::Boot => {
+                self.reset();
+                return true;
+            }
+        }
+        let frame = self.nes().frame_number() as u64;
+        self.rewound(frame);
+        self.last_input = self.played.last().copied().unwrap_or(0);
+        self.booting = !in_level(self.nes_mut());
+        self.inputs_future.clear();
+        self.controller.forget();
+        self.next_state = self.personality.confident;
+        self.death_spot = None;
+        self.deaths = 0;
+        self.errored = None;
+        true
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /// Writes what the input log still holds to disk, after where Mario is
+    /// now to check replays against.
+    pub fn flush_log(&mut self) {
+        if self.log.is_none() {
+            return;
+        }
+        let nes = self.nes_mut();
+        let (frame, position, ram) = (
+            nes.frame_number() as u64,
+            scroll(nes),
+            Ram::of(nes).checksum(),
+        );
+        self.log_with(|log| {
+            log.check(frame, position, ram)?;
+            log.flush()
+        });
+    }
+
+    /// Forgets what was played from `frame` on, after going back to it.
+    fn rewound(&mut self, frame: u64) {
+        self.played.truncate(frame as usize);
+        if let Some(run) = self.run.as_mut() {
+            if !run.rewind(frame) {
+                self.run = None;
+            }
+        }
+        self.log_with(|log| log.revert(frame));
+    }
+
+    /// Counts `input` as played on the next frame.
+    fn record(&mut self, input: u8) {
+        self.played.push(input);
+        if let Some(run) = self.run.as_mut() {
+            run.inputs.push(input);
+        }
+        self.log_with(|log| log.input(input));
+    }
+
+    pub fn nes(&self) -> &NES<NROM, FastPPU> {
+        &self.states.back().unwrap().nes
+    }
+
+    pub fn nes_mut(&mut self) -> &mut NES<NROM, FastPPU> {
+        &mut self.states.back_mut().unwrap().nes
+    }
+
+    /// Counts a simulation tick, returning how many frames to run in it.
+    /// `slot` spreads the batches of off-screen Marios over the ticks.
+    pub fn frames_due(&mut self, slot: u32, settings: &Settings) -> u32 {
+        if std::mem::take(&mut self.shown) {
+            self.hidden_for = 0;
+        } else {
+            self.hidden_for = self.hidden_for.saturating_add(1);
+        }
+        if self.paused {
+            // stepping goes one frame at a time, whatever would be skipped
+            return match self.steps.checked_sub(1) {
+                Some(steps) => {
+                    self.steps = steps;
+                    1
+                }
+                None => 0,
+            };
+        }
+
+        match settings.frame_skip {
+            Some(skip)
+                if (self.offscreen || self.hidden_for >= skip.hidden_after) && skip.batch > 1 =>
+            {
+                if slot % skip.batch == 0 {
+                    skip.batch
+                } else {
+                    0
+                }
+            }
+            _ => 1,
+        }
+    }
+}
+
+/// Locks `mario`, even if a thread panicked while holding him. A panic in one
+/// of his frames already marks him errored, so he is still fine to look at.
+pub fn lock(mario: &Mutex<Mario>) -> MutexGuard<'_, Mario> {
+    mario.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Plays `inputs` on a freshly booted NES, one every frame.
+pub fn replay(rom: Vec<u8>, inputs: &[u8]) -> NES<NROM, FastPPU> {
+    let input = Arc::new(AtomicU8::new(0));
+    let mut nes = boot(rom);
+    nes.controllers = Controllers::standard(&input);
+    for &item in inputs {
+        input.store(item, Ordering::Relaxed);
+        nes.next_frame();
+    }
+    nes
+}
+
+fn boot(rom: Vec<u8>) -> NES<NROM, FastPPU> {
+    NES::new(
+        NROM::from_ines(rom),
+        Controllers::disconnected(),
+        FastPPU::new(),
+    )
+}
+
+/// Drops states from the back of `states` until one from before `target` is
+/// found, or the oldest state is reached, going back from the state at frame
+/// `current`. None if that one is from before `target` already, or there is
+/// nothing to go back to.
+fn revert<T>(
+    states: &mut VecDeque<T>,
+    current: u64,
+    target: u64,
+    frame: impl Fn(&T) -> u64,
+) -> Option<T> {
+    let mut reverted = None;
+    let mut at = current;
+    while at >= target {
+        match states.pop_back() {
+            Some(state) => {
+                at = frame(&state);
+                reverted = Some(state);
+            }
+            None => break,
+        }
+    }
+    reverted
+}
+
+/// Input that gets a booting game into its first level, pressing and
+/// releasing start on the title screen and waiting out the screens after it.
+/// Returns None once a level is running.
+fn boot_input(nes: &mut NES<NROM, FastPPU>, last_input: u8) -> Option<u8> {
+    if in_level(nes) {
+        None
+    } else if title_menu(nes) && last_input & START == 0 {
+        // start only registers when it wasn't held the frame before
+        Some(START)
+    } else {
+        Some(0)
+    }
+}
+
+/// Advances the emulator of `mario` by a single frame, or by several during a
+/// cutscene, planning new inputs or reverting to an earlier state when needed.
+pub fn next_frame(mario: &mut Mario, settings: &Settings) {
+    let start = Instant::now();
+    play_frame(mario, settings);
+    mario.cost.frames += 1;
+    mario.cost.total += start.elapsed();
+}
+
+/// Takes the state being played off `states` for the frame, and puts it back
+/// even if the frame panics, so an errored Mario can still be looked at and
+/// reset.
+fn play_frame(mario: &mut Mario, settings: &Settings) {
+    let mut nes = mario.states.pop_back().unwrap().nes;
+    let played = panic::catch_unwind(AssertUnwindSafe(|| play(mario, &mut nes, settings)));
+    mario.states.push_back(State::new(nes));
+    if let Err(payload) = played {
+        panic::resume_unwind(payload);
+    }
+}
+
+fn play(mario: &mut Mario, nes: &mut NES<NROM, FastPPU>, settings: &Settings) {
+    let policy = &settings.revert;
+    let input = Arc::new(AtomicU8::new(0));
+    nes.controllers = Controllers::standard(&input);
+    if let Some(warp) = mario.warp {
+        warp.apply(nes);
+    }
+    let mut score = objective_fitness(nes, mario.objective, settings.powerup_bonus);
+
+    if mario.booting && mario.inputs_future.is_empty() {
+        match boot_input(nes, mario.last_input) {
+            Some(item) => mario.inputs_future.push_back(item),
+            None => {
+                debug!(frame = nes.frame_number(), "booted into the first level");
+                mario.booting = false;
+            }
+        }
+    }
+
+    // get new inputs
+    if mario.inputs_future.is_empty() {
+        if !mario.controller.reverts() {
+            // the controller deals with dying itself
+        } else if score == Fitness::Dying(false) || score == Fitness::Dying(true) {
+            // do revert
+            let timeout = score == Fitness::Dying(true);
+            // the page within the level, so deaths a few pixels apart count as the same spot
+            let spot = scroll(nes) >> 8;
+            let repeats = if mario.death_spot == Some(spot) {
+                mario.deaths
+            } else {
+                0
+            };
+            mario.death_spot = Some(spot);
+            mario.deaths = repeats + 1;
+            if let Some(obstacles) = &mario.obstacles {
+                obstacles.lock().unwrap().revert(depth(nes), spot);
+            }
+
+            let frame = policy.target(nes.frame_number() as u64, timeout, repeats);
+            debug!(
+                timeout,
+                repeats,
+                from = nes.frame_number(),
+                to = frame,
+                "reverting"
+            );
+            let from = nes.frame_number() as u64;
+            if let Some(state) = revert(&mut mario.states, from, frame, |state| {
+                state.nes.frame_number() as u64
+            }) {
+                *nes = state.nes;
+            }
+            mario.rewound(nes.frame_number() as u64);
+            nes.controllers = Controllers::standard(&input);
+            score = objective_fitness(nes, mario.objective, settings.powerup_bonus);
+
+            mario.next_state = mario.personality.confident;
+        } else if mario.next_state == 0 {
+            // remove previous states if we just cleared a level
+            if victory(nes) {
+                if let Some(run) = mario.run.take() {
+                    debug!(frame = nes.frame_number(), "level cleared");
+                    mario.cleared = Some(run);
+                }
+                mario.states.clear();
+                mario.death_spot = None;
+                mario.deaths = 0;
+            } else if policy.checkpoint_due(
+                mario.states.back_mut().map(|state| fitness(&mut state.nes)),
+                &fitness(nes),
+            ) {
+                let clone = Instant::now();
+                mario.states.push_back(State::new(nes.clone()));
+                mario.cost.clone += clone.elapsed();
+                if settings.max_memory.is_none() && mario.states.len() > MAX_STATES {
+                    mario.states.pop_front();
+                }
+            }
+
+            mario.next_state = mario.personality.confident;
+        } else {
+            mario.next_state -= 1;
+        }
+
+        if settings.cutscene_speed > 1 && matches!(score, Fitness::Cutscene | Fitness::Flagpole(_))
+        {
+            // no need to plan anything, just get it over with
+            input.store(0, Ordering::Relaxed);
+            mario.last_input = 0;
+            let step = Instant::now();
+            for _ in 0..settings.cutscene_speed {
+                mario.record(0);
+                nes.next_frame();
+                if !matches!(fitness(nes), Fitness::Cutscene | Fitness::Flagpole(_)) {
+                    break;
+                }
+            }
+            mario.cost.step += step.elapsed();
+            return;
+        }
+
+        mario.effective = match settings.annealing {
+            Some(annealing) => annealing.apply(&mario.personality, depth(nes)),
+            None => mario.personality.clone(),
+        };
+
+        let known = match &mario.obstacles {
+            Some(obstacles) => {
+                let (level, spot) = (depth(nes), scroll(nes) >> 8);
+                match obstacles.lock().unwrap().get(level, spot) {
+                    Some(obstacle) => obstacle.macros.clone(),
+                    None => Vec::new(),
+                }
+            }
+            None => Vec::new(),
+        };
+        let frame = nes.frame_number();
+        let mut turn = Turn {
+            observation: Observation::read(nes),
+            nes,
+            score,
+            last_input: mario.last_input,
+            personality: &mario.effective,
+            objective: mario.objective,
+            powerup_bonus: settings.powerup_bonus,
+            known,
+            cost: &mut mario.cost,
+        };
+        mario.inputs_future = mario.controller.decide(&mut turn, &mut rand::thread_rng());
+        if nes.frame_number() < frame {
+            // the controller went back to an earlier state
+            nes.controllers = Controllers::standard(&input);
+            mario.rewound(nes.frame_number() as u64);
+        }
+    }
+
+    // set input
+    let item = mario.inputs_future.pop_front().unwrap();
+    mario.last_input = item;
+    input.store(item, Ordering::Relaxed);
+
+    // a run starts over in every new level
+    let level = depth(nes);
+    let new_level = match &mario.run {
+        Some(run) => run.depth != level,
+        None => true,
+    };
+    if new_level && in_level(nes) {
+        mario.run = Some(Run::new(nes));
+    }
+    mario.record(item);
+
+    // next frame
+    let step = Instant::now();
+    nes.next_frame();
+    mario.cost.step += step.elapsed();
+
+    if let Some(obstacles) = &mario.obstacles {
+        let spot = scroll(nes) >> 8;
+        if spot > mario.spot {
+            let from = mario.played.len().saturating_sub(MACRO_FRAMES);
+            obstacles
+                .lock()
+                .unwrap()
+                .passed(level, mario.spot, &mario.played[from..]);
+        }
+        mario.spot = spot;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_early_in_run_reverts_to_start() {
+        let policy = RevertPolicy::default();
+        assert_eq!(policy.target(100, true, 0), 0);
+        assert_eq!(policy.target(policy.timeout, true, 0), 0);
+        assert_eq!(policy.target(0, true, 0), 0);
+    }
+
+    #[test]
+    fn timeout_goes_back_a_fixed_amount() {
+        let policy = RevertPolicy::default();
+        assert_eq!(policy.target(policy.timeout + 500, true, 0), 500);
+    }
+
+    #[test]
+    fn death_reverts_to_before_current_frame() {
+        assert_eq!(RevertPolicy::default().target(1234, false, 0), 1234);
+    }
+
+    #[test]
+    fn repeated_deaths_back_off() {
+        let policy = RevertPolicy {
+            death: 10,
+            backoff: 100,
+            ..RevertPolicy::default()
+        };
+        assert_eq!(policy.depth(false, 0), 10);
+        assert_eq!(policy.depth(false, 1), 110);
+        assert_eq!(policy.depth(false, 2), 210);
+        assert_eq!(policy.depth(false, 3), 410);
+        assert_eq!(policy.depth(false, 200), u64::MAX);
+    }
+
+    #[test]
+    fn depth_is_capped() {
+        let policy = RevertPolicy {
+            backoff: 100,
+            max: 250,
+            ..RevertPolicy::default()
+        };
+        assert_eq!(policy.depth(false, 2), 200);
+        assert_eq!(policy.depth(false, 3), 250);
+        assert_eq!(policy.depth(true, 0), 250);
+    }
+
+    #[test]
+    fn checkpoint_needs_progress() {
+        let policy = RevertPolicy::default();
+        let last = || Some(Fitness::Level(1000, 0));
+        assert!(!policy.checkpoint_due(last(), &Fitness::Level(1000, 0)));
+        assert!(!policy.checkpoint_due(last(), &Fitness::Level(1000 + policy.checkpoint - 1, 0)));
+        assert!(!policy.checkpoint_due(last(), &Fitness::Level(500, 0)));
+        assert!(policy.checkpoint_due(last(), &Fitness::Level(1000 + policy.checkpoint, 0)));
+    }
+
+    #[test]
+    fn checkpoint_on_level_transition() {
+        let policy = RevertPolicy::default();
+        let last = Some(Fitness::Level(0x0000_0f00, 0));
+        assert!(policy.checkpoint_due(last, &Fitness::Level(0x0001_0000, 0)));
+        assert!(policy.checkpoint_due(None, &Fitness::Level(0, 0)));
+        assert!(policy.checkpoint_due(Some(Fitness::Cutscene), &Fitness::Level(0, 0)));
+    }
+
+    #[test]
+    fn no_checkpoint_outside_levels() {
+        let policy = RevertPolicy::default();
+        assert!(!policy.checkpoint_due(None, &Fitness::Cutscene));
+        assert!(!policy.checkpoint_due(Some(Fitness::Level(0, 0)), &Fitness::Dying(false)));
+    }
+
+    #[test]
+    fn revert_stops_at_first_older_state() {
+        let mut states: VecDeque<u64> = vec![0, 100, 200, 300].into();
+        let state = revert(&mut states, 400, 250, |&frame| frame);
+        assert_eq!(state, Some(200));
+        assert_eq!(states, [0, 100]);
+    }
+
+    #[test]
+    fn revert_past_start_keeps_oldest_state() {
+        let mut states: VecDeque<u64> = vec![0, 100, 200].into();
+        let target = RevertPolicy::default().target(300, true, 0);
+        let state = revert(&mut states, 300, target, |&frame| frame);
+        assert_eq!(state, Some(0));
+        assert!(states.is_empty());
+    }
+
+    #[test]
+    fn revert_without_saved_states_keeps_current() {
+        let mut states = VecDeque::new();
+        let state = revert(&mut states, 300, 0, |&frame| frame);
+        assert_eq!(state, None);
+    }
+}
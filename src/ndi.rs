@@ -0,0 +1,156 @@
+//! Sending frames as an NDI source, which OBS and other production software
+//! pick up over the network by name.
+//!
+//! The NDI runtime is loaded when a sender is created rather than linked in,
+//! so building needs no SDK and sending only needs the runtime installed.
+
+use std::{
+    env,
+    ffi::CString,
+    os::raw::{c_char, c_float, c_int, c_void},
+    path::PathBuf,
+    ptr,
+};
+
+use libloading::Library;
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+const LIBRARY: &str = "Processing.NDI.Lib.x64.dll";
+#[cfg(target_os = "macos")]
+const LIBRARY: &str = "libndi.dylib";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LIBRARY: &str = "libndi.so.5";
+
+/// Set by the runtime installer to the directory it installed to.
+const RUNTIME_DIR: &str = "NDI_RUNTIME_DIR_V5";
+
+const FOURCC_RGBA: u32 = u32::from_le_bytes(*b"RGBA");
+const PROGRESSIVE: c_int = 1;
+/// Has the runtime make up timecodes as frames are sent.
+const SYNTHESIZE_TIMECODE: i64 = i64::MAX;
+/// What frames are announced at, the rate the window renders at.
+const FRAME_RATE: c_int = 60;
+
+type Instance = *mut c_void;
+type Initialize = unsafe extern "C" fn() -> bool;
+type Create = unsafe extern "C" fn(*const SendCreate) -> Instance;
+type SendVideo = unsafe extern "C" fn(Instance, *const VideoFrame);
+type Destroy = unsafe extern "C" fn(Instance);
+
+/// `NDIlib_send_create_t`
+#[repr(C)]
+struct SendCreate {
+    name: *const c_char,
+    groups: *const c_char,
+    clock_video: bool,
+    clock_audio: bool,
+}
+
+/// `NDIlib_video_frame_v2_t`
+#[repr(C)]
+struct VideoFrame {
+    xres: c_int,
+    yres: c_int,
+    fourcc: u32,
+    frame_rate_n: c_int,
+    frame_rate_d: c_int,
+    picture_aspect_ratio: c_float,
+    frame_format_type: c_int,
+    timecode: i64,
+    data: *const u8,
+    line_stride: c_int,
+    metadata: *const c_char,
+    timestamp: i64,
+}
+
+#[derive(Debug, Error)]
+pub enum NdiError {
+    #[error("could not load the NDI runtime, is it installed?")]
+    Load(#[from] libloading::Error),
+    #[error("the NDI runtime does not support this CPU")]
+    Initialize,
+    #[error("could not create an NDI source called {0:?}")]
+    Create(String),
+}
+
+/// An NDI source frames are sent to.
+pub struct Sender {
+    instance: Instance,
+    send_video: SendVideo,
+    destroy: Destroy,
+    // the functions above point into the library, so it outlives them
+    _library: Library,
+}
+
+impl Sender {
+    /// Loads the NDI runtime and announces a source called `name`.
+    pub fn new(name: &str) -> Result<Sender, NdiError> {
+        let path = match env::var_os(RUNTIME_DIR) {
+            Some(dir) => PathBuf::from(dir).join(LIBRARY),
+            None => PathBuf::from(LIBRARY),
+        };
+        let c_name = CString::new(name).map_err(|_| NdiError::Create(name.to_owned()))?;
+
+        // SAFETY: the runtime runs nothing when loaded, and the functions are
+        // declared as in its headers
+        unsafe {
+            let library = Library::new(path)?;
+            let initialize = *library.get::<Initialize>(b"NDIlib_initialize\0")?;
+            let create = *library.get::<Create>(b"NDIlib_send_create\0")?;
+            let send_video = *library.get::<SendVideo>(b"NDIlib_send_send_video_v2\0")?;
+            let destroy = *library.get::<Destroy>(b"NDIlib_send_destroy\0")?;
+            if !initialize() {
+                return Err(NdiError::Initialize);
+            }
+
+            // frames are paced by the render loop, not held back by the sender
+            let settings = SendCreate {
+                name: c_name.as_ptr(),
+                groups: ptr::null(),
+                clock_video: false,
+                clock_audio: false,
+            };
+            let instance = create(&settings);
+            if instance.is_null() {
+                return Err(NdiError::Create(name.to_owned()));
+            }
+            Ok(Sender {
+                instance,
+                send_video,
+                destroy,
+                _library: library,
+            })
+        }
+    }
+
+    /// Sends a frame of `width` by `height` RGBA pixels, row after row.
+    pub fn send(&mut self, width: usize, height: usize, pixels: &[u8]) {
+        assert_eq!(pixels.len(), width * height * 4, "frame size mismatch");
+        let frame = VideoFrame {
+            xres: width as c_int,
+            yres: height as c_int,
+            fourcc: FOURCC_RGBA,
+            frame_rate_n: FRAME_RATE,
+            frame_rate_d: 1,
+            // square pixels
+            picture_aspect_ratio: 0.0,
+            frame_format_type: PROGRESSIVE,
+            timecode: SYNTHESIZE_TIMECODE,
+            data: pixels.as_ptr(),
+            line_stride: (width * 4) as c_int,
+            metadata: ptr::null(),
+            timestamp: 0,
+        };
+        // SAFETY: the frame describes `pixels`, which the runtime is done with
+        // when the call returns
+        unsafe { (self.send_video)(self.instance, &frame) }
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        // SAFETY: the instance was created by this runtime and isn't used again
+        unsafe { (self.destroy)(self.instance) }
+    }
+}
@@ -0,0 +1,635 @@
+//! NeuroEvolution of Augmenting Topologies: small neural networks that start
+//! out without hidden nodes and grow them as they are bred.
+//!
+//! Genomes are evaluated one at a time with [`Pool::take`] and
+//! [`Pool::report`]. Once every genome of a generation has a fitness, the pool
+//! splits them into species of similar genomes and breeds the next generation,
+//! giving each species offspring in proportion to how well it did.
+
+use std::collections::HashMap;
+
+use rand::{seq::SliceRandom, Rng};
+
+/// Chance of every weight being changed when a genome is mutated.
+const MUTATE_WEIGHTS: f64 = 0.8;
+/// Chance of a changed weight being replaced rather than nudged.
+const REPLACE_WEIGHT: f64 = 0.1;
+const NUDGE: f32 = 0.2;
+const ADD_CONNECTION: f64 = 0.1;
+const ADD_NODE: f64 = 0.03;
+const TOGGLE: f64 = 0.02;
+/// Chance of an offspring having two parents instead of one.
+const CROSSOVER: f64 = 0.75;
+
+/// Weights of excess and disjoint genes and of weight differences in the
+/// distance between two genomes.
+const EXCESS: f32 = 1.0;
+const DISJOINT: f32 = 1.0;
+const WEIGHT: f32 = 0.4;
+/// Genomes closer than this are of the same species.
+const SPECIES_DISTANCE: f32 = 3.0;
+
+/// A connection between two nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gene {
+    pub from: usize,
+    pub to: usize,
+    pub weight: f32,
+    pub enabled: bool,
+    /// Identifies the same connection across genomes, for crossover and for
+    /// telling species apart.
+    pub innovation: u32,
+}
+
+/// Hands out innovation numbers and hidden nodes so that the same structural
+/// mutation gets the same numbers in every genome of a generation.
+#[derive(Clone, Debug)]
+pub struct Innovations {
+    connections: HashMap<(usize, usize), u32>,
+    splits: HashMap<u32, usize>,
+    next_innovation: u32,
+    next_node: usize,
+}
+
+impl Innovations {
+    fn new(nodes: usize) -> Innovations {
+        Innovations {
+            connections: HashMap::new(),
+            splits: HashMap::new(),
+            next_innovation: 0,
+            next_node: nodes,
+        }
+    }
+
+    fn connection(&mut self, from: usize, to: usize) -> u32 {
+        let next = &mut self.next_innovation;
+        *self.connections.entry((from, to)).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+
+    /// The hidden node that splits the connection with `innovation`.
+    fn split(&mut self, innovation: u32) -> usize {
+        let next = &mut self.next_node;
+        *self.splits.entry(innovation).or_insert_with(|| {
+            *next += 1;
+            *next - 1
+        })
+    }
+}
+
+/// The connections of a network with a fixed number of inputs and outputs.
+/// Nodes are numbered inputs first, then outputs, then hidden nodes.
+#[derive(Clone, Debug)]
+pub struct Genome {
+    pub inputs: usize,
+    pub outputs: usize,
+    /// Sorted by innovation number.
+    pub genes: Vec<Gene>,
+}
+
+impl Genome {
+    /// A genome without connections, which every lineage starts from.
+    pub fn minimal(inputs: usize, outputs: usize) -> Genome {
+        Genome {
+            inputs,
+            outputs,
+            genes: Vec::new(),
+        }
+    }
+
+    fn is_input(&self, node: usize) -> bool {
+        node < self.inputs
+    }
+
+    fn is_output(&self, node: usize) -> bool {
+        (self.inputs..self.inputs + self.outputs).contains(&node)
+    }
+
+    /// Every node with a connection, along with every input and output.
+    fn nodes(&self) -> Vec<usize> {
+        let mut nodes: Vec<usize> = (0..self.inputs + self.outputs).collect();
+        for gene in self.genes.iter() {
+            nodes.extend([gene.from, gene.to]);
+        }
+        nodes.sort_unstable();
+        nodes.dedup();
+        nodes
+    }
+
+    /// Whether `to` can be reached from `from` over enabled or disabled
+    /// connections, which a new connection from `to` to `from` would make a
+    /// cycle of.
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut seen = vec![from];
+        while let Some(node) = stack.pop() {
+            if node == to {
+                return true;
+            }
+            for gene in self.genes.iter().filter(|gene| gene.from == node) {
+                if !seen.contains(&gene.to) {
+                    seen.push(gene.to);
+                    stack.push(gene.to);
+                }
+            }
+        }
+        false
+    }
+
+    fn insert(&mut self, gene: Gene) {
+        let at = self
+            .genes
+            .partition_point(|other| other.innovation < gene.innovation);
+        self.genes.insert(at, gene);
+    }
+
+    pub fn mutate(&mut self, innovations: &mut Innovations, rng: &mut impl Rng) {
+        if rng.gen_bool(MUTATE_WEIGHTS) {
+            for gene in self.genes.iter_mut() {
+                if rng.gen_bool(REPLACE_WEIGHT) {
+                    gene.weight = rng.gen_range(-2.0..2.0);
+                } else {
+                    gene.weight += rng.gen_range(-NUDGE..NUDGE);
+                }
+            }
+        }
+        if rng.gen_bool(ADD_CONNECTION) {
+            self.add_connection(innovations, rng);
+        }
+        if rng.gen_bool(ADD_NODE) {
+            self.add_node(innovations, rng);
+        }
+        if rng.gen_bool(TOGGLE) {
+            if let Some(gene) = self.genes.choose_mut(rng) {
+                gene.enabled = !gene.enabled;
+            }
+        }
+    }
+
+    /// Connects two unconnected nodes, never into an input or back into a
+    /// node that leads to the start of the connection.
+    fn add_connection(&mut self, innovations: &mut Innovations, rng: &mut impl Rng) {
+        let nodes = self.nodes();
+        let from = *nodes.choose(rng).unwrap();
+        let to = *nodes.choose(rng).unwrap();
+        if self.is_input(to)
+            || self.is_output(from)
+            || from == to
+            || self
+                .genes
+                .iter()
+                .any(|gene| (gene.from, gene.to) == (from, to))
+            || self.reaches(to, from)
+        {
+            return;
+        }
+        self.insert(Gene {
+            from,
+            to,
+            weight: rng.gen_range(-2.0..2.0),
+            enabled: true,
+            innovation: innovations.connection(from, to),
+        });
+    }
+
+    /// Splits an enabled connection in two with a hidden node in between,
+    /// which leaves what the network computes about the same.
+    fn add_node(&mut self, innovations: &mut Innovations, rng: &mut impl Rng) {
+        let enabled: Vec<usize> = (0..self.genes.len())
+            .filter(|&i| self.genes[i].enabled)
+            .collect();
+        let split = match enabled.choose(rng) {
+            Some(&i) => i,
+            None => return,
+        };
+        let old = self.genes[split];
+        let node = innovations.split(old.innovation);
+        if self.genes.iter().any(|gene| gene.to == node) {
+            return;
+        }
+        self.genes[split].enabled = false;
+        self.insert(Gene {
+            from: old.from,
+            to: node,
+            weight: 1.0,
+            enabled: true,
+            innovation: innovations.connection(old.from, node),
+        });
+        self.insert(Gene {
+            from: node,
+            to: old.to,
+            weight: old.weight,
+            enabled: true,
+            innovation: innovations.connection(node, old.to),
+        });
+    }
+
+    /// An offspring of `self` and the less fit `other`, with the matching
+    /// genes of either and the rest of `self`.
+    pub fn crossover(&self, other: &Genome, rng: &mut impl Rng) -> Genome {
+        let genes = self
+            .genes
+            .iter()
+            .map(|gene| {
+                match other
+                    .genes
+                    .binary_search_by_key(&gene.innovation, |other| other.innovation)
+                {
+                    Ok(i) if rng.gen_bool(0.5) => Gene {
+                        enabled: gene.enabled,
+                        ..other.genes[i]
+                    },
+                    _ => *gene,
+                }
+            })
+            .collect();
+        Genome {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            genes,
+        }
+    }
+
+    /// How far apart two genomes are: how many genes only one of them has,
+    /// and how different the weights of the genes both have are.
+    pub fn distance(&self, other: &Genome) -> f32 {
+        let last = |genome: &Genome| genome.genes.last().map(|gene| gene.innovation);
+        let shared_end = match (last(self), last(other)) {
+            (Some(a), Some(b)) => a.min(b),
+            _ => 0,
+        };
+
+        let (mut excess, mut disjoint, mut matching, mut difference) = (0, 0, 0, 0.0);
+        for (genome, against) in [(self, other), (other, self)] {
+            for gene in genome.genes.iter() {
+                match against
+                    .genes
+                    .binary_search_by_key(&gene.innovation, |other| other.innovation)
+                {
+                    Ok(i) => {
+                        // counted from both sides
+                        matching += 1;
+                        difference += (gene.weight - against.genes[i].weight).abs();
+                    }
+                    Err(_) if gene.innovation > shared_end => excess += 1,
+                    Err(_) => disjoint += 1,
+                }
+            }
+        }
+
+        let size = self.genes.len().max(other.genes.len()).max(1) as f32;
+        let weights = if matching == 0 {
+            0.0
+        } else {
+            difference / matching as f32
+        };
+        EXCESS * excess as f32 / size + DISJOINT * disjoint as f32 / size + WEIGHT * weights
+    }
+}
+
+/// A genome ready to be run, with its nodes in an order in which every node
+/// comes after the nodes it gets input from.
+#[derive(Clone, Debug)]
+pub struct Network {
+    inputs: usize,
+    outputs: usize,
+    /// The input connections of every computed node, in order.
+    order: Vec<(usize, Vec<(usize, f32)>)>,
+    values: HashMap<usize, f32>,
+}
+
+impl Network {
+    pub fn new(genome: &Genome) -> Network {
+        let enabled: Vec<&Gene> = genome.genes.iter().filter(|gene| gene.enabled).collect();
+        let mut done: Vec<usize> = (0..genome.inputs).collect();
+        let mut pending: Vec<usize> = genome
+            .nodes()
+            .into_iter()
+            .filter(|&node| !genome.is_input(node))
+            .collect();
+
+        let mut order = Vec::new();
+        while !pending.is_empty() {
+            let ready = pending.iter().position(|&node| {
+                enabled
+                    .iter()
+                    .filter(|gene| gene.to == node)
+                    .all(|gene| done.contains(&gene.from))
+            });
+            // genomes never have cycles, but a broken one shouldn't hang
+            let node = pending.remove(ready.unwrap_or(0));
+            let incoming = enabled
+                .iter()
+                .filter(|gene| gene.to == node)
+                .map(|gene| (gene.from, gene.weight))
+                .collect();
+            order.push((node, incoming));
+            done.push(node);
+        }
+
+        Network {
+            inputs: genome.inputs,
+            outputs: genome.outputs,
+            order,
+            values: HashMap::new(),
+        }
+    }
+
+    /// The outputs for `inputs`, each between -1 and 1.
+    pub fn activate(&mut self, inputs: &[f32]) -> Vec<f32> {
+        self.values.clear();
+        for (node, &value) in inputs.iter().take(self.inputs).enumerate() {
+            self.values.insert(node, value);
+        }
+        for (node, incoming) in self.order.iter() {
+            let sum: f32 = incoming
+                .iter()
+                .map(|(from, weight)| self.values.get(from).copied().unwrap_or(0.0) * weight)
+                .sum();
+            let value = if incoming.is_empty() { 0.0 } else { sum.tanh() };
+            self.values.insert(*node, value);
+        }
+        (self.inputs..self.inputs + self.outputs)
+            .map(|node| self.values.get(&node).copied().unwrap_or(0.0))
+            .collect()
+    }
+}
+
+/// A genome handed out for evaluation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ticket {
+    generation: u32,
+    index: usize,
+}
+
+/// The genomes of a generation, with the fitness of those evaluated so far.
+pub struct Pool {
+    genomes: Vec<(Genome, Option<f64>)>,
+    /// The genome that each species is compared to.
+    species: Vec<Genome>,
+    innovations: Innovations,
+    generation: u32,
+    /// Genomes still to be handed out, last first.
+    queue: Vec<usize>,
+    best: Option<(Genome, f64)>,
+}
+
+impl Pool {
+    /// A first generation of `size` minimal genomes with a mutation each.
+    pub fn new(inputs: usize, outputs: usize, size: usize, rng: &mut impl Rng) -> Pool {
+        let mut innovations = Innovations::new(inputs + outputs);
+        let genomes = (0..size.max(1))
+            .map(|_| {
+                let mut genome = Genome::minimal(inputs, outputs);
+                genome.add_connection(&mut innovations, rng);
+                genome.mutate(&mut innovations, rng);
+                (genome, None)
+            })
+            .collect();
+        Pool {
+            queue: (0..size.max(1)).rev().collect(),
+            genomes,
+            species: Vec::new(),
+            innovations,
+            generation: 0,
+            best: None,
+        }
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn species(&self) -> usize {
+        self.species.len()
+    }
+
+    /// The fittest genome evaluated so far, over every generation.
+    pub fn best(&self) -> Option<&(Genome, f64)> {
+        self.best.as_ref()
+    }
+
+    /// A genome to evaluate, and the ticket to [`report`](Pool::report) its
+    /// fitness with. Once every genome has been handed out, this is the best
+    /// one so far without a ticket until the rest are reported.
+    pub fn take(&mut self) -> (Option<Ticket>, Genome) {
+        if let Some(index) = self.queue.pop() {
+            let ticket = Ticket {
+                generation: self.generation,
+                index,
+            };
+            return (Some(ticket), self.genomes[index].0.clone());
+        }
+        let best = match &self.best {
+            Some((genome, _)) => genome.clone(),
+            None => self.genomes[0].0.clone(),
+        };
+        (None, best)
+    }
+
+    /// Hands out the genome of `ticket` again, for when its evaluation was
+    /// cut short.
+    pub fn abandon(&mut self, ticket: Ticket) {
+        if ticket.generation == self.generation && !self.queue.contains(&ticket.index) {
+            self.queue.push(ticket.index);
+        }
+    }
+
+    /// Gives the genome of `ticket` its fitness, breeding the next generation
+    /// once every genome has one. Returns whether it did.
+    pub fn report(&mut self, ticket: Ticket, fitness: f64, rng: &mut impl Rng) -> bool {
+        if ticket.generation != self.generation {
+            return false;
+        }
+        let genome = match self.genomes.get_mut(ticket.index) {
+            Some(genome) => genome,
+            None => return false,
+        };
+        genome.1 = Some(fitness);
+        let is_best = match &self.best {
+            Some((_, best)) => fitness > *best,
+            None => true,
+        };
+        if is_best {
+            self.best = Some((genome.0.clone(), fitness));
+        }
+
+        if self.genomes.iter().all(|(_, fitness)| fitness.is_some()) {
+            self.breed(rng);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn breed(&mut self, rng: &mut impl Rng) {
+        let size = self.genomes.len();
+        let evaluated: Vec<(Genome, f64)> = self
+            .genomes
+            .drain(..)
+            .map(|(genome, fitness)| (genome, fitness.unwrap_or(0.0)))
+            .collect();
+
+        // split into species
+        let mut members: Vec<Vec<(Genome, f64)>> = vec![Vec::new(); self.species.len()];
+        for (genome, fitness) in evaluated {
+            match self
+                .species
+                .iter()
+                .position(|species| genome.distance(species) < SPECIES_DISTANCE)
+            {
+                Some(i) => members[i].push((genome, fitness)),
+                None => {
+                    self.species.push(genome.clone());
+                    members.push(vec![(genome, fitness)]);
+                }
+            }
+        }
+        let mut species: Vec<Vec<(Genome, f64)>> = members
+            .into_iter()
+            .filter(|members| !members.is_empty())
+            .collect();
+        for members in species.iter_mut() {
+            members.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        }
+
+        // fitness shared within a species, so no single one takes over
+        let shared: Vec<f64> = species
+            .iter()
+            .map(|members| {
+                let total: f64 = members.iter().map(|(_, fitness)| fitness.max(0.0)).sum();
+                total / members.len() as f64
+            })
+            .collect();
+        let total: f64 = shared.iter().sum();
+
+        let mut next = Vec::with_capacity(size);
+        for (members, shared) in species.iter().zip(shared.iter()) {
+            let share = if total > 0.0 {
+                shared / total
+            } else {
+                1.0 / species.len() as f64
+            };
+            let offspring = (share * size as f64).round() as usize;
+            if offspring == 0 {
+                continue;
+            }
+            // the champion carries over unchanged
+            next.push((members[0].0.clone(), None));
+
+            let parents = &members[..members.len().div_ceil(2)];
+            for _ in 1..offspring {
+                let (a, fitness_a) = parents.choose(rng).unwrap();
+                let mut child = if rng.gen_bool(CROSSOVER) {
+                    let (b, fitness_b) = parents.choose(rng).unwrap();
+                    if fitness_a >= fitness_b {
+                        a.crossover(b, rng)
+                    } else {
+                        b.crossover(a, rng)
+                    }
+                } else {
+                    a.clone()
+                };
+                child.mutate(&mut self.innovations, rng);
+                next.push((child, None));
+            }
+        }
+
+        // rounding leaves a few too many or too few
+        next.truncate(size);
+        while next.len() < size {
+            let (parent, _) = species[0].choose(rng).unwrap();
+            let mut child = parent.clone();
+            child.mutate(&mut self.innovations, rng);
+            next.push((child, None));
+        }
+
+        self.species = species.iter().map(|members| members[0].0.clone()).collect();
+        self.genomes = next;
+        self.queue = (0..size).rev().collect();
+        self.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gene(from: usize, to: usize, weight: f32, innovation: u32) -> Gene {
+        Gene {
+            from,
+            to,
+            weight,
+            enabled: true,
+            innovation,
+        }
+    }
+
+    #[test]
+    fn networks_run_hidden_nodes_first() {
+        // 0 -> 3 -> 2, 1 -> 2
+        let genome = Genome {
+            inputs: 2,
+            outputs: 1,
+            genes: vec![gene(3, 2, 1.0, 0), gene(1, 2, -1.0, 1), gene(0, 3, 2.0, 2)],
+        };
+        let mut network = Network::new(&genome);
+        let output = network.activate(&[1.0, 0.5])[0];
+        let expected = (2.0f32.tanh() - 0.5).tanh();
+        assert!((output - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn structural_mutations_never_make_cycles() {
+        let mut rng = rand::thread_rng();
+        let mut innovations = Innovations::new(4);
+        let mut genome = Genome::minimal(3, 1);
+        for _ in 0..200 {
+            genome.add_connection(&mut innovations, &mut rng);
+            genome.add_node(&mut innovations, &mut rng);
+        }
+        for gene in genome.genes.iter() {
+            assert!(!genome.reaches(gene.to, gene.from));
+            assert!(!genome.is_input(gene.to));
+        }
+        assert!(genome
+            .genes
+            .windows(2)
+            .all(|pair| pair[0].innovation < pair[1].innovation));
+    }
+
+    #[test]
+    fn distance_counts_unshared_genes() {
+        let a = Genome {
+            inputs: 2,
+            outputs: 1,
+            genes: vec![gene(0, 2, 1.0, 0), gene(1, 2, 1.0, 1)],
+        };
+        let mut b = a.clone();
+        assert_eq!(a.distance(&b), 0.0);
+        b.genes.push(gene(3, 2, 1.0, 3));
+        assert!(a.distance(&b) > 0.0);
+    }
+
+    #[test]
+    fn generations_keep_their_size() {
+        let mut rng = rand::thread_rng();
+        let mut pool = Pool::new(4, 2, 20, &mut rng);
+        for generation in 0..3 {
+            assert_eq!(pool.generation(), generation);
+            let mut handed = Vec::new();
+            while let (Some(ticket), _) = pool.take() {
+                handed.push(ticket);
+            }
+            assert_eq!(handed.len(), 20);
+            pool.abandon(handed[0]);
+            assert_eq!(pool.take().0, Some(handed[0]));
+            for (i, ticket) in handed.into_iter().enumerate() {
+                pool.report(ticket, i as f64, &mut rng);
+            }
+        }
+        assert_eq!(pool.best().map(|(_, fitness)| *fitness), Some(19.0));
+    }
+}
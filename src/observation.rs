@@ -0,0 +1,190 @@
+//! What a controller sees of the game on a frame: where Mario and the enemies
+//! are, how fast Mario moves and what tiles are around him.
+
+use crate::smb::{ram::ENEMY_SLOTS, Memory};
+
+/// Tiles sampled around Mario, 16 pixels apart, from behind him forward and
+/// from above him down.
+pub const PROBE_COLUMNS: [i32; 5] = [-1, 0, 1, 2, 3];
+pub const PROBE_ROWS: [i32; 5] = [-2, -1, 0, 1, 2];
+/// Length of [`Observation::vector`]: speeds, the nearest enemy and how far
+/// away it is, the tiles and a bias.
+pub const FEATURES: usize = 6 + PROBE_COLUMNS.len() * PROBE_ROWS.len() + 1;
+
+/// Where something is, by the top left of its sprite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// Horizontal position in the level.
+    pub x: u16,
+    /// Pixels from the top of the screen, negative above it.
+    pub y: i16,
+}
+
+impl Position {
+    /// The position `player_x` and `player_y` style coordinates are at.
+    fn of(x: u16, y: u16) -> Position {
+        Position {
+            x,
+            y: (i32::from(y) - 256) as i16,
+        }
+    }
+
+    /// Where the player is.
+    pub fn player(nes: &mut impl Memory) -> Position {
+        Position::of(nes.player_x(), nes.player_y())
+    }
+
+    /// The middle of a 16 pixel sprite at this position.
+    fn center(self) -> (i32, i32) {
+        (i32::from(self.x) + 8, i32::from(self.y) + 8)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub player: Position,
+    /// Horizontal speed, negative when moving left.
+    pub x_speed: i8,
+    /// Vertical speed, negative when moving up.
+    pub y_speed: i8,
+    /// Every enemy slot, with the position of the enemy in it if any.
+    pub enemies: [Option<Position>; ENEMY_SLOTS as usize],
+    /// The metatile at every probe around the middle of Mario, by row and
+    /// then by column, 0 being empty.
+    pub tiles: [[u8; PROBE_COLUMNS.len()]; PROBE_ROWS.len()],
+    /// Horizontal position in the level of the left edge of the screen.
+    pub screen_x: u16,
+}
+
+impl Observation {
+    pub fn read(nes: &mut impl Memory) -> Observation {
+        let player = Position::player(nes);
+        let mut enemies = [None; ENEMY_SLOTS as usize];
+        for (slot, enemy) in (0..ENEMY_SLOTS).zip(enemies.iter_mut()) {
+            *enemy = nes.enemy(slot).map(|(x, y)| Position::of(x, y));
+        }
+        let (x, y) = player.center();
+        let mut tiles = [[0; PROBE_COLUMNS.len()]; PROBE_ROWS.len()];
+        for (row, tiles) in PROBE_ROWS.into_iter().zip(tiles.iter_mut()) {
+            for (column, tile) in PROBE_COLUMNS.into_iter().zip(tiles.iter_mut()) {
+                let (px, py) = (x + column * 16, y + row * 16);
+                if px >= 0 && py >= 0 {
+                    *tile = nes.tile(px as u16, py as u16);
+                }
+            }
+        }
+        Observation {
+            player,
+            x_speed: nes.player_x_speed(),
+            y_speed: nes.player_y_speed(),
+            enemies,
+            tiles,
+            screen_x: nes.screen_x(),
+        }
+    }
+
+    /// Where `position` is on the frame, in pixels from its top left corner.
+    pub fn on_screen(&self, position: Position) -> (i32, i32) {
+        let x = position.x.wrapping_sub(self.screen_x) as i16;
+        (i32::from(x), i32::from(position.y))
+    }
+
+    /// How far the nearest enemy is from Mario in pixels, right and down.
+    pub fn nearest_enemy(&self) -> Option<(i32, i32)> {
+        let (x, y) = self.player.center();
+        self.enemies
+            .iter()
+            .flatten()
+            .map(|enemy| {
+                let (ex, ey) = enemy.center();
+                (ex - x, ey - y)
+            })
+            .min_by_key(|(dx, dy)| dx.abs() + dy.abs())
+    }
+
+    /// The observation as numbers roughly between -1 and 1, for networks.
+    pub fn vector(&self) -> [f32; FEATURES] {
+        let mut features = [0.0; FEATURES];
+        features[0] = f32::from(self.x_speed) / 40.0;
+        features[1] = f32::from(self.y_speed) / 5.0;
+        features[2] = (self.player.center().1 as f32 / 240.0).clamp(-1.0, 1.0);
+        if let Some((dx, dy)) = self.nearest_enemy() {
+            features[3] = 1.0;
+            features[4] = (dx as f32 / 256.0).clamp(-1.0, 1.0);
+            features[5] = (dy as f32 / 256.0).clamp(-1.0, 1.0);
+        }
+        for (feature, &tile) in features[6..].iter_mut().zip(self.tiles.iter().flatten()) {
+            if tile != 0 {
+                *feature = 1.0;
+            }
+        }
+        features[FEATURES - 1] = 1.0;
+        features
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smb::{ram::*, Ram};
+
+    /// Mario at x 0x120 on the visible screen.
+    fn mario() -> Ram {
+        Ram::default()
+            .with(PLAYER_PAGE, 1)
+            .with(PLAYER_X, 0x20)
+            .with(PLAYER_Y_SCREEN, 1)
+            .with(PLAYER_Y, 0xb0)
+            .with(SCREEN_PAGE, 1)
+    }
+
+    #[test]
+    fn reads_tiles_and_enemies() {
+        // a block right in front of Mario and a goomba 64 pixels ahead
+        let mut ram = mario()
+            .with(
+                TILES + TILES_PER_PAGE + (0xb8 - 32) / 16 * 16 + 0x38 / 16,
+                0x51,
+            )
+            .with(ENEMY_FLAGS + 2, 1)
+            .with(ENEMY_PAGE + 2, 1)
+            .with(ENEMY_X + 2, 0x60)
+            .with(ENEMY_Y_SCREEN + 2, 1)
+            .with(ENEMY_Y + 2, 0xb0);
+        let observation = Observation::read(&mut ram);
+
+        assert_eq!(observation.player, Position { x: 0x120, y: 0xb0 });
+        assert_eq!(observation.on_screen(observation.player), (0x20, 0xb0));
+        assert_eq!(observation.enemies[2], Some(Position { x: 0x160, y: 0xb0 }));
+        assert_eq!(observation.enemies.iter().flatten().count(), 1);
+        assert_eq!(observation.nearest_enemy(), Some((64, 0)));
+        assert_eq!(observation.tiles[2][2], 0x51);
+        assert_eq!(
+            observation
+                .tiles
+                .iter()
+                .flatten()
+                .filter(|&&tile| tile != 0)
+                .count(),
+            1
+        );
+
+        let vector = observation.vector();
+        assert_eq!(vector[3..6], [1.0, 0.25, 0.0]);
+        assert_eq!(vector[6 + 2 * PROBE_COLUMNS.len() + 2], 1.0);
+        assert_eq!(vector[FEATURES - 1], 1.0);
+    }
+
+    #[test]
+    fn pages_off_screen_read_as_empty() {
+        // the tile behind Mario is on the page before the screen
+        let mut ram = mario()
+            .with(PLAYER_X, 0x00)
+            .with(SCREEN_X, 0x10)
+            .with(TILES + (0xb8 - 32) / 16 * 16 + 0xf8 / 16, 0x51);
+        let observation = Observation::read(&mut ram);
+        assert_eq!(observation.tiles[2][0], 0);
+        assert_eq!(observation.nearest_enemy(), None);
+        assert_eq!(observation.vector()[3], 0.0);
+    }
+}
@@ -0,0 +1,237 @@
+//! Spots in levels where Marios keep dying, and the inputs that got them past,
+//! for planners to try again when they get there.
+//!
+//! Every level is kept in a file of its own, named after it like `1-2.obstacles`,
+//! with a line for every spot:
+//!
+//! ```text
+//! <spot> <reverts> <times>:<inputs in hex> <times>:<inputs in hex> ...
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+};
+
+use crate::smb::Warp;
+
+/// Reverts at a spot before it counts as an obstacle.
+pub const OBSTACLE_REVERTS: u32 = 8;
+/// Frames of input before getting past an obstacle that are remembered as
+/// what got past it.
+pub const MACRO_FRAMES: usize = 30;
+/// Macros kept for every obstacle, dropping those that got past least often.
+const MACROS: usize = 16;
+const EXTENSION: &str = "obstacles";
+
+/// A spot where Marios died, and what got them past it once it became an
+/// obstacle.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Obstacle {
+    pub reverts: u32,
+    /// Inputs that got past, with how many times they did, most first.
+    pub macros: Vec<(Vec<u8>, u32)>,
+}
+
+impl Obstacle {
+    fn is_obstacle(&self) -> bool {
+        self.reverts >= OBSTACLE_REVERTS
+    }
+}
+
+/// The obstacles of every level by its [`depth`](crate::smb::depth), and
+/// within it by spot: the page of the level, as Marios dying are counted.
+#[derive(Clone, Debug, Default)]
+pub struct Obstacles {
+    levels: HashMap<u32, BTreeMap<u32, Obstacle>>,
+}
+
+impl Obstacles {
+    /// Counts a revert after dying at `spot`.
+    pub fn revert(&mut self, depth: u32, spot: u32) {
+        let obstacle = self
+            .levels
+            .entry(depth)
+            .or_default()
+            .entry(spot)
+            .or_default();
+        obstacle.reverts = obstacle.reverts.saturating_add(1);
+    }
+
+    /// The obstacle at `spot`, if Marios died there often enough for it to
+    /// be one.
+    pub fn get(&self, depth: u32, spot: u32) -> Option<&Obstacle> {
+        self.levels
+            .get(&depth)?
+            .get(&spot)
+            .filter(|obstacle| obstacle.is_obstacle())
+    }
+
+    /// Remembers that `inputs` got a Mario past `spot`, if it is an obstacle.
+    pub fn passed(&mut self, depth: u32, spot: u32, inputs: &[u8]) {
+        let obstacle = match self
+            .levels
+            .get_mut(&depth)
+            .and_then(|level| level.get_mut(&spot))
+        {
+            Some(obstacle) if obstacle.is_obstacle() => obstacle,
+            _ => return,
+        };
+        match obstacle
+            .macros
+            .iter_mut()
+            .find(|(known, _)| known == inputs)
+        {
+            Some((_, times)) => *times += 1,
+            None => obstacle.macros.push((inputs.to_vec(), 1)),
+        }
+        obstacle.macros.sort_by(|(_, a), (_, b)| b.cmp(a));
+        obstacle.macros.truncate(MACROS);
+    }
+
+    /// Obstacles in every level, counting only spots Marios died at often
+    /// enough.
+    pub fn len(&self) -> usize {
+        self.levels
+            .values()
+            .flat_map(|level| level.values())
+            .filter(|obstacle| obstacle.is_obstacle())
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn write_level(level: &BTreeMap<u32, Obstacle>) -> String {
+        let mut out = String::new();
+        for (spot, obstacle) in level {
+            write!(out, "{} {}", spot, obstacle.reverts).unwrap();
+            for (inputs, times) in obstacle.macros.iter() {
+                write!(out, " {}:", times).unwrap();
+                for input in inputs {
+                    write!(out, "{:02x}", input).unwrap();
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn read_level(text: &str) -> Result<BTreeMap<u32, Obstacle>, String> {
+        let mut level = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let invalid = || format!("invalid obstacle {:?}", line);
+            let mut fields = line.split_whitespace();
+            let mut number = || fields.next().and_then(|field| field.parse().ok());
+            let (spot, reverts) = match (number(), number()) {
+                (Some(spot), Some(reverts)) => (spot, reverts),
+                _ => return Err(invalid()),
+            };
+            let mut obstacle = Obstacle {
+                reverts,
+                macros: Vec::new(),
+            };
+            for field in fields {
+                let (times, hex) = field.split_once(':').ok_or_else(invalid)?;
+                let inputs = (0..hex.len())
+                    .step_by(2)
+                    .map(|i| {
+                        hex.get(i..i + 2)
+                            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    })
+                    .collect::<Option<Vec<u8>>>()
+                    .ok_or_else(invalid)?;
+                obstacle
+                    .macros
+                    .push((inputs, times.parse().map_err(|_| invalid())?));
+            }
+            level.insert(spot, obstacle);
+        }
+        Ok(level)
+    }
+
+    /// Reads every level kept in `dir`, which may not exist yet.
+    pub fn load(dir: &Path) -> io::Result<Obstacles> {
+        let mut obstacles = Obstacles::default();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(obstacles),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let warp: Warp = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(stem) => stem.parse().map_err(invalid_data)?,
+                None => continue,
+            };
+            let level = Obstacles::read_level(&fs::read_to_string(&path)?).map_err(invalid_data)?;
+            obstacles.levels.insert(warp.depth(), level);
+        }
+        Ok(obstacles)
+    }
+
+    /// Writes every level to a file of its own in `dir`.
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for (&depth, level) in self.levels.iter() {
+            let warp = Warp {
+                world: (depth / 4) as u8,
+                level: (depth % 4) as u8,
+            };
+            let path = dir.join(format!("{}.{}", warp, EXTENSION));
+            fs::write(path, Obstacles::write_level(level))?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_frequent_deaths_make_obstacles() {
+        let mut obstacles = Obstacles::default();
+        for _ in 1..OBSTACLE_REVERTS {
+            obstacles.revert(0, 3);
+        }
+        obstacles.passed(0, 3, &[0x82]);
+        assert_eq!(obstacles.get(0, 3), None);
+
+        obstacles.revert(0, 3);
+        obstacles.passed(0, 3, &[0x82]);
+        obstacles.passed(0, 3, &[0x83]);
+        obstacles.passed(0, 3, &[0x83]);
+        let obstacle = obstacles.get(0, 3).unwrap();
+        assert_eq!(obstacle.macros, vec![(vec![0x83], 2), (vec![0x82], 1)]);
+        assert_eq!(obstacles.len(), 1);
+    }
+
+    #[test]
+    fn levels_read_back() {
+        let mut level = BTreeMap::new();
+        level.insert(
+            0x0102,
+            Obstacle {
+                reverts: 12,
+                macros: vec![(vec![0x82, 0x83, 0x02], 3), (vec![], 1)],
+            },
+        );
+        level.insert(7, Obstacle::default());
+        let text = Obstacles::write_level(&level);
+        assert_eq!(text, "7 0\n258 12 3:828302 1:\n");
+        assert_eq!(Obstacles::read_level(&text), Ok(level));
+        assert!(Obstacles::read_level("7 zero").is_err());
+        assert!(Obstacles::read_level("7 0 3:8").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Writing to the RAM of a Mario from scripts, for showcase segments like
+//! handing one a star or more time. Only addresses allowed on the command
+//! line can be written, so a script can't break the game by accident.
+
+/// The addresses of RAM that may be written.
+#[derive(Clone, Debug, Default)]
+pub struct Allowlist {
+    /// Address and length of every range allowed.
+    ranges: Vec<(u16, u16)>,
+}
+
+impl Allowlist {
+    /// Allows writing `len` bytes from `address`.
+    pub fn allow(&mut self, address: u16, len: u16) {
+        self.ranges.push((address, len));
+    }
+
+    /// Whether all `len` bytes from `address` may be written.
+    pub fn allows(&self, address: u16, len: usize) -> bool {
+        let allowed = |byte: u32| {
+            self.ranges.iter().any(|&(start, len)| {
+                (u32::from(start)..u32::from(start) + u32::from(len)).contains(&byte)
+            })
+        };
+        (u32::from(address)..u32::from(address) + len as u32).all(allowed)
+    }
+}
+
+/// A write to the RAM of a Mario.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Poke {
+    /// The zero-based Mario written to.
+    pub instance: usize,
+    /// The address or field the script wrote to.
+    pub target: String,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// What the bytes were before.
+    pub old: Vec<u8>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_allowed_bytes_are_written() {
+        let mut allowlist = Allowlist::default();
+        assert!(!allowlist.allows(0x0756, 1));
+        allowlist.allow(0x0756, 1);
+        allowlist.allow(0x07f8, 3);
+        assert!(allowlist.allows(0x0756, 1));
+        assert!(!allowlist.allows(0x0755, 2));
+        assert!(allowlist.allows(0x07f9, 2));
+        assert!(!allowlist.allows(0x07f9, 3));
+    }
+}
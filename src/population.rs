@@ -0,0 +1,544 @@
+use std::{
+    any::Any,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Barrier, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use spin_sleep::LoopHelper;
+use threadpool::ThreadPool;
+use tracing::{debug, debug_span, error, info, trace, warn};
+
+use crate::{
+    affinity::Placement,
+    mario::{self, next_frame, Mario, Personality, Settings, STATE_BYTES},
+    smb::{scroll, Objective},
+    stagnation::Intervention,
+    timings::{Phase, Spans, Timings},
+};
+
+/// Most frames of input a Mario plans at once, however often the lookahead is
+/// deepened.
+const MAX_LOOKAHEAD: u32 = 80;
+/// Highest chance per frame of changing direction or jumping that mutation
+/// goes up to.
+const MAX_MUTATION: f32 = 0.5;
+
+/// Ticks in a row the simulation has to miss its rate before it is reported.
+const BEHIND_WARNING: u32 = 60;
+
+/// Ticks played before calibrating, to get every Mario into the first level
+/// where planning ahead starts.
+const CALIBRATION_WARMUP: u32 = 360;
+/// Ticks timed for every population size tried.
+const CALIBRATION_TICKS: u32 = 60;
+/// Share of a frame a calibrated population may take, leaving the rest for
+/// drawing.
+const CALIBRATION_HEADROOM: f64 = 0.8;
+
+/// How well the simulation keeps up with the frame rate of its region.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+    pub ticks: u64,
+    /// Ticks that took longer than a frame.
+    pub missed: u64,
+    /// Ticks in a row up to now that took longer than a frame.
+    pub behind: u32,
+    /// Ticks per second actually achieved, smoothed over about a second.
+    pub rate: f64,
+    /// The zero-based instance that took longest in the last tick, and how
+    /// long it took.
+    pub slowest: (usize, Duration),
+    /// Frames that panicked, over the whole run.
+    pub crashes: u64,
+    /// Ticks that panicked outside of a Mario and were started over.
+    pub restarts: u64,
+    /// Times the thread running the simulation died and it was started over
+    /// on a new one.
+    pub respawns: u64,
+    /// Rollouts over the whole run whose result was cached, and those that
+    /// had to be played.
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Time every phase of a tick takes, added up over all Marios.
+    pub timings: Timings,
+    /// When the last tick finished.
+    pub last_tick: Option<Instant>,
+}
+
+impl Stats {
+    fn update(&mut self, delta: Duration, work: Duration, frame: Duration, tick: Tick) {
+        self.ticks += 1;
+        self.crashes += tick.crashes;
+        self.cache_hits += tick.cache_hits;
+        self.cache_misses += tick.cache_misses;
+        self.timings.update(&tick.spans);
+        if work > frame {
+            self.missed += 1;
+            self.behind += 1;
+        } else {
+            self.behind = 0;
+        }
+        if !delta.is_zero() {
+            let rate = 1.0 / delta.as_secs_f64();
+            self.rate = if self.ticks == 1 {
+                rate
+            } else {
+                self.rate + (rate - self.rate) / 60.0
+            };
+        }
+        self.slowest = tick.slowest;
+        self.last_tick = Some(Instant::now());
+    }
+
+    /// Time since the last tick finished, which stays near a frame while the
+    /// simulation runs.
+    pub fn stalled(&self) -> Duration {
+        self.last_tick.map_or(Duration::ZERO, |at| at.elapsed())
+    }
+
+    /// Share of rollouts whose result was cached, over the whole run.
+    pub fn cache_hit_rate(&self) -> f64 {
+        cache_hit_rate(self.cache_hits, self.cache_misses)
+    }
+}
+
+/// Share of `hits` among all rollouts, or 0 without any.
+pub fn cache_hit_rate(hits: u64, misses: u64) -> f64 {
+    hits as f64 / (hits + misses).max(1) as f64
+}
+
+/// What happened during a single tick.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Tick {
+    /// The zero-based instance that took longest, and how long it took.
+    slowest: (usize, Duration),
+    crashes: u64,
+    cache_hits: u64,
+    cache_misses: u64,
+    spans: Spans,
+}
+
+pub type Population = Vec<Arc<Mutex<Mario>>>;
+
+/// Creates `size` Marios with random personalities going for `objective`, each
+/// booting its own copy of `rom`.
+pub fn spawn(rom: &[u8], size: usize, objective: Objective) -> Population {
+    let mut rng = rand::thread_rng();
+    (0..size)
+        .map(|_| {
+            let mut mario = Mario::new(Personality::random(&mut rng), rom.to_vec());
+            mario.objective = objective;
+            for _ in 0..rng.gen_range(0..20) {
+                mario.inputs_future.push_back(0)
+            }
+            Arc::new(Mutex::new(mario))
+        })
+        .collect()
+}
+
+/// Shakes up a population that stopped making progress.
+pub fn intervene(marios: &[Arc<Mutex<Mario>>], intervention: Intervention) {
+    info!(intervention = intervention.name(), "population stagnated");
+    match intervention {
+        Intervention::Mutate => {
+            for mario in marios {
+                let personality = &mut mario::lock(mario).personality;
+                personality.twitchy = (personality.twitchy * 2.0).min(MAX_MUTATION);
+                personality.jumpy = (personality.jumpy * 2.0).min(MAX_MUTATION);
+            }
+        }
+        Intervention::Inject => {
+            // the quarter that got the least far starts over with a new personality
+            let mut ranked: Vec<_> = marios
+                .iter()
+                .map(|mario| (scroll(mario::lock(mario).nes_mut()), mario))
+                .collect();
+            ranked.sort_by_key(|&(position, _)| position);
+
+            let mut rng = rand::thread_rng();
+            for (_, mario) in ranked.iter().take((marios.len() + 3) / 4) {
+                mario::lock(mario).personality = Personality::random(&mut rng);
+            }
+        }
+        Intervention::Lookahead => {
+            for mario in marios {
+                let personality = &mut mario::lock(mario).personality;
+                personality.playful = (personality.playful * 2).min(MAX_LOOKAHEAD);
+            }
+        }
+    }
+}
+
+/// Halves how far ahead every Mario plans, for when the simulation can't keep
+/// up.
+pub fn throttle(marios: &[Arc<Mutex<Mario>>]) {
+    info!("simulation behind, planning less far ahead");
+    for mario in marios {
+        let personality = &mut mario::lock(mario).personality;
+        personality.playful = (personality.playful / 2).max(1);
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_string(),
+        },
+    }
+}
+
+/// Advances every Mario by the frames due in tick number `ticks`.
+///
+/// A Mario whose frame panics is marked as errored and either reset or left
+/// alone from then on, so one broken instance doesn't take the rest with it.
+pub(crate) fn tick(
+    pool: &ThreadPool,
+    marios: &[Arc<Mutex<Mario>>],
+    settings: &Settings,
+    ticks: u32,
+) -> Tick {
+    let span = debug_span!("tick");
+    let _span = span.enter();
+
+    let result = Arc::new(Mutex::new(Tick::default()));
+    for (i, mario) in marios.iter().enumerate() {
+        let mario = mario.clone();
+        let span = span.clone();
+        let result = result.clone();
+        let settings = *settings;
+        pool.execute(move || {
+            let _span = debug_span!(parent: &span, "mario", instance = i + 1).entered();
+            let start = Instant::now();
+            // a panic elsewhere while holding the lock doesn't stop the Mario
+            let mut mario = mario::lock(&mario);
+            let locked = start.elapsed();
+            if mario.errored.is_some() {
+                if !settings.reset_errored {
+                    return;
+                }
+                info!(instance = i + 1, "resetting errored mario");
+                mario.reset();
+            }
+
+            let slot = ticks.wrapping_add(i as u32);
+            let before = mario.cost;
+            let ran = catch_unwind(AssertUnwindSafe(|| {
+                for _ in 0..mario.frames_due(slot, &settings) {
+                    next_frame(&mut mario, &settings);
+                }
+            }));
+
+            let time = start.elapsed();
+            let mut result = result.lock().unwrap();
+            result.cache_hits += mario.cost.cache_hits.saturating_sub(before.cache_hits);
+            result.cache_misses += mario.cost.cache_misses.saturating_sub(before.cache_misses);
+            let spans = &mut result.spans;
+            spans.add(Phase::Lock, locked);
+            spans.add(
+                Phase::Rollouts,
+                mario.cost.rollouts.saturating_sub(before.rollouts),
+            );
+            spans.add(Phase::Step, mario.cost.step.saturating_sub(before.step));
+            spans.add(Phase::Clone, mario.cost.clone.saturating_sub(before.clone));
+            if let Err(payload) = ran {
+                let message = panic_message(&*payload);
+                error!(instance = i + 1, %message, "mario crashed");
+                mario.errored = Some(message);
+                result.crashes += 1;
+            }
+            if time > result.slowest.1 {
+                result.slowest = (i, time);
+            }
+        });
+    }
+
+    pool.join();
+    if let Some(max_memory) = settings.max_memory {
+        evict_states(marios, max_memory);
+    }
+    let result = result.lock().unwrap();
+    *result
+}
+
+/// The newest of the states to drop so only `keep` of `saved` are left, going
+/// by when they were saved.
+fn eviction_cutoff(mut saved: Vec<u64>, keep: usize) -> Option<u64> {
+    let drop = saved.len().checked_sub(keep)?.checked_sub(1)?;
+    Some(*saved.select_nth_unstable(drop).1)
+}
+
+/// Drops the oldest saved states of the whole population until they fit in
+/// `max_memory` bytes. The state a Mario is playing is never dropped.
+fn evict_states(marios: &[Arc<Mutex<Mario>>], max_memory: u64) {
+    let keep = (max_memory / STATE_BYTES) as usize;
+    let saved = marios
+        .iter()
+        .flat_map(|mario| {
+            let mario = mario::lock(mario);
+            let played = mario.states.len().saturating_sub(1);
+            mario
+                .states
+                .iter()
+                .take(played)
+                .map(|state| state.saved)
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let cutoff = match eviction_cutoff(saved, keep) {
+        Some(cutoff) => cutoff,
+        None => return,
+    };
+
+    let mut evicted = 0;
+    for mario in marios {
+        let mut mario = mario::lock(mario);
+        while mario.states.len() > 1 && mario.states[0].saved <= cutoff {
+            mario.states.pop_front();
+            evicted += 1;
+        }
+    }
+    debug!(
+        evicted,
+        keep, "over the memory budget, dropped the oldest states"
+    );
+}
+
+/// A pool of `threads` workers that have each moved to `placement`.
+fn worker_pool(threads: usize, placement: &Placement) -> ThreadPool {
+    let pool = ThreadPool::new(threads);
+    if !placement.is_default() {
+        // no job finishes before every worker has one, so each gets exactly one
+        let barrier = Arc::new(Barrier::new(threads));
+        for _ in 0..threads {
+            let (barrier, placement) = (barrier.clone(), placement.clone());
+            pool.execute(move || {
+                placement.apply("worker");
+                barrier.wait();
+            });
+        }
+        pool.join();
+    }
+    pool
+}
+
+/// Runs every Mario at the frame rate of its region on a pool of `threads` workers
+/// placed at `workers`, calling `after_tick` with how well that is going once
+/// all of them have advanced a frame, counting on from `stats`. Nothing is run
+/// while `paused` is set. Never returns.
+///
+/// A tick that panics is logged and the loop carries on with the next one
+/// from wherever the Marios got to, so the simulation never silently stops.
+pub fn simulate(
+    marios: &[Arc<Mutex<Mario>>],
+    threads: usize,
+    workers: &Placement,
+    settings: Settings,
+    paused: &AtomicBool,
+    mut stats: Stats,
+    mut after_tick: impl FnMut(&Stats),
+) -> ! {
+    let pool = worker_pool(threads, workers);
+    let fps = settings.region.fps();
+    let frame = Duration::from_secs(1) / fps;
+    let mut loop_helper = LoopHelper::builder().build_with_target_rate(f64::from(fps));
+    let mut ticks: u32 = 0;
+
+    loop {
+        let delta = loop_helper.loop_start();
+        if paused.load(Ordering::Relaxed) {
+            loop_helper.loop_sleep();
+            continue;
+        }
+        let start = Instant::now();
+        trace!(?delta, "simulation tick");
+
+        let ran = catch_unwind(AssertUnwindSafe(|| {
+            let result = tick(&pool, marios, &settings, ticks);
+            stats.update(delta, start.elapsed(), frame, result);
+            if stats.behind == BEHIND_WARNING {
+                warn!(
+                    rate = stats.rate,
+                    slowest = stats.slowest.0 + 1,
+                    time = ?stats.slowest.1,
+                    "simulation can't keep up"
+                );
+            }
+            after_tick(&stats);
+        }));
+        ticks = ticks.wrapping_add(1);
+
+        if let Err(payload) = ran {
+            let message = panic_message(&*payload);
+            error!(%message, "simulation tick crashed, carrying on");
+            stats.restarts += 1;
+        }
+
+        loop_helper.loop_sleep();
+    }
+}
+
+/// Called after every tick, by whichever simulation is running.
+type AfterTick = dyn FnMut(&Stats) + Send;
+
+/// Runs [`simulate`] on a thread of its own, and can start it over on a new
+/// thread if that one dies.
+///
+/// A simulation that hangs instead, inside `after_tick` or on a Mario whose
+/// frame never returns, is left alone: its thread can't be stopped, and a new
+/// one would wait on the locks it holds all the same.
+pub struct Supervisor {
+    marios: Population,
+    threads: usize,
+    workers: Placement,
+    settings: Settings,
+    paused: Arc<AtomicBool>,
+    after_tick: Arc<Mutex<AfterTick>>,
+    running: Option<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    /// A supervisor for the simulation of `marios`, see [`simulate`]. Nothing
+    /// runs until it is [`start`](Supervisor::start)ed.
+    pub fn new(
+        marios: Population,
+        threads: usize,
+        workers: Placement,
+        settings: Settings,
+        paused: Arc<AtomicBool>,
+        after_tick: impl FnMut(&Stats) + Send + 'static,
+    ) -> Supervisor {
+        Supervisor {
+            marios,
+            threads,
+            workers,
+            settings,
+            paused,
+            after_tick: Arc::new(Mutex::new(after_tick)),
+            running: None,
+        }
+    }
+
+    /// Starts the simulation counting on from `stats`, from wherever the
+    /// Marios are now. It should not be running already, see
+    /// [`died`](Supervisor::died).
+    pub fn start(&mut self, stats: Stats) {
+        let marios = self.marios.clone();
+        let (threads, workers, settings) = (self.threads, self.workers.clone(), self.settings);
+        let paused = self.paused.clone();
+        let after_tick = self.after_tick.clone();
+        let spawned = thread::Builder::new()
+            .name("simulation".to_owned())
+            .spawn(move || {
+                simulate(
+                    &marios,
+                    threads,
+                    &workers,
+                    settings,
+                    &paused,
+                    stats,
+                    |stats| {
+                        let mut after_tick =
+                            after_tick.lock().unwrap_or_else(PoisonError::into_inner);
+                        (*after_tick)(stats)
+                    },
+                )
+            });
+        match spawned {
+            Ok(running) => self.running = Some(running),
+            Err(e) => error!("could not start the simulation: {}", e),
+        }
+    }
+
+    /// Whether the thread the simulation was started on is gone, or it
+    /// couldn't be started at all.
+    pub fn died(&self) -> bool {
+        match &self.running {
+            Some(running) => running.is_finished(),
+            None => true,
+        }
+    }
+}
+
+/// Workers to run the simulation on: one for every core but one, which is
+/// left for drawing.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(1).max(1))
+}
+
+/// The most Marios, up to `max`, that `threads` workers placed at `workers`
+/// keep at the frame rate of the region with some of the frame to spare.
+///
+/// Tries population sizes by halves, timing a second of ticks of the first
+/// Marios of a population that has got into the first level.
+pub fn calibrate(
+    rom: &[u8],
+    max: usize,
+    threads: usize,
+    workers: &Placement,
+    settings: Settings,
+    objective: Objective,
+) -> usize {
+    let marios = spawn(rom, max, objective);
+    let pool = worker_pool(threads, workers);
+    let budget = (Duration::from_secs(1) / settings.region.fps()).mul_f64(CALIBRATION_HEADROOM);
+    let mut ticks = 0;
+    for _ in 0..CALIBRATION_WARMUP {
+        tick(&pool, &marios, &settings, ticks);
+        ticks += 1;
+    }
+
+    let (mut fits, mut fails) = (1, max + 1);
+    while fails - fits > 1 {
+        let size = (fits + fails) / 2;
+        let start = Instant::now();
+        for _ in 0..CALIBRATION_TICKS {
+            tick(&pool, &marios[..size], &settings, ticks);
+            ticks += 1;
+        }
+        let per_tick = start.elapsed() / CALIBRATION_TICKS;
+        debug!(size, ?per_tick, ?budget, "timed a population");
+        if per_tick <= budget {
+            fits = size;
+        } else {
+            fails = size;
+        }
+    }
+    fits
+}
+
+/// Runs every Mario for `frames` ticks as fast as it goes on a pool of
+/// `threads` workers, returning how long that took.
+pub fn bench(
+    marios: &[Arc<Mutex<Mario>>],
+    threads: usize,
+    settings: Settings,
+    frames: u32,
+) -> Duration {
+    let pool = ThreadPool::new(threads);
+    let start = Instant::now();
+    for ticks in 0..frames {
+        tick(&pool, marios, &settings, ticks);
+    }
+    start.elapsed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cutoff_keeps_the_newest() {
+        assert_eq!(eviction_cutoff(vec![4, 9, 1, 7, 3], 2), Some(4));
+        assert_eq!(eviction_cutoff(vec![4, 9, 1], 0), Some(9));
+        assert_eq!(eviction_cutoff(vec![4, 9, 1], 3), None);
+        assert_eq!(eviction_cutoff(Vec::new(), 0), None);
+    }
+}
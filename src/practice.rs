@@ -0,0 +1,177 @@
+//! Playing one stretch of a level over and over with a whole population, to
+//! see which personalities get past it and how often.
+
+use std::{
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+use threadpool::ThreadPool;
+use tracing::debug;
+
+use crate::{
+    mario::{self, Mario, Settings},
+    population, savestate,
+    smb::{depth, fitness, in_level, Fitness, Memory},
+};
+
+/// Where every attempt starts and what counts as getting through.
+pub struct Segment {
+    start: NES<NROM, FastPPU>,
+    /// The inputs that got to `start`, for Marios to continue from.
+    inputs: Vec<u8>,
+    depth: u32,
+    /// Horizontal position in the level to reach.
+    pub target: u16,
+    /// Frames an attempt gets before it counts as failed.
+    pub timeout: u32,
+}
+
+impl Segment {
+    /// The segment from the save state at `path` to `target`, loaded into
+    /// `mario`.
+    pub fn load(mario: &mut Mario, path: &Path, target: u16, timeout: u32) -> io::Result<Segment> {
+        savestate::load(mario, path)?;
+        let start = mario.nes().clone();
+        Ok(Segment {
+            depth: depth(mario.nes_mut()),
+            start,
+            inputs: mario.played.clone(),
+            target,
+            timeout,
+        })
+    }
+
+    /// Puts `mario` back at the start of the segment.
+    pub fn restart(&self, mario: &mut Mario) {
+        mario.restore(self.start.clone(), self.inputs.clone());
+    }
+
+    /// How an attempt that has run for `frames` frames went, or `None` if it
+    /// isn't over yet.
+    pub fn judge(&self, nes: &mut impl Memory, frames: u32) -> Option<Outcome> {
+        let depth = depth(nes);
+        if depth > self.depth
+            || depth == self.depth && in_level(nes) && nes.player_x() >= self.target
+        {
+            return Some(Outcome::Cleared(frames));
+        }
+        match fitness(nes) {
+            Fitness::Dying(false) => Some(Outcome::Died),
+            Fitness::Dying(true) => Some(Outcome::TimedOut),
+            _ if frames >= self.timeout => Some(Outcome::TimedOut),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Got to the target, taking this many frames.
+    Cleared(u32),
+    Died,
+    TimedOut,
+}
+
+/// Outcomes of the attempts of a single Mario.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tally {
+    pub attempts: u32,
+    pub cleared: u32,
+    pub died: u32,
+    pub timed_out: u32,
+    /// Frames the cleared attempts took, summed.
+    pub frames: u64,
+}
+
+impl Tally {
+    pub fn record(&mut self, outcome: Outcome) {
+        self.attempts += 1;
+        match outcome {
+            Outcome::Cleared(frames) => {
+                self.cleared += 1;
+                self.frames += u64::from(frames);
+            }
+            Outcome::Died => self.died += 1,
+            Outcome::TimedOut => self.timed_out += 1,
+        }
+    }
+
+    /// Share of attempts that got to the target, from 0 to 1.
+    pub fn rate(&self) -> f64 {
+        f64::from(self.cleared) / f64::from(self.attempts.max(1))
+    }
+
+    /// Frames a cleared attempt took on average.
+    pub fn mean_frames(&self) -> Option<f64> {
+        match self.cleared {
+            0 => None,
+            cleared => Some(self.frames as f64 / f64::from(cleared)),
+        }
+    }
+}
+
+/// Has every Mario attempt `segment` until it made `attempts` attempts, on a
+/// pool of `threads` workers, returning how each did.
+pub fn run(
+    marios: &[Arc<Mutex<Mario>>],
+    threads: usize,
+    settings: Settings,
+    segment: &Segment,
+    attempts: u32,
+) -> Vec<Tally> {
+    let pool = ThreadPool::new(threads);
+    let mut tallies = vec![Tally::default(); marios.len()];
+    let mut frames = vec![0; marios.len()];
+    for mario in marios {
+        segment.restart(&mut mario::lock(mario));
+    }
+
+    let mut ticks: u32 = 0;
+    while tallies.iter().any(|tally| tally.attempts < attempts) {
+        population::tick(&pool, marios, &settings, ticks);
+        ticks = ticks.wrapping_add(1);
+
+        for (i, mario) in marios.iter().enumerate() {
+            let mut mario = mario::lock(mario);
+            frames[i] += 1;
+            let outcome = match segment.judge(mario.nes_mut(), frames[i]) {
+                Some(outcome) => outcome,
+                None => continue,
+            };
+            if tallies[i].attempts < attempts {
+                debug!(instance = i + 1, ?outcome, "attempt over");
+                tallies[i].record(outcome);
+            }
+            segment.restart(&mut mario);
+            frames[i] = 0;
+        }
+    }
+    tallies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tallies_outcomes() {
+        let mut tally = Tally::default();
+        assert_eq!(tally.rate(), 0.0);
+        assert_eq!(tally.mean_frames(), None);
+
+        for outcome in [
+            Outcome::Cleared(100),
+            Outcome::Died,
+            Outcome::Cleared(300),
+            Outcome::TimedOut,
+        ] {
+            tally.record(outcome);
+        }
+        assert_eq!(tally.attempts, 4);
+        assert_eq!(tally.rate(), 0.5);
+        assert_eq!(tally.mean_frames(), Some(200.0));
+    }
+}
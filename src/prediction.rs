@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+/// Points a viewer gets for picking the Mario that won a round.
+pub const POINTS: u32 = 100;
+
+/// Viewers predicting which Mario gets to the next level first, one round
+/// after another.
+#[derive(Default)]
+pub struct Predictions {
+    /// Depth a Mario has to reach to win the current round, set by the first
+    /// update.
+    target: Option<u32>,
+    round: u32,
+    picks: HashMap<String, usize>,
+    points: HashMap<String, u32>,
+    last_winner: Option<usize>,
+}
+
+impl Predictions {
+    pub fn new() -> Predictions {
+        Predictions::default()
+    }
+
+    /// Records that `viewer` thinks the zero-based `instance` wins this round,
+    /// replacing what they picked before.
+    pub fn predict(&mut self, viewer: &str, instance: usize) {
+        self.picks.insert(viewer.to_owned(), instance);
+    }
+
+    /// Checks the depth of every Mario, see [`crate::smb::depth`]. Once one
+    /// reaches the level after the deepest one when the round started, the
+    /// viewers who picked it score and a new round starts. Returns the
+    /// zero-based winner of the round that was just decided.
+    pub fn update(&mut self, depths: &[u32]) -> Option<usize> {
+        let deepest = depths.iter().copied().max()?;
+        let target = *self.target.get_or_insert(deepest + 1);
+        let winner = depths.iter().position(|&depth| depth >= target)?;
+
+        for (viewer, &pick) in self.picks.iter() {
+            if pick == winner {
+                *self.points.entry(viewer.clone()).or_default() += POINTS;
+            }
+        }
+        self.picks.clear();
+        self.round += 1;
+        self.target = Some(deepest + 1);
+        self.last_winner = Some(winner);
+        Some(winner)
+    }
+
+    /// Rounds decided so far.
+    pub fn round(&self) -> u32 {
+        self.round
+    }
+
+    pub fn target(&self) -> Option<u32> {
+        self.target
+    }
+
+    pub fn last_winner(&self) -> Option<usize> {
+        self.last_winner
+    }
+
+    /// Who picked which zero-based instance this round.
+    pub fn picks(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.picks
+            .iter()
+            .map(|(viewer, &instance)| (viewer.as_str(), instance))
+    }
+
+    /// Every viewer that ever scored and their points, most points first.
+    pub fn standings(&self) -> Vec<(&str, u32)> {
+        let mut standings: Vec<_> = self
+            .points
+            .iter()
+            .map(|(viewer, &points)| (viewer.as_str(), points))
+            .collect();
+        standings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        standings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_to_the_next_level_wins() {
+        let mut predictions = Predictions::new();
+        assert_eq!(predictions.update(&[0, 0, 0]), None);
+        assert_eq!(predictions.target(), Some(1));
+
+        predictions.predict("alice", 2);
+        predictions.predict("bob", 0);
+        assert_eq!(predictions.update(&[0, 0, 1]), Some(2));
+
+        assert_eq!(predictions.round(), 1);
+        assert_eq!(predictions.standings(), vec![("alice", POINTS)]);
+        assert_eq!(predictions.picks().count(), 0);
+    }
+
+    #[test]
+    fn next_round_goes_one_level_further() {
+        let mut predictions = Predictions::new();
+        predictions.update(&[0, 0]);
+        predictions.update(&[1, 0]);
+        assert_eq!(predictions.target(), Some(2));
+
+        predictions.predict("alice", 1);
+        predictions.predict("alice", 0);
+        assert_eq!(predictions.update(&[1, 1]), None);
+        assert_eq!(predictions.update(&[2, 1]), Some(0));
+        assert_eq!(predictions.standings(), vec![("alice", POINTS)]);
+    }
+}
@@ -0,0 +1,117 @@
+//! The bytes of RAM that differ between two states of the game, named after
+//! the fields of the [memory map](crate::smb::map) they are in, for finding
+//! out what the game keeps where.
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use crate::smb::{map::FIELDS, Ram};
+
+/// A byte of RAM that differs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change {
+    pub address: u16,
+    pub before: u8,
+    pub after: u8,
+}
+
+impl Change {
+    /// The field of the memory map the byte is in, and how far into it.
+    pub fn field(&self) -> Option<(&'static str, u16)> {
+        FIELDS
+            .iter()
+            .find(|&&(_, start, len)| (start..start + len).contains(&self.address))
+            .map(|&(name, start, _)| (name, self.address - start))
+    }
+
+    /// The field the byte is in, like `lives` or `enemy_x[2]`, or nothing if
+    /// it isn't in one.
+    pub fn field_name(&self) -> String {
+        match self.field() {
+            Some((name, 0)) if is_single(name) => name.to_owned(),
+            Some((name, offset)) => format!("{}[{}]", name, offset),
+            None => String::new(),
+        }
+    }
+}
+
+/// Whether field `name` is a single byte.
+fn is_single(name: &str) -> bool {
+    FIELDS
+        .iter()
+        .any(|&(field, _, len)| field == name && len == 1)
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#06x} {:<20} {:#04x} -> {:#04x}",
+            self.address,
+            self.field_name(),
+            self.before,
+            self.after
+        )
+    }
+}
+
+/// Every byte that differs from `before` to `after`, by address.
+pub fn diff(before: &Ram, after: &Ram) -> Vec<Change> {
+    (0..)
+        .zip(before.0.iter().zip(after.0.iter()))
+        .filter(|(_, (before, after))| before != after)
+        .map(|(address, (&before, &after))| Change {
+            address,
+            before,
+            after,
+        })
+        .collect()
+}
+
+/// Writes `changes` to `out` as CSV, with a header.
+pub fn write_csv(changes: &[Change], out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "address,field,before,after")?;
+    for change in changes {
+        writeln!(
+            out,
+            "{:#06x},{},{},{}",
+            change.address,
+            change.field_name(),
+            change.before,
+            change.after
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smb::ram;
+
+    #[test]
+    fn changes_are_named_after_fields() {
+        let before = Ram::default().with(ram::LIVES, 2).with(0x0001, 7);
+        let after = Ram::default()
+            .with(ram::LIVES, 1)
+            .with(0x0001, 7)
+            .with(ram::ENEMY_X + 2, 0x40)
+            .with(0x07ff, 1);
+        let changes = diff(&before, &after);
+        let fields: Vec<String> = changes.iter().map(Change::field_name).collect();
+        assert_eq!(fields, ["enemy_x[2]", "lives", ""]);
+        assert_eq!(
+            changes[1].to_string(),
+            "0x075a lives                0x02 -> 0x01"
+        );
+
+        let mut csv = Vec::new();
+        write_csv(&changes[..1], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "address,field,before,after\n0x0089,enemy_x[2],0,64\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Addresses of RAM watched by name, for experimenting with what the game
+//! keeps where without hardcoding another read like those in
+//! [`fitness`](crate::smb::fitness). Every Mario's watches are read once a
+//! frame and handed to scripts with the rest of his values.
+
+use crate::smb::{map::FIELDS, Memory};
+
+/// Size of the internal RAM of the NES, which addresses are watched in.
+const RAM_SIZE: u16 = 0x800;
+
+/// Bytes of RAM watched as `name`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watch {
+    pub name: String,
+    pub address: u16,
+    pub len: u16,
+    /// The zero-based Mario it is watched for, or every Mario.
+    pub instance: Option<usize>,
+}
+
+impl Watch {
+    /// Watches `target` as `name`, see [`target`].
+    pub fn new(name: String, target: &str, instance: Option<usize>) -> Result<Watch, String> {
+        let (address, len) = self::target(target)?;
+        Watch::at(name, address, len, instance)
+    }
+
+    /// Watches `len` bytes from `address` as `name`.
+    pub fn at(
+        name: String,
+        address: u16,
+        len: u16,
+        instance: Option<usize>,
+    ) -> Result<Watch, String> {
+        check(address, len)?;
+        Ok(Watch {
+            name,
+            address,
+            len,
+            instance,
+        })
+    }
+}
+
+/// The address and length of `target`, which is either an address in internal
+/// RAM like `0x075a` or `1882`, or the name of an address of the
+/// [memory map](crate::smb::map) like `lives`, over all its bytes.
+pub fn target(target: &str) -> Result<(u16, u16), String> {
+    let (address, len) = match FIELDS.iter().find(|(field, _, _)| *field == target) {
+        Some(&(_, address, len)) => (address, len),
+        None => {
+            let address = match target.strip_prefix("0x") {
+                Some(hex) => u16::from_str_radix(hex, 16),
+                None => target.parse(),
+            }
+            .map_err(|_| format!("expected an address or a field of RAM, got {:?}", target))?;
+            (address, 1)
+        }
+    };
+    check(address, len)?;
+    Ok((address, len))
+}
+
+/// Fails unless there are `len` bytes of internal RAM from `address`.
+pub fn check(address: u16, len: u16) -> Result<(), String> {
+    if len == 0 || u32::from(address) + u32::from(len) > u32::from(RAM_SIZE) {
+        return Err(format!(
+            "{} bytes from {:#06x} are not all in internal RAM",
+            len, address
+        ));
+    }
+    Ok(())
+}
+
+/// Every watch, in the order they were added.
+#[derive(Default)]
+pub struct Watches {
+    watches: Vec<Watch>,
+}
+
+impl Watches {
+    /// Adds `watch`, in place of the one of the same name for the same
+    /// Marios.
+    pub fn add(&mut self, watch: Watch) {
+        match self
+            .watches
+            .iter_mut()
+            .find(|old| old.name == watch.name && old.instance == watch.instance)
+        {
+            Some(old) => *old = watch,
+            None => self.watches.push(watch),
+        }
+    }
+
+    /// Stops watching `name` for any Mario. Returns whether it was watched.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.watches.len();
+        self.watches.retain(|watch| watch.name != name);
+        self.watches.len() != len
+    }
+
+    /// The bytes of every watch of zero-based `instance`, read from `memory`.
+    /// A watch for that Mario in particular hides the one of the same name
+    /// for all of them.
+    pub fn read(&self, instance: usize, memory: &mut impl Memory) -> Vec<(String, Vec<u8>)> {
+        let mut values: Vec<(String, Vec<u8>)> = Vec::new();
+        for watch in self.watches.iter() {
+            if watch.instance.is_some_and(|watched| watched != instance) {
+                continue;
+            }
+            let bytes = (watch.address..watch.address + watch.len)
+                .map(|address| memory.read(address))
+                .collect();
+            match values.iter_mut().find(|(name, _)| *name == watch.name) {
+                Some(value) if watch.instance.is_some() => value.1 = bytes,
+                Some(_) => {}
+                None => values.push((watch.name.clone(), bytes)),
+            }
+        }
+        values
+    }
+}
+
+/// `bytes` as shown by the `ram_watch` instruction: a single byte in decimal
+/// and hexadecimal, more in hexadecimal only.
+pub fn format(bytes: &[u8]) -> String {
+    match bytes {
+        [byte] => format!("{} ({:#04x})", byte, byte),
+        _ => bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smb::{ram, Ram};
+
+    #[test]
+    fn watches_read_by_instance() {
+        let mut watches = Watches::default();
+        let all = |name: &str, target| Watch::new(name.to_owned(), target, None).unwrap();
+        watches.add(all("lives", "lives"));
+        watches.add(all("speed", "0x57"));
+        watches.add(Watch::new("speed".to_owned(), "134", Some(1)).unwrap());
+        watches.add(all("timer", "timer"));
+        assert!(Watch::new("x".to_owned(), "0x800", None).is_err());
+        assert!(Watch::new("x".to_owned(), "player", None).is_err());
+
+        let mut memory = Ram::default()
+            .with(ram::LIVES, 2)
+            .with(ram::PLAYER_X_SPEED, 24)
+            .with(ram::PLAYER_X, 0x40)
+            .with(ram::TIMER, 4);
+        let speed = |instance| {
+            let mut values = watches.read(instance, &mut memory.clone());
+            values.retain(|(name, _)| name == "speed");
+            values
+        };
+        assert_eq!(speed(0), [("speed".to_owned(), vec![24])]);
+        assert_eq!(speed(1), [("speed".to_owned(), vec![0x40])]);
+
+        assert!(watches.remove("speed"));
+        assert!(!watches.remove("speed"));
+        assert_eq!(
+            watches.read(1, &mut memory),
+            [
+                ("lives".to_owned(), vec![2]),
+                ("timer".to_owned(), vec![4, 0, 0])
+            ]
+        );
+        assert_eq!(format(&[24]), "24 (0x18)");
+        assert_eq!(format(&[4, 0, 0x1f]), "04 00 1f");
+    }
+}
@@ -0,0 +1,120 @@
+//! The leaderboard of the population, telling when a Mario overtakes another.
+
+/// A Mario that moved up or down the leaderboard, all zero-based.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RankChange {
+    pub instance: usize,
+    pub old: usize,
+    pub new: usize,
+}
+
+/// Instances ordered by fitness, the furthest first. A Mario only passes one
+/// ahead of him once he is further by more than a margin, so two Marios
+/// running back and forth next to each other don't swap places every frame.
+#[derive(Default)]
+pub struct Ranking {
+    margin: u32,
+    /// Zero-based instances by rank.
+    order: Vec<usize>,
+}
+
+impl Ranking {
+    pub fn new(margin: u32) -> Ranking {
+        Ranking {
+            margin,
+            order: Vec::new(),
+        }
+    }
+
+    /// Reorders the instances by `fitness`, as [`crate::smb::scroll`] gives
+    /// it, and returns every one whose rank changed. Instances that are new
+    /// since the last update join at the bottom, and the ones below an
+    /// instance that is gone move up, without a change.
+    pub fn update(&mut self, fitness: &[u32]) -> Vec<RankChange> {
+        let count = fitness.len();
+        self.order.retain(|&instance| instance < count);
+        for instance in 0..count {
+            if !self.order.contains(&instance) {
+                self.order.push(instance);
+            }
+        }
+        let old = self.order.clone();
+
+        // being further by more than the margin can't go in circles, so this
+        // ends once no one is that far ahead of the one above him
+        let ahead = |a: usize, b: usize| fitness[a] > fitness[b].saturating_add(self.margin);
+        let mut swapped = true;
+        while swapped {
+            swapped = false;
+            for rank in 1..count {
+                if ahead(self.order[rank], self.order[rank - 1]) {
+                    self.order.swap(rank, rank - 1);
+                    swapped = true;
+                }
+            }
+        }
+
+        let mut changes = Vec::new();
+        for (new, &instance) in self.order.iter().enumerate() {
+            if old[new] != instance {
+                let old = old.iter().position(|&other| other == instance).unwrap();
+                changes.push(RankChange { instance, old, new });
+            }
+        }
+        changes
+    }
+
+    /// Zero-based instances by rank, as of the last update.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overtaking_takes_more_than_the_margin() {
+        let mut ranking = Ranking::new(16);
+        assert!(ranking.update(&[300, 200, 100]).is_empty());
+        assert_eq!(ranking.order(), [0, 1, 2]);
+
+        // a lead of 16 is not enough yet
+        assert!(ranking.update(&[300, 200, 216]).is_empty());
+        let changes = ranking.update(&[300, 200, 217]);
+        assert_eq!(
+            changes,
+            [
+                RankChange {
+                    instance: 2,
+                    old: 2,
+                    new: 1
+                },
+                RankChange {
+                    instance: 1,
+                    old: 1,
+                    new: 2
+                },
+            ]
+        );
+
+        // the last overtakes both at once
+        let changes = ranking.update(&[300, 0x0100_0000, 217]);
+        assert_eq!(ranking.order(), [1, 0, 2]);
+        assert_eq!(
+            changes[0],
+            RankChange {
+                instance: 1,
+                old: 2,
+                new: 0
+            }
+        );
+        assert_eq!(changes.len(), 3);
+
+        // falling back a little doesn't swap them back either
+        assert!(ranking.update(&[300, 0x0100_0000, 290]).is_empty());
+        assert!(ranking.update(&[300]).is_empty());
+        assert_eq!(ranking.order(), [0]);
+    }
+}
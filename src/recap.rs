@@ -0,0 +1,238 @@
+use std::{
+    fs::{create_dir_all, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+/// Marios the recap shows a snapshot of.
+pub const TOP: usize = 3;
+
+/// Where one instance is, for [`Recap::update`].
+pub struct Progress {
+    /// Position in the game, see [`crate::smb::scroll`].
+    pub position: u32,
+    /// Levels into the game, see [`crate::smb::depth`].
+    pub depth: u32,
+    pub dying: bool,
+}
+
+/// The furthest an instance got, as the level and the position in it.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Best {
+    depth: u32,
+    position: u32,
+}
+
+impl Best {
+    fn level(&self) -> String {
+        format!("{}-{}", self.depth / 4 + 1, self.depth % 4 + 1)
+    }
+
+    /// Pixels into the area.
+    fn x(&self) -> u32 {
+        self.position & 0xffff
+    }
+}
+
+/// A summary of how a session went, to post once it is over.
+pub struct Recap {
+    started: Instant,
+    best: Vec<Best>,
+    deaths: Vec<u64>,
+    dying: Vec<bool>,
+    /// The furthest level any Mario got to.
+    deepest: Option<u32>,
+    records: u64,
+}
+
+impl Recap {
+    pub fn new(instances: usize) -> Recap {
+        Recap {
+            started: Instant::now(),
+            best: vec![Best::default(); instances],
+            deaths: vec![0; instances],
+            dying: vec![false; instances],
+            deepest: None,
+            records: 0,
+        }
+    }
+
+    /// Takes where every instance is now. A death is counted when an instance
+    /// starts dying and a record when one gets further into the game than any
+    /// did before.
+    pub fn update(&mut self, progress: impl IntoIterator<Item = Progress>) {
+        for (i, progress) in progress.into_iter().enumerate().take(self.best.len()) {
+            let best = Best {
+                depth: progress.depth,
+                position: progress.position,
+            };
+            self.best[i] = self.best[i].max(best);
+
+            if progress.dying && !self.dying[i] {
+                self.deaths[i] += 1;
+            }
+            self.dying[i] = progress.dying;
+
+            match self.deepest {
+                Some(deepest) if progress.depth > deepest => {
+                    self.deepest = Some(progress.depth);
+                    self.records += 1;
+                }
+                None => self.deepest = Some(progress.depth),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn deaths(&self) -> u64 {
+        self.deaths.iter().sum()
+    }
+
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// The zero-based instances that got the furthest, furthest first.
+    pub fn top(&self, n: usize) -> Vec<usize> {
+        let mut instances: Vec<usize> = (0..self.best.len()).collect();
+        instances.sort_by(|&a, &b| self.best[b].cmp(&self.best[a]));
+        instances.truncate(n);
+        instances
+    }
+
+    /// Name of the snapshot of the zero-based `instance` next to the recap.
+    pub fn snapshot_name(instance: usize) -> String {
+        format!("mario-{}.png", instance + 1)
+    }
+
+    /// Writes `recap.json` and `recap.md` to `dir`, creating it if needed.
+    /// The Markdown shows the snapshots named by [`Recap::snapshot_name`] of
+    /// the [`TOP`] instances, which are left to the caller to save.
+    pub fn write(&self, dir: &Path) -> io::Result<()> {
+        create_dir_all(dir)?;
+        let mut json = BufWriter::new(File::create(dir.join("recap.json"))?);
+        self.write_json(&mut json)?;
+        json.flush()?;
+        let mut markdown = BufWriter::new(File::create(dir.join("recap.md"))?);
+        self.write_markdown(&mut markdown)?;
+        markdown.flush()
+    }
+
+    pub fn write_json(&self, mut out: impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "{{\"seconds\":{:.0},\"deaths\":{},\"records\":{},\"top\":[",
+            self.started.elapsed().as_secs_f64(),
+            self.deaths(),
+            self.records
+        )?;
+        for (i, instance) in self.top(TOP).into_iter().enumerate() {
+            let comma = if i == 0 { "" } else { "," };
+            write!(out, "{}{}", comma, instance + 1)?;
+        }
+        write!(out, "],\"instances\":[")?;
+        for (i, best) in self.best.iter().enumerate() {
+            let comma = if i == 0 { "" } else { "," };
+            write!(
+                out,
+                "{}{{\"instance\":{},\"level\":\"{}\",\"x\":{},\"position\":{},\"deaths\":{}}}",
+                comma,
+                i + 1,
+                best.level(),
+                best.x(),
+                best.position,
+                self.deaths[i]
+            )?;
+        }
+        writeln!(out, "]}}")
+    }
+
+    pub fn write_markdown(&self, mut out: impl Write) -> io::Result<()> {
+        let minutes = self.started.elapsed().as_secs() / 60;
+        writeln!(out, "# Session recap")?;
+        writeln!(out)?;
+        writeln!(out, "- {}h{:02} of Marios", minutes / 60, minutes % 60)?;
+        writeln!(out, "- {} deaths", self.deaths())?;
+        writeln!(out, "- {} records broken", self.records)?;
+        writeln!(out)?;
+        writeln!(out, "## Furthest")?;
+        for (rank, instance) in self.top(TOP).into_iter().enumerate() {
+            let best = &self.best[instance];
+            writeln!(out)?;
+            writeln!(
+                out,
+                "{}. Mario #{}, level {} at {} px",
+                rank + 1,
+                instance + 1,
+                best.level(),
+                best.x()
+            )?;
+            writeln!(out)?;
+            writeln!(
+                out,
+                "   ![Mario #{}]({})",
+                instance + 1,
+                Recap::snapshot_name(instance)
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "## Every Mario")?;
+        writeln!(out)?;
+        writeln!(out, "| Mario | Level | X | Deaths |")?;
+        writeln!(out, "|---|---|---|---|")?;
+        for (i, best) in self.best.iter().enumerate() {
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                i + 1,
+                best.level(),
+                best.x(),
+                self.deaths[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(position: u32, depth: u32, dying: bool) -> Progress {
+        Progress {
+            position,
+            depth,
+            dying,
+        }
+    }
+
+    #[test]
+    fn counts_deaths_once_and_records_past_the_start() {
+        let mut recap = Recap::new(2);
+        recap.update([progress(10, 0, false), progress(20, 0, false)]);
+        recap.update([progress(15, 0, true), progress(30, 1, false)]);
+        recap.update([progress(15, 0, true), progress(40, 1, true)]);
+        recap.update([progress(0, 0, false), progress(0, 1, false)]);
+
+        assert_eq!(recap.deaths(), 2);
+        assert_eq!(recap.records(), 1);
+        assert_eq!(recap.top(2), vec![1, 0]);
+    }
+
+    #[test]
+    fn json_lists_every_instance() {
+        let mut recap = Recap::new(2);
+        recap.update([progress(0x0001_0020, 5, false), progress(7, 0, false)]);
+
+        let mut json = Vec::new();
+        recap.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"top\":[1,2]"), "{}", json);
+        assert!(
+            json.contains("{\"instance\":1,\"level\":\"2-2\",\"x\":32,"),
+            "{}",
+            json
+        );
+    }
+}
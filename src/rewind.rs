@@ -0,0 +1,78 @@
+//! Looking back through the [states](crate::mario::Mario::states) a Mario
+//! keeps, one at a time, to see what happened before a death without saving
+//! them and opening them elsewhere.
+//!
+//! States are told apart by when they were [saved](crate::mario::State::saved),
+//! so the one shown stays the same while the Mario plays on, until it is
+//! dropped and the viewer moves on to the oldest state still kept.
+
+/// Which of the states of a Mario is shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewer {
+    /// Number of the Mario, from 1.
+    pub instance: usize,
+    /// When the state shown was saved, or nothing to follow the newest.
+    shown: Option<u64>,
+}
+
+impl Viewer {
+    /// Shows the newest state of Mario number `instance`.
+    pub fn new(instance: usize) -> Viewer {
+        Viewer {
+            instance,
+            shown: None,
+        }
+    }
+
+    /// Where the state shown is among states saved at `saved`, oldest first.
+    /// There is always at least one state.
+    pub fn index(&self, saved: &[u64]) -> usize {
+        let last = saved.len().saturating_sub(1);
+        match self.shown {
+            Some(shown) => saved.partition_point(|&state| state < shown).min(last),
+            None => last,
+        }
+    }
+
+    /// Shows the state at `index` among states saved at `saved`, or the
+    /// newest if there is none that far.
+    pub fn seek(&mut self, saved: &[u64], index: usize) {
+        self.shown = match saved.get(index) {
+            Some(&state) if index + 1 < saved.len() => Some(state),
+            _ => None,
+        };
+    }
+
+    /// Shows the state `by` states newer than the one shown, or older if
+    /// `by` is negative.
+    pub fn scrub(&mut self, saved: &[u64], by: isize) {
+        let index = self.index(saved).saturating_add_signed(by);
+        self.seek(saved, index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubbing_sticks_to_a_state() {
+        let mut viewer = Viewer::new(3);
+        assert_eq!(viewer.index(&[10, 20, 30]), 2);
+        viewer.scrub(&[10, 20, 30], -1);
+        assert_eq!(viewer.index(&[10, 20, 30]), 1);
+
+        // the state shown stays put as newer ones are saved
+        assert_eq!(viewer.index(&[10, 20, 30, 40]), 1);
+        viewer.scrub(&[10, 20, 30, 40], -5);
+        assert_eq!(viewer.index(&[10, 20, 30, 40]), 0);
+        assert_eq!(viewer.index(&[20, 30, 40]), 0);
+
+        // going all the way forward follows the newest again
+        viewer.scrub(&[20, 30, 40], 2);
+        assert_eq!(viewer.index(&[20, 30, 40, 50]), 3);
+        viewer.seek(&[20, 30, 40, 50], 1);
+        assert_eq!(viewer.index(&[20, 30, 40, 50]), 1);
+        assert_eq!(viewer.index(&[5]), 0);
+    }
+}
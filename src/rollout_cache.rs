@@ -0,0 +1,122 @@
+//! How rollouts turned out, so a Mario stuck at the same state doesn't play
+//! the same inputs on a copy of the game again and again.
+//!
+//! Rollouts are keyed by a hash of the RAM they start from and a hash of their
+//! inputs. The game keeps all of its state in RAM between frames, so the same
+//! pair plays out the same way.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::smb::{ram::fnv1a, Fitness};
+
+/// Rollouts remembered per Mario, forgetting the least recently used first.
+pub const ROLLOUT_CACHE: usize = 1024;
+
+/// The state a rollout starts from and the inputs it plays, both hashed.
+pub type Key = (u64, u64);
+
+/// The key of playing `inputs` from the state with RAM checksum `state`.
+pub fn key(state: u64, inputs: &[u8]) -> Key {
+    (state, fnv1a(inputs))
+}
+
+/// A least recently used cache of rollout results.
+#[derive(Debug)]
+pub struct RolloutCache {
+    capacity: usize,
+    /// The result of every rollout, with when it was last used.
+    results: HashMap<Key, (Fitness, u64)>,
+    /// Every key by when it was last used, oldest first.
+    used: BTreeMap<u64, Key>,
+    clock: u64,
+}
+
+impl Default for RolloutCache {
+    fn default() -> Self {
+        RolloutCache::new(ROLLOUT_CACHE)
+    }
+}
+
+impl RolloutCache {
+    pub fn new(capacity: usize) -> RolloutCache {
+        RolloutCache {
+            capacity,
+            results: HashMap::new(),
+            used: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// The result of the rollout, if it was played before.
+    pub fn get(&mut self, key: Key) -> Option<Fitness> {
+        let (fitness, used) = self.results.get_mut(&key)?;
+        self.used.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.used.insert(self.clock, key);
+        Some(*fitness)
+    }
+
+    /// Remembers the result of a rollout, forgetting the one used longest ago
+    /// when full.
+    pub fn insert(&mut self, key: Key, fitness: Fitness) {
+        self.clock += 1;
+        if let Some((_, used)) = self.results.insert(key, (fitness, self.clock)) {
+            self.used.remove(&used);
+        }
+        self.used.insert(self.clock, key);
+        while self.results.len() > self.capacity {
+            match self.used.pop_first() {
+                Some((_, oldest)) => self.results.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.used.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_least_recently_used() {
+        let mut cache = RolloutCache::new(2);
+        let (a, b, c) = (key(1, &[0x82]), key(1, &[0x83]), key(2, &[0x82]));
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+
+        cache.insert(a, Fitness::Dying(false));
+        cache.insert(b, Fitness::Cutscene);
+        assert_eq!(cache.get(a), Some(Fitness::Dying(false)));
+        cache.insert(c, Fitness::Flagpole(1));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(b), None);
+        assert_eq!(cache.get(a), Some(Fitness::Dying(false)));
+        assert_eq!(cache.get(c), Some(Fitness::Flagpole(1)));
+    }
+
+    #[test]
+    fn inserting_again_replaces() {
+        let mut cache = RolloutCache::new(2);
+        let a = key(1, &[0x82]);
+        cache.insert(a, Fitness::Cutscene);
+        cache.insert(a, Fitness::Dying(true));
+        cache.insert(key(3, &[]), Fitness::Cutscene);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(a), Some(Fitness::Dying(true)));
+    }
+}
@@ -0,0 +1,213 @@
+//! Checking that a ROM is one the SMB memory map was written for, before
+//! booting it and reading nonsense out of its RAM.
+
+use std::str::FromStr;
+
+use thiserror::Error;
+
+/// Bytes of an iNES header.
+const HEADER: usize = 16;
+const TRAINER: usize = 512;
+const PRG_BANK: usize = 0x4000;
+const CHR_BANK: usize = 0x2000;
+
+#[derive(Debug, Error)]
+pub enum RomError {
+    #[error("not an iNES file")]
+    NotINes,
+    #[error("file is {actual} bytes, but its header says {expected}")]
+    Truncated { expected: usize, actual: usize },
+    #[error("uses mapper {0}, but only NROM (mapper 0) is emulated")]
+    Mapper(u8),
+}
+
+/// The TV system a ROM was made for, which sets how many frames the game
+/// expects per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    /// Frames per second, rounded the way the rest of shellkick counts time.
+    pub fn fps(self) -> u32 {
+        match self {
+            Region::Ntsc => 60,
+            Region::Pal => 50,
+        }
+    }
+
+    /// Frames that make up `seconds`.
+    pub fn frames(self, seconds: f32) -> u32 {
+        (seconds * self.fps() as f32) as u32
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            _ => Err(format!("unknown region {:?}, expected ntsc or pal", s)),
+        }
+    }
+}
+
+/// What the iNES header says about a ROM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// Program ROM in 16 KiB banks.
+    pub prg_banks: u8,
+    /// Character ROM in 8 KiB banks.
+    pub chr_banks: u8,
+    pub mapper: u8,
+    pub trainer: bool,
+    /// Keeps save RAM powered by a battery, see [`crate::sram`].
+    pub battery: bool,
+    /// Made for PAL consoles, going by the TV system flag.
+    pub pal: bool,
+}
+
+impl Header {
+    pub fn parse(rom: &[u8]) -> Result<Header, RomError> {
+        if rom.len() < HEADER || &rom[..4] != b"NES\x1a" {
+            return Err(RomError::NotINes);
+        }
+        // old dumping tools left their name ("DiskDude!") over the bytes after
+        // the sixth, which only NES 2.0 headers may use past the ninth
+        let nes2 = rom[7] & 0x0c == 0x08;
+        let dirty = !nes2 && rom[12..HEADER].iter().any(|&byte| byte != 0);
+        let (flags7, flags9) = if dirty { (0, 0) } else { (rom[7], rom[9]) };
+        let header = Header {
+            prg_banks: rom[4],
+            chr_banks: rom[5],
+            mapper: (flags7 & 0xf0) | (rom[6] >> 4),
+            trainer: rom[6] & 0x04 != 0,
+            battery: rom[6] & 0x02 != 0,
+            pal: flags9 & 0x01 != 0,
+        };
+        let expected = HEADER
+            + if header.trainer { TRAINER } else { 0 }
+            + usize::from(header.prg_banks) * PRG_BANK
+            + usize::from(header.chr_banks) * CHR_BANK;
+        if rom.len() < expected {
+            return Err(RomError::Truncated {
+                expected,
+                actual: rom.len(),
+            });
+        }
+        if header.mapper != 0 {
+            return Err(RomError::Mapper(header.mapper));
+        }
+        Ok(header)
+    }
+
+    pub fn region(&self) -> Region {
+        if self.pal {
+            Region::Pal
+        } else {
+            Region::Ntsc
+        }
+    }
+}
+
+/// A dump the memory map is known to work with.
+pub struct Known {
+    pub name: &'static str,
+    /// CRC32 of the whole file, header included.
+    pub crc32: u32,
+    /// SHA-1 of the whole file, header included.
+    pub sha1: &'static str,
+}
+
+pub const KNOWN: &[Known] = &[Known {
+    name: "Super Mario Bros. (World)",
+    crc32: 0x3337_ec46,
+    sha1: "ea343f4e445a9050d4b4fbac2c77d0693b1d0922",
+}];
+
+/// A ROM that passed [`Header::parse`], with its hashes.
+pub struct Identity {
+    pub header: Header,
+    pub crc32: u32,
+    /// Lowercase hex.
+    pub sha1: String,
+    /// Which known dump it is, if any. An unknown ROM may be a hack or a
+    /// revision the memory map doesn't fit.
+    pub known: Option<&'static Known>,
+}
+
+pub fn identify(rom: &[u8]) -> Result<Identity, RomError> {
+    let header = Header::parse(rom)?;
+    let crc32 = crc32fast::hash(rom);
+    let sha1 = sha1_smol::Sha1::from(rom).digest().to_string();
+    let known = KNOWN
+        .iter()
+        .find(|known| known.crc32 == crc32 && known.sha1 == sha1);
+    Ok(Identity {
+        header,
+        crc32,
+        sha1,
+        known,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nrom() -> Vec<u8> {
+        let mut rom = b"NES\x1a\x02\x01\x01\x00".to_vec();
+        rom.resize(HEADER + 2 * PRG_BANK + CHR_BANK, 0);
+        rom
+    }
+
+    #[test]
+    fn reads_nrom_headers() {
+        let header = Header::parse(&nrom()).unwrap();
+        assert_eq!(header.prg_banks, 2);
+        assert_eq!(header.mapper, 0);
+        assert_eq!(header.region(), Region::Ntsc);
+        assert!(!header.battery);
+        assert!(identify(&nrom()).unwrap().known.is_none());
+    }
+
+    #[test]
+    fn rejects_what_cannot_be_booted() {
+        assert!(matches!(Header::parse(b"MZ"), Err(RomError::NotINes)));
+
+        let mut rom = nrom();
+        rom.truncate(rom.len() - 1);
+        assert!(matches!(
+            Header::parse(&rom),
+            Err(RomError::Truncated { .. })
+        ));
+
+        let mut rom = nrom();
+        rom[6] = 0x10;
+        assert!(matches!(Header::parse(&rom), Err(RomError::Mapper(1))));
+    }
+
+    #[test]
+    fn ignores_what_dumping_tools_wrote_over_the_header() {
+        let mut rom = nrom();
+        rom[7..HEADER].copy_from_slice(b"DiskDude!");
+        let header = Header::parse(&rom).unwrap();
+        assert_eq!(header.mapper, 0);
+        assert_eq!(header.region(), Region::Ntsc);
+    }
+
+    #[test]
+    fn pal_roms_run_at_50_hz() {
+        let mut rom = nrom();
+        rom[9] = 0x01;
+        let region = Header::parse(&rom).unwrap().region();
+        assert_eq!(region, Region::Pal);
+        assert_eq!(region.frames(120.0), 6000);
+        assert_eq!(Region::Ntsc.frames(120.0), 360 * 20);
+    }
+}
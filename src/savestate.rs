@@ -0,0 +1,111 @@
+//! Save states that keep working across versions of shellkick.
+//!
+//! A copy of the emulator changes shape whenever the emulator does, so a
+//! state is stored as the inputs that lead to it from a freshly booted NES
+//! instead: [`MAGIC`], a [`VERSION`] byte and a little-endian `u64` checksum
+//! of the ROM, followed by an [input log](crate::input_log) without reverts
+//! that ends in a check of where the game should be.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+
+use crate::{
+    input_log::{timeline, Entry, InputLog, Reader},
+    mario::{replay, Mario},
+    smb::{ram::fnv1a, scroll, Ram},
+};
+
+pub const MAGIC: &[u8; 4] = b"SKSS";
+/// The format written, and the newest one read.
+pub const VERSION: u8 = 1;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Writes where `mario` is now to `path`. Marios that started in a later
+/// level can't be saved, as only their inputs would be, and neither can
+/// Marios that crashed.
+pub fn save(mario: &mut Mario, path: &Path) -> io::Result<()> {
+    if let Some(message) = &mario.errored {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "Mario crashed, so where he is can't be trusted: {}",
+                message
+            ),
+        ));
+    }
+    if let Some(warp) = mario.warp {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Mario was warped to {}, which inputs can't replay", warp),
+        ));
+    }
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION])?;
+    out.write_all(&fnv1a(mario.rom()).to_le_bytes())?;
+
+    let mut log = InputLog::new(out)?;
+    for &input in mario.played.iter() {
+        log.input(input)?;
+    }
+    let nes = mario.nes_mut();
+    let (frame, position, ram) = (
+        nes.frame_number() as u64,
+        scroll(nes),
+        Ram::of(nes).checksum(),
+    );
+    log.check(frame, position, ram)?;
+    log.flush()
+}
+
+/// Puts `mario` where the state at `path` is, failing if the state was saved
+/// with another ROM or doesn't replay to the same place.
+pub fn load(mario: &mut Mario, path: &Path) -> io::Result<()> {
+    let (nes, inputs) = open(mario.rom(), path)?;
+    mario.restore(nes, inputs);
+    Ok(())
+}
+
+/// Replays the state at `path` on a freshly booted NES with `rom`, returning
+/// it with the inputs that got it there. Fails like [`load`].
+pub fn open(rom: &[u8], path: &Path) -> io::Result<(NES<NROM, FastPPU>, Vec<u8>)> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut header = [0; 13];
+    input.read_exact(&mut header)?;
+    let (magic, rest) = header.split_at(4);
+    let (version, checksum) = rest.split_at(1);
+    if magic != MAGIC {
+        return Err(invalid("not a save state".to_owned()));
+    }
+    if version[0] > VERSION {
+        return Err(invalid(format!(
+            "save state version {} is newer than the {} this build reads",
+            version[0], VERSION
+        )));
+    }
+    if u64::from_le_bytes(checksum.try_into().unwrap()) != fnv1a(rom) {
+        return Err(invalid("save state was made with another ROM".to_owned()));
+    }
+
+    let entries = Reader::new(input)?.collect::<io::Result<Vec<Entry>>>()?;
+    let ram = match entries.last() {
+        Some(&Entry::Check { ram, .. }) => ram,
+        _ => return Err(invalid("save state ends without a check".to_owned())),
+    };
+    let inputs = timeline(entries);
+    let mut nes = replay(rom.to_vec(), &inputs);
+    if Ram::of(&mut nes).checksum() != ram {
+        return Err(invalid(
+            "save state doesn't replay to where it was saved".to_owned(),
+        ));
+    }
+    Ok((nes, inputs))
+}
@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use crate::luanim::{Mat3, Vec2};
+
+/// Largest whole factor frames are scaled up by before [`Scaling::Sharp`]
+/// smooths them the rest of the way.
+pub const MAX_PRESCALE: usize = 8;
+
+/// How NES frames are resampled when drawn at a scale that isn't a whole
+/// number of screen pixels per NES pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scaling {
+    /// Nearest neighbour, which makes some pixels wider than others.
+    Nearest,
+    /// Shrinks the frame to the nearest whole scale and lines it up with the
+    /// screen pixels.
+    Snap,
+    /// Scales up by a whole number with nearest neighbour and the rest of the
+    /// way smoothly, so only the edges between pixels are blended.
+    Sharp,
+}
+
+impl Scaling {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scaling::Nearest => "nearest",
+            Scaling::Snap => "snap",
+            Scaling::Sharp => "sharp",
+        }
+    }
+}
+
+impl FromStr for Scaling {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Scaling::Nearest),
+            "snap" => Ok(Scaling::Snap),
+            "sharp" => Ok(Scaling::Sharp),
+            _ => Err(format!(
+                "unknown scaling {:?}, expected nearest, snap or sharp",
+                s
+            )),
+        }
+    }
+}
+
+/// Screen pixels per unit along the x axis of `transform`.
+fn screen_scale(transform: Mat3) -> f32 {
+    (transform.a * transform.a + transform.b * transform.b).sqrt()
+}
+
+/// Where and at how many units per NES pixel to draw a `width` by `height`
+/// frame meant for `origin` at `pixel` units per NES pixel, so that every NES
+/// pixel covers the same whole number of screen pixels. The frame stays
+/// centered on where it would have been.
+pub fn snap(transform: Mat3, origin: Vec2, pixel: f32, width: f32, height: f32) -> (Vec2, f32) {
+    let scale = screen_scale(transform);
+    if scale == 0.0 {
+        return (origin, pixel);
+    }
+    let snapped = (scale * pixel).floor().max(1.0) / scale;
+    let centered = origin + (0.5 * (pixel - snapped)) * Vec2::new(width, height);
+
+    let on_screen = transform * centered;
+    let rounded = Vec2::new(on_screen.x.round(), on_screen.y.round());
+    match transform.inverse() {
+        Some(inverse) => (inverse * rounded, snapped),
+        None => (centered, snapped),
+    }
+}
+
+/// Whole factor to scale a frame up by before smoothing it to `pixel` units
+/// per NES pixel under `transform`.
+pub fn prescale(transform: Mat3, pixel: f32) -> usize {
+    ((screen_scale(transform) * pixel).floor() as usize).clamp(1, MAX_PRESCALE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snap_rounds_down_to_whole_pixels() {
+        // 3.75 units per pixel at twice the size is 7.5 screen pixels
+        let transform = Mat3::scale(2.0, 2.0);
+        let (origin, pixel) = snap(transform, Vec2::new(0.0, 0.0), 3.75, 256.0, 240.0);
+        assert_eq!(pixel * 2.0, 7.0);
+        // centered: a quarter screen pixel lost on every NES pixel
+        assert_eq!(2.0 * origin, Vec2::new(64.0, 60.0));
+    }
+
+    #[test]
+    fn snap_never_goes_below_one_pixel() {
+        let transform = Mat3::scale(0.5, 0.5);
+        let (_, pixel) = snap(transform, Vec2::new(0.0, 0.0), 1.0, 256.0, 240.0);
+        assert_eq!(pixel * 0.5, 1.0);
+    }
+
+    #[test]
+    fn prescale_is_whole_and_capped() {
+        assert_eq!(prescale(Mat3::identity(), 0.5), 1);
+        assert_eq!(prescale(Mat3::identity(), 3.75), 3);
+        assert_eq!(prescale(Mat3::scale(4.0, 4.0), 3.75), MAX_PRESCALE);
+    }
+}
@@ -0,0 +1,259 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use mlua::Result;
+use tracing::{info, warn};
+
+use crate::luanim::{Animation, Backend, Input, Layer, Vec2};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Cut,
+    Fade,
+    Slide,
+}
+
+impl FromStr for Transition {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "cut" => Ok(Transition::Cut),
+            "fade" => Ok(Transition::Fade),
+            "slide" => Ok(Transition::Slide),
+            _ => Err(format!(
+                "unknown transition {:?}, expected cut, fade or slide",
+                s
+            )),
+        }
+    }
+}
+
+/// Lets scripts (or anything else holding a clone) ask for a scene switch,
+/// which is picked up by [`Scenes::render`].
+#[derive(Clone, Default)]
+pub struct SceneSwitch(Arc<Mutex<Option<String>>>);
+
+impl SceneSwitch {
+    pub fn request(&self, name: impl Into<String>) {
+        *self.0.lock().unwrap() = Some(name.into());
+    }
+
+    pub(crate) fn take(&self) -> Option<String> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+pub struct Scene<B> {
+    pub name: String,
+    pub animation: Animation<B>,
+}
+
+impl<B> Scene<B> {
+    pub fn new(name: impl Into<String>, animation: Animation<B>) -> Scene<B> {
+        Scene {
+            name: name.into(),
+            animation,
+        }
+    }
+}
+
+/// A set of named animations of which one is shown at a time, with animated
+/// transitions between them.
+pub struct Scenes<B> {
+    scenes: Vec<Scene<B>>,
+    current: usize,
+    previous: Option<(usize, Instant)>,
+    switch: SceneSwitch,
+    /// Drawn every frame to a window of its own for the operator, besides
+    /// the scene shown, and hears everything the other scenes hear.
+    dashboard: Option<Scene<B>>,
+
+    /// How to animate from one scene to the next.
+    pub transition: Transition,
+    /// How long a transition takes.
+    pub duration: Duration,
+    /// Move on to the next scene after this long, if set.
+    pub rotate: Option<Duration>,
+    shown: Instant,
+    rendered: Instant,
+}
+
+impl<B: Backend> Scenes<B> {
+    /// Creates a scene manager showing the first of `scenes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `scenes` is empty.
+    pub fn new(scenes: Vec<Scene<B>>, switch: SceneSwitch) -> Scenes<B> {
+        assert!(!scenes.is_empty(), "at least one scene is needed");
+        Scenes {
+            scenes,
+            current: 0,
+            previous: None,
+            switch,
+            dashboard: None,
+            transition: Transition::Fade,
+            duration: Duration::from_secs(1),
+            rotate: None,
+            shown: Instant::now(),
+            rendered: Instant::now(),
+        }
+    }
+
+    pub fn current(&self) -> &Scene<B> {
+        &self.scenes[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Scene<B> {
+        &mut self.scenes[self.current]
+    }
+
+    /// Every scene, the dashboard included.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Scene<B>> {
+        self.scenes.iter_mut().chain(self.dashboard.as_mut())
+    }
+
+    /// The scenes being drawn: the current one, the one being transitioned
+    /// away from if any, and the dashboard.
+    pub fn drawn(&self) -> impl Iterator<Item = &Scene<B>> {
+        let previous = self.previous.map(|(previous, _)| &self.scenes[previous]);
+        std::iter::once(&self.scenes[self.current])
+            .chain(previous)
+            .chain(self.dashboard.as_ref())
+    }
+
+    /// Replaces the animation of the scene called `name`, restarting its clock.
+    pub fn replace(&mut self, name: &str, animation: Animation<B>) {
+        if let Some(scene) = self.scenes.iter_mut().find(|scene| scene.name == name) {
+            *scene = Scene::new(name, animation);
+        }
+    }
+
+    /// Draws `scene` to the operator's window every frame, see
+    /// [`Scenes::render_dashboard`].
+    pub fn set_dashboard(&mut self, scene: Scene<B>) {
+        self.dashboard = Some(scene);
+    }
+
+    /// Stops drawing the dashboard, for when its window is closed.
+    pub fn close_dashboard(&mut self) {
+        self.dashboard = None;
+    }
+
+    /// Starts a transition to the scene at `index`. Does nothing if it is out
+    /// of range or already shown.
+    pub fn show(&mut self, index: usize) {
+        if index >= self.scenes.len() || index == self.current {
+            return;
+        }
+        info!(scene = %self.scenes[index].name, "switching scene");
+        self.previous = Some((self.current, Instant::now()));
+        self.current = index;
+        self.shown = Instant::now();
+    }
+
+    /// Starts a transition to the scene called `name`.
+    pub fn show_named(&mut self, name: &str) {
+        match self.scenes.iter().position(|scene| scene.name == name) {
+            Some(index) => self.show(index),
+            None => warn!(scene = name, "no such scene"),
+        }
+    }
+
+    pub fn show_next(&mut self) {
+        self.show((self.current + 1) % self.scenes.len());
+    }
+
+    /// Passes `input` on to the scene that is shown.
+    pub fn input(&mut self, input: &Input) -> Result<()> {
+        self.scenes[self.current].animation.input(input)
+    }
+
+    /// Passes `input` on to the dashboard, if there is one.
+    pub fn dashboard_input(&mut self, input: &Input) -> Result<()> {
+        match self.dashboard.as_mut() {
+            Some(dashboard) => dashboard.animation.input(input),
+            None => Ok(()),
+        }
+    }
+
+    /// Moves every scene's timeline along and draws the current frame,
+    /// compositing two scenes while a transition is in progress.
+    pub fn render(&mut self) -> Result<()> {
+        let dt = self.rendered.elapsed().as_secs_f32();
+        self.rendered = Instant::now();
+        for scene in self.iter_mut() {
+            scene.animation.tick(dt);
+        }
+
+        if let Some(name) = self.switch.take() {
+            self.show_named(&name);
+        }
+        if let Some(rotate) = self.rotate {
+            if self.shown.elapsed() >= rotate {
+                self.show_next();
+            }
+        }
+
+        let progress = self.previous.map(|(previous, start)| {
+            let t = start.elapsed().as_secs_f32() / self.duration.as_secs_f32();
+            (previous, t)
+        });
+
+        match progress {
+            Some((previous, t)) if t < 1.0 && self.transition != Transition::Cut => {
+                let (width, _) = self.scenes[self.current].animation.size();
+                let (outgoing, incoming) = match self.transition {
+                    Transition::Fade => (
+                        Layer {
+                            alpha: 1.0 - t,
+                            ..Layer::default()
+                        },
+                        Layer {
+                            alpha: t,
+                            ..Layer::default()
+                        },
+                    ),
+                    _ => (
+                        Layer {
+                            offset: Vec2::new(-t * width, 0.0),
+                            ..Layer::default()
+                        },
+                        Layer {
+                            offset: Vec2::new((1.0 - t) * width, 0.0),
+                            ..Layer::default()
+                        },
+                    ),
+                };
+
+                let animation = &mut self.scenes[previous].animation;
+                animation.clear();
+                animation.draw(outgoing)?;
+
+                self.scenes[self.current].animation.draw(incoming)
+            }
+            _ => {
+                self.previous = None;
+                let animation = &mut self.scenes[self.current].animation;
+                animation.clear();
+                animation.draw(Layer::default())
+            }
+        }
+    }
+
+    /// Draws the current frame of the dashboard, if there is one. Its
+    /// timeline is moved along by [`Scenes::render`].
+    pub fn render_dashboard(&mut self) -> Result<()> {
+        match self.dashboard.as_mut() {
+            Some(dashboard) => {
+                dashboard.animation.clear();
+                dashboard.animation.draw(Layer::default())
+            }
+            None => Ok(()),
+        }
+    }
+}
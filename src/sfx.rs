@@ -0,0 +1,196 @@
+//! Short sound effects played when something happens to the Marios, so the
+//! show has sound without emulating the APU. Playing them needs the `audio`
+//! feature.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+/// Something that has a sound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sound {
+    /// A Mario lost a life.
+    Death,
+    /// A Mario cleared a level.
+    Clear,
+    /// A Mario got to a level no one got to before.
+    Record,
+}
+
+impl Sound {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sound::Death => "death",
+            Sound::Clear => "clear",
+            Sound::Record => "record",
+        }
+    }
+
+    /// Time a sound has to wait after it played before it plays again, so a
+    /// room full of Marios dying doesn't become noise.
+    pub fn cooldown(&self) -> Duration {
+        match self {
+            Sound::Death => Duration::from_millis(500),
+            Sound::Clear => Duration::from_secs(2),
+            Sound::Record => Duration::from_secs(5),
+        }
+    }
+}
+
+impl FromStr for Sound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "death" => Ok(Sound::Death),
+            "clear" => Ok(Sound::Clear),
+            "record" => Ok(Sound::Record),
+            _ => Err(format!(
+                "unknown sound {:?}, expected death, clear or record",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SfxError {
+    #[cfg(feature = "audio")]
+    #[error("could not open the audio device")]
+    Device(#[from] rodio::StreamError),
+    #[error("could not read sound {}", .path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[cfg(feature = "audio")]
+    #[error("could not decode sound {}", .path.display())]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: rodio::decoder::DecoderError,
+    },
+    #[cfg(feature = "audio")]
+    #[error("could not play sound")]
+    Play(#[from] rodio::PlayError),
+    #[error("sound effects need shellkick built with the audio feature")]
+    Unsupported,
+}
+
+/// When every sound last played.
+#[derive(Default)]
+pub struct Cooldowns {
+    played: HashMap<Sound, Instant>,
+}
+
+impl Cooldowns {
+    /// Whether `sound` is done cooling down at `now`, in which case it counts
+    /// as played then.
+    pub fn ready(&mut self, sound: Sound, now: Instant) -> bool {
+        match self.played.get(&sound) {
+            Some(&played) if now.saturating_duration_since(played) < sound.cooldown() => false,
+            _ => {
+                self.played.insert(sound, now);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+type Clip = rodio::source::Buffered<rodio::Decoder<std::io::BufReader<std::fs::File>>>;
+
+/// Sound files decoded up front, played on the default audio device.
+#[cfg(feature = "audio")]
+pub struct Player {
+    // sounds stop when the stream is dropped
+    _stream: rodio::OutputStream,
+    handle: rodio::OutputStreamHandle,
+    clips: HashMap<Sound, Clip>,
+    volume: f32,
+    cooldowns: Cooldowns,
+}
+
+#[cfg(feature = "audio")]
+impl Player {
+    /// Opens the default audio device and decodes every file, played for its
+    /// sound at `volume`, from 0 for silent to 1 for as loud as the file.
+    pub fn new(files: &[(Sound, PathBuf)], volume: f32) -> Result<Player, SfxError> {
+        use rodio::Source;
+
+        let (stream, handle) = rodio::OutputStream::try_default()?;
+        let mut clips = HashMap::new();
+        for (sound, path) in files {
+            let file = std::fs::File::open(path).map_err(|source| SfxError::Read {
+                path: path.clone(),
+                source,
+            })?;
+            let decoder = rodio::Decoder::new(std::io::BufReader::new(file)).map_err(|source| {
+                SfxError::Decode {
+                    path: path.clone(),
+                    source,
+                }
+            })?;
+            clips.insert(*sound, decoder.buffered());
+        }
+        Ok(Player {
+            _stream: stream,
+            handle,
+            clips,
+            volume,
+            cooldowns: Cooldowns::default(),
+        })
+    }
+
+    /// Plays `sound` if it has a file and isn't cooling down.
+    pub fn play(&mut self, sound: Sound) -> Result<(), SfxError> {
+        use rodio::Source;
+
+        let clip = match self.clips.get(&sound) {
+            Some(clip) => clip.clone(),
+            None => return Ok(()),
+        };
+        if self.cooldowns.ready(sound, Instant::now()) {
+            let source = clip.amplify(self.volume).convert_samples::<f32>();
+            self.handle.play_raw(source)?;
+        }
+        Ok(())
+    }
+}
+
+/// Stands in for the player of the `audio` feature, which can't be created.
+#[cfg(not(feature = "audio"))]
+pub struct Player;
+
+#[cfg(not(feature = "audio"))]
+impl Player {
+    pub fn new(_files: &[(Sound, PathBuf)], _volume: f32) -> Result<Player, SfxError> {
+        Err(SfxError::Unsupported)
+    }
+
+    pub fn play(&mut self, _sound: Sound) -> Result<(), SfxError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sounds_cool_down_on_their_own() {
+        let now = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+        assert!(cooldowns.ready(Sound::Death, now));
+        assert!(!cooldowns.ready(Sound::Death, now + Duration::from_millis(499)));
+        assert!(cooldowns.ready(Sound::Clear, now));
+        assert!(cooldowns.ready(Sound::Death, now + Duration::from_millis(500)));
+        assert_eq!("record".parse(), Ok(Sound::Record));
+    }
+}
@@ -0,0 +1,136 @@
+//! Moving the addresses of the [memory map](super::ram) for ROM hacks that
+//! keep their state elsewhere.
+//!
+//! An override is a Lua file returning a table from the lowercase names of
+//! the addresses in [`super::ram`] to where the hack keeps them instead:
+//!
+//! ```lua
+//! return { world = 0x07f0, level = 0x07f1 }
+//! ```
+//!
+//! Only emulators read through the override. [`Ram`](super::Ram) snapshots
+//! keep the addresses of the original game, which tests are written against.
+
+use std::sync::OnceLock;
+
+use mlua::{Lua, Table};
+
+use super::ram::{
+    AREA, AREA_TYPE, COINS, ENEMY_FLAGS, ENEMY_PAGE, ENEMY_SLOTS, ENEMY_X, ENEMY_Y, ENEMY_Y_SCREEN,
+    FLAGPOLE_SCORE, GAME_ENGINE, LEVEL, LIVES, MODE, MODE_TASK, PLAYER_PAGE, PLAYER_X,
+    PLAYER_X_SPEED, PLAYER_Y, PLAYER_Y_SCREEN, PLAYER_Y_SPEED, POWERUP, SCORE, SCREEN_PAGE,
+    SCREEN_X, STOMP_CHAIN, TILES, TILES_PER_PAGE, TIMER, WORLD,
+};
+
+/// Every address that can be moved, with its name and how many bytes it
+/// spans.
+pub const FIELDS: [(&str, u16, u16); 28] = [
+    ("game_engine", GAME_ENGINE, 1),
+    ("enemy_flags", ENEMY_FLAGS, ENEMY_SLOTS),
+    ("player_x_speed", PLAYER_X_SPEED, 1),
+    ("player_page", PLAYER_PAGE, 1),
+    ("enemy_page", ENEMY_PAGE, ENEMY_SLOTS),
+    ("player_x", PLAYER_X, 1),
+    ("enemy_x", ENEMY_X, ENEMY_SLOTS),
+    ("player_y_speed", PLAYER_Y_SPEED, 1),
+    ("player_y_screen", PLAYER_Y_SCREEN, 1),
+    ("enemy_y_screen", ENEMY_Y_SCREEN, ENEMY_SLOTS),
+    ("player_y", PLAYER_Y, 1),
+    ("enemy_y", ENEMY_Y, ENEMY_SLOTS),
+    ("flagpole_score", FLAGPOLE_SCORE, 1),
+    ("stomp_chain", STOMP_CHAIN, 1),
+    ("tiles", TILES, 2 * TILES_PER_PAGE),
+    ("screen_page", SCREEN_PAGE, 1),
+    ("screen_x", SCREEN_X, 1),
+    ("area_type", AREA_TYPE, 1),
+    ("powerup", POWERUP, 1),
+    ("lives", LIVES, 1),
+    ("level", LEVEL, 1),
+    ("coins", COINS, 1),
+    ("world", WORLD, 1),
+    ("area", AREA, 1),
+    ("mode", MODE, 1),
+    ("mode_task", MODE_TASK, 1),
+    ("score", SCORE, 6),
+    ("timer", TIMER, 3),
+];
+
+static MAP: OnceLock<MemoryMap> = OnceLock::new();
+
+/// Where every address of the original game is in a hack.
+pub struct MemoryMap {
+    /// The address read for every address of internal RAM.
+    table: Box<[u16; 0x800]>,
+}
+
+impl MemoryMap {
+    /// Evaluates the override in `source`, failing on names that aren't in
+    /// [`FIELDS`] or addresses outside internal RAM.
+    pub fn from_lua(source: &str) -> mlua::Result<MemoryMap> {
+        let lua = Lua::new();
+        let overrides: Table = lua.load(source).eval()?;
+
+        let mut table = Box::new([0; 0x800]);
+        for (addr, entry) in table.iter_mut().enumerate() {
+            *entry = addr as u16;
+        }
+        for pair in overrides.pairs::<String, u16>() {
+            let (name, moved) = pair?;
+            let (_, original, len) = FIELDS
+                .iter()
+                .find(|(field, _, _)| *field == name)
+                .ok_or_else(|| mlua::Error::RuntimeError(format!("unknown address {:?}", name)))?;
+            if u32::from(moved) + u32::from(*len) > 0x800 {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "{} moved to {:#06x}, outside internal RAM",
+                    name, moved
+                )));
+            }
+            for offset in 0..*len {
+                table[usize::from(original + offset)] = moved + offset;
+            }
+        }
+        Ok(MemoryMap { table })
+    }
+
+    pub fn translate(&self, addr: u16) -> u16 {
+        match self.table.get(usize::from(addr)) {
+            Some(&moved) => moved,
+            None => addr,
+        }
+    }
+}
+
+/// Makes emulators read through `map` from now on. Can only be done once,
+/// giving `map` back if there already is one.
+pub fn install(map: MemoryMap) -> Result<(), MemoryMap> {
+    MAP.set(map)
+}
+
+/// Where emulators read `addr` of the original game.
+pub fn translate(addr: u16) -> u16 {
+    match MAP.get() {
+        Some(map) => map.translate(addr),
+        None => addr,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_every_byte_of_a_field() {
+        let map = MemoryMap::from_lua("return { world = 0x07f0, score = 0x0100 }").unwrap();
+        assert_eq!(map.translate(WORLD), 0x07f0);
+        assert_eq!(map.translate(SCORE + 5), 0x0105);
+        assert_eq!(map.translate(LEVEL), LEVEL);
+        assert_eq!(map.translate(0x2000), 0x2000);
+    }
+
+    #[test]
+    fn rejects_unknown_names_and_addresses() {
+        assert!(MemoryMap::from_lua("return { warp = 1 }").is_err());
+        assert!(MemoryMap::from_lua("return { timer = 0x07ff }").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+use std::str::FromStr;
+
+pub mod map;
+pub mod ram;
+pub mod warp;
+
+use ram::{engine, mode, TASK_RUNNING};
+pub use ram::{AreaType, Memory, Powerup, Ram};
+pub use warp::Warp;
+
+/// Pixels of distance a coin is worth to a coin-hungry Mario.
+const COIN_WEIGHT: u64 = 64;
+/// Points of score worth a pixel of distance.
+const POINTS_PER_PIXEL: u64 = 10;
+/// Pixels of distance a unit of horizontal speed is worth.
+const SPEED_WEIGHT: u64 = 16;
+/// Pixels of distance each enemy in a chain of stomps is worth.
+const STOMP_WEIGHT: u64 = 32;
+
+/// What a Mario tries to get the most of on its way through a level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Objective {
+    /// Only getting as far as possible.
+    Distance,
+    /// Points from stomping enemies, breaking blocks and collecting items.
+    Score,
+    Coins,
+    /// Running at full speed rather than getting somewhere slowly.
+    Speed,
+}
+
+impl FromStr for Objective {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "distance" => Ok(Objective::Distance),
+            "score" => Ok(Objective::Score),
+            "coins" => Ok(Objective::Coins),
+            "speed" => Ok(Objective::Speed),
+            _ => Err(format!(
+                "unknown objective {:?}, expected distance, score, coins or speed",
+                s
+            )),
+        }
+    }
+}
+
+/// How well a Mario is doing, from worst to best in the order of the
+/// variants. Ties within a variant are broken by its fields in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fitness {
+    /// Dying, or running out of time if set.
+    Dying(bool),
+    /// Position in the game, and time left on the level timer to tell apart
+    /// Marios that got equally far.
+    Level(u64, u16),
+    /// Sliding down the flagpole, with how high it was grabbed from 0 to 4.
+    Flagpole(u8),
+    Cutscene,
+}
+
+impl Fitness {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Fitness::Dying(false) => "dying",
+            Fitness::Dying(true) => "timeout",
+            Fitness::Cutscene => "cutscene",
+            Fitness::Level(..) => "level",
+            Fitness::Flagpole(_) => "flagpole",
+        }
+    }
+}
+
+/// Position in the game, with the world and area above the position within
+/// the area so later levels always count as further.
+fn position(world: u8, area: u8, x: u16) -> u32 {
+    u32::from(world) << 24 | u32::from(area) << 16 | u32::from(x)
+}
+
+pub fn fitness(nes: &mut impl Memory) -> Fitness {
+    let mario_position = position(nes.world(), nes.area(), nes.player_x());
+
+    let engine_state = nes.game_engine_state();
+    let task = nes.mode_task();
+    let game_mode = nes.mode();
+
+    let cutscene = engine_state <= engine::END_LEVEL
+        || engine_state == engine::ENTRANCE
+        || game_mode == mode::VICTORY
+        || (game_mode == mode::GAME && task != TASK_RUNNING);
+    let dying = (nes.player_y() > 456
+        || engine_state == engine::LOSE_LIFE
+        || engine_state == engine::DEATH
+        || game_mode == mode::TITLE
+        || game_mode == mode::GAME_OVER)
+        && !cutscene;
+
+    let time = nes.timer();
+    let out_of_time = time == 0 && !cutscene;
+
+    if dying || out_of_time {
+        Fitness::Dying(out_of_time)
+    } else if engine_state == engine::FLAGPOLE {
+        Fitness::Flagpole(nes.flagpole_score())
+    } else if cutscene {
+        Fitness::Cutscene
+    } else {
+        Fitness::Level(u64::from(mario_position), time)
+    }
+}
+
+/// Like [`fitness`], with the distance into a level weighed against what
+/// `objective` asks for, a bonus for stomping enemies, and `powerup_bonus`
+/// pixels for every powerup Mario has.
+pub fn objective_fitness(
+    nes: &mut impl Memory,
+    objective: Objective,
+    powerup_bonus: u64,
+) -> Fitness {
+    match fitness(nes) {
+        Fitness::Level(position, time) => {
+            let bonus = match objective {
+                Objective::Distance => 0,
+                Objective::Score => nes.score() / POINTS_PER_PIXEL,
+                Objective::Coins => u64::from(nes.coins()) * COIN_WEIGHT,
+                Objective::Speed => u64::from(nes.player_x_speed().max(0) as u8) * SPEED_WEIGHT,
+            };
+            let stomps = u64::from(nes.stomp_chain()) * STOMP_WEIGHT;
+            let powerup = nes.powerup() as u64 * powerup_bonus;
+            Fitness::Level(position + bonus + stomps + powerup, time)
+        }
+        other => other,
+    }
+}
+
+pub fn victory(nes: &mut impl Memory) -> bool {
+    nes.mode() == mode::VICTORY
+}
+
+/// How many levels into the game Mario is, counting from zero at 1-1.
+pub fn depth(nes: &mut impl Memory) -> u32 {
+    u32::from(nes.world()) * 4 + u32::from(nes.level())
+}
+
+/// Whether the title screen is up and waiting for start, which includes the
+/// demo it plays.
+pub fn title_menu(nes: &mut impl Memory) -> bool {
+    nes.mode() == mode::TITLE && nes.mode_task() == TASK_RUNNING
+}
+
+/// Whether the game is running a level, as opposed to being on the title or
+/// the screens before a level.
+pub fn in_level(nes: &mut impl Memory) -> bool {
+    nes.mode() == mode::GAME && nes.mode_task() == TASK_RUNNING
+}
+
+/// Position in the game of the left edge of the screen.
+pub fn scroll(nes: &mut impl Memory) -> u32 {
+    position(nes.world(), nes.area(), nes.screen_x())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::*;
+
+    fn samples() -> Vec<Fitness> {
+        vec![
+            Fitness::Dying(false),
+            Fitness::Dying(true),
+            Fitness::Level(0, 0),
+            Fitness::Level(0, 400),
+            Fitness::Level(100, 0),
+            Fitness::Level(100, 300),
+            Fitness::Level(0x0001_0000, 0),
+            Fitness::Level(u64::MAX, u16::MAX),
+            Fitness::Flagpole(0),
+            Fitness::Flagpole(4),
+            Fitness::Cutscene,
+        ]
+    }
+
+    #[test]
+    fn samples_are_in_order() {
+        let samples = samples();
+        for (i, a) in samples.iter().enumerate() {
+            for (j, b) in samples.iter().enumerate() {
+                assert_eq!(a.cmp(b), i.cmp(&j), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn order_is_antisymmetric() {
+        for a in samples() {
+            for b in samples() {
+                assert_eq!(a.cmp(&b), b.cmp(&a).reverse(), "{:?} vs {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn order_is_transitive() {
+        let samples = samples();
+        for a in &samples {
+            for b in &samples {
+                for c in &samples {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "{:?} <= {:?} <= {:?}", a, b, c);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn order_agrees_with_eq() {
+        for a in samples() {
+            for b in samples() {
+                assert_eq!(a == b, a.cmp(&b) == Ordering::Equal, "{:?} vs {:?}", a, b);
+                assert_eq!(a.partial_cmp(&b), Some(a.cmp(&b)));
+            }
+        }
+    }
+
+    #[test]
+    fn time_left_breaks_ties() {
+        assert!(Fitness::Level(100, 300) > Fitness::Level(100, 200));
+        assert!(Fitness::Level(101, 0) > Fitness::Level(100, 300));
+    }
+
+    #[test]
+    fn any_progress_beats_dying() {
+        assert!(Fitness::Level(0, 0) > Fitness::Dying(true));
+        assert!(Fitness::Cutscene > Fitness::Level(u64::MAX, u16::MAX));
+        assert!(Fitness::Cutscene > Fitness::Flagpole(4));
+    }
+}
@@ -0,0 +1,298 @@
+//! Where Super Mario Bros. keeps its state in RAM, and accessors to read it.
+
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+
+/// What the player object is doing, see [`engine`].
+pub const GAME_ENGINE: u16 = 0x000e;
+/// Whether each of the [`ENEMY_SLOTS`] holds an enemy.
+pub const ENEMY_FLAGS: u16 = 0x000f;
+/// Signed horizontal speed of the player.
+pub const PLAYER_X_SPEED: u16 = 0x0057;
+/// Page of the level the player is on.
+pub const PLAYER_PAGE: u16 = 0x006d;
+/// Page of the level each enemy is on.
+pub const ENEMY_PAGE: u16 = 0x006e;
+/// Horizontal position of the player within the page.
+pub const PLAYER_X: u16 = 0x0086;
+pub const ENEMY_X: u16 = 0x0087;
+/// Signed vertical speed of the player, positive when falling.
+pub const PLAYER_Y_SPEED: u16 = 0x009f;
+/// Vertical screen the player is on, 1 being the visible one.
+pub const PLAYER_Y_SCREEN: u16 = 0x00b5;
+pub const ENEMY_Y_SCREEN: u16 = 0x00b6;
+pub const PLAYER_Y: u16 = 0x00ce;
+pub const ENEMY_Y: u16 = 0x00cf;
+/// How high the flagpole was grabbed, from 0 at the bottom to 4 at the top.
+pub const FLAGPOLE_SCORE: u16 = 0x010f;
+/// Enemies stomped since the player last touched the ground.
+pub const STOMP_CHAIN: u16 = 0x0484;
+/// The metatiles of the two pages of the level around the screen, see
+/// [`Memory::tile`].
+pub const TILES: u16 = 0x0500;
+/// Page of the level the left edge of the screen is on.
+pub const SCREEN_PAGE: u16 = 0x071a;
+/// Horizontal position of the left edge of the screen within the page.
+pub const SCREEN_X: u16 = 0x071c;
+/// See [`AreaType`].
+pub const AREA_TYPE: u16 = 0x074e;
+/// See [`Powerup`].
+pub const POWERUP: u16 = 0x0756;
+/// Lives left besides the current one.
+pub const LIVES: u16 = 0x075a;
+/// Level within the world, from 0.
+pub const LEVEL: u16 = 0x075c;
+pub const COINS: u16 = 0x075e;
+/// World, from 0.
+pub const WORLD: u16 = 0x075f;
+/// Area within the world, which unlike [`LEVEL`] also counts the areas
+/// before some levels.
+pub const AREA: u16 = 0x0760;
+/// What the game as a whole is doing, see [`mode`].
+pub const MODE: u16 = 0x0770;
+/// Step of the current mode, see [`TASK_RUNNING`].
+pub const MODE_TASK: u16 = 0x0772;
+/// Six decimal digits, one per byte, most significant first.
+pub const SCORE: u16 = 0x07dd;
+/// Three decimal digits, one per byte, most significant first.
+pub const TIMER: u16 = 0x07f8;
+
+/// The [`MODE_TASK`] of a mode once it is done setting up: the title menu,
+/// or a level being played.
+pub const TASK_RUNNING: u8 = 3;
+
+/// Enemies the game keeps track of at once.
+pub const ENEMY_SLOTS: u16 = 5;
+/// Bytes of [`TILES`] per page: 13 rows of 16 metatiles.
+pub const TILES_PER_PAGE: u16 = 0xd0;
+
+/// Values of [`GAME_ENGINE`].
+pub mod engine {
+    pub const VERTICAL_PIPE: u8 = 3;
+    pub const FLAGPOLE: u8 = 4;
+    pub const END_LEVEL: u8 = 5;
+    pub const LOSE_LIFE: u8 = 6;
+    pub const ENTRANCE: u8 = 7;
+    pub const PLAYER_CONTROL: u8 = 8;
+    pub const DEATH: u8 = 11;
+}
+
+/// Values of [`MODE`].
+pub mod mode {
+    pub const TITLE: u8 = 0;
+    pub const GAME: u8 = 1;
+    pub const VICTORY: u8 = 2;
+    pub const GAME_OVER: u8 = 3;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaType {
+    Water,
+    Ground,
+    Underground,
+    Castle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Powerup {
+    Small,
+    Big,
+    Fire,
+}
+
+impl Powerup {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Powerup::Small => "small",
+            Powerup::Big => "big",
+            Powerup::Fire => "fire",
+        }
+    }
+}
+
+/// Where the RAM of a game is read from: a running emulator, or a snapshot of
+/// its memory.
+pub trait Memory {
+    fn read(&mut self, addr: u16) -> u8;
+
+    /// A number of decimal digits stored one per byte from `addr`.
+    fn read_digits(&mut self, addr: u16, digits: u16) -> u64 {
+        (addr..addr + digits).fold(0, |value, addr| value * 10 + u64::from(self.read(addr)))
+    }
+
+    fn game_engine_state(&mut self) -> u8 {
+        self.read(GAME_ENGINE)
+    }
+
+    fn mode(&mut self) -> u8 {
+        self.read(MODE)
+    }
+
+    fn mode_task(&mut self) -> u8 {
+        self.read(MODE_TASK)
+    }
+
+    /// Horizontal position of the player within the level.
+    fn player_x(&mut self) -> u16 {
+        u16::from(self.read(PLAYER_PAGE)) << 8 | u16::from(self.read(PLAYER_X))
+    }
+
+    /// Vertical position of the player, where 256 is the top of the screen.
+    fn player_y(&mut self) -> u16 {
+        u16::from(self.read(PLAYER_Y_SCREEN)) << 8 | u16::from(self.read(PLAYER_Y))
+    }
+
+    /// Horizontal speed of the player, negative when moving left.
+    fn player_x_speed(&mut self) -> i8 {
+        self.read(PLAYER_X_SPEED) as i8
+    }
+
+    /// Vertical speed of the player, negative when moving up.
+    fn player_y_speed(&mut self) -> i8 {
+        self.read(PLAYER_Y_SPEED) as i8
+    }
+
+    /// Position of the enemy in `slot` like that of the player, if there is
+    /// one.
+    fn enemy(&mut self, slot: u16) -> Option<(u16, u16)> {
+        if slot >= ENEMY_SLOTS || self.read(ENEMY_FLAGS + slot) == 0 {
+            return None;
+        }
+        let x = u16::from(self.read(ENEMY_PAGE + slot)) << 8 | u16::from(self.read(ENEMY_X + slot));
+        let y =
+            u16::from(self.read(ENEMY_Y_SCREEN + slot)) << 8 | u16::from(self.read(ENEMY_Y + slot));
+        Some((x, y))
+    }
+
+    /// The metatile at horizontal position `x` in the level and `y` pixels
+    /// from the top of the screen, 0 being empty. Only the page of the level
+    /// the screen is on and the next one are known; the rest reads as empty.
+    fn tile(&mut self, x: u16, y: u16) -> u8 {
+        let page = x >> 8;
+        let screen = self.screen_x() >> 8;
+        if !(32..240).contains(&y) || page < screen || page > screen + 1 {
+            return 0;
+        }
+        let row = (y - 32) / 16;
+        let column = (x & 0xff) / 16;
+        self.read(TILES + (page & 1) * TILES_PER_PAGE + row * 16 + column)
+    }
+
+    /// Horizontal position of the left edge of the screen within the level.
+    fn screen_x(&mut self) -> u16 {
+        u16::from(self.read(SCREEN_PAGE)) << 8 | u16::from(self.read(SCREEN_X))
+    }
+
+    fn world(&mut self) -> u8 {
+        self.read(WORLD)
+    }
+
+    fn level(&mut self) -> u8 {
+        self.read(LEVEL)
+    }
+
+    fn area(&mut self) -> u8 {
+        self.read(AREA)
+    }
+
+    fn area_type(&mut self) -> AreaType {
+        match self.read(AREA_TYPE) & 0b11 {
+            0 => AreaType::Water,
+            1 => AreaType::Ground,
+            2 => AreaType::Underground,
+            _ => AreaType::Castle,
+        }
+    }
+
+    /// Time left on the level timer.
+    fn timer(&mut self) -> u16 {
+        self.read_digits(TIMER, 3) as u16
+    }
+
+    /// The score shown at the top of the screen.
+    fn score(&mut self) -> u64 {
+        self.read_digits(SCORE, 6)
+    }
+
+    fn coins(&mut self) -> u8 {
+        self.read(COINS)
+    }
+
+    fn stomp_chain(&mut self) -> u8 {
+        self.read(STOMP_CHAIN)
+    }
+
+    fn flagpole_score(&mut self) -> u8 {
+        self.read(FLAGPOLE_SCORE)
+    }
+
+    fn powerup(&mut self) -> Powerup {
+        match self.read(POWERUP) {
+            0 => Powerup::Small,
+            1 => Powerup::Big,
+            _ => Powerup::Fire,
+        }
+    }
+
+    /// Lives left, counting the current one.
+    fn lives(&mut self) -> u8 {
+        self.read(LIVES).saturating_add(1)
+    }
+}
+
+impl Memory for NES<NROM, FastPPU> {
+    /// Reads `addr`, or where a hack keeps it, see [`super::map`].
+    fn read(&mut self, addr: u16) -> u8 {
+        NES::read(self, super::map::translate(addr))
+    }
+}
+
+/// The 64-bit FNV-1a hash of `bytes`.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A copy of the 2 KiB of internal RAM of the NES.
+#[derive(Clone)]
+pub struct Ram(pub [u8; 0x800]);
+
+impl Default for Ram {
+    fn default() -> Self {
+        Ram([0; 0x800])
+    }
+}
+
+impl Ram {
+    /// Copies the internal RAM of `memory`.
+    pub fn of(memory: &mut impl Memory) -> Ram {
+        let mut ram = Ram::default();
+        for (addr, byte) in ram.0.iter_mut().enumerate() {
+            *byte = memory.read(addr as u16);
+        }
+        ram
+    }
+
+    /// A hash of the contents, to tell whether two states match without
+    /// keeping all of them.
+    pub fn checksum(&self) -> u64 {
+        fnv1a(&self.0)
+    }
+
+    /// Sets the byte at `addr`, for building a state to test against.
+    pub fn with(mut self, addr: u16, value: u8) -> Ram {
+        self.0[usize::from(addr) & 0x7ff] = value;
+        self
+    }
+}
+
+impl Memory for Ram {
+    /// Reads internal RAM and its mirrors, and zero anywhere else.
+    fn read(&mut self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            self.0[usize::from(addr) & 0x7ff]
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Starting a game in a later level by writing where the game is into RAM
+//! while it gets from the title screen into the first level.
+
+use std::{fmt, str::FromStr};
+
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+use rand::Rng;
+
+use super::{
+    depth, in_level, map,
+    ram::{AREA, LEVEL, WORLD},
+    Memory,
+};
+
+/// Worlds whose second level starts with an area of its own, walking into a
+/// pipe, which counts towards [`AREA`] but not towards [`LEVEL`].
+const INTRO_AREAS: [u8; 4] = [0, 1, 3, 6];
+
+/// A level to start in, both counted from zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Warp {
+    pub world: u8,
+    pub level: u8,
+}
+
+impl Warp {
+    /// Any level from 1-1 to 8-4.
+    pub fn random(rng: &mut impl Rng) -> Warp {
+        Warp {
+            world: rng.gen_range(0..8),
+            level: rng.gen_range(0..4),
+        }
+    }
+
+    /// The level the game is in, or is about to start.
+    pub fn of(nes: &mut impl Memory) -> Warp {
+        Warp {
+            world: nes.world(),
+            level: nes.level(),
+        }
+    }
+
+    /// The [`depth`] of the level.
+    pub fn depth(&self) -> u32 {
+        u32::from(self.world) * 4 + u32::from(self.level)
+    }
+
+    fn area(&self) -> u8 {
+        if self.level >= 1 && INTRO_AREAS.contains(&self.world) {
+            self.level + 1
+        } else {
+            self.level
+        }
+    }
+
+    /// Writes the level into the RAM of a game that hasn't got there yet.
+    /// Has to be done on every frame until the level is running, as the title
+    /// screen resets it when start is pressed.
+    pub fn apply(&self, nes: &mut NES<NROM, FastPPU>) {
+        if in_level(nes) || depth(nes) >= self.depth() {
+            return;
+        }
+        for (addr, value) in [
+            (WORLD, self.world),
+            (LEVEL, self.level),
+            (AREA, self.area()),
+        ] {
+            nes.write(map::translate(addr), value);
+        }
+    }
+}
+
+impl fmt::Display for Warp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.world + 1, self.level + 1)
+    }
+}
+
+impl FromStr for Warp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once('-')
+            .and_then(|(world, level)| Some((world.parse().ok()?, level.parse().ok()?)));
+        match parsed {
+            Some((world @ 1..=8, level @ 1..=4)) => Ok(Warp {
+                world: world - 1,
+                level: level - 1,
+            }),
+            _ => Err(format!(
+                "unknown level {:?}, expected WORLD-LEVEL from 1-1 to 8-4",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_levels_counted_from_one() {
+        let warp: Warp = "4-2".parse().unwrap();
+        assert_eq!(warp, Warp { world: 3, level: 1 });
+        assert_eq!(warp.area(), 2);
+        assert_eq!(warp.to_string(), "4-2");
+        assert_eq!("3-2".parse::<Warp>().unwrap().area(), 1);
+        for wrong in ["9-1", "1-0", "1", "a-b"] {
+            assert!(wrong.parse::<Warp>().is_err());
+        }
+    }
+}
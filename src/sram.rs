@@ -0,0 +1,96 @@
+//! Battery-backed save RAM kept on disk between runs, one file per ROM.
+//!
+//! SMB has none, but with `--save-ram` a ROM whose header says it has a
+//! battery gets what was stored [loaded](SaveRam::load) into the cartridge of
+//! every Mario whenever he boots, and the save RAM of the Mario furthest ahead
+//! [stored](SaveRam::store) on exit.
+
+use std::{
+    fs::{create_dir_all, read, rename, write},
+    io,
+    path::{Path, PathBuf},
+};
+
+use fastnes::{cart::NROM, nes::NES, ppu::FastPPU};
+
+use crate::smb::ram::fnv1a;
+
+/// Where cartridges put their save RAM for the CPU to see, and how much of it
+/// there can be.
+pub const START: u16 = 0x6000;
+pub const SIZE: u16 = 0x2000;
+
+/// Copies `data` into the save RAM of `nes`, as much of it as fits.
+pub fn write_to(nes: &mut NES<NROM, FastPPU>, data: &[u8]) {
+    for (addr, &byte) in (START..START + SIZE).zip(data) {
+        nes.write(addr, byte);
+    }
+}
+
+/// What is in the save RAM of `nes`.
+pub fn read_from(nes: &mut NES<NROM, FastPPU>) -> Vec<u8> {
+    (START..START + SIZE)
+        .map(|addr| NES::read(nes, addr))
+        .collect()
+}
+
+pub struct SaveRam {
+    path: PathBuf,
+}
+
+impl SaveRam {
+    /// The save RAM of `rom`, kept in `dir` under the checksum of the ROM, so
+    /// revisions and hacks of a game don't overwrite each other's saves.
+    pub fn new(dir: &Path, rom: &[u8]) -> SaveRam {
+        SaveRam {
+            path: dir.join(format!("{:016x}.sav", fnv1a(rom))),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What was stored last, or `None` if nothing was stored yet.
+    pub fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        match read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces what is stored with `data`. The new contents are written next
+    /// to the old ones first, so stopping halfway never loses a save.
+    pub fn store(&self, data: &[u8]) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        let partial = self.path.with_extension("sav.partial");
+        write(&partial, data)?;
+        rename(&partial, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    #[test]
+    fn stores_per_rom() {
+        let dir = temp_dir().join(format!("shellkick-sram-{}", std::process::id()));
+        let game = SaveRam::new(&dir, b"game");
+        let hack = SaveRam::new(&dir, b"hack");
+
+        assert_eq!(game.load().unwrap(), None);
+        game.store(&[1, 2, 3]).unwrap();
+        hack.store(&[4]).unwrap();
+        game.store(&[5, 6]).unwrap();
+        assert_eq!(game.load().unwrap(), Some(vec![5, 6]));
+        assert_eq!(hack.load().unwrap(), Some(vec![4]));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
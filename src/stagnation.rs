@@ -0,0 +1,76 @@
+use std::str::FromStr;
+
+/// What to do to the population when it stops making progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Intervention {
+    /// Make every Mario twitchier and jumpier.
+    Mutate,
+    /// Give the Marios that got the least far fresh random personalities.
+    Inject,
+    /// Make every Mario plan further ahead.
+    Lookahead,
+}
+
+impl Intervention {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Intervention::Mutate => "mutate",
+            Intervention::Inject => "inject",
+            Intervention::Lookahead => "lookahead",
+        }
+    }
+}
+
+impl FromStr for Intervention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mutate" => Ok(Intervention::Mutate),
+            "inject" => Ok(Intervention::Inject),
+            "lookahead" => Ok(Intervention::Lookahead),
+            _ => Err(format!(
+                "unknown intervention {:?}, expected mutate, inject or lookahead",
+                s
+            )),
+        }
+    }
+}
+
+/// Watches the best fitness of the whole population to tell when it has
+/// stopped getting anywhere.
+pub struct Stagnation {
+    period: u32,
+    best: u32,
+    since: u32,
+}
+
+impl Stagnation {
+    /// Reports stagnation after `period` frames without a new best.
+    pub fn new(period: u32) -> Stagnation {
+        Stagnation {
+            period: period.max(1),
+            best: 0,
+            since: 0,
+        }
+    }
+
+    /// Counts a simulated frame in which `best` was the best fitness. Returns
+    /// true once no Mario has beaten the best so far for the whole period,
+    /// after which the count starts over to give an intervention time to
+    /// work.
+    pub fn update(&mut self, best: u32) -> bool {
+        if best > self.best {
+            self.best = best;
+            self.since = 0;
+            return false;
+        }
+        self.since += 1;
+        if self.since >= self.period {
+            self.since = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
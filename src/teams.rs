@@ -0,0 +1,139 @@
+//! Splitting the population in two halves that play differently, and keeping
+//! score of which half does better.
+
+use crate::{experiment::Param, mario::Personality};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Team {
+    A,
+    B,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::A, Team::B];
+
+    /// The first half of `instances` is on team A, the rest on team B.
+    pub fn of(instance: usize, instances: usize) -> Team {
+        if instance < instances / 2 {
+            Team::A
+        } else {
+            Team::B
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Team::A => "a",
+            Team::B => "b",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Team::A => 0,
+            Team::B => 1,
+        }
+    }
+}
+
+/// How the personalities of each team are changed from random ones.
+#[derive(Clone, Debug, Default)]
+pub struct Teams {
+    configs: [Vec<(Param, f64)>; 2],
+}
+
+impl Teams {
+    /// Teams with the [personal](Param::personal) parameters in `a` and `b`
+    /// set.
+    pub fn new(a: Vec<(Param, f64)>, b: Vec<(Param, f64)>) -> Teams {
+        Teams { configs: [a, b] }
+    }
+
+    /// Gives `personality` what sets `team` apart. Has to be done again
+    /// whenever the personality is replaced.
+    pub fn shape(&self, team: Team, personality: &mut Personality) {
+        for &(param, value) in self.configs[team.index()].iter() {
+            param.shape(value, personality);
+        }
+    }
+}
+
+/// How a team is doing as a whole.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Aggregate {
+    pub members: usize,
+    /// Furthest position of any member, see [`scroll`](crate::smb::scroll).
+    pub best: u32,
+    /// Positions of all members, summed.
+    pub total: u64,
+    /// Most levels into the game of any member.
+    pub deepest: u32,
+    /// Levels cleared by all members together.
+    pub clears: u32,
+}
+
+impl Aggregate {
+    pub fn mean(&self) -> f64 {
+        self.total as f64 / self.members.max(1) as f64
+    }
+}
+
+/// Running totals of both teams.
+#[derive(Clone, Debug, Default)]
+pub struct Scoreboard {
+    teams: [Aggregate; 2],
+}
+
+impl Scoreboard {
+    /// Counts where every instance is now, given as its position and depth in
+    /// instance order. Clears are kept.
+    pub fn update(&mut self, progress: &[(u32, u32)]) {
+        for (team, aggregate) in Team::ALL.into_iter().zip(self.teams.iter_mut()) {
+            let members = progress
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| Team::of(i, progress.len()) == team)
+                .map(|(_, progress)| progress);
+            *aggregate = Aggregate {
+                clears: aggregate.clears,
+                ..Aggregate::default()
+            };
+            for &(position, depth) in members {
+                aggregate.members += 1;
+                aggregate.best = aggregate.best.max(position);
+                aggregate.total += u64::from(position);
+                aggregate.deepest = aggregate.deepest.max(depth);
+            }
+        }
+    }
+
+    /// Counts a level cleared by `instance` of `instances`.
+    pub fn cleared(&mut self, instance: usize, instances: usize) {
+        self.teams[Team::of(instance, instances).index()].clears += 1;
+    }
+
+    pub fn team(&self, team: Team) -> &Aggregate {
+        &self.teams[team.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halves_are_scored_apart() {
+        let mut scoreboard = Scoreboard::default();
+        scoreboard.update(&[(100, 0), (300, 1), (50, 0), (70, 0)]);
+        scoreboard.cleared(1, 4);
+
+        let a = scoreboard.team(Team::A);
+        assert_eq!((a.members, a.best, a.deepest, a.clears), (2, 300, 1, 1));
+        assert_eq!(a.mean(), 200.0);
+        let b = scoreboard.team(Team::B);
+        assert_eq!((b.members, b.best, b.deepest, b.clears), (2, 70, 0, 0));
+
+        scoreboard.update(&[(0, 0); 4]);
+        assert_eq!(scoreboard.team(Team::A).clears, 1);
+    }
+}
@@ -0,0 +1,178 @@
+//! A strip of messages scrolling from right to left, like the ticker at the
+//! bottom of a news channel. Scripts queue messages and place the strip, the
+//! scrolling and measuring happens here.
+//!
+//! Lengths are in font sizes, so the layout stays the same at any size.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Space between two messages.
+pub const GAP: f32 = 4.0;
+
+struct Queued {
+    text: String,
+    /// Dropped when it couldn't be shown before this.
+    expires: Instant,
+}
+
+/// A message on the strip.
+struct Shown {
+    text: String,
+    /// Offset of the start of the message from the left of the strip.
+    x: f32,
+    /// Byte offset and end of every character, from the start of the message.
+    chars: Vec<(usize, f32)>,
+}
+
+impl Shown {
+    fn width(&self) -> f32 {
+        self.chars.last().map_or(0.0, |&(_, end)| end)
+    }
+}
+
+/// Messages waiting to be shown and the ones scrolling by.
+#[derive(Default)]
+pub struct Ticker {
+    queue: VecDeque<Queued>,
+    shown: VecDeque<Shown>,
+    scrolled: Option<Instant>,
+}
+
+impl Ticker {
+    /// Queues `text`, to be dropped if the messages before it keep it off the
+    /// strip for longer than `lifetime`. Returns whether it was queued, which
+    /// it isn't if `lifetime` is too long to tell when it runs out.
+    pub fn push(&mut self, text: String, lifetime: Duration, now: Instant) -> bool {
+        match now.checked_add(lifetime) {
+            Some(expires) => {
+                self.queue.push_back(Queued { text, expires });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops every message, waiting or shown.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.shown.clear();
+    }
+
+    /// Scrolls the messages on a strip `width` long at `speed` per second,
+    /// from the last time it scrolled, and moves the messages that fit onto
+    /// it. `measure` gives the width of some text.
+    pub fn scroll<E>(
+        &mut self,
+        now: Instant,
+        width: f32,
+        speed: f32,
+        mut measure: impl FnMut(&str) -> Result<f32, E>,
+    ) -> Result<(), E> {
+        let elapsed = match self.scrolled.replace(now) {
+            Some(scrolled) => now.saturating_duration_since(scrolled).as_secs_f32(),
+            None => 0.0,
+        };
+        for shown in self.shown.iter_mut() {
+            shown.x -= speed * elapsed;
+        }
+        while self
+            .shown
+            .front()
+            .is_some_and(|shown| shown.x + shown.width() < 0.0)
+        {
+            self.shown.pop_front();
+        }
+
+        self.queue.retain(|queued| queued.expires > now);
+        loop {
+            // the next message follows the last one, or comes in from the right
+            let x = match self.shown.back() {
+                Some(last) => last.x + last.width() + GAP,
+                None => width,
+            };
+            if x > width {
+                break;
+            }
+            let text = match self.queue.pop_front() {
+                Some(queued) => queued.text,
+                None => break,
+            };
+            let mut chars = Vec::new();
+            for (i, c) in text.char_indices() {
+                chars.push((i, measure(&text[..i + c.len_utf8()])?));
+            }
+            self.shown.push_back(Shown { text, x, chars });
+        }
+        Ok(())
+    }
+
+    /// The parts of the messages that are entirely on a strip `width` long,
+    /// with their offset from the left of it.
+    pub fn visible(&self, width: f32) -> Vec<(f32, &str)> {
+        let mut visible = Vec::new();
+        for shown in self.shown.iter() {
+            let mut start = 0.0;
+            let mut from = None;
+            let mut to = 0;
+            for (i, &(byte, end)) in shown.chars.iter().enumerate() {
+                if shown.x + start >= 0.0 && shown.x + end <= width {
+                    from.get_or_insert((byte, start));
+                    to = shown
+                        .chars
+                        .get(i + 1)
+                        .map_or(shown.text.len(), |&(next, _)| next);
+                }
+                start = end;
+            }
+            if let Some((from, offset)) = from {
+                visible.push((shown.x + offset, &shown.text[from..to]));
+            }
+        }
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every character is as wide as a font size.
+    fn measure(text: &str) -> Result<f32, ()> {
+        Ok(text.chars().count() as f32)
+    }
+
+    #[test]
+    fn messages_scroll_in_from_the_right() {
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut ticker = Ticker::default();
+        ticker.push("hello".to_owned(), second * 60, now);
+        ticker.push("world".to_owned(), second * 60, now);
+        ticker.push("late".to_owned(), second, now);
+
+        ticker.scroll(now, 10.0, 2.0, measure).unwrap();
+        assert!(ticker.visible(10.0).is_empty());
+        ticker.scroll(now + second, 10.0, 2.0, measure).unwrap();
+        assert_eq!(ticker.visible(10.0), [(8.0, "he")]);
+
+        // the second follows a gap behind the first, once there is room
+        ticker.scroll(now + second * 4, 10.0, 2.0, measure).unwrap();
+        assert_eq!(ticker.visible(10.0), [(2.0, "hello")]);
+        ticker.scroll(now + second * 5, 10.0, 2.0, measure).unwrap();
+        assert_eq!(ticker.visible(10.0), [(0.0, "hello"), (9.0, "w")]);
+
+        // characters going off on the left are cut
+        ticker.scroll(now + second * 6, 10.0, 1.0, measure).unwrap();
+        assert_eq!(ticker.visible(10.0), [(0.0, "ello"), (8.0, "wo")]);
+        // the last one waited too long to ever show
+        ticker
+            .scroll(now + second * 30, 10.0, 1.0, measure)
+            .unwrap();
+        assert!(ticker.visible(10.0).is_empty());
+        // and one that would never run out isn't queued
+        assert!(!ticker.push("forever".to_owned(), Duration::MAX, now));
+    }
+}
@@ -0,0 +1,104 @@
+//! Named countdowns for races, segment cutoffs and breaks, kept in real time
+//! so every scene sees the same time left and is told once when one runs out.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+/// Countdowns by name.
+#[derive(Default)]
+pub struct Timers {
+    ends: BTreeMap<String, Instant>,
+}
+
+impl Timers {
+    /// Starts the countdown `name`, over again if it is already running.
+    /// Returns whether it started, which it doesn't if `duration` is too long
+    /// to count down from `now`.
+    pub fn start(&mut self, name: &str, duration: Duration, now: Instant) -> bool {
+        match now.checked_add(duration) {
+            Some(end) => {
+                self.ends.insert(name.to_owned(), end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops the countdown `name` without it running out. Returns whether it
+    /// was running.
+    pub fn cancel(&mut self, name: &str) -> bool {
+        self.ends.remove(name).is_some()
+    }
+
+    /// Time left on the countdown `name`, if it is running.
+    pub fn remaining(&self, name: &str, now: Instant) -> Option<Duration> {
+        let end = self.ends.get(name)?;
+        Some(end.saturating_duration_since(now))
+    }
+
+    /// Every countdown that is running with the time left on it, by name.
+    pub fn all(&self, now: Instant) -> impl Iterator<Item = (&str, Duration)> {
+        self.ends
+            .iter()
+            .map(move |(name, end)| (name.as_str(), end.saturating_duration_since(now)))
+    }
+
+    /// Removes the countdowns that ran out and returns their names, the one
+    /// that ran out first first.
+    pub fn expire(&mut self, now: Instant) -> Vec<String> {
+        let mut expired: Vec<(String, Instant)> = Vec::new();
+        self.ends.retain(|name, &mut end| {
+            if end > now {
+                return true;
+            }
+            expired.push((name.clone(), end));
+            false
+        });
+        expired.sort_by_key(|&(_, end)| end);
+        expired.into_iter().map(|(name, _)| name).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers_run_out_once() {
+        let now = Instant::now();
+        let mut timers = Timers::default();
+        timers.start("race", Duration::from_secs(60), now);
+        timers.start("break", Duration::from_secs(30), now);
+        timers.start("cutoff", Duration::from_secs(10), now);
+        assert!(timers.cancel("cutoff"));
+        assert!(!timers.cancel("cutoff"));
+
+        let later = now + Duration::from_secs(45);
+        assert_eq!(
+            timers.remaining("race", later),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(timers.remaining("break", later), Some(Duration::ZERO));
+        assert_eq!(timers.expire(later), ["break"]);
+        assert!(timers.expire(later).is_empty());
+
+        // starting it again resets it
+        timers.start("race", Duration::from_secs(60), later);
+        let end = later + Duration::from_secs(60);
+        assert_eq!(
+            timers.all(end).collect::<Vec<_>>(),
+            [("race", Duration::ZERO)]
+        );
+        assert_eq!(timers.expire(end), ["race"]);
+        assert_eq!(timers.remaining("race", end), None);
+    }
+
+    #[test]
+    fn endless_timers_do_not_start() {
+        let mut timers = Timers::default();
+        assert!(!timers.start("race", Duration::MAX, Instant::now()));
+        assert_eq!(timers.all(Instant::now()).count(), 0);
+    }
+}
@@ -0,0 +1,166 @@
+//! How long every phase of drawing a frame and of simulating a tick takes,
+//! shown over the scene with F3 and to scripts through `simulation()`.
+
+use std::time::Duration;
+
+use crate::luanim::{Backend, Mat3};
+
+const TEXT_SIZE: f32 = 20.0;
+const LINE_HEIGHT: f32 = TEXT_SIZE * 1.25;
+const MARGIN: f32 = 16.0;
+
+/// Frames or ticks the averages are smoothed over.
+const SMOOTHING: f64 = 60.0;
+
+pub const PHASES: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Sending what every Mario is like to the scripts.
+    Values,
+    /// Running the animation functions, not counting their instructions.
+    Lua,
+    /// Handling instructions, not counting the uploads they cause.
+    Instructions,
+    /// Drawing NES frames and uploading them as images.
+    Upload,
+    /// Flushing the canvas of every scene drawn.
+    Flush,
+    /// Presenting the frame.
+    Swap,
+    /// Waiting for the lock of a Mario before playing its frames.
+    Lock,
+    /// Trying out inputs on clones of the emulator.
+    Rollouts,
+    /// Emulating the frames actually played.
+    Step,
+    /// Cloning the emulator for rollouts and saved states.
+    Clone,
+}
+
+impl Phase {
+    /// The phases of drawing a frame, in order.
+    pub const DRAW: [Phase; 6] = [
+        Phase::Values,
+        Phase::Lua,
+        Phase::Instructions,
+        Phase::Upload,
+        Phase::Flush,
+        Phase::Swap,
+    ];
+    /// The phases of a Mario's share of a tick.
+    pub const TICK: [Phase; 4] = [Phase::Lock, Phase::Rollouts, Phase::Step, Phase::Clone];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Values => "values",
+            Phase::Lua => "lua",
+            Phase::Instructions => "instructions",
+            Phase::Upload => "upload",
+            Phase::Flush => "flush",
+            Phase::Swap => "swap",
+            Phase::Lock => "lock",
+            Phase::Rollouts => "rollouts",
+            Phase::Step => "step",
+            Phase::Clone => "clone",
+        }
+    }
+}
+
+/// Time spent in every phase of a single frame or tick.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spans([Duration; PHASES]);
+
+impl Spans {
+    pub fn add(&mut self, phase: Phase, time: Duration) {
+        self.0[phase as usize] += time;
+    }
+
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.0[phase as usize]
+    }
+}
+
+/// Time every phase takes per frame or tick, smoothed over about a second.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    /// Seconds taken by every phase.
+    average: [f64; PHASES],
+    samples: u64,
+}
+
+impl Timings {
+    pub fn update(&mut self, spans: &Spans) {
+        self.samples += 1;
+        for (average, span) in self.average.iter_mut().zip(spans.0) {
+            let span = span.as_secs_f64();
+            *average = if self.samples == 1 {
+                span
+            } else {
+                *average + (span - *average) / SMOOTHING
+            };
+        }
+    }
+
+    pub fn get(&self, phase: Phase) -> Duration {
+        Duration::from_secs_f64(self.average[phase as usize])
+    }
+
+    /// The time taken by all of `phases` together.
+    pub fn total(&self, phases: &[Phase]) -> Duration {
+        phases.iter().map(|&phase| self.get(phase)).sum()
+    }
+}
+
+/// Draws the time every phase of drawing takes per frame, and every phase of
+/// simulating per tick, at the top left of `canvas`. Simulation phases are
+/// added up over all workers.
+pub fn draw(canvas: &mut impl Backend, frame: &Timings, tick: &Timings) {
+    let line = |name: &str, time: Duration| format!("{:<14}{:>8.2} ms", name, millis(time));
+    let mut lines = vec![line("frame", frame.total(&Phase::DRAW))];
+    lines.extend(
+        Phase::DRAW
+            .iter()
+            .map(|&phase| line(&format!("  {}", phase.name()), frame.get(phase))),
+    );
+    lines.push(line("tick (cpu)", tick.total(&Phase::TICK)));
+    lines.extend(
+        Phase::TICK
+            .iter()
+            .map(|&phase| line(&format!("  {}", phase.name()), tick.get(phase))),
+    );
+
+    for (i, line) in lines.iter().enumerate() {
+        let y = MARGIN + (i + 1) as f32 * LINE_HEIGHT;
+        // a line that can't be drawn is not worth reporting from the overlay
+        let _ = canvas.fill_text(Mat3::identity(), MARGIN, y, TEXT_SIZE, line, None);
+    }
+    canvas.flush();
+}
+
+pub fn millis(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timings_start_at_the_first_sample_then_smooth() {
+        let mut spans = Spans::default();
+        spans.add(Phase::Lua, Duration::from_millis(2));
+        spans.add(Phase::Lua, Duration::from_millis(4));
+        spans.add(Phase::Swap, Duration::from_millis(10));
+
+        let mut timings = Timings::default();
+        timings.update(&spans);
+        assert_eq!(timings.get(Phase::Lua), Duration::from_millis(6));
+        assert_eq!(timings.total(&Phase::DRAW), Duration::from_millis(16));
+
+        timings.update(&Spans::default());
+        let lua = timings.get(Phase::Lua);
+        assert!(lua < Duration::from_millis(6) && lua > Duration::from_millis(5));
+        assert_eq!(timings.get(Phase::Step), Duration::ZERO);
+    }
+}
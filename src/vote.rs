@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::mario::Personality;
+
+/// A trait viewers can vote to give a Mario more of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Trait {
+    Patient,
+    Bold,
+    Twitchy,
+    Jumpy,
+}
+
+impl Trait {
+    pub const ALL: [Trait; 4] = [Trait::Patient, Trait::Bold, Trait::Twitchy, Trait::Jumpy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Trait::Patient => "patient",
+            Trait::Bold => "bold",
+            Trait::Twitchy => "twitchy",
+            Trait::Jumpy => "jumpy",
+        }
+    }
+
+    /// Raises this trait of `personality` by a fifth of the range random
+    /// personalities get, up to the top of that range.
+    pub fn apply(&self, personality: &mut Personality) {
+        match self {
+            Trait::Patient => personality.patient = (personality.patient + 2).min(10),
+            Trait::Bold => personality.bold = (personality.bold + 2).min(10),
+            Trait::Twitchy => personality.twitchy = (personality.twitchy + 0.04).min(0.2),
+            Trait::Jumpy => personality.jumpy = (personality.jumpy + 0.04).min(0.2),
+        }
+    }
+}
+
+impl FromStr for Trait {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "patient" => Ok(Trait::Patient),
+            "bold" => Ok(Trait::Bold),
+            "twitchy" => Ok(Trait::Twitchy),
+            "jumpy" => Ok(Trait::Jumpy),
+            _ => Err(format!(
+                "unknown trait {:?}, expected patient, bold, twitchy or jumpy",
+                s
+            )),
+        }
+    }
+}
+
+/// Viewers voting on which trait one Mario gets more of, open until a
+/// deadline.
+pub struct Vote {
+    /// The zero-based instance the result is applied to.
+    pub instance: usize,
+    closes: Instant,
+    ballots: HashMap<String, Trait>,
+}
+
+impl Vote {
+    /// Opens a vote for `duration`, or returns `None` if that is too long to
+    /// tell when it closes.
+    pub fn new(instance: usize, duration: Duration) -> Option<Vote> {
+        Some(Vote {
+            instance,
+            closes: Instant::now().checked_add(duration)?,
+            ballots: HashMap::new(),
+        })
+    }
+
+    /// Counts `viewer` as voting for `choice`, replacing their earlier vote.
+    pub fn cast(&mut self, viewer: &str, choice: Trait) {
+        self.ballots.insert(viewer.to_owned(), choice);
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.closes.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// Votes per trait, in the order of [`Trait::ALL`].
+    pub fn tally(&self) -> [(Trait, usize); 4] {
+        Trait::ALL.map(|choice| {
+            let votes = self.ballots.values().filter(|&&c| c == choice).count();
+            (choice, votes)
+        })
+    }
+
+    /// The trait with the most votes, the earliest in [`Trait::ALL`] on a tie,
+    /// or `None` when nobody voted.
+    pub fn leader(&self) -> Option<Trait> {
+        let mut leader = None;
+        let mut most = 0;
+        for (choice, votes) in self.tally() {
+            if votes > most {
+                leader = Some(choice);
+                most = votes;
+            }
+        }
+        leader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_votes_wins_and_ties_go_to_the_first_trait() {
+        let mut vote = Vote::new(0, Duration::from_secs(30)).unwrap();
+        assert_eq!(vote.leader(), None);
+
+        vote.cast("alice", Trait::Jumpy);
+        vote.cast("bob", Trait::Bold);
+        assert_eq!(vote.leader(), Some(Trait::Bold));
+
+        vote.cast("carol", Trait::Jumpy);
+        vote.cast("bob", Trait::Jumpy);
+        assert_eq!(vote.leader(), Some(Trait::Jumpy));
+        assert_eq!(vote.tally()[3], (Trait::Jumpy, 3));
+    }
+
+    #[test]
+    fn endless_votes_do_not_open() {
+        assert!(Vote::new(0, Duration::MAX).is_none());
+    }
+
+    #[test]
+    fn traits_stay_in_range() {
+        let mut personality = Personality {
+            patient: 9,
+            bold: 1,
+            playful: 10,
+            twitchy: 0.19,
+            jumpy: 0.01,
+            confident: 1,
+            rollouts: 3,
+        };
+        Trait::Patient.apply(&mut personality);
+        Trait::Twitchy.apply(&mut personality);
+        Trait::Jumpy.apply(&mut personality);
+        assert_eq!(personality.patient, 10);
+        assert_eq!(personality.twitchy, 0.2);
+        assert!((personality.jumpy - 0.05).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,249 @@
+//! Chart instructions for dashboards, drawn with the core luanim primitives in
+//! the current transform and line width.
+
+use std::{
+    collections::VecDeque,
+    f32::consts::TAU,
+    sync::{Arc, Mutex},
+};
+
+use mlua::{Error, FromLuaMulti};
+
+use crate::{
+    diversity::Diversity,
+    history::FitnessHistory,
+    luanim::{Backend, Options, PathCmd, Screen},
+    mario::Personality,
+    observation::{Observation, Position},
+};
+
+/// Horizontal speed, as the game keeps it, of moving a pixel per frame.
+const X_SPEED_PER_PIXEL: f32 = 16.0;
+/// Frames of movement the velocity drawn by [`hitboxes`] reaches ahead.
+const VELOCITY_FRAMES: f32 = 8.0;
+
+/// Registers the `radar`, `sparkline` and `progress` instructions:
+///
+/// - `radar(x, y, radius, instance)` draws the personality of an instance as a
+///   radar chart centered on `(x, y)`;
+/// - `sparkline(x, y, width, height, instance)` draws the fitness history of
+///   an instance in the box with its top left corner at `(x, y)`;
+/// - `progress(x, y, width, height, value, max)` draws a bar filled for
+///   `value / max`.
+pub fn register<B: Backend>(
+    options: Options<B>,
+    personalities: Vec<Personality>,
+    history: Arc<Mutex<FitnessHistory>>,
+) -> Options<B> {
+    options
+        .instruction("radar", move |lua, args, screen| {
+            let (x, y, radius, instance): (f32, f32, f32, usize) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            let personality = instance
+                .checked_sub(1)
+                .and_then(|i| personalities.get(i))
+                .ok_or_else(|| no_instance(instance))?;
+            radar(screen, x, y, radius, &personality.traits());
+            Ok(())
+        })
+        .instruction("sparkline", move |lua, args, screen| {
+            let (x, y, width, height, instance): (f32, f32, f32, f32, usize) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            let history = history.lock().unwrap();
+            let values = instance
+                .checked_sub(1)
+                .and_then(|i| history.get(i))
+                .ok_or_else(|| no_instance(instance))?;
+            sparkline(screen, x, y, width, height, values, history.capacity());
+            Ok(())
+        })
+        .instruction("progress", |lua, args, screen| {
+            let (x, y, width, height, value, max): (f32, f32, f32, f32, f32, f32) =
+                FromLuaMulti::from_lua_multi(args, lua)?;
+            let fraction = if max > 0.0 { value / max } else { 0.0 };
+            progress(screen, x, y, width, height, fraction);
+            Ok(())
+        })
+}
+
+/// Registers `diversity_chart(x, y, width, height)`, which draws how the
+/// diversity of the personalities in the population went over time like
+/// `sparkline`.
+pub fn register_diversity<B: Backend>(
+    options: Options<B>,
+    diversity: Arc<Mutex<Diversity>>,
+) -> Options<B> {
+    options.instruction("diversity_chart", move |lua, args, screen| {
+        let (x, y, width, height): (f32, f32, f32, f32) = FromLuaMulti::from_lua_multi(args, lua)?;
+        let diversity = diversity.lock().unwrap();
+        // in thousandths, as sparklines draw whole numbers
+        let values = diversity
+            .samples()
+            .iter()
+            .map(|sample| (sample.personalities * 1000.0) as u32)
+            .collect();
+        sparkline(screen, x, y, width, height, &values, diversity.capacity());
+        Ok(())
+    })
+}
+
+fn no_instance(instance: usize) -> Error {
+    Error::RuntimeError(format!("no instance {}", instance))
+}
+
+/// Draws the axes and outline of a radar chart and the polygon of `values`,
+/// which should lie in 0..=1. The first axis points up.
+pub fn radar<B: Backend>(screen: &mut Screen<B>, x: f32, y: f32, radius: f32, values: &[f32]) {
+    let corner = |i: usize, length: f32| {
+        let angle = i as f32 / values.len() as f32 * TAU;
+        screen.point_at(x + angle.sin() * length, y - angle.cos() * length)
+    };
+
+    let center = screen.point_at(x, y);
+    let mut outline = Vec::with_capacity(values.len() * 3 + 1);
+    let mut shape = Vec::with_capacity(values.len() + 1);
+    for (i, value) in values.iter().enumerate() {
+        outline.push(PathCmd::MoveTo(center));
+        outline.push(PathCmd::LineTo(corner(i, radius)));
+
+        let point = corner(i, radius * value.clamp(0.0, 1.0));
+        shape.push(match i {
+            0 => PathCmd::MoveTo(point),
+            _ => PathCmd::LineTo(point),
+        });
+    }
+    for i in 0..values.len() {
+        outline.push(match i {
+            0 => PathCmd::MoveTo(corner(i, radius)),
+            _ => PathCmd::LineTo(corner(i, radius)),
+        });
+    }
+    outline.push(PathCmd::Close);
+    shape.push(PathCmd::Close);
+
+    let width = screen.line_width;
+    screen.canvas.stroke_path(&outline, width / 2.0);
+    screen.canvas.stroke_path(&shape, width * 2.0);
+}
+
+/// Draws `values` as a line in the given box, scaled between their minimum and
+/// maximum. The box has room for `capacity` values and fills from the left.
+pub fn sparkline<B: Backend>(
+    screen: &mut Screen<B>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    values: &VecDeque<u32>,
+    capacity: usize,
+) {
+    let (min, max) = match (values.iter().min(), values.iter().max()) {
+        (Some(&min), Some(&max)) => (min, max),
+        _ => return,
+    };
+    let range = (max - min) as f32;
+    let step = width / capacity.saturating_sub(1).max(1) as f32;
+
+    let line: Vec<_> = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            let level = if range > 0.0 {
+                (value - min) as f32 / range
+            } else {
+                0.5
+            };
+            let point = screen.point_at(x + i as f32 * step, y + height - level * height);
+            match i {
+                0 => PathCmd::MoveTo(point),
+                _ => PathCmd::LineTo(point),
+            }
+        })
+        .collect();
+
+    let width = screen.line_width;
+    screen.canvas.stroke_path(&line, width);
+}
+
+/// Draws the outline of a bar with its top left corner at `(x, y)`, filled
+/// from the left for `fraction` of its width.
+pub fn progress<B: Backend>(
+    screen: &mut Screen<B>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    fraction: f32,
+) {
+    let outline = [
+        PathCmd::MoveTo(screen.point_at(x, y)),
+        PathCmd::LineTo(screen.point_at(x + width, y)),
+        PathCmd::LineTo(screen.point_at(x + width, y + height)),
+        PathCmd::LineTo(screen.point_at(x, y + height)),
+        PathCmd::Close,
+    ];
+    let line_width = screen.line_width;
+    screen.canvas.stroke_path(&outline, line_width);
+
+    // the fill is a line as thick as the inside of the bar
+    let fraction = fraction.clamp(0.0, 1.0);
+    if fraction > 0.0 {
+        let inset = height / 4.0;
+        let middle = y + height / 2.0;
+        let fill = [
+            PathCmd::MoveTo(screen.point_at(x + inset, middle)),
+            PathCmd::LineTo(screen.point_at(x + inset + (width - inset * 2.0) * fraction, middle)),
+        ];
+        let thickness = (height - inset * 2.0) * screen.rough_scale();
+        screen.canvas.stroke_path(&fill, thickness);
+    }
+}
+
+/// Draws what `observation` says over the frame it was read from, with its
+/// top left corner at `(x, y)` and `pixel` units per NES pixel: the box of
+/// Mario, the boxes of the enemies and where Mario is headed at his speed.
+pub fn hitboxes<B: Backend>(
+    screen: &mut Screen<B>,
+    x: f32,
+    y: f32,
+    pixel: f32,
+    observation: &Observation,
+) {
+    let at = |screen: &Screen<B>, (px, py): (i32, i32)| {
+        screen.point_at(x + px as f32 * pixel, y + py as f32 * pixel)
+    };
+    // everything is drawn as a 16 pixel sprite, by its top left corner
+    let sprite = |screen: &Screen<B>, position: Position| {
+        let (left, top) = observation.on_screen(position);
+        [
+            PathCmd::MoveTo(at(screen, (left, top))),
+            PathCmd::LineTo(at(screen, (left + 16, top))),
+            PathCmd::LineTo(at(screen, (left + 16, top + 16))),
+            PathCmd::LineTo(at(screen, (left, top + 16))),
+            PathCmd::Close,
+        ]
+    };
+
+    let width = screen.line_width;
+    for &enemy in observation.enemies.iter().flatten() {
+        let outline = sprite(screen, enemy);
+        screen.canvas.stroke_path(&outline, width / 2.0);
+    }
+    let outline = sprite(screen, observation.player);
+    screen.canvas.stroke_path(&outline, width * 2.0);
+
+    let (left, top) = observation.on_screen(observation.player);
+    let center = (left as f32 + 8.0, top as f32 + 8.0);
+    let velocity = (
+        f32::from(observation.x_speed) / X_SPEED_PER_PIXEL * VELOCITY_FRAMES,
+        f32::from(observation.y_speed) * VELOCITY_FRAMES,
+    );
+    let line = [
+        PathCmd::MoveTo(screen.point_at(x + center.0 * pixel, y + center.1 * pixel)),
+        PathCmd::LineTo(screen.point_at(
+            x + (center.0 + velocity.0) * pixel,
+            y + (center.1 + velocity.1) * pixel,
+        )),
+    ];
+    screen.canvas.stroke_path(&line, width);
+}
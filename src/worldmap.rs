@@ -0,0 +1,139 @@
+//! Which level every Mario is in and how fast anyone got through each level,
+//! for scenes that show the population spread over a map of the game.
+
+use std::collections::BTreeMap;
+
+use crate::smb::Warp;
+
+/// What an instance is doing on a tick, for [`WorldMap::update`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sighting {
+    /// The level Mario is in, or `None` on the title screen.
+    pub level: Option<Warp>,
+    pub lives: u8,
+    /// Frame number of the NES.
+    pub frame: u64,
+}
+
+/// A Mario's go at a level, from the frame he was first seen in it or lost a
+/// life in it.
+#[derive(Clone, Copy, Debug)]
+struct Attempt {
+    level: Warp,
+    started: u64,
+    lives: u8,
+}
+
+#[derive(Default)]
+pub struct WorldMap {
+    /// The attempt of every instance, by zero-based instance.
+    attempts: Vec<Option<Attempt>>,
+    /// Fewest frames anyone took through every level, from the start of an
+    /// attempt to being seen in a later level.
+    best: BTreeMap<Warp, u64>,
+}
+
+impl WorldMap {
+    /// Updates where every instance is from what was seen of it, by
+    /// zero-based instance.
+    pub fn update(&mut self, sightings: &[Sighting]) {
+        self.attempts.resize(sightings.len(), None);
+        for (attempt, sighting) in self.attempts.iter_mut().zip(sightings) {
+            let level = match sighting.level {
+                Some(level) => level,
+                None => {
+                    *attempt = None;
+                    continue;
+                }
+            };
+            let fresh = Attempt {
+                level,
+                started: sighting.frame,
+                lives: sighting.lives,
+            };
+            match attempt {
+                Some(current) if current.level == level => {
+                    // a lost life, or a state loaded from before the attempt
+                    if sighting.lives < current.lives || sighting.frame < current.started {
+                        current.started = sighting.frame;
+                    }
+                    current.lives = sighting.lives;
+                }
+                Some(current) if level.depth() > current.level.depth() => {
+                    if let Some(frames) = sighting.frame.checked_sub(current.started) {
+                        let best = self.best.entry(current.level).or_insert(frames);
+                        *best = (*best).min(frames);
+                    }
+                    *attempt = Some(fresh);
+                }
+                _ => *attempt = Some(fresh),
+            }
+        }
+    }
+
+    /// The level the zero-based instance `index` is in, if it is in one.
+    pub fn level(&self, index: usize) -> Option<Warp> {
+        self.attempts
+            .get(index)
+            .copied()
+            .flatten()
+            .map(|attempt| attempt.level)
+    }
+
+    /// How many instances are in every level anyone is in.
+    pub fn counts(&self) -> BTreeMap<Warp, usize> {
+        let mut counts = BTreeMap::new();
+        for attempt in self.attempts.iter().flatten() {
+            *counts.entry(attempt.level).or_default() += 1;
+        }
+        counts
+    }
+
+    /// The fewest frames anyone took through every level someone finished.
+    pub fn best(&self) -> &BTreeMap<Warp, u64> {
+        &self.best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seen(level: &str, lives: u8, frame: u64) -> Sighting {
+        Sighting {
+            level: Some(level.parse().unwrap()),
+            lives,
+            frame,
+        }
+    }
+
+    #[test]
+    fn best_times_start_over_on_a_lost_life() {
+        let mut map = WorldMap::default();
+        map.update(&[seen("1-1", 3, 100), seen("1-1", 3, 100)]);
+        map.update(&[seen("1-1", 2, 500), seen("1-1", 3, 900)]);
+        map.update(&[seen("1-2", 2, 1000), seen("1-1", 3, 1000)]);
+        assert_eq!(map.best().get(&"1-1".parse().unwrap()), Some(&500));
+
+        map.update(&[seen("1-2", 2, 1100), seen("4-1", 3, 1200)]);
+        assert_eq!(map.best().get(&"1-1".parse().unwrap()), Some(&500));
+        assert_eq!(map.level(1), Some("4-1".parse().unwrap()));
+
+        let counts = map.counts();
+        assert_eq!(counts.get(&"1-2".parse().unwrap()), Some(&1));
+        assert_eq!(counts.get(&"4-1".parse().unwrap()), Some(&1));
+    }
+
+    #[test]
+    fn the_title_screen_is_in_no_level() {
+        let mut map = WorldMap::default();
+        map.update(&[seen("1-1", 3, 0)]);
+        map.update(&[Sighting {
+            level: None,
+            lives: 3,
+            frame: 10,
+        }]);
+        assert_eq!(map.level(0), None);
+        assert!(map.counts().is_empty());
+    }
+}
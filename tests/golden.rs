@@ -0,0 +1,130 @@
+//! Renders the scripts in `tests/golden` with the software [`Raster`] backend and
+//! compares the result against the reference PNG of the same name.
+//!
+//! After an intended rendering change, run with `UPDATE_GOLDEN=1` to write new
+//! references and review them before committing.
+
+use std::{env, path::PathBuf};
+
+use mlua::FromLuaMulti;
+use shellkick::luanim::{Animation, Backend, Options, PathCmd, Raster};
+use tiny_skia::Pixmap;
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 144;
+
+/// Largest difference in any channel for two pixels to count as equal.
+const CHANNEL_TOLERANCE: u8 = 16;
+/// Fraction of pixels that may differ, to allow for anti-aliasing changes.
+const PIXEL_TOLERANCE: f32 = 0.005;
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn render(name: &str, options: Options<Raster>) -> Pixmap {
+    let script = golden_dir().join(format!("{}.lua", name));
+    let lib_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("luanim/src");
+
+    let canvas = Raster::new(WIDTH, HEIGHT).unwrap();
+    let mut animation = Animation::new(script, canvas, options.lib_path(lib_path))
+        .unwrap_or_else(|e| panic!("could not load {}: {}", name, e));
+    animation
+        .advance_time(0.0)
+        .unwrap_or_else(|e| panic!("could not render {}: {}", name, e));
+    animation.canvas_mut().pixmap().clone()
+}
+
+fn check(name: &str, options: Options<Raster>) {
+    let actual = render(name, options);
+    let reference = golden_dir().join(format!("{}.png", name));
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save_png(&reference).unwrap();
+        return;
+    }
+
+    let expected = Pixmap::load_png(&reference).unwrap_or_else(|e| {
+        panic!(
+            "could not read {} ({}), run with UPDATE_GOLDEN=1 to create it",
+            reference.display(),
+            e
+        )
+    });
+    assert_eq!(
+        (expected.width(), expected.height()),
+        (actual.width(), actual.height()),
+        "{} has the wrong size",
+        name
+    );
+
+    let different = expected
+        .pixels()
+        .iter()
+        .zip(actual.pixels())
+        .filter(|(a, b)| {
+            let channels = [
+                a.red().abs_diff(b.red()),
+                a.green().abs_diff(b.green()),
+                a.blue().abs_diff(b.blue()),
+                a.alpha().abs_diff(b.alpha()),
+            ];
+            channels.into_iter().any(|diff| diff > CHANNEL_TOLERANCE)
+        })
+        .count();
+
+    let allowed = (expected.pixels().len() as f32 * PIXEL_TOLERANCE) as usize;
+    if different > allowed {
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.png", name));
+        actual.save_png(&out).unwrap();
+        panic!(
+            "{} differs from its reference in {} pixels (at most {} allowed), got {}",
+            name,
+            different,
+            allowed,
+            out.display()
+        );
+    }
+}
+
+#[test]
+fn circle() {
+    check("circle", Options::new());
+}
+
+#[test]
+fn path() {
+    check("path", Options::new());
+}
+
+#[test]
+fn transform() {
+    check("transform", Options::new());
+}
+
+#[test]
+fn text() {
+    check("text", Options::new());
+}
+
+#[test]
+fn ellipse() {
+    check("ellipse", Options::new());
+}
+
+#[test]
+fn custom() {
+    let options = Options::<Raster>::new().instruction("cross", |lua, args, screen| {
+        let (x, y, r): (f32, f32, f32) = FromLuaMulti::from_lua_multi(args, lua)?;
+        let path = [
+            PathCmd::MoveTo(screen.point_at(x - r, y - r)),
+            PathCmd::LineTo(screen.point_at(x + r, y + r)),
+            PathCmd::MoveTo(screen.point_at(x + r, y - r)),
+            PathCmd::LineTo(screen.point_at(x - r, y + r)),
+        ];
+        let width = screen.line_width;
+        screen.canvas.stroke_path(&path, width);
+        Ok(())
+    });
+    check("custom", options);
+}
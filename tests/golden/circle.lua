@@ -0,0 +1,7 @@
+local op = canvas.instructions
+
+return function(_, emit)
+  emit(op.circle, 0, 0, 64)
+  emit(op.circle, -192, -96, 16)
+  emit(op.circle, 192, 96, 8)
+end
@@ -0,0 +1,6 @@
+local op = canvas.instructions
+
+return function(_, emit)
+  emit(op.cross, -64, 0, 32)
+  emit(op.cross, 64, 0, 16)
+end
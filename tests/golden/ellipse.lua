@@ -0,0 +1,21 @@
+local op = canvas.instructions
+
+return function(_, emit)
+  emit(op.ellipse, -128, 0, 48)
+
+  -- uniform scales still draw circles
+  emit(op.push_transform, "scale", false, 1.5, 0, 0, 1.5, 128, 0)
+  emit(op.ellipse, 0, 0, 32)
+  emit(op.pop_transform)
+
+  -- scaling x and y differently stretches it, rotating turns it along
+  local c = math.sqrt(0.5)
+  emit(op.push_transform, "scale", false, 2, 0, 0, 0.5, 0, -72)
+  emit(op.ellipse, 0, 0, 24)
+  emit(op.pop_transform)
+  emit(op.push_transform, "rotate", false, c, c, -c, c, 0, 72)
+  emit(op.push_transform, "scale", false, 2, 0, 0, 0.5, 0, 0)
+  emit(op.ellipse, 0, 0, 24)
+  emit(op.pop_transform)
+  emit(op.pop_transform)
+end
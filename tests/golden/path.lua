@@ -0,0 +1,18 @@
+local op = canvas.instructions
+
+return function(_, emit)
+  -- open path
+  emit(op.line_width, 4)
+  emit(op.move_to, -224, -96)
+  emit(op.line_to, -96, 96)
+  emit(op.line_to, 0, -96)
+  emit(op.stroke_path)
+
+  -- closed path
+  emit(op.line_width, 12)
+  emit(op.move_to, 64, -64)
+  emit(op.line_to, 192, -64)
+  emit(op.line_to, 128, 64)
+  emit(op.close_path)
+  emit(op.stroke_path)
+end
@@ -0,0 +1,15 @@
+local op = canvas.instructions
+
+return function(_, emit)
+  -- text is placed in pixels from the center rather than in the units other
+  -- shapes use, only its size follows the transform
+  emit(op.text, -112, -40, 1, "luanim")
+  emit(op.text, -112, 0, 2, "big")
+
+  -- text scales with its transform, not just its size
+  emit(op.push_transform, "scale", false, 0.5, 0, 0, 3, 0, 0)
+  emit(op.text, 0, 0, 1, "tall")
+  emit(op.pop_transform)
+
+  emit(op.text_wrap, 40, -40, 1, 128, "wrapped over\nseveral short lines")
+end
@@ -0,0 +1,32 @@
+local op = canvas.instructions
+
+return function(_, emit)
+  local function square()
+    emit(op.move_to, -32, -32)
+    emit(op.line_to, 32, -32)
+    emit(op.line_to, 32, 32)
+    emit(op.line_to, -32, 32)
+    emit(op.close_path)
+    emit(op.stroke_path)
+  end
+
+  emit(op.line_width, 4)
+
+  -- translated and scaled
+  emit(op.push_transform, "scale", false, 2, 0, 0, 2, -128, 0)
+  square()
+  emit(op.circle, 0, 0, 8)
+  emit(op.pop_transform)
+
+  -- rotated by 45 degrees, nested in a translation
+  local c = math.sqrt(0.5)
+  emit(op.push_transform, "translate", false, 1, 0, 0, 1, 128, 0)
+  emit(op.push_transform, "rotate", false, c, c, -c, c, 0, 0)
+  square()
+  emit(op.pop_transform)
+  emit(op.circle, 0, 0, 8)
+  emit(op.pop_transform)
+
+  -- back at the root
+  square()
+end
@@ -0,0 +1,327 @@
+//! Checks the readings of the SMB memory map against RAM states of known
+//! moments in the game.
+//!
+//! The states are built from the addresses the game uses for them. The test
+//! that boots the actual game needs a ROM at `rom/smb.nes`, which is not part
+//! of the repository, so it is ignored unless asked for with
+//! `cargo test -- --ignored`.
+
+use std::{
+    env::temp_dir,
+    fs::read,
+    panic::{catch_unwind, AssertUnwindSafe},
+};
+
+use rand::RngCore;
+use shellkick::{
+    controller::{Controller, InputPlan, Turn},
+    input_log::{timeline, Entry, InputLog, Reader},
+    mario::{next_frame, replay, Mario, Personality, Reset, Settings},
+    smb::{
+        depth, fitness, in_level, objective_fitness,
+        ram::{self, engine, mode, TASK_RUNNING},
+        scroll, title_menu, victory, Fitness, Memory, Objective, Powerup, Ram,
+    },
+};
+
+/// Mario standing in 1-1 with 400 on the clock, 2 screens and 64 pixels in.
+fn playing() -> Ram {
+    Ram::default()
+        .with(ram::MODE, mode::GAME)
+        .with(ram::MODE_TASK, TASK_RUNNING)
+        .with(ram::GAME_ENGINE, engine::PLAYER_CONTROL)
+        .with(ram::TIMER, 4)
+        .with(ram::TIMER + 1, 0)
+        .with(ram::TIMER + 2, 0)
+        .with(ram::PLAYER_Y_SCREEN, 1)
+        .with(ram::PLAYER_Y, 0x80)
+        .with(ram::PLAYER_PAGE, 2)
+        .with(ram::PLAYER_X, 0x40)
+        .with(ram::SCREEN_PAGE, 2)
+        .with(ram::SCREEN_X, 0x30)
+}
+
+#[test]
+fn playing_reads_position_and_time() {
+    let mut state = playing();
+    assert_eq!(fitness(&mut state), Fitness::Level(0x0240, 400));
+    assert_eq!(scroll(&mut state), 0x0230);
+    assert!(in_level(&mut state));
+    assert!(!victory(&mut state));
+    assert_eq!(depth(&mut state), 0);
+}
+
+#[test]
+fn falling_in_a_pit_is_death() {
+    let mut state = playing().with(ram::PLAYER_Y, 0xd0);
+    assert_eq!(fitness(&mut state), Fitness::Dying(false));
+}
+
+#[test]
+fn getting_hit_is_death() {
+    let mut state = playing().with(ram::GAME_ENGINE, engine::DEATH);
+    assert_eq!(fitness(&mut state), Fitness::Dying(false));
+}
+
+#[test]
+fn running_out_of_time_is_a_timeout() {
+    let mut state = playing().with(ram::TIMER, 0);
+    assert_eq!(fitness(&mut state), Fitness::Dying(true));
+}
+
+#[test]
+fn flagpole_reads_grab_height() {
+    let mut state = playing()
+        .with(ram::GAME_ENGINE, engine::FLAGPOLE)
+        .with(ram::FLAGPOLE_SCORE, 3);
+    assert_eq!(fitness(&mut state), Fitness::Flagpole(3));
+}
+
+#[test]
+fn warp_pipe_is_a_cutscene() {
+    let mut state = playing().with(ram::GAME_ENGINE, engine::VERTICAL_PIPE);
+    assert_eq!(fitness(&mut state), Fitness::Cutscene);
+}
+
+#[test]
+fn water_level_counts_distance() {
+    // 2-2, a water level
+    let mut state = playing()
+        .with(ram::WORLD, 1)
+        .with(ram::LEVEL, 1)
+        .with(ram::AREA, 2)
+        .with(ram::AREA_TYPE, 0); // water
+    assert_eq!(fitness(&mut state), Fitness::Level(0x0102_0240, 400));
+    assert_eq!(scroll(&mut state), 0x0102_0230);
+    assert_eq!(depth(&mut state), 5);
+}
+
+#[test]
+fn victory_is_a_cutscene() {
+    let mut state = playing().with(ram::MODE, mode::VICTORY);
+    assert!(victory(&mut state));
+    assert_eq!(fitness(&mut state), Fitness::Cutscene);
+}
+
+#[test]
+fn screens_before_a_level_are_not_in_it() {
+    let mut state = playing().with(ram::MODE_TASK, 1);
+    assert!(!in_level(&mut state));
+    assert_eq!(fitness(&mut state), Fitness::Cutscene);
+}
+
+#[test]
+fn title_menu_waits_for_start() {
+    let mut state = Ram::default()
+        .with(ram::MODE, mode::TITLE)
+        .with(ram::MODE_TASK, TASK_RUNNING);
+    assert!(title_menu(&mut state));
+    assert!(!in_level(&mut state));
+}
+
+#[test]
+fn objectives_add_to_distance() {
+    let mut state = playing()
+        .with(ram::COINS, 2)
+        .with(ram::SCORE + 3, 5) // score 500
+        .with(ram::PLAYER_X_SPEED, 0x18); // running right
+    let distance = objective_fitness(&mut state, Objective::Distance, 0);
+    assert_eq!(distance, Fitness::Level(0x0240, 400));
+    assert!(objective_fitness(&mut state, Objective::Coins, 0) > distance);
+    assert!(objective_fitness(&mut state, Objective::Score, 0) > distance);
+    assert!(objective_fitness(&mut state, Objective::Speed, 0) > distance);
+}
+
+#[test]
+fn powerups_and_lives() {
+    let mut state = playing().with(ram::POWERUP, 2).with(ram::LIVES, 2);
+    assert_eq!(state.powerup(), Powerup::Fire);
+    assert_eq!(state.lives(), 3);
+
+    let small = objective_fitness(&mut playing(), Objective::Distance, 16);
+    assert_eq!(small, Fitness::Level(0x0240, 400));
+    let fire = objective_fitness(&mut state, Objective::Distance, 16);
+    assert_eq!(fire, Fitness::Level(0x0240 + 32, 400));
+}
+
+#[test]
+fn ram_checksum_tells_states_apart() {
+    let mut state = playing();
+    let copy = Ram::of(&mut state);
+    assert_eq!(copy.checksum(), state.checksum());
+    assert_ne!(copy.with(ram::PLAYER_X, 0x41).checksum(), state.checksum());
+}
+
+#[test]
+#[ignore = "needs rom/smb.nes"]
+fn logged_inputs_replay_in_sync() {
+    let rom = read("rom/smb.nes").expect("rom/smb.nes is needed for this test");
+    let personality = Personality {
+        patient: 5,
+        bold: 5,
+        playful: 10,
+        twitchy: 0.1,
+        jumpy: 0.1,
+        confident: 1,
+        rollouts: 3,
+    };
+    let path = temp_dir().join(format!("shellkick-replay-{}", std::process::id()));
+    let mut mario = Mario::new(personality, rom.clone());
+    mario.log = Some(InputLog::create(&path).unwrap());
+    let settings = Settings::default();
+    for _ in 0..2000 {
+        next_frame(&mut mario, &settings);
+    }
+    mario.flush_log();
+
+    let entries: Vec<Entry> = Reader::open(&path).unwrap().map(Result::unwrap).collect();
+    std::fs::remove_file(&path).unwrap();
+    let ram = match entries.last() {
+        Some(&Entry::Check { ram, .. }) => ram,
+        other => panic!("log should end in a check, got {:?}", other),
+    };
+    let mut nes = replay(rom, &timeline(entries));
+    assert_eq!(Ram::of(&mut nes).checksum(), ram);
+}
+
+#[test]
+#[ignore = "needs rom/smb.nes"]
+fn boots_into_first_level() {
+    let rom = read("rom/smb.nes").expect("rom/smb.nes is needed for this test");
+    let personality = Personality {
+        patient: 5,
+        bold: 5,
+        playful: 10,
+        twitchy: 0.1,
+        jumpy: 0.1,
+        confident: 1,
+        rollouts: 3,
+    };
+    let mut mario = Mario::new(personality, rom);
+    let settings = Settings::default();
+
+    let mut frames = 0;
+    while mario.booting {
+        assert!(
+            frames < 1200,
+            "still not in a level after {} frames",
+            frames
+        );
+        next_frame(&mut mario, &settings);
+        frames += 1;
+    }
+
+    let nes = mario.nes_mut();
+    assert!(in_level(nes));
+    assert_eq!(depth(nes), 0);
+    assert!(matches!(fitness(nes), Fitness::Level(..)));
+}
+
+#[test]
+#[ignore = "needs rom/smb.nes"]
+fn reset_goes_back_to_level_start() {
+    let rom = read("rom/smb.nes").expect("rom/smb.nes is needed for this test");
+    let personality = Personality {
+        patient: 5,
+        bold: 5,
+        playful: 10,
+        twitchy: 0.1,
+        jumpy: 0.1,
+        confident: 1,
+        rollouts: 3,
+    };
+    let mut mario = Mario::new(personality, rom.clone());
+    let settings = Settings::default();
+    assert!(
+        !mario.reset_to(Reset::Level),
+        "not in a level while booting"
+    );
+    while mario.booting {
+        next_frame(&mut mario, &settings);
+    }
+    for _ in 0..200 {
+        next_frame(&mut mario, &settings);
+    }
+
+    let start = mario.run.as_ref().unwrap().start.frame_number();
+    assert!(mario.reset_to(Reset::Level));
+    assert_eq!(mario.nes().frame_number(), start);
+    assert_eq!(mario.played.len(), start as usize);
+    let mut nes = replay(rom, &mario.played);
+    assert_eq!(
+        Ram::of(&mut nes).checksum(),
+        Ram::of(mario.nes_mut()).checksum()
+    );
+
+    assert!(mario.reset_to(Reset::Boot));
+    assert!(mario.booting);
+    assert!(mario.played.is_empty());
+}
+
+#[test]
+#[ignore = "needs rom/smb.nes"]
+fn paused_marios_only_play_steps() {
+    let rom = read("rom/smb.nes").expect("rom/smb.nes is needed for this test");
+    let personality = Personality {
+        patient: 5,
+        bold: 5,
+        playful: 10,
+        twitchy: 0.1,
+        jumpy: 0.1,
+        confident: 1,
+        rollouts: 3,
+    };
+    let mut mario = Mario::new(personality, rom);
+    let settings = Settings::default();
+    mario.paused = true;
+    mario.steps = 2;
+    let frames: Vec<u32> = (0..3)
+        .map(|slot| mario.frames_due(slot, &settings))
+        .collect();
+    assert_eq!(frames, [1, 1, 0]);
+}
+
+/// Panics as soon as it is asked for inputs.
+struct Crashing;
+
+impl Controller for Crashing {
+    fn decide(&mut self, _: &mut Turn, _: &mut dyn RngCore) -> InputPlan {
+        panic!("crashing on purpose");
+    }
+
+    fn name(&self) -> &'static str {
+        "crashing"
+    }
+}
+
+#[test]
+#[ignore = "needs rom/smb.nes"]
+fn panicking_frame_keeps_the_state() {
+    let rom = read("rom/smb.nes").expect("rom/smb.nes is needed for this test");
+    let personality = Personality {
+        patient: 5,
+        bold: 5,
+        playful: 10,
+        twitchy: 0.1,
+        jumpy: 0.1,
+        confident: 1,
+        rollouts: 3,
+    };
+    let mut mario = Mario::new(personality, rom);
+    let settings = Settings::default();
+    while mario.booting {
+        next_frame(&mut mario, &settings);
+    }
+    let frame = mario.nes().frame_number();
+
+    mario.controller = Box::new(Crashing);
+    mario.inputs_future.clear();
+    let ran = catch_unwind(AssertUnwindSafe(|| next_frame(&mut mario, &settings)));
+    assert!(ran.is_err());
+    assert_eq!(mario.nes().frame_number(), frame);
+    assert!(in_level(mario.nes_mut()));
+
+    mario.reset();
+    assert!(mario.booting);
+    assert_eq!(mario.states.len(), 1);
+}